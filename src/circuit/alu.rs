@@ -0,0 +1,137 @@
+use crate::circuit::gate::*;
+
+/// Number of opcode lines an `Alu` expects.
+pub const OPCODE_WIDTH: usize = 4;
+
+/// Operations understood by `Alu`. The discriminant is the opcode,
+/// `op[0]` being its least significant bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AluOp {
+    Add = 0,
+    Sub = 1,
+    And = 2,
+    Or  = 3,
+    Xor = 4,
+    Not = 5,
+    Shl = 6,
+    Shr = 7,
+    Cmp = 8,
+}
+
+/// N-bit ALU built from `FullAdder`s and basic gates.
+///
+/// Bit 0 of every bus is the least significant one. `Not`, `Shl` and `Shr`
+/// only use `a`. `Cmp` computes `a - b` for the flags and drives a zero result.
+/// Unused opcodes drive a zero result with all flags but `zero` low.
+///
+/// Flags:
+/// - `carry` – adder carry out (set when no borrow for `Sub`/`Cmp`),
+///   or the bit shifted out for `Shl`/`Shr`
+/// - `zero` – result (difference for `Cmp`) is all zeros
+/// - `negative` – most significant bit of the result (difference for `Cmp`)
+/// - `overflow` – signed overflow of `Add`/`Sub`/`Cmp`
+#[derive(Debug)]
+pub struct Alu {
+    pub result: Vec<GateRef>,
+    pub carry: GateRef,
+    pub zero: GateRef,
+    pub negative: GateRef,
+    pub overflow: GateRef,
+}
+
+impl AluOp {
+    pub const ALL: [AluOp; 9] = [
+        AluOp::Add, AluOp::Sub, AluOp::And, AluOp::Or, AluOp::Xor,
+        AluOp::Not, AluOp::Shl, AluOp::Shr, AluOp::Cmp,
+    ];
+
+    pub fn code(self) -> u8 { self as u8 }
+
+    /// Opcode lines, least significant first.
+    pub fn bits(self) -> [bool; OPCODE_WIDTH] {
+        let c = self.code();
+        [c & 1 != 0, c & 2 != 0, c & 4 != 0, c & 8 != 0]
+    }
+}
+
+impl Alu {
+    pub fn new(a: Vec<GateRef>, b: Vec<GateRef>, op: [GateRef; OPCODE_WIDTH]) -> Self {
+        assert_eq!(a.len(), b.len(), "ALU operands must have the same width");
+        assert!(!a.is_empty(), "ALU needs at least one bit");
+        let n = a.len();
+
+        let op_n: Vec<GateRef> = op.iter().map(|o| gate(NotGate::new(o.clone()))).collect();
+        let select = |code: AluOp| {
+            let bits = code.bits();
+            let lits: Vec<GateRef> = (0..OPCODE_WIDTH)
+                .map(|i| if bits[i] { op[i].clone() } else { op_n[i].clone() })
+                .collect();
+            and_tree(&lits)
+        };
+
+        let sel_add = select(AluOp::Add);
+        let sel_sub = select(AluOp::Sub);
+        let sel_and = select(AluOp::And);
+        let sel_or  = select(AluOp::Or);
+        let sel_xor = select(AluOp::Xor);
+        let sel_not = select(AluOp::Not);
+        let sel_shl = select(AluOp::Shl);
+        let sel_shr = select(AluOp::Shr);
+        let sel_cmp = select(AluOp::Cmp);
+
+        let subtract  = gate(OrGate::new(sel_sub.clone(), sel_cmp.clone()));
+        let arith_out = gate(OrGate::new(sel_add.clone(), sel_sub.clone()));
+        let arith_any = gate(OrGate::new(arith_out.clone(), sel_cmp.clone()));
+
+        // two's complement: a + !b + 1 when subtracting
+        let b_eff: Vec<GateRef> = b.iter().map(|bi| gate(XorGate::new(bi.clone(), subtract.clone()))).collect();
+        let mut carry = subtract.clone();
+        let mut sum = Vec::with_capacity(n);
+        for i in 0..n {
+            let fa = FullAdder::new(a[i].clone(), b_eff[i].clone(), carry);
+            sum.push(fa.sum);
+            carry = fa.carry;
+        }
+
+        let mut result = Vec::with_capacity(n);
+        let mut flag_src = Vec::with_capacity(n);
+        for i in 0..n {
+            let mut terms = vec![
+                gate(AndGate::new(sum[i].clone(), arith_out.clone())),
+                gate(AndGate::new(gate(AndGate::new(a[i].clone(), b[i].clone())), sel_and.clone())),
+                gate(AndGate::new(gate(OrGate::new(a[i].clone(), b[i].clone())), sel_or.clone())),
+                gate(AndGate::new(gate(XorGate::new(a[i].clone(), b[i].clone())), sel_xor.clone())),
+                gate(AndGate::new(gate(NotGate::new(a[i].clone())), sel_not.clone())),
+            ];
+            if i > 0 {
+                terms.push(gate(AndGate::new(a[i - 1].clone(), sel_shl.clone())));
+            }
+            if i + 1 < n {
+                terms.push(gate(AndGate::new(a[i + 1].clone(), sel_shr.clone())));
+            }
+            let r = or_tree(&terms);
+            flag_src.push(gate(OrGate::new(r.clone(), gate(AndGate::new(sum[i].clone(), sel_cmp.clone())))));
+            result.push(r);
+        }
+
+        let carry_flag = or_tree(&[
+            gate(AndGate::new(carry, arith_any.clone())),
+            gate(AndGate::new(a[n - 1].clone(), sel_shl)),
+            gate(AndGate::new(a[0].clone(), sel_shr)),
+        ]);
+
+        let same_sign  = gate(XnorGate::new(a[n - 1].clone(), b_eff[n - 1].clone()));
+        let sign_flip  = gate(XorGate::new(sum[n - 1].clone(), a[n - 1].clone()));
+        let overflow   = and_tree(&[arith_any, same_sign, sign_flip]);
+        let zero       = gate(NotGate::new(or_tree(&flag_src)));
+        let negative   = flag_src[n - 1].clone();
+
+        Self {
+            result,
+            carry: carry_flag,
+            zero,
+            negative,
+            overflow,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::circuit::gate::*;
+
+/// Sum bus (bit 0 first) and carry out of an adder or subtractor.
+#[derive(Debug)]
+pub struct AdderOutput {
+    pub sum: Vec<GateRef>,
+    pub carry: GateRef,
+}
+
+/// Result of comparing two unsigned buses.
+#[derive(Debug)]
+pub struct ComparatorOutput {
+    pub lt: GateRef,
+    pub eq: GateRef,
+    pub gt: GateRef,
+}
+
+fn constant(level: Signal) -> GateRef {
+    gate(ConstGate::new(level))
+}
+
+fn check_widths(a: &[GateRef], b: &[GateRef]) {
+    assert_eq!(a.len(), b.len(), "operands must have the same width");
+    assert!(!a.is_empty(), "operands need at least one bit");
+}
+
+/// 2:1 multiplexer, `a0` when `sel` is low and `a1` when it is high.
+pub fn mux2(sel: GateRef, a0: GateRef, a1: GateRef) -> GateRef {
+    let sel_n = gate(NotGate::new(sel.clone()));
+    gate(OrGate::new(
+        gate(AndGate::new(sel_n, a0)),
+        gate(AndGate::new(sel, a1)),
+    ))
+}
+
+/// Chain of `FullAdder`s, one per bit.
+pub fn ripple_carry_adder(a: &[GateRef], b: &[GateRef], cin: GateRef) -> AdderOutput {
+    check_widths(a, b);
+
+    let mut carry = cin;
+    let mut sum = Vec::with_capacity(a.len());
+    for (ai, bi) in a.iter().zip(b) {
+        let fa = FullAdder::new(ai.clone(), bi.clone(), carry);
+        sum.push(fa.sum);
+        carry = fa.carry;
+    }
+    AdderOutput { sum, carry }
+}
+
+/// Adder whose carries are all computed in two levels from the
+/// generate (`a & b`) and propagate (`a ^ b`) terms.
+///
+/// Depth grows with `log(width)` but the carry logic grows quadratically.
+pub fn carry_lookahead_adder(a: &[GateRef], b: &[GateRef], cin: GateRef) -> AdderOutput {
+    check_widths(a, b);
+    let n = a.len();
+
+    let g: Vec<GateRef> = a.iter().zip(b).map(|(x, y)| gate(AndGate::new(x.clone(), y.clone()))).collect();
+    let p: Vec<GateRef> = a.iter().zip(b).map(|(x, y)| gate(XorGate::new(x.clone(), y.clone()))).collect();
+
+    // carries[i] is the carry into bit i
+    let mut carries = vec![cin.clone()];
+    for i in 0..n {
+        // c(i+1) = g(i) | p(i)g(i-1) | ... | p(i)..p(0)cin
+        let mut terms = Vec::with_capacity(i + 2);
+        for j in (0..=i).rev() {
+            let mut lits = p[j + 1..=i].to_vec();
+            lits.push(g[j].clone());
+            terms.push(and_tree(&lits));
+        }
+        let mut lits = p[..=i].to_vec();
+        lits.push(cin.clone());
+        terms.push(and_tree(&lits));
+
+        carries.push(or_tree(&terms));
+    }
+
+    let sum = (0..n).map(|i| gate(XorGate::new(p[i].clone(), carries[i].clone()))).collect();
+    AdderOutput { sum, carry: carries[n].clone() }
+}
+
+/// Ripple-carry blocks of `block` bits. Every block after the first is
+/// computed for both carry-in values and the real carry selects one.
+pub fn carry_select_adder(a: &[GateRef], b: &[GateRef], cin: GateRef, block: usize) -> AdderOutput {
+    check_widths(a, b);
+    assert!(block > 0, "block size must be at least 1");
+
+    let mut chunks = a.chunks(block).zip(b.chunks(block));
+    let (a0, b0) = chunks.next().unwrap();
+    let first = ripple_carry_adder(a0, b0, cin);
+
+    let mut sum = first.sum;
+    let mut carry = first.carry;
+    for (ak, bk) in chunks {
+        let if_low  = ripple_carry_adder(ak, bk, constant(Signal::Low));
+        let if_high = ripple_carry_adder(ak, bk, constant(Signal::High));
+
+        for (s0, s1) in if_low.sum.into_iter().zip(if_high.sum) {
+            sum.push(mux2(carry.clone(), s0, s1));
+        }
+        carry = mux2(carry, if_low.carry, if_high.carry);
+    }
+    AdderOutput { sum, carry }
+}
+
+/// `a - b` as `a + !b + 1`. The carry out is high when no borrow occurred,
+/// i.e. when `a >= b` as unsigned numbers.
+pub fn subtractor(a: &[GateRef], b: &[GateRef]) -> AdderOutput {
+    check_widths(a, b);
+    let b_n: Vec<GateRef> = b.iter().map(|x| gate(NotGate::new(x.clone()))).collect();
+    ripple_carry_adder(a, &b_n, constant(Signal::High))
+}
+
+/// Unsigned array multiplier. The product has `a.len() + b.len()` bits.
+pub fn array_multiplier(a: &[GateRef], b: &[GateRef]) -> Vec<GateRef> {
+    assert!(!a.is_empty() && !b.is_empty(), "operands need at least one bit");
+    let pp = |i: usize, j: usize| gate(AndGate::new(a[i].clone(), b[j].clone()));
+
+    // running partial sum, aligned so acc[0] is bit `row` of the product
+    let mut acc: Vec<GateRef> = (0..a.len()).map(|i| pp(i, 0)).collect();
+    let mut acc_carry = constant(Signal::Low);
+    let mut product = Vec::with_capacity(a.len() + b.len());
+
+    for j in 1..b.len() {
+        product.push(acc.remove(0));
+        acc.push(acc_carry);
+
+        let row: Vec<GateRef> = (0..a.len()).map(|i| pp(i, j)).collect();
+        let added = ripple_carry_adder(&acc, &row, constant(Signal::Low));
+        acc = added.sum;
+        acc_carry = added.carry;
+    }
+
+    product.extend(acc);
+    product.push(acc_carry);
+    product
+}
+
+/// High when both buses carry the same value.
+pub fn equality_comparator(a: &[GateRef], b: &[GateRef]) -> GateRef {
+    check_widths(a, b);
+    let bits: Vec<GateRef> = a.iter().zip(b).map(|(x, y)| gate(XnorGate::new(x.clone(), y.clone()))).collect();
+    and_tree(&bits)
+}
+
+/// Unsigned magnitude comparator, scanning from the most significant bit.
+pub fn magnitude_comparator(a: &[GateRef], b: &[GateRef]) -> ComparatorOutput {
+    check_widths(a, b);
+
+    let mut eq = constant(Signal::High);
+    let mut gt_terms = Vec::new();
+    let mut lt_terms = Vec::new();
+    for (x, y) in a.iter().zip(b).rev() {
+        let x_n = gate(NotGate::new(x.clone()));
+        let y_n = gate(NotGate::new(y.clone()));
+        gt_terms.push(gate(AndGate::new(eq.clone(), gate(AndGate::new(x.clone(), y_n)))));
+        lt_terms.push(gate(AndGate::new(eq.clone(), gate(AndGate::new(x_n, y.clone())))));
+        eq = gate(AndGate::new(eq, gate(XnorGate::new(x.clone(), y.clone()))));
+    }
+
+    ComparatorOutput {
+        lt: or_tree(&lt_terms),
+        eq,
+        gt: or_tree(&gt_terms),
+    }
+}
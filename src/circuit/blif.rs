@@ -0,0 +1,575 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::Signal;
+use crate::circuit::lut::{TruthTable, MAX_LUT_INPUTS};
+use crate::circuit::subcircuit::{CellDef, CellKind, SubcircuitDef};
+use crate::circuit::verilog::{self, Item, Module};
+
+/// Net a latch without a control signal is clocked by.
+pub const GLOBAL_CLOCK: &str = "clk";
+
+/// Widest XOR written as a cover; it takes a row per odd input pattern.
+const MAX_XOR_INPUTS: usize = 12;
+
+/// Directives that carry timing or naming hints only.
+const IGNORED: &[&str] = &[
+    ".default_input_arrival", ".default_output_required", ".default_input_drive", ".default_output_load",
+    ".default_max_input_load", ".input_arrival", ".output_required", ".input_drive", ".output_load",
+    ".max_input_load", ".wire_load_slope", ".wire", ".area", ".delay", ".cname", ".attr", ".param",
+];
+
+/// A BLIF net name for a circuit net: no whitespace, and neither `#`
+/// (comments) nor `=` (`.subckt` connections).
+fn clean(name: &str) -> String {
+    name.chars().map(|c| if c.is_whitespace() || matches!(c, '#' | '=' | '\\') { '_' } else { c }).collect()
+}
+
+/// Port names of a model. An output that is also an input gets a port of
+/// its own, so `.subckt` connections stay unambiguous.
+fn ports(m: &Module) -> (Vec<String>, Vec<String>) {
+    let ins = m.inputs.iter().map(|n| clean(n)).collect();
+    let outs = m.outputs.iter()
+        .map(|n| if m.inputs.contains(n) { format!("{}$out", clean(n)) } else { clean(n) })
+        .collect();
+    (ins, outs)
+}
+
+/// Writes `.names` with the on-set `rows`.
+fn names(text: &mut String, ins: &[&String], out: &str, rows: impl IntoIterator<Item = String>) {
+    let nets: Vec<String> = ins.iter().map(|n| clean(n)).chain([clean(out)]).collect();
+    writeln!(text, ".names {}", nets.join(" ")).unwrap();
+    for row in rows {
+        if ins.is_empty() {
+            text.push_str("1\n");
+        } else {
+            writeln!(text, "{row} 1").unwrap();
+        }
+    }
+}
+
+fn init(level: Signal) -> char {
+    match level {
+        Signal::Low => '0',
+        Signal::High => '1',
+        _ => '3',
+    }
+}
+
+fn render(m: &Module, modules: &BTreeMap<String, Module>) -> Result<String, String> {
+    let (ins, outs) = ports(m);
+    let mut text = format!(".model {}\n", m.name);
+    if !ins.is_empty() {
+        writeln!(text, ".inputs {}", ins.join(" ")).unwrap();
+    }
+    if !outs.is_empty() {
+        writeln!(text, ".outputs {}", outs.join(" ")).unwrap();
+    }
+    let no_form = |what: &str, net: &str| Err(format!("{what} '{net}' in '{}' has no BLIF form; BLIF nets only carry 0 and 1", m.name));
+
+    for item in &m.items {
+        match item {
+            Item::Const(out, Signal::Low) => names(&mut text, &[], out, []),
+            Item::Const(out, Signal::High) => names(&mut text, &[], out, [String::new()]),
+            Item::Const(out, _) => return no_form("constant", out),
+            Item::Prim(op, out, inputs) => {
+                let k = inputs.len();
+                let ins: Vec<&String> = inputs.iter().collect();
+                let one_hot = |c: char| (0..k).map(move |i| (0..k).map(|j| if i == j { c } else { '-' }).collect::<String>());
+                let rows: Vec<String> = match *op {
+                    "and" | "buf" => vec!["1".repeat(k)],
+                    "nor" | "not" => vec!["0".repeat(k)],
+                    "or" => one_hot('1').collect(),
+                    "nand" => one_hot('0').collect(),
+                    "xor" | "xnor" => {
+                        if k > MAX_XOR_INPUTS {
+                            return Err(format!("'{out}' in '{}' is an XOR of {k} inputs; at most {MAX_XOR_INPUTS} are written", m.name));
+                        }
+                        let odd = *op == "xor";
+                        (0..1usize << k)
+                            .filter(|r| (r.count_ones() % 2 == 1) == odd)
+                            .map(|r| (0..k).map(|i| if r >> i & 1 == 1 { '1' } else { '0' }).collect())
+                            .collect()
+                    }
+                    _ => return no_form("tri-state", out),
+                };
+                names(&mut text, &ins, out, rows);
+            }
+            Item::Table { out, inputs, table } => {
+                let ins: Vec<&String> = inputs.iter().collect();
+                let rows = (0..table.rows())
+                    .filter(|r| table.get(*r))
+                    .map(|r| (0..table.inputs()).map(|i| if r >> i & 1 == 1 { '1' } else { '0' }).collect());
+                names(&mut text, &ins, out, rows);
+            }
+            Item::Assign(out, src) => names(&mut text, &[src], out, ["1".to_string()]),
+            Item::Dff { q, d, clk, init: level } => {
+                writeln!(text, ".latch {} {} re {} {}", clean(d), clean(q), clean(clk), init(*level)).unwrap();
+            }
+            Item::Dlatch { q, d, enable, init: level } => {
+                writeln!(text, ".latch {} {} ah {} {}", clean(d), clean(q), clean(enable), init(*level)).unwrap();
+            }
+            Item::OpenDrain(out, _) => return no_form("open-drain output", out),
+            Item::Pull(net, _) => return no_form("pull resistor on", net),
+            Item::SrLatch { q, .. } => return no_form("SR latch", q),
+            Item::Tran { a, .. } => return no_form("transistor switch at", a),
+            Item::Instance { module, ports: conns, .. } => {
+                let (sub_ins, sub_outs) = ports(&modules[module]);
+                let conns: Vec<String> = sub_ins.iter().chain(&sub_outs)
+                    .zip(conns)
+                    .map(|(formal, (_, net))| format!("{formal}={}", clean(net)))
+                    .collect();
+                writeln!(text, ".subckt {module} {}", conns.join(" ")).unwrap();
+            }
+        }
+    }
+    for (net, port) in m.outputs.iter().zip(&outs) {
+        if m.inputs.contains(net) {
+            names(&mut text, &[net], port, ["1".to_string()]);
+        }
+    }
+    text.push_str(".end\n");
+    Ok(text)
+}
+
+/// BLIF for `circuit` with `top` as the first model.
+///
+/// Gates and lookup tables become `.names` covers, flip-flops rising-edge
+/// and D latches active-high `.latch`es, and every subcircuit module used
+/// (one per definition and parameter values, as for Verilog) a `.model`
+/// instantiated with `.subckt`. BLIF is two-valued, so tri-states,
+/// open-drain outputs, pull resistors and transistor networks give an
+/// error, like the behavioural parts Verilog export refuses.
+pub fn export(circuit: &Circuit, top: &str) -> Result<String, String> {
+    let (top, modules) = verilog::design(circuit, top)?;
+    let mut text = render(&top, &modules)?;
+    for m in modules.values() {
+        text.push('\n');
+        text.push_str(&render(m, &modules)?);
+    }
+    Ok(text)
+}
+
+/// A `.names` cover: rows of the input plane with the output column.
+struct Cover {
+    line: usize,
+    inputs: Vec<String>,
+    output: String,
+    rows: Vec<(String, char)>,
+}
+
+struct Latch {
+    line: usize,
+    input: String,
+    output: String,
+    /// Type and control net, absent for the global clock.
+    control: Option<(String, String)>,
+    init: Signal,
+}
+
+struct Subckt {
+    line: usize,
+    model: String,
+    conns: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Model {
+    name: String,
+    line: usize,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    covers: Vec<Cover>,
+    latches: Vec<Latch>,
+    subckts: Vec<Subckt>,
+}
+
+/// Lines without comments, continuations joined, with the number of the
+/// line each starts on.
+fn lines(text: &str) -> Vec<(usize, Vec<String>)> {
+    let mut out = Vec::new();
+    let mut pending: Option<(usize, Vec<String>)> = None;
+    for (n, raw) in text.lines().enumerate() {
+        let code = raw.split('#').next().unwrap_or("").trim_end();
+        let (code, more) = match code.strip_suffix('\\') {
+            Some(code) => (code, true),
+            None => (code, false),
+        };
+        let (_, words) = pending.get_or_insert_with(|| (n + 1, Vec::new()));
+        words.extend(code.split_whitespace().map(String::from));
+        if !more {
+            let line = pending.take().unwrap();
+            if !line.1.is_empty() {
+                out.push(line);
+            }
+        }
+    }
+    out.extend(pending.filter(|(_, words)| !words.is_empty()));
+    out
+}
+
+fn parse(text: &str) -> Result<Vec<Model>, String> {
+    let mut models: Vec<Model> = Vec::new();
+    let mut open = false;
+    let mut in_cover = false;
+    for (line, words) in lines(text) {
+        let err = |msg: String| Err(format!("line {line}: {msg}"));
+        let cmd = words[0].as_str();
+        if !cmd.starts_with('.') {
+            let (Some(m), true) = (models.last_mut().filter(|_| open), in_cover) else {
+                return err(format!("'{cmd}' is not a directive or a row of a .names cover"));
+            };
+            let cover = m.covers.last_mut().unwrap();
+            let (plane, out) = match words.as_slice() {
+                [out] if cover.inputs.is_empty() => (String::new(), out),
+                [plane, out] if plane.len() == cover.inputs.len() && plane.chars().all(|c| matches!(c, '0' | '1' | '-')) => (plane.clone(), out),
+                _ => return err(format!("bad cover row for '{}'; expected {} of 0, 1 or - and the output", cover.output, cover.inputs.len())),
+            };
+            let out = match out.as_str() {
+                "0" => '0',
+                "1" => '1',
+                _ => return err(format!("cover output '{out}' must be 0 or 1")),
+            };
+            if cover.rows.first().is_some_and(|(_, o)| *o != out) {
+                return err(format!("cover of '{}' mixes on-set and off-set rows", cover.output));
+            }
+            cover.rows.push((plane, out));
+            continue;
+        }
+        in_cover = false;
+        if cmd == ".model" {
+            let name = words.get(1).cloned().unwrap_or_else(|| "top".into());
+            models.push(Model { name, line, ..Model::default() });
+            open = true;
+            continue;
+        }
+        if IGNORED.contains(&cmd) {
+            continue;
+        }
+        if !open {
+            // a file may leave out `.model` for its only model
+            if models.is_empty() && cmd != ".end" {
+                models.push(Model { name: "top".into(), line, ..Model::default() });
+                open = true;
+            } else {
+                return err(format!("'{cmd}' outside a .model"));
+            }
+        }
+        let m = models.last_mut().unwrap();
+        let args = &words[1..];
+        match cmd {
+            ".inputs" | ".clock" => m.inputs.extend(args.iter().filter(|a| !m.inputs.contains(a)).cloned().collect::<Vec<_>>()),
+            ".outputs" => m.outputs.extend(args.iter().cloned()),
+            ".names" => {
+                let Some((output, inputs)) = args.split_last() else { return err(".names needs an output".into()) };
+                m.covers.push(Cover { line, inputs: inputs.to_vec(), output: output.clone(), rows: Vec::new() });
+                in_cover = true;
+            }
+            ".latch" => {
+                let (input, output, rest) = match args {
+                    [input, output, rest @ ..] if rest.len() <= 3 => (input.clone(), output.clone(), rest),
+                    _ => return err(".latch takes an input, an output, a type and control, and an initial value".into()),
+                };
+                let (control, init) = match rest {
+                    [] => (None, None),
+                    [init] => (None, Some(init)),
+                    [kind, control] => (Some((kind.clone(), control.clone())), None),
+                    [kind, control, init] => (Some((kind.clone(), control.clone())), Some(init)),
+                    _ => unreachable!(),
+                };
+                let init = match init.map(String::as_str) {
+                    Some("1") => Signal::High,
+                    None | Some("0" | "2" | "3") => Signal::Low,
+                    Some(other) => return err(format!("bad initial value '{other}'")),
+                };
+                let control = control.filter(|(_, c)| c != "NIL");
+                m.latches.push(Latch { line, input, output, control, init });
+            }
+            ".subckt" => {
+                let Some((model, conns)) = args.split_first() else { return err(".subckt needs a model".into()) };
+                let conns = conns.iter()
+                    .map(|c| c.split_once('=').map(|(f, a)| (f.to_string(), a.to_string())))
+                    .collect::<Option<Vec<_>>>();
+                let Some(conns) = conns else { return err("connections are written formal=actual".into()) };
+                m.subckts.push(Subckt { line, model: model.clone(), conns });
+            }
+            ".end" => open = false,
+            ".gate" | ".mlatch" => return err(format!("library-mapped '{cmd}' is not supported")),
+            _ => return err(format!("'{cmd}' is not supported")),
+        }
+    }
+    Ok(models)
+}
+
+/// Subcircuit names for the nets of a model, or for the models: `.` would
+/// read as an instance path and braces as a template, so they become `_`.
+/// Names without them are kept; the others get a numeric suffix when that
+/// would make two names one.
+#[derive(Default)]
+struct Names {
+    to_sub: HashMap<String, String>,
+    used: HashSet<String>,
+}
+
+impl Names {
+    fn new<'a>(names: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut all = Self::default();
+        let (plain, mangled): (Vec<&String>, Vec<&String>) = names.into_iter().partition(|n| !n.contains(['.', '{', '}']));
+        for name in plain.into_iter().chain(mangled) {
+            if all.to_sub.contains_key(name) {
+                continue;
+            }
+            let base = name.replace(['.', '{', '}'], "_");
+            let sub = std::iter::once(base.clone())
+                .chain((1..).map(|n| format!("{base}_{n}")))
+                .find(|s| !all.used.contains(s))
+                .unwrap();
+            all.used.insert(sub.clone());
+            all.to_sub.insert(name.clone(), sub);
+        }
+        all
+    }
+
+    fn get(&self, name: &str) -> String {
+        self.to_sub[name].clone()
+    }
+}
+
+/// Every net name of `m`, ports first.
+fn net_names(m: &Model) -> impl Iterator<Item = &String> {
+    m.inputs.iter().chain(&m.outputs)
+        .chain(m.covers.iter().flat_map(|c| c.inputs.iter().chain([&c.output])))
+        .chain(m.latches.iter().flat_map(|l| [&l.input, &l.output].into_iter().chain(l.control.as_ref().map(|(_, c)| c))))
+        .chain(m.subckts.iter().flat_map(|s| s.conns.iter().map(|(_, a)| a)))
+}
+
+/// Builds the cells of one model.
+struct Lower<'a> {
+    models: &'a HashMap<&'a str, &'a Model>,
+    /// Models given `GLOBAL_CLOCK` as an extra last input, see `implicit_clock`.
+    clocked: &'a HashSet<&'a str>,
+    /// Model names, and net names by model.
+    modules: &'a Names,
+    nets: &'a HashMap<&'a str, Names>,
+    model: &'a Model,
+    inputs: Vec<String>,
+    cells: Vec<CellDef>,
+    inverted: HashMap<String, String>,
+}
+
+impl Lower<'_> {
+    fn net(&self, name: &str) -> String {
+        self.nets[self.model.name.as_str()].get(name)
+    }
+
+    fn cell(&mut self, name: String, kind: CellKind, inputs: Vec<String>) -> String {
+        self.cells.push(CellDef { name: name.clone(), kind, inputs, repeat: None, value: None });
+        name
+    }
+
+    fn not(&mut self, input: &str) -> String {
+        if let Some(n) = self.inverted.get(input) {
+            return n.clone();
+        }
+        let n = self.cell(format!("{input}$n"), CellKind::Not, vec![input.to_string()]);
+        self.inverted.insert(input.to_string(), n.clone());
+        n
+    }
+
+    /// A LUT when it fits, otherwise AND terms into an OR (a NOR for an
+    /// off-set cover).
+    fn cover(&mut self, c: &Cover) -> Result<(), String> {
+        let out = self.net(&c.output);
+        let ins: Vec<String> = c.inputs.iter().map(|n| self.net(n)).collect();
+        let on = c.rows.first().is_none_or(|(_, o)| *o == '1');
+        if ins.is_empty() {
+            let level = if !c.rows.is_empty() && on { Signal::High } else { Signal::Low };
+            self.cell(out, CellKind::Const { level }, Vec::new());
+            return Ok(());
+        }
+        if ins.len() <= MAX_LUT_INPUTS {
+            let covered = |row: usize| c.rows.iter().any(|(plane, _)| {
+                plane.chars().enumerate().all(|(i, ch)| ch == '-' || (ch == '1') == (row >> i & 1 == 1))
+            });
+            let table = TruthTable::from_fn(ins.len(), |row| covered(row) == on).map_err(|e| format!("line {}: {e}", c.line))?;
+            self.cell(out, CellKind::Lut { table }, ins);
+            return Ok(());
+        }
+        let mut terms = Vec::new();
+        for (n, (plane, _)) in c.rows.iter().enumerate() {
+            let mut literals = Vec::new();
+            for (ch, input) in plane.chars().zip(&ins) {
+                match ch {
+                    '1' => literals.push(input.clone()),
+                    '0' => literals.push(self.not(input)),
+                    _ => {}
+                }
+            }
+            let name = format!("{out}$t{n}");
+            terms.push(match literals.len() {
+                0 => self.cell(name, CellKind::Const { level: Signal::High }, Vec::new()),
+                1 => literals.remove(0),
+                _ => self.cell(name, CellKind::And, literals),
+            });
+        }
+        match terms.len() {
+            0 => self.cell(out, CellKind::Const { level: if on { Signal::Low } else { Signal::High } }, Vec::new()),
+            1 => self.cell(out, if on { CellKind::Buffer } else { CellKind::Not }, terms),
+            _ => self.cell(out, if on { CellKind::Or } else { CellKind::Nor }, terms),
+        };
+        Ok(())
+    }
+
+    fn latch(&mut self, l: &Latch) -> Result<(), String> {
+        let (kind, control) = match &l.control {
+            Some((kind, control)) => (kind.as_str(), self.net(control)),
+            None => {
+                if !self.inputs.iter().any(|i| i == GLOBAL_CLOCK) {
+                    self.inputs.push(GLOBAL_CLOCK.into());
+                }
+                ("re", GLOBAL_CLOCK.to_string())
+            }
+        };
+        let (d, q) = (self.net(&l.input), self.net(&l.output));
+        match kind {
+            "re" => self.cell(q, CellKind::Dff { init: l.init }, vec![d, control]),
+            "fe" => {
+                let clk = self.not(&control);
+                self.cell(q, CellKind::Dff { init: l.init }, vec![d, clk])
+            }
+            "ah" => self.cell(q, CellKind::Dlatch, vec![d, control]),
+            "al" => {
+                let en = self.not(&control);
+                self.cell(q, CellKind::Dlatch, vec![d, en])
+            }
+            other => return Err(format!("line {}: latch type '{other}' is not supported", l.line)),
+        };
+        Ok(())
+    }
+
+    fn subckt(&mut self, s: &Subckt, name: String) -> Result<(), String> {
+        let err = |msg: String| Err(format!("line {}: {msg}", s.line));
+        let Some(sub) = self.models.get(s.model.as_str()) else { return err(format!("unknown model '{}'", s.model)) };
+        if let Some((formal, _)) = s.conns.iter().find(|(f, _)| !sub.inputs.contains(f) && !sub.outputs.contains(f)) {
+            return err(format!("model '{}' has no port '{formal}'", s.model));
+        }
+        let (nets, model) = (self.nets, self.model);
+        let actual = |port: &String| s.conns.iter().find(|(f, _)| f == port).map(|(_, a)| nets[model.name.as_str()].get(a));
+        let mut inputs = Vec::new();
+        for port in &sub.inputs {
+            match actual(port) {
+                Some(a) => inputs.push(a),
+                None => return err(format!("input '{port}' of '{}' is not connected", s.model)),
+            }
+        }
+        if self.clocked.contains(s.model.as_str()) {
+            if !self.inputs.iter().any(|i| i == GLOBAL_CLOCK) {
+                self.inputs.push(GLOBAL_CLOCK.into());
+            }
+            inputs.push(GLOBAL_CLOCK.into());
+        }
+        for port in &sub.outputs {
+            if let Some(a) = actual(port) {
+                let port = nets[s.model.as_str()].get(port);
+                self.cell(a, CellKind::Wire, vec![format!("{name}.{port}")]);
+            }
+        }
+        self.cell(name, CellKind::Instance { module: self.modules.get(&s.model), params: BTreeMap::new() }, inputs);
+        Ok(())
+    }
+
+    fn run(mut self) -> Result<SubcircuitDef, String> {
+        let m = self.model;
+        for c in &m.covers {
+            self.cover(c)?;
+        }
+        for l in &m.latches {
+            self.latch(l)?;
+        }
+        // instances are named after their model, clear of every net
+        let taken = &self.nets[m.name.as_str()].used;
+        let mut count: HashMap<&str, usize> = HashMap::new();
+        for s in &m.subckts {
+            let name = loop {
+                let n = count.entry(&s.model).or_default();
+                let name = format!("{}_{n}", self.modules.get(&s.model));
+                *n += 1;
+                if !taken.contains(&name) {
+                    break name;
+                }
+            };
+            self.subckt(s, name)?;
+        }
+
+        let inputs: Vec<String> = self.inputs.iter().map(|n| self.net(n)).collect();
+        let defined: BTreeSet<&String> = inputs.iter().chain(self.cells.iter().map(|c| &c.name)).collect();
+        let outputs: Vec<String> = m.outputs.iter().map(|n| self.net(n)).collect();
+        let used = self.cells.iter().flat_map(|c| &c.inputs).chain(&outputs);
+        if let Some(undriven) = used.filter(|n| !n.contains('.')).find(|n| !defined.contains(n)) {
+            let blif = net_names(m).find(|n| self.net(n) == *undriven).unwrap_or(undriven);
+            return Err(format!("model '{}' (line {}): net '{blif}' is never driven", m.name, m.line));
+        }
+        Ok(SubcircuitDef { name: self.modules.get(&m.name), params: Vec::new(), inputs, outputs, cells: self.cells })
+    }
+}
+
+/// Models that get `GLOBAL_CLOCK` without declaring it: those with a
+/// `.latch` lacking a control, and those instantiating such a model, at any
+/// depth.
+fn implicit_clock<'a>(models: &[&'a Model]) -> HashSet<&'a str> {
+    let mut clocked = HashSet::new();
+    loop {
+        let found: Vec<&str> = models.iter()
+            .filter(|m| !clocked.contains(m.name.as_str()) && !m.inputs.iter().any(|i| i == GLOBAL_CLOCK))
+            .filter(|m| {
+                m.latches.iter().any(|l| l.control.is_none())
+                    || m.subckts.iter().any(|s| clocked.contains(s.model.as_str()))
+            })
+            .map(|m| m.name.as_str())
+            .collect();
+        if found.is_empty() {
+            return clocked;
+        }
+        clocked.extend(found);
+    }
+}
+
+/// Reads BLIF into a `Circuit`.
+///
+/// The first `.model` is built in the circuit itself (see
+/// `Circuit::build_top`), its inputs becoming `InputGate`s; the others
+/// become subcircuits, instantiated by `.subckt` under the names
+/// `{model}_{n}`. A `.names` cover of up to `MAX_LUT_INPUTS` inputs becomes
+/// a LUT, a wider one AND terms into an OR. Rising- and falling-edge
+/// `.latch`es become flip-flops and level-sensitive ones D latches; those
+/// without a control are clocked by `GLOBAL_CLOCK`, which is added to the
+/// inputs of their model and of every model above it. `.` and braces in
+/// names become `_`, with a suffix where that would join two names.
+pub fn import(text: &str) -> Result<Circuit, String> {
+    let models = parse(text)?;
+    let Some(top) = models.first() else { return Err("no .model".into()) };
+    let mut by_name: HashMap<&str, &Model> = HashMap::new();
+    for m in &models {
+        if by_name.insert(&m.name, m).is_some() {
+            return Err(format!("line {}: model '{}' is defined twice", m.line, m.name));
+        }
+    }
+    let clocked = implicit_clock(&models.iter().collect::<Vec<_>>());
+    // unknown models are named too, to be reported by `Lower::subckt`
+    let modules = Names::new(models.iter().map(|m| &m.name).chain(models.iter().flat_map(|m| m.subckts.iter().map(|s| &s.model))));
+    let clock = GLOBAL_CLOCK.to_string();
+    let nets: HashMap<&str, Names> = models.iter()
+        .map(|m| (m.name.as_str(), Names::new(net_names(m).chain(clocked.contains(m.name.as_str()).then_some(&clock)))))
+        .collect();
+    let lower = |m| Lower {
+        models: &by_name, clocked: &clocked, modules: &modules, nets: &nets,
+        model: m, inputs: m.inputs.clone(), cells: Vec::new(), inverted: HashMap::new(),
+    }.run();
+    let mut circuit = Circuit::new();
+    for m in &models[1..] {
+        circuit.define(lower(m)?).map_err(|e| format!("model '{}' (line {}): {e}", m.name, m.line))?;
+    }
+    circuit.build_top(&lower(top)?).map_err(|e| format!("model '{}' (line {}): {e}", top.name, top.line))?;
+    Ok(circuit)
+}
@@ -1,41 +1,69 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 use std::rc::Rc;
 use std::cell::RefCell;
 
 use crate::circuit::gate::Gate;
 use crate::circuit::wire::Wire;
 use super::gate::*;
-use crate::circuit::gate::{FullAdder, HalfAdder, ClockGate};
+use crate::circuit::gate::{FullAdder, HalfAdder};
+use crate::circuit::alu::{Alu, OPCODE_WIDTH};
+use crate::circuit::arith::{self, AdderOutput};
+use crate::circuit::memory::{MemPins, MemoryCore, Ram, Rom};
+use crate::circuit::display::{DisplayBus, FrameBuffer, PixelDisplay, PixelFormat};
+use crate::circuit::terminal::{Keyboard, Terminal, TtyOutput};
+use crate::circuit::stimulus::{OneShot, PatternGenerator, RandomBit};
+use crate::circuit::switch::{SwitchNetwork, SwitchNode};
+use crate::circuit::lut::{LutGate, TruthTable};
+use crate::circuit::closure::ClosureGate;
+use crate::circuit::subcircuit::{self, InstanceInfo, SubcircuitDef};
+use crate::circuit::param::Params;
+use crate::circuit::library::Library;
+use crate::circuit::netlist::{GateKind, Loader, NetList, Saver, NETLIST_VERSION};
+use crate::circuit::migrate;
+use crate::circuit::blif;
+use crate::circuit::verilog;
+use crate::circuit::verilog_import;
+use crate::circuit::flatten::{self, Flattened};
+use crate::circuit::fsm::{self, Fsm, StateEncoding, StateMachine, StateTable};
 use crate::circuit::gate::Signal;
-use serde::{Serialize, Deserialize};
+use anyhow::Context;
 
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug)]
 pub struct Circuit {
-    #[serde(skip)]
     gates: HashMap<String, Rc<RefCell<dyn Gate>>>,
     outputs: Vec<String>,
+    memories: HashMap<String, Rc<RefCell<MemoryCore>>>,
+    displays: HashMap<String, Rc<RefCell<FrameBuffer>>>,
+    terminals: HashMap<String, Rc<RefCell<Terminal>>>,
+    fsms: HashMap<String, Rc<StateMachine>>,
+    subcircuits: HashMap<String, SubcircuitDef>,
+    instances: BTreeMap<String, InstanceInfo>,
+    libraries: BTreeMap<String, Library>,
 }
 
-// #[derive(Serialize, Deserialize)]
-// #[serde(tag = "kind")]
-// enum SerGate {
-//     Const   { level: Signal },
-//     Input   { value: Signal },
-// }
+/// Upper bound on clock passes per `step`, for clocks derived from registers.
+const MAX_SETTLE_PASSES: usize = 64;
 
-// #[derive(Serialize, Deserialize)]
-// struct SerCircuit {
-//     version: u8,               
-//     gates:   Vec<(String, SerGate)>,
-//     outputs: Vec<String>,
-// }
+impl Default for Circuit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Circuit {
     pub fn new() -> Self {
         Self {
             gates: HashMap::new(),
             outputs: Vec::new(),
+            memories: HashMap::new(),
+            displays: HashMap::new(),
+            terminals: HashMap::new(),
+            fsms: HashMap::new(),
+            subcircuits: HashMap::new(),
+            instances: BTreeMap::new(),
+            libraries: BTreeMap::new(),
         }
     }
 
@@ -77,6 +105,27 @@ impl Circuit {
             .ok_or_else(|| format!("Gate '{}' not found", from_gate_id))?
             .clone();
 
+        self.with_wire(wire_id, |wire| wire.connect(gate))
+    }
+
+    /// Like `connect`, but keeps the wire's other drivers (shared buses,
+    /// wired-AND, pull resistors).
+    pub fn add_driver(&mut self, from_gate_id: &str, wire_id: &str) -> Result<(), String> {
+        let gate = self.lookup(from_gate_id)?;
+        self.with_wire(wire_id, |wire| wire.add_driver(gate))
+    }
+
+    /// Stops `from_gate_id` driving `wire_id`.
+    pub fn disconnect(&mut self, from_gate_id: &str, wire_id: &str) -> Result<(), String> {
+        let gate = self.lookup(from_gate_id)?;
+        if self.with_wire(wire_id, |wire| wire.remove_driver(&gate))? {
+            Ok(())
+        } else {
+            Err(format!("Gate '{from_gate_id}' does not drive '{wire_id}'"))
+        }
+    }
+
+    fn with_wire<T>(&self, wire_id: &str, f: impl FnOnce(&mut Wire) -> T) -> Result<T, String> {
         let wire_ref = self.gates.get(wire_id)
             .ok_or_else(|| format!("Wire '{}' not found", wire_id))?
             .clone();
@@ -85,13 +134,253 @@ impl Circuit {
         let any_wire = wire.as_any();
 
         if let Some(wire) = any_wire.downcast_mut::<Wire>() {
-            wire.connect(gate);
-            Ok(())
+            Ok(f(wire))
         } else {
             Err(format!("Gate '{}' is not a Wire", wire_id))
         }
     }
 
+    /// Attaches a pull-up resistor `id` to wire `wire_id`.
+    pub fn add_pull_up(&mut self, id: &str, wire_id: &str) -> Result<(), String> {
+        self.add_pull(id, wire_id, PullResistor::up())
+    }
+
+    /// Attaches a pull-down resistor `id` to wire `wire_id`.
+    pub fn add_pull_down(&mut self, id: &str, wire_id: &str) -> Result<(), String> {
+        self.add_pull(id, wire_id, PullResistor::down())
+    }
+
+    fn add_pull(&mut self, id: &str, wire_id: &str, pull: PullResistor) -> Result<(), String> {
+        let pull: GateRef = Rc::new(RefCell::new(pull));
+        self.with_wire(wire_id, |wire| wire.add_driver(pull.clone()))?;
+        self.add_gate(id, pull);
+        Ok(())
+    }
+
+    /// Registers state machine `id` stepping on rising `clock_id` edges.
+    /// Gate `id` stands for the machine, its outputs become `output_ids`.
+    pub fn add_fsm(&mut self, id: &str, table: StateTable, clock_id: &str, input_ids: &[&str], output_ids: &[&str]) -> Result<(), String> {
+        if output_ids.len() != table.outputs.len() {
+            return Err(format!("state table has {} outputs, got {} ids", table.outputs.len(), output_ids.len()));
+        }
+        let clock = self.lookup(clock_id)?;
+        let inputs = self.lookup_all(input_ids)?;
+
+        let fsm = Fsm::new(table, clock, inputs)?;
+        self.add_gate(id, fsm.body());
+        self.add_bus(output_ids, fsm.outputs);
+        self.fsms.insert(id.into(), fsm.machine);
+        Ok(())
+    }
+
+    /// Builds `table` from gates (see `fsm::synthesize`). Flip-flop `i` of
+    /// the state register is registered as `{id}.q{i}`.
+    pub fn add_synthesized_fsm(&mut self, id: &str, table: &StateTable, encoding: StateEncoding, clock_id: &str, input_ids: &[&str], output_ids: &[&str]) -> Result<(), String> {
+        if output_ids.len() != table.outputs.len() {
+            return Err(format!("state table has {} outputs, got {} ids", table.outputs.len(), output_ids.len()));
+        }
+        let clock = self.lookup(clock_id)?;
+        let inputs = self.lookup_all(input_ids)?;
+
+        let gen = fsm::synthesize(table, encoding, clock, inputs)?;
+        for (i, ff) in gen.state.into_iter().enumerate() {
+            self.add_gate(format!("{id}.q{i}"), ff);
+        }
+        self.add_bus(output_ids, gen.outputs);
+        Ok(())
+    }
+
+    pub fn fsm(&self, id: &str) -> Option<Rc<StateMachine>> {
+        self.fsms.get(id).cloned()
+    }
+
+    pub fn fsm_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.fsms.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Adds a combinational `ClosureGate` named `name` computing `output_ids` from `input_ids`.
+    pub fn add_closure_gate<F>(&mut self, name: &str, input_ids: &[&str], output_ids: &[&str], f: F) -> Result<(), String>
+    where
+        F: Fn(&[Signal]) -> Vec<Signal> + 'static,
+    {
+        let gate = ClosureGate::new(name, self.lookup_all(input_ids)?, output_ids.len(), f);
+        self.add_closure(output_ids, gate)
+    }
+
+    /// Registers the outputs of a prebuilt `ClosureGate`, e.g. a clocked one.
+    pub fn add_closure(&mut self, output_ids: &[&str], gate: ClosureGate) -> Result<(), String> {
+        if output_ids.len() != gate.outputs.len() {
+            return Err(format!("closure gate has {} outputs, got {} ids", gate.outputs.len(), output_ids.len()));
+        }
+        self.add_bus(output_ids, gate.outputs);
+        Ok(())
+    }
+
+    /// Makes `def` available to `instantiate`, replacing any definition of
+    /// the same name. Existing instances keep their gates.
+    pub fn define(&mut self, def: SubcircuitDef) -> Result<(), String> {
+        def.check()?;
+        self.subcircuits.insert(def.name.clone(), def);
+        Ok(())
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&SubcircuitDef> {
+        self.subcircuits.get(name)
+    }
+
+    pub fn definition_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.subcircuits.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Defines every component of `lib`, replacing definitions of the same
+    /// name, and keeps `lib` under its name. Nothing is defined if `lib` is
+    /// invalid.
+    pub fn import_library(&mut self, lib: Library) -> Result<(), String> {
+        lib.check()?;
+        for c in &lib.components {
+            self.subcircuits.insert(c.definition.name.clone(), c.definition.clone());
+        }
+        self.libraries.insert(lib.name.clone(), lib);
+        Ok(())
+    }
+
+    pub fn library(&self, name: &str) -> Option<&Library> {
+        self.libraries.get(name)
+    }
+
+    /// Names of the imported libraries, sorted.
+    pub fn library_names(&self) -> Vec<String> {
+        self.libraries.keys().cloned().collect()
+    }
+
+    /// Builds an instance of subcircuit `module` at `path`, its input ports
+    /// driven by `input_ids`. Its gates are registered under `{path}.`, e.g.
+    /// `cpu.alu.add0.sum`, and its output ports as `{path}.{port}`.
+    pub fn instantiate(&mut self, module: &str, path: &str, input_ids: &[&str]) -> Result<(), String> {
+        self.instantiate_with(module, path, &Params::new(), input_ids)
+    }
+
+    /// `instantiate` with some parameters of `module` set; the others keep
+    /// their defaults.
+    pub fn instantiate_with(&mut self, module: &str, path: &str, params: &Params, input_ids: &[&str]) -> Result<(), String> {
+        if path.is_empty() || self.instances.contains_key(path) || self.gates.contains_key(path) {
+            return Err(format!("instance path '{path}' is empty or already used"));
+        }
+        let inputs = self.lookup_all(input_ids)?;
+        let built = subcircuit::elaborate(&self.subcircuits, module, path, params, inputs)?;
+        for (id, gate) in built.gates {
+            self.add_gate(id, gate);
+        }
+        self.instances.extend(built.instances);
+        Ok(())
+    }
+
+    /// Builds `def` at the top level rather than as an instance: its input
+    /// ports become `InputGate`s and its output ports registered outputs,
+    /// while cells and instances keep their own names. The modules it uses
+    /// must be defined already.
+    pub fn build_top(&mut self, def: &SubcircuitDef) -> Result<(), String> {
+        def.check()?;
+        let mut defs = self.subcircuits.clone();
+        defs.insert(def.name.clone(), def.clone());
+        let (inputs, outputs) = subcircuit::ports(&defs, &def.name, &Params::new())?;
+        let pins: Vec<GateRef> = inputs.iter().map(|_| Rc::new(RefCell::new(InputGate::new(false))) as GateRef).collect();
+        let built = subcircuit::elaborate(&defs, &def.name, &def.name, &Params::new(), pins.clone())?;
+
+        let prefix = format!("{}.", def.name);
+        let local = |id: &str| id.strip_prefix(&prefix).unwrap_or(id).to_string();
+        let ids = built.gates.iter().map(|(id, _)| id).chain(built.instances.iter().map(|(p, _)| p)).map(|id| local(id));
+        if let Some(id) = ids.into_iter().find(|id| self.gates.contains_key(id) || self.instances.contains_key(id)) {
+            return Err(format!("'{id}' of '{}' is already used", def.name));
+        }
+        for (port, pin) in inputs.iter().zip(pins) {
+            self.add_gate(port.clone(), pin);
+        }
+        for (id, gate) in built.gates {
+            let id = local(&id);
+            if !inputs.contains(&id) {
+                self.add_gate(id, gate);
+            }
+        }
+        for (path, info) in built.instances {
+            if path != def.name {
+                self.instances.insert(local(&path), info);
+            }
+        }
+        for port in outputs {
+            self.add_output(port);
+        }
+        Ok(())
+    }
+
+    /// Module of the instance at `path`, nested ones included.
+    pub fn instance_module(&self, path: &str) -> Option<&str> {
+        self.instances.get(path).map(|i| i.module.as_str())
+    }
+
+    /// Parameter values of the instance at `path`, defaults included.
+    pub fn instance_params(&self, path: &str) -> Option<&Params> {
+        self.instances.get(path).map(|i| &i.params)
+    }
+
+    /// Input and output port names of `module` built with `params`.
+    pub fn subcircuit_ports(&self, module: &str, params: &Params) -> Result<(Vec<String>, Vec<String>), String> {
+        subcircuit::ports(&self.subcircuits, module, params)
+    }
+
+    /// Elaborates `module` as the top of a design into a new flat circuit
+    /// built from the definitions known here; see `flatten::flatten`.
+    pub fn flatten(&self, module: &str, params: &Params) -> Result<Flattened, String> {
+        flatten::flatten(&self.subcircuits, module, params)
+    }
+
+    /// Every instance path, nested ones included, in order.
+    pub fn instance_paths(&self) -> Vec<String> {
+        self.instances.keys().cloned().collect()
+    }
+
+    /// Removes the instance at `path` with everything below it.
+    pub fn remove_instance(&mut self, path: &str) -> Result<(), String> {
+        if !self.instances.contains_key(path) {
+            return Err(format!("no instance '{path}'"));
+        }
+        let prefix = format!("{path}.");
+        self.gates.retain(|id, _| !id.starts_with(&prefix));
+        self.instances.retain(|p, _| p != path && !p.starts_with(&prefix));
+        Ok(())
+    }
+
+    /// Adds `id` computing `table` over `input_ids` (input 0 is bit 0 of the row).
+    pub fn add_lut(&mut self, id: &str, input_ids: &[&str], table: TruthTable) -> Result<(), String> {
+        let lut = LutGate::new(self.lookup_all(input_ids)?, table)?;
+        self.add_gate(id, Rc::new(RefCell::new(lut)));
+        Ok(())
+    }
+
+    /// Makes nodes of a transistor network visible to the rest of the
+    /// circuit: each `(node, id)` pair registers gate `id` reading `node`.
+    pub fn add_switch_network(&mut self, net: SwitchNetwork, exports: &[(&str, &str)]) -> Result<(), String> {
+        let net = Rc::new(net);
+        let nodes = exports.iter()
+            .map(|(node, _)| SwitchNode::new(net.clone(), node))
+            .collect::<Result<Vec<_>, _>>()?;
+        for ((_, id), node) in exports.iter().zip(nodes) {
+            self.add_gate(*id, Rc::new(RefCell::new(node)));
+        }
+        Ok(())
+    }
+
+    /// Adds `id`, an open-drain output of `input_id`.
+    pub fn add_open_drain(&mut self, id: &str, input_id: &str) -> Result<(), String> {
+        let input = self.lookup(input_id)?;
+        self.add_gate(id, Rc::new(RefCell::new(OpenDrainGate::new(input))));
+        Ok(())
+    }
+
     pub fn add_halfadder(&mut self, a_id: &str, b_id: &str, sum_id: &str, carry_id: &str) -> Result<(), String> {
         let a = self.gates.get(a_id)
             .ok_or_else(|| format!("Gate '{}' not found", a_id))?
@@ -130,6 +419,7 @@ impl Circuit {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add_4bit_adder(
         &mut self, 
         a0_id: &str, a1_id: &str, a2_id: &str, a3_id: &str,
@@ -139,40 +429,508 @@ impl Circuit {
         cout_id: &str,
         ) -> Result<(), String> {
         
-        let a = [
-            self.gates.get(a0_id).ok_or_else(|| format!("Gate '{}' not found", a0_id))?.clone(),
-            self.gates.get(a1_id).ok_or_else(|| format!("Gate '{}' not found", a1_id))?.clone(),
-            self.gates.get(a2_id).ok_or_else(|| format!("Gate '{}' not found", a2_id))?.clone(),
-            self.gates.get(a3_id).ok_or_else(|| format!("Gate '{}' not found", a3_id))?.clone(),
+        self.add_ripple_carry_adder(
+            &[a0_id, a1_id, a2_id, a3_id],
+            &[b0_id, b1_id, b2_id, b3_id],
+            cin_id,
+            &sum_ids,
+            cout_id,
+        )
+    }
+
+    pub fn add_ripple_carry_adder(&mut self, a_ids: &[&str], b_ids: &[&str], cin_id: &str, sum_ids: &[&str], cout_id: &str) -> Result<(), String> {
+        self.add_adder(a_ids, b_ids, cin_id, sum_ids, cout_id, arith::ripple_carry_adder)
+    }
+
+    pub fn add_carry_lookahead_adder(&mut self, a_ids: &[&str], b_ids: &[&str], cin_id: &str, sum_ids: &[&str], cout_id: &str) -> Result<(), String> {
+        self.add_adder(a_ids, b_ids, cin_id, sum_ids, cout_id, arith::carry_lookahead_adder)
+    }
+
+    pub fn add_carry_select_adder(&mut self, a_ids: &[&str], b_ids: &[&str], cin_id: &str, sum_ids: &[&str], cout_id: &str, block: usize) -> Result<(), String> {
+        if block == 0 {
+            return Err("carry-select block size must be at least 1".into());
+        }
+        self.add_adder(a_ids, b_ids, cin_id, sum_ids, cout_id, |a, b, cin| arith::carry_select_adder(a, b, cin, block))
+    }
+
+    /// Registers `a - b` (two's complement) as `diff_ids` and the no-borrow flag as `carry_id`.
+    pub fn add_subtractor(&mut self, a_ids: &[&str], b_ids: &[&str], diff_ids: &[&str], carry_id: &str) -> Result<(), String> {
+        Self::check_bus(a_ids, b_ids, diff_ids.len())?;
+        let a = self.lookup_all(a_ids)?;
+        let b = self.lookup_all(b_ids)?;
+
+        let out = arith::subtractor(&a, &b);
+        self.add_bus(diff_ids, out.sum);
+        self.add_gate(carry_id, out.carry);
+        Ok(())
+    }
+
+    /// Registers the unsigned product of `a` and `b`; `product_ids` needs `a + b` bits.
+    pub fn add_multiplier(&mut self, a_ids: &[&str], b_ids: &[&str], product_ids: &[&str]) -> Result<(), String> {
+        if a_ids.is_empty() || b_ids.is_empty() || product_ids.len() != a_ids.len() + b_ids.len() {
+            return Err(format!(
+                "multiplier bus widths differ: a={}, b={}, product={}",
+                a_ids.len(), b_ids.len(), product_ids.len()
+            ));
+        }
+        let a = self.lookup_all(a_ids)?;
+        let b = self.lookup_all(b_ids)?;
+
+        let product = arith::array_multiplier(&a, &b);
+        self.add_bus(product_ids, product);
+        Ok(())
+    }
+
+    pub fn add_equality_comparator(&mut self, a_ids: &[&str], b_ids: &[&str], eq_id: &str) -> Result<(), String> {
+        Self::check_bus(a_ids, b_ids, a_ids.len())?;
+        let a = self.lookup_all(a_ids)?;
+        let b = self.lookup_all(b_ids)?;
+
+        self.add_gate(eq_id, arith::equality_comparator(&a, &b));
+        Ok(())
+    }
+
+    /// Registers the unsigned `[lt, eq, gt]` outputs of comparing `a` with `b`.
+    pub fn add_magnitude_comparator(&mut self, a_ids: &[&str], b_ids: &[&str], out_ids: [&str; 3]) -> Result<(), String> {
+        Self::check_bus(a_ids, b_ids, a_ids.len())?;
+        let a = self.lookup_all(a_ids)?;
+        let b = self.lookup_all(b_ids)?;
+
+        let cmp = arith::magnitude_comparator(&a, &b);
+        self.add_gate(out_ids[0], cmp.lt);
+        self.add_gate(out_ids[1], cmp.eq);
+        self.add_gate(out_ids[2], cmp.gt);
+        Ok(())
+    }
+
+    fn add_adder<F>(&mut self, a_ids: &[&str], b_ids: &[&str], cin_id: &str, sum_ids: &[&str], cout_id: &str, build: F) -> Result<(), String>
+    where F: FnOnce(&[Rc<RefCell<dyn Gate>>], &[Rc<RefCell<dyn Gate>>], Rc<RefCell<dyn Gate>>) -> AdderOutput
+    {
+        Self::check_bus(a_ids, b_ids, sum_ids.len())?;
+        let a = self.lookup_all(a_ids)?;
+        let b = self.lookup_all(b_ids)?;
+        let cin = self.lookup(cin_id)?;
+
+        let out = build(&a, &b, cin);
+        self.add_bus(sum_ids, out.sum);
+        self.add_gate(cout_id, out.carry);
+        Ok(())
+    }
+
+    fn check_bus(a_ids: &[&str], b_ids: &[&str], out_width: usize) -> Result<(), String> {
+        if a_ids.is_empty() || a_ids.len() != b_ids.len() || a_ids.len() != out_width {
+            return Err(format!(
+                "bus widths differ: a={}, b={}, out={}",
+                a_ids.len(), b_ids.len(), out_width
+            ));
+        }
+        Ok(())
+    }
+
+    fn add_bus(&mut self, ids: &[&str], gates: Vec<Rc<RefCell<dyn Gate>>>) {
+        for (id, g) in ids.iter().zip(gates) {
+            self.add_gate(*id, g);
+        }
+    }
+
+    /// Number of logic gates feeding the registered outputs.
+    pub fn gate_count(&self) -> usize {
+        gate_count(&self.output_gates())
+    }
+
+    /// Longest gate chain feeding any registered output.
+    pub fn depth(&self) -> usize {
+        logic_depth(&self.output_gates())
+    }
+
+    fn output_gates(&self) -> Vec<Rc<RefCell<dyn Gate>>> {
+        self.outputs.iter().filter_map(|id| self.gates.get(id).cloned()).collect()
+    }
+
+    /// Registers the segments `a`..`g` of a BCD (or hex) to seven-segment decoder.
+    pub fn add_seven_seg_decoder(&mut self, input_ids: [&str; 4], segment_ids: [&str; 7], hex: bool) -> Result<(), String> {
+        let inputs = [
+            self.lookup(input_ids[0])?,
+            self.lookup(input_ids[1])?,
+            self.lookup(input_ids[2])?,
+            self.lookup(input_ids[3])?,
         ];
-    
-        let b = [
-            self.gates.get(b0_id).ok_or_else(|| format!("Gate '{}' not found", b0_id))?.clone(),
-            self.gates.get(b1_id).ok_or_else(|| format!("Gate '{}' not found", b1_id))?.clone(),
-            self.gates.get(b2_id).ok_or_else(|| format!("Gate '{}' not found", b2_id))?.clone(),
-            self.gates.get(b3_id).ok_or_else(|| format!("Gate '{}' not found", b3_id))?.clone(),
+
+        let dec = SevenSegDecoder::new(inputs, hex);
+        self.add_bus(&segment_ids, dec.segments.to_vec());
+        Ok(())
+    }
+
+    /// Builds an `Alu` over the `a`/`b` buses (bit 0 first) and registers its
+    /// result bits plus the `[carry, zero, negative, overflow]` flags.
+    pub fn add_alu(
+        &mut self,
+        a_ids: &[&str],
+        b_ids: &[&str],
+        op_ids: [&str; OPCODE_WIDTH],
+        result_ids: &[&str],
+        flag_ids: [&str; 4],
+    ) -> Result<(), String> {
+        Self::check_bus(a_ids, b_ids, result_ids.len())?;
+
+        let a = self.lookup_all(a_ids)?;
+        let b = self.lookup_all(b_ids)?;
+        let op = [
+            self.lookup(op_ids[0])?,
+            self.lookup(op_ids[1])?,
+            self.lookup(op_ids[2])?,
+            self.lookup(op_ids[3])?,
         ];
 
-        let mut carry = self.gates.get(cin_id).ok_or_else(|| format!("Gate '{}' not found",cin_id))?.clone();
+        let alu = Alu::new(a, b, op);
+
+        self.add_bus(result_ids, alu.result);
+        self.add_gate(flag_ids[0], alu.carry);
+        self.add_gate(flag_ids[1], alu.zero);
+        self.add_gate(flag_ids[2], alu.negative);
+        self.add_gate(flag_ids[3], alu.overflow);
+
+        Ok(())
+    }
+
+    fn lookup(&self, id: &str) -> Result<Rc<RefCell<dyn Gate>>, String> {
+        self.gates.get(id)
+            .cloned()
+            .ok_or_else(|| format!("Gate '{}' not found", id))
+    }
 
-        for i in 0..4 {
-            let fa = FullAdder::new(a[i].clone(), b[i].clone(),carry.clone());
-            self.add_gate(sum_ids[i], fa.sum.clone());
-            carry = fa.carry.clone();
+    fn lookup_all(&self, ids: &[&str]) -> Result<Vec<Rc<RefCell<dyn Gate>>>, String> {
+        ids.iter().map(|id| self.lookup(id)).collect()
+    }
+
+    /// Registers a ROM over `core`; its data bits become `data_ids`.
+    pub fn add_rom(&mut self, id: &str, core: MemoryCore, addr_ids: &[&str], cs_id: Option<&str>, data_ids: &[&str]) -> Result<(), String> {
+        let pins = MemPins::rom(addr_ids.to_vec(), cs_id).map(|id| self.lookup(id))?;
+        self.check_memory(&core, &pins, data_ids)?;
+
+        let rom = Rom::new(Rc::new(RefCell::new(core)), pins.addr, pins.chip_select);
+        self.add_bus(data_ids, rom.data);
+        self.memories.insert(id.into(), rom.core);
+        Ok(())
+    }
+
+    /// Registers a RAM over `core`; its data outputs become `data_ids`.
+    /// With `pins.clock` set writes happen on a rising clock edge, otherwise
+    /// whenever the circuit settles with the write enabled.
+    pub fn add_ram(&mut self, id: &str, core: MemoryCore, pins: MemPins<&str>, data_ids: &[&str]) -> Result<(), String> {
+        let pins = pins.map(|id| self.lookup(id))?;
+        if pins.write_enable.is_none() {
+            return Err(format!("RAM '{id}' needs a write enable"));
         }
+        if pins.data_in.len() != core.data_width() {
+            return Err(format!("RAM '{id}' data input is {} bits, memory is {}", pins.data_in.len(), core.data_width()));
+        }
+        self.check_memory(&core, &pins, data_ids)?;
 
-        self.add_gate(cout_id, carry);
-        
+        let ram = Ram::new(Rc::new(RefCell::new(core)), pins);
+        self.add_bus(data_ids, ram.data);
+        self.memories.insert(id.into(), ram.core);
+        Ok(())
+    }
+
+    fn check_memory(&self, core: &MemoryCore, pins: &MemPins<Rc<RefCell<dyn Gate>>>, data_ids: &[&str]) -> Result<(), String> {
+        if pins.addr.len() != core.addr_width() || data_ids.len() != core.data_width() {
+            return Err(format!(
+                "memory is {}x{} bits, got {} address and {} data lines",
+                core.addr_width(), core.data_width(), pins.addr.len(), data_ids.len()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn memory(&self, id: &str) -> Option<Rc<RefCell<MemoryCore>>> {
+        self.memories.get(id).cloned()
+    }
+
+    pub fn memory_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.memories.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Registers a `width` x `height` pixel display as gate `id`.
+    pub fn add_display(&mut self, id: &str, width: usize, height: usize, bus: DisplayBus<&str>, format: PixelFormat) -> Result<(), String> {
+        let bus = bus.map(|id| self.lookup(id))?;
+        bus.check(width, height, format)?;
+
+        let display = PixelDisplay::new(width, height, bus, format);
+        self.displays.insert(id.into(), display.frame());
+        self.add_gate(id, Rc::new(RefCell::new(display)));
         Ok(())
-    }    
+    }
+
+    pub fn display(&self, id: &str) -> Option<Rc<RefCell<FrameBuffer>>> {
+        self.displays.get(id).cloned()
+    }
+
+    pub fn display_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.displays.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Registers gate `id`, which prints the byte on `data_ids` (bit 0 first)
+    /// to terminal `terminal` on every rising edge of `strobe_id`.
+    pub fn add_tty(&mut self, id: &str, terminal: &str, data_ids: &[&str], strobe_id: &str) -> Result<(), String> {
+        if data_ids.len() > 8 {
+            return Err(format!("TTY '{id}' takes at most 8 data bits, got {}", data_ids.len()));
+        }
+        let data = self.lookup_all(data_ids)?;
+        let strobe = self.lookup(strobe_id)?;
+
+        let tty = TtyOutput::new(self.terminal_or_new(terminal), data, strobe);
+        self.add_gate(id, Rc::new(RefCell::new(tty)));
+        Ok(())
+    }
+
+    /// Registers a keyboard reading from terminal `terminal`: 8 data bits and a ready flag.
+    pub fn add_keyboard(&mut self, terminal: &str, ack_id: &str, data_ids: &[&str], ready_id: &str) -> Result<(), String> {
+        if data_ids.len() != 8 {
+            return Err(format!("keyboard needs 8 data ids, got {}", data_ids.len()));
+        }
+        let ack = self.lookup(ack_id)?;
+
+        let kb = Keyboard::new(self.terminal_or_new(terminal), ack);
+        self.add_bus(data_ids, kb.data);
+        self.add_gate(ready_id, kb.ready);
+        Ok(())
+    }
+
+    fn terminal_or_new(&mut self, name: &str) -> Rc<RefCell<Terminal>> {
+        self.terminals.entry(name.into()).or_default().clone()
+    }
+
+    pub fn terminal(&self, name: &str) -> Option<Rc<RefCell<Terminal>>> {
+        self.terminals.get(name).cloned()
+    }
+
+    pub fn terminal_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.terminals.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Adds a generator playing `pattern` (see `PatternGenerator::parse`).
+    pub fn add_pattern(&mut self, id: &str, pattern: &str, hold: usize, repeat: bool) -> Result<(), String> {
+        let gen = PatternGenerator::parse(pattern, hold, repeat)?;
+        self.add_gate(id, Rc::new(RefCell::new(gen)));
+        Ok(())
+    }
+
+    pub fn add_one_shot(&mut self, id: &str, trigger_id: &str, width: usize, retriggerable: bool) -> Result<(), String> {
+        let trigger = self.lookup(trigger_id)?;
+        self.add_gate(id, Rc::new(RefCell::new(OneShot::new(trigger, width, retriggerable))));
+        Ok(())
+    }
+
+    pub fn add_random(&mut self, id: &str, seed: u64) {
+        self.add_gate(id, Rc::new(RefCell::new(RandomBit::new(seed))));
+    }
 
+    /// Everything needed to rebuild this circuit: every gate with its inputs
+    /// and state, the outputs, the named memories, displays, terminals and
+    /// state machines, and the subcircuit definitions and instances.
+    /// Fails on gates that cannot be saved, such as closures.
+    pub fn to_netlist(&self) -> Result<NetList, String> {
+        let mut ids: Vec<(&String, &GateRef)> = self.gates.iter().collect();
+        ids.sort_by_key(|(id, _)| *id);
+        let mut cx = Saver::new(&ids);
+        let mut net = NetList { version: NETLIST_VERSION, outputs: self.outputs.clone(), ..NetList::default() };
+
+        let save = |id: &str, g: &GateRef, cx: &mut Saver| {
+            g.borrow().to_netlist(cx).ok_or_else(|| format!("gate '{id}' ({}) cannot be saved", g.borrow().description()))
+        };
+        for (id, g) in &ids {
+            let kind = match cx.name(g) {
+                of if of != **id => GateKind::Alias { of },
+                _ => save(id, g, &mut cx)?,
+            };
+            net.gates.insert(id.to_string(), kind);
+        }
+
+        fn sorted<T>(map: &HashMap<String, T>) -> Vec<(&String, &T)> {
+            let mut v: Vec<_> = map.iter().collect();
+            v.sort_by_key(|(id, _)| *id);
+            v
+        }
+        for (id, core) in sorted(&self.memories) {
+            net.memories.insert(id.clone(), cx.shared(core, |_| core.borrow().to_netlist()));
+        }
+        for (id, frame) in sorted(&self.displays) {
+            net.displays.insert(id.clone(), cx.shared(frame, |_| frame.borrow().to_netlist()));
+        }
+        for (id, term) in sorted(&self.terminals) {
+            net.terminals.insert(id.clone(), cx.shared(term, |_| term.borrow().to_netlist()));
+        }
+        for (id, machine) in sorted(&self.fsms) {
+            net.fsms.insert(id.clone(), cx.shared(machine, |cx| machine.to_netlist(cx)));
+        }
+
+        while let Some((id, g)) = cx.next_internal() {
+            let kind = save(&id, &g, &mut cx)?;
+            net.internal.insert(id, kind);
+        }
+        net.shared = cx.list.into_iter().map(|s| s.unwrap()).collect();
+
+        net.libraries = self.libraries.values().cloned().collect();
+        net.subcircuits = sorted(&self.subcircuits).into_iter().map(|(_, d)| d.clone()).collect();
+        net.instances = self.instances.clone();
+        Ok(net)
+    }
+
+    /// Rebuilds a circuit written by `to_netlist`.
+    pub fn from_netlist(net: &NetList) -> Result<Self, String> {
+        if net.version != NETLIST_VERSION {
+            return Err(format!("netlist version {} is not supported", net.version));
+        }
+        let mut c = Circuit::new();
+        for lib in &net.libraries {
+            c.import_library(lib.clone())?;
+        }
+        for def in &net.subcircuits {
+            c.define(def.clone())?;
+        }
+        c.instances = net.instances.clone();
+
+        let mut cx = Loader::new(net);
+        for id in net.gates.keys() {
+            let g = cx.gate(id)?;
+            c.add_gate(id, g);
+        }
+        for id in net.internal.keys() {
+            cx.gate(id)?;
+        }
+        cx.finish()?;
+
+        for (id, i) in &net.memories {
+            c.memories.insert(id.clone(), cx.shared(*i)?);
+        }
+        for (id, i) in &net.displays {
+            c.displays.insert(id.clone(), cx.shared(*i)?);
+        }
+        for (id, i) in &net.terminals {
+            c.terminals.insert(id.clone(), cx.shared(*i)?);
+        }
+        for (id, i) in &net.fsms {
+            c.fsms.insert(id.clone(), cx.shared(*i)?);
+        }
+        if let Some(id) = net.outputs.iter().find(|id| !c.gates.contains_key(*id)) {
+            return Err(format!("output '{id}' is not a gate"));
+        }
+        c.outputs = net.outputs.clone();
+        Ok(c)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        let net = self.to_netlist().map_err(anyhow::Error::msg)?;
+        Ok(serde_json::to_string_pretty(&net)?)
+    }
+
+    /// Reads a netlist of this or any older version.
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let doc = serde_json::from_str(text).context("parsing circuit")?;
+        let net = migrate::read_netlist(doc).map_err(anyhow::Error::msg)?;
+        Self::from_netlist(&net).map_err(anyhow::Error::msg)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("loading {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = self.to_json()?;
+        std::fs::write(path, text).with_context(|| format!("saving {}", path.display()))
+    }
+
+    /// Structural Verilog with `top` as the top module; see `verilog::export`.
+    pub fn to_verilog(&self, top: &str) -> Result<String, String> {
+        verilog::export(self, top)
+    }
+
+    pub fn save_verilog(&self, path: impl AsRef<Path>, top: &str) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = self.to_verilog(top).map_err(anyhow::Error::msg)?;
+        std::fs::write(path, text).with_context(|| format!("saving {}", path.display()))
+    }
+
+    /// Reads gate-level Verilog, `top` naming the top module if the file
+    /// has several; see `verilog_import::import`. Diagnostics come one per
+    /// line as `line:col: message`.
+    pub fn from_verilog(text: &str, top: Option<&str>) -> Result<Self, String> {
+        verilog_import::import(text, top)
+            .map_err(|diags| diags.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"))
+    }
+
+    pub fn load_verilog(path: impl AsRef<Path>, top: Option<&str>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_verilog(&text, top).map_err(anyhow::Error::msg).with_context(|| format!("loading {}", path.display()))
+    }
+
+    /// Berkeley BLIF with `top` as the first model; see `blif::export`.
+    pub fn to_blif(&self, top: &str) -> Result<String, String> {
+        blif::export(self, top)
+    }
+
+    pub fn save_blif(&self, path: impl AsRef<Path>, top: &str) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = self.to_blif(top).map_err(anyhow::Error::msg)?;
+        std::fs::write(path, text).with_context(|| format!("saving {}", path.display()))
+    }
+
+    /// Reads BLIF, the first model being the circuit itself; see
+    /// `blif::import`.
+    pub fn from_blif(text: &str) -> Result<Self, String> {
+        blif::import(text)
+    }
+
+    pub fn load_blif(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_blif(&text).map_err(anyhow::Error::msg).with_context(|| format!("loading {}", path.display()))
+    }
+
+    /// Advances every time source once (toggling clocks, playing the next
+    /// pattern bit, ...), then lets clocked gates react to the new levels.
     pub fn step(&mut self) {
-        for gate in self.gates.values() {
-            if let Some(clock) = gate.borrow_mut().as_any().downcast_mut::<ClockGate>() {
-                clock.tick()
+        scheduled(|| {
+            for gate in reachable(&self.gates.values().cloned().collect::<Vec<_>>()) {
+                gate.borrow().advance();
             }
-        }
+        });
+
+        self.settle();
+    }
 
+    /// Updates every clocked gate that sees an active edge. All of them sample
+    /// their inputs before any of them changes, so registers behave as if they
+    /// were clocked at the same instant. Repeats while edges keep appearing,
+    /// e.g. in ripple counters.
+    pub fn settle(&self) {
+        let all = reachable(&self.gates.values().cloned().collect::<Vec<_>>());
+
+        scheduled(|| {
+            for _ in 0..MAX_SETTLE_PASSES {
+                let edged: Vec<_> = all.iter().filter(|g| g.borrow().clock_edge()).collect();
+                if edged.is_empty() {
+                    break;
+                }
+                for g in &edged {
+                    g.borrow().capture();
+                }
+                for g in &edged {
+                    g.borrow().commit();
+                }
+            }
+        });
     }
 
     pub fn eval(&self) -> HashMap<String, bool> {
@@ -207,49 +965,11 @@ impl Circuit {
         self.gates.get(id).cloned()
     }
 
+    /// Removes the gate registered as `id` along with any memory or display of that name.
     pub fn remove_gate(&mut self, id:&str) {
         self.gates.remove(id);  
+        self.memories.remove(id);
+        self.displays.remove(id);
+        self.fsms.remove(id);
     }
-
-    // pub fn save(&self, path: &str) -> anyhow::Result<()> {
-    //     let mut vec = Vec::new();
-
-    //     for (id, rc) in &self.gates {
-    //         let mut g = rc.borrow_mut();
-
-    //         if let Some(c) = g.as_any().downcast_ref::<ConstGate>() {
-    //             vec.push((id.clone(), SerGate::Const { level: c.eval() }));
-    //         } else if let Some(inp) = g.as_any().downcast_ref::<InputGate>() {
-    //             vec.push((id.clone(), SerGate::Input { value: inp.eval() }));
-    //         }
-    //     }
-
-    //     let obj = SerCircuit { version: 2, gates: vec, outputs: self.outputs.clone() };
-    //     let json = serde_json::to_string_pretty(&obj)?;
-    //     std::fs::write(path, json)?;
-    //     Ok(())
-    // }
-
-    // pub fn load(path: &str) -> anyhow::Result<Self> {
-    //     let text   = std::fs::read_to_string(path)?;
-    //     let parsed : SerCircuit = serde_json::from_str(&text)?;
-
-    //     if parsed.version != 2 {
-    //         anyhow::bail!("unsupported file version: {}", parsed.version);
-    //     }
-
-    //     let mut circ = Circuit::new();
-
-    //     for (id, sg) in parsed.gates {
-    //         match sg {
-    //             SerGate::Const { level } =>
-    //                 circ.add_gate(&id, Rc::new(RefCell::new(ConstGate::new(level)))),
-    //             SerGate::Input { value } =>
-    //                 circ.add_gate(&id, Rc::new(RefCell::new(InputGate::new(value.is_high())))),
-    //         }
-    //     }
-
-    //     circ.outputs = parsed.outputs;
-    //     Ok(circ)
-    // }
 }
@@ -0,0 +1,159 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::circuit::gate::*;
+
+/// Closures read buses with `pack`, re-exported for them.
+pub use crate::circuit::gate::pack;
+
+type OutputFn = dyn Fn(&dyn Any, &[Signal]) -> Vec<Signal>;
+type UpdateFn = dyn Fn(&dyn Any, &[Signal]) -> Box<dyn Any>;
+
+/// Behavioural component whose outputs come from Rust closures, for
+/// reference models and quick experiments.
+///
+/// A plain closure gate maps its inputs straight to its outputs. A clocked
+/// one also keeps a state of any type: on each rising clock edge `update`
+/// computes the next state from the current one and the inputs, and
+/// `output` derives the outputs from the state and the inputs.
+#[derive(Debug)]
+pub struct ClosureGate {
+    pub outputs: Vec<GateRef>,
+}
+
+struct ClosureShared {
+    name: String,
+    inputs: Vec<GateRef>,
+    clock: Option<GateRef>,
+    state: RefCell<Box<dyn Any>>,
+    next: RefCell<Option<Box<dyn Any>>>,
+    output: Box<OutputFn>,
+    update: Option<Box<UpdateFn>>,
+    last_clk: RefCell<Signal>,
+}
+
+/// One output of a `ClosureGate`.
+#[derive(Debug)]
+pub struct ClosurePort {
+    shared: Rc<ClosureShared>,
+    index: usize,
+}
+
+impl fmt::Debug for ClosureShared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClosureShared")
+            .field("name", &self.name)
+            .field("inputs", &self.inputs.len())
+            .field("clocked", &self.clock.is_some())
+            .finish()
+    }
+}
+
+/// The low `width` bits of `value`, bit 0 first.
+pub fn unpack(value: u64, width: usize) -> Vec<Signal> {
+    (0..width)
+        .map(|i| if i < 64 && value >> i & 1 == 1 { Signal::High } else { Signal::Low })
+        .collect()
+}
+
+impl ClosureGate {
+    /// Combinational gate with `width` outputs computed by `f`.
+    pub fn new<F>(name: &str, inputs: Vec<GateRef>, width: usize, f: F) -> Self
+    where
+        F: Fn(&[Signal]) -> Vec<Signal> + 'static,
+    {
+        let shared = ClosureShared {
+            name: name.into(),
+            inputs,
+            clock: None,
+            state: RefCell::new(Box::new(())),
+            next: RefCell::new(None),
+            output: Box::new(move |_, levels| f(levels)),
+            update: None,
+            last_clk: RefCell::new(Signal::Low),
+        };
+        Self::ports(shared, width)
+    }
+
+    /// Sequential gate holding a state of type `S`, starting at `init`.
+    pub fn clocked<S, U, O>(name: &str, clock: GateRef, inputs: Vec<GateRef>, width: usize, init: S, update: U, output: O) -> Self
+    where
+        S: 'static,
+        U: Fn(&S, &[Signal]) -> S + 'static,
+        O: Fn(&S, &[Signal]) -> Vec<Signal> + 'static,
+    {
+        fn state<S: 'static>(s: &dyn Any) -> &S {
+            s.downcast_ref::<S>().expect("closure gate state changed type")
+        }
+        let shared = ClosureShared {
+            name: name.into(),
+            inputs,
+            clock: Some(clock),
+            state: RefCell::new(Box::new(init)),
+            next: RefCell::new(None),
+            output: Box::new(move |s, levels| output(state(s), levels)),
+            update: Some(Box::new(move |s, levels| Box::new(update(state(s), levels)))),
+            last_clk: RefCell::new(Signal::Low),
+        };
+        Self::ports(shared, width)
+    }
+
+    fn ports(shared: ClosureShared, width: usize) -> Self {
+        let shared = Rc::new(shared);
+        let outputs = (0..width)
+            .map(|index| Rc::new(RefCell::new(ClosurePort { shared: shared.clone(), index })) as GateRef)
+            .collect();
+        Self { outputs }
+    }
+}
+
+impl ClosureShared {
+    fn levels(&self) -> Vec<Signal> {
+        self.inputs.iter().map(|g| g.borrow().eval()).collect()
+    }
+}
+
+impl Gate for ClosurePort {
+    /// A closure that returns too few outputs leaves the rest at `X`.
+    fn eval(&self) -> Signal {
+        let s = &self.shared;
+        let outputs = (s.output)(s.state.borrow().as_ref(), &s.levels());
+        outputs.get(self.index).copied().unwrap_or(Signal::X)
+    }
+
+    fn description(&self) -> String {
+        format!("{}[{}]", self.shared.name, self.index)
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        self.shared.inputs.iter().chain(&self.shared.clock).cloned().collect()
+    }
+
+    // the ports share one clock history, so only one of them updates the state
+    fn clock_edge(&self) -> bool {
+        let Some(clock) = &self.shared.clock else {
+            return false;
+        };
+        rising(clock, &self.shared.last_clk)
+    }
+
+    fn capture(&self) {
+        let s = &self.shared;
+        if let Some(update) = &s.update {
+            let next = update(s.state.borrow().as_ref(), &s.levels());
+            *s.next.borrow_mut() = Some(next);
+        }
+    }
+
+    fn commit(&self) {
+        if let Some(next) = self.shared.next.borrow_mut().take() {
+            *self.shared.state.borrow_mut() = next;
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::gate::*;
+use crate::circuit::netlist::{GateKind, Loader, Saver, SharedKind};
+
+/// How a pixel value is read from the data bus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PixelFormat {
+    /// One bit, lit pixels are white.
+    Mono,
+    /// Three bits: red, green, blue.
+    Rgb111,
+    /// 24 bits: blue in bits 0-7, green in 8-15, red in 16-23.
+    Rgb888,
+}
+
+/// How a `PixelDisplay` is driven. Every write happens on the rising edge of `write`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DisplayBus<T> {
+    /// `x`/`y` select a pixel, `data` holds its colour in the display's `PixelFormat`.
+    AddressData { x: Vec<T>, y: Vec<T>, data: Vec<T>, write: T },
+    /// `row` selects a row, `columns` holds one on/off line per pixel of it.
+    RowColumn { row: Vec<T>, columns: Vec<T>, write: T },
+}
+
+/// RGB pixels of a display, row by row from the top left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameBuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+/// Write-only pixel display. It drives no signal; `eval` returns the write strobe.
+#[derive(Debug)]
+pub struct PixelDisplay {
+    frame: Rc<RefCell<FrameBuffer>>,
+    bus: DisplayBus<GateRef>,
+    format: PixelFormat,
+    last_write: RefCell<Signal>,
+    pending: RefCell<Vec<(usize, usize, [u8; 3])>>,
+}
+
+impl PixelFormat {
+    pub fn bits(self) -> usize {
+        match self {
+            PixelFormat::Mono => 1,
+            PixelFormat::Rgb111 => 3,
+            PixelFormat::Rgb888 => 24,
+        }
+    }
+
+    fn decode(self, value: u64) -> [u8; 3] {
+        let on = |bit: u64| if value >> bit & 1 == 1 { 0xFF } else { 0 };
+        match self {
+            PixelFormat::Mono => [on(0); 3],
+            PixelFormat::Rgb111 => [on(0), on(1), on(2)],
+            PixelFormat::Rgb888 => [(value >> 16) as u8, (value >> 8) as u8, value as u8],
+        }
+    }
+}
+
+impl<T> DisplayBus<T> {
+    pub fn map<U, E>(self, mut f: impl FnMut(T) -> Result<U, E>) -> Result<DisplayBus<U>, E> {
+        let mut bus = |v: Vec<T>| v.into_iter().map(&mut f).collect::<Result<Vec<U>, E>>();
+        Ok(match self {
+            DisplayBus::AddressData { x, y, data, write } => DisplayBus::AddressData {
+                x: bus(x)?,
+                y: bus(y)?,
+                data: bus(data)?,
+                write: f(write)?,
+            },
+            DisplayBus::RowColumn { row, columns, write } => DisplayBus::RowColumn {
+                row: bus(row)?,
+                columns: bus(columns)?,
+                write: f(write)?,
+            },
+        })
+    }
+
+    fn write(&self) -> &T {
+        match self {
+            DisplayBus::AddressData { write, .. } | DisplayBus::RowColumn { write, .. } => write,
+        }
+    }
+
+    /// Checks bus widths against a `width` x `height` display.
+    pub fn check(&self, width: usize, height: usize, format: PixelFormat) -> Result<(), String> {
+        let fits = |bits: usize, n: usize| bits < usize::BITS as usize && 1 << bits >= n;
+        match self {
+            DisplayBus::AddressData { x, y, data, .. } => {
+                if !fits(x.len(), width) || !fits(y.len(), height) {
+                    return Err(format!("{}/{} address bits cannot reach {width}x{height} pixels", x.len(), y.len()));
+                }
+                if data.len() != format.bits() {
+                    return Err(format!("{:?} needs {} data bits, got {}", format, format.bits(), data.len()));
+                }
+            }
+            DisplayBus::RowColumn { row, columns, .. } => {
+                if !fits(row.len(), height) || columns.len() != width {
+                    return Err(format!("row/column bus of {}/{} lines does not match {width}x{height} pixels", row.len(), columns.len()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FrameBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![[0; 3]; width * height] }
+    }
+
+    pub(crate) fn restore(width: usize, height: usize, pixels: &[[u8; 3]]) -> Result<Self, String> {
+        if pixels.len() != width * height {
+            return Err(format!("{} pixels do not fill {width}x{height}", pixels.len()));
+        }
+        Ok(Self { width, height, pixels: pixels.to_vec() })
+    }
+
+    pub(crate) fn to_netlist(&self) -> SharedKind {
+        SharedKind::Frame { width: self.width, height: self.height, pixels: self.pixels.clone() }
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = rgb;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|p| *p = [0; 3]);
+    }
+
+    /// Encodes the frame as an 8-bit RGB PNG (uncompressed deflate).
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.height * (1 + 3 * self.width));
+        for row in self.pixels.chunks(self.width.max(1)) {
+            raw.push(0); // filter: none
+            raw.extend(row.iter().flatten());
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend((self.width as u32).to_be_bytes());
+        ihdr.extend((self.height as u32).to_be_bytes());
+        ihdr.extend([8, 2, 0, 0, 0]); // 8 bit, truecolour, deflate, no filter, no interlace
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &ihdr);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_png()).with_context(|| format!("saving {}", path.display()))
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend((b << 16 | a).to_be_bytes());
+    out
+}
+
+impl PixelDisplay {
+    pub fn new(width: usize, height: usize, bus: DisplayBus<GateRef>, format: PixelFormat) -> Self {
+        if let Err(e) = bus.check(width, height, format) {
+            panic!("{e}");
+        }
+        Self {
+            frame: Rc::new(RefCell::new(FrameBuffer::new(width, height))),
+            bus,
+            format,
+            last_write: RefCell::new(Signal::Low),
+            pending: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Display as saved by `Circuit::save`, drawing into shared frame `frame`.
+    pub(crate) fn restore(cx: &mut Loader, frame: usize, bus: &DisplayBus<String>, format: PixelFormat, last_write: Signal) -> Result<Self, String> {
+        let frame = cx.shared::<RefCell<FrameBuffer>>(frame)?;
+        let bus = bus.clone().map(|id| cx.gate(&id))?;
+        let (width, height) = (frame.borrow().width, frame.borrow().height);
+        bus.check(width, height, format)?;
+        Ok(Self { frame, bus, format, last_write: RefCell::new(last_write), pending: RefCell::new(Vec::new()) })
+    }
+
+    pub fn frame(&self) -> Rc<RefCell<FrameBuffer>> {
+        self.frame.clone()
+    }
+
+    /// Snapshot of what the display currently shows.
+    pub fn capture_frame(&self) -> FrameBuffer {
+        self.frame.borrow().clone()
+    }
+}
+
+impl Gate for PixelDisplay {
+    fn eval(&self) -> Signal {
+        self.bus.write().borrow().eval()
+    }
+
+    fn description(&self) -> String {
+        let f = self.frame.borrow();
+        format!("PixelDisplay({}x{}, {:?})", f.width(), f.height(), self.format)
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        let frame = cx.shared(&self.frame, |_| self.frame.borrow().to_netlist());
+        let bus = self.bus.clone().map(|g| Ok::<_, ()>(cx.name(&g))).unwrap();
+        Some(GateKind::Display { frame, bus, format: self.format, last_write: *self.last_write.borrow() })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        match &self.bus {
+            DisplayBus::AddressData { x, y, data, write } => {
+                x.iter().chain(y).chain(data).chain([write]).cloned().collect()
+            }
+            DisplayBus::RowColumn { row, columns, write } => {
+                row.iter().chain(columns).chain([write]).cloned().collect()
+            }
+        }
+    }
+
+    fn clock_edge(&self) -> bool {
+        rising(self.bus.write(), &self.last_write)
+    }
+
+    fn capture(&self) {
+        let mut pending = self.pending.borrow_mut();
+        pending.clear();
+
+        match &self.bus {
+            DisplayBus::AddressData { x, y, data, .. } => {
+                if let (Some(x), Some(y), Some(v)) = (bus_value(x), bus_value(y), bus_value(data)) {
+                    pending.push((x as usize, y as usize, self.format.decode(v)));
+                }
+            }
+            DisplayBus::RowColumn { row, columns, .. } => {
+                if let Some(y) = bus_value(row) {
+                    for (x, col) in columns.iter().enumerate() {
+                        let on = col.borrow().eval().is_high();
+                        pending.push((x, y as usize, PixelFormat::Mono.decode(on as u64)));
+                    }
+                }
+            }
+        }
+    }
+
+    fn commit(&self) {
+        let mut frame = self.frame.borrow_mut();
+        for (x, y, rgb) in self.pending.borrow_mut().drain(..) {
+            frame.set_pixel(x, y, rgb);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use crate::circuit::alu::{Alu, OPCODE_WIDTH};
+use crate::circuit::arith::{self, mux2};
+use crate::circuit::circuit::Circuit;
+use crate::circuit::fsm::{FsmKind, StateTable};
+use crate::circuit::gate::*;
+use crate::circuit::memory::MemoryCore;
+use crate::circuit::wire::Wire;
+
+/// A ready-made design. `inputs` are the `InputGate`s meant to be driven by
+/// hand, `outputs` the signals worth watching; clocked designs are driven by
+/// a `ClockGate` registered as `clk`.
+#[derive(Debug)]
+pub struct Example {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub circuit: Circuit,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+type Builder = fn() -> Example;
+
+/// Every example by name, in menu order.
+pub const EXAMPLES: [(&str, Builder); 6] = [
+    ("4-bit ripple adder", ripple_adder4),
+    ("8-bit lookahead adder", lookahead_adder8),
+    ("4-bit counter", counter4),
+    ("Traffic light", traffic_light),
+    ("UART transmitter", uart_tx),
+    ("8-bit CPU", cpu_demo),
+];
+
+/// Builds the example called `name`.
+pub fn example(name: &str) -> Option<Example> {
+    EXAMPLES.iter().find(|(n, _)| *n == name).map(|(_, build)| build())
+}
+
+fn constant(level: Signal) -> GateRef {
+    gate(ConstGate::new(level))
+}
+
+fn bus(prefix: &str, width: usize) -> Vec<String> {
+    (0..width).map(|i| format!("{prefix}{i}")).collect()
+}
+
+fn refs(ids: &[String]) -> Vec<&str> {
+    ids.iter().map(String::as_str).collect()
+}
+
+/// Adds a low `InputGate` per id.
+fn add_inputs(c: &mut Circuit, ids: &[String]) -> Vec<GateRef> {
+    ids.iter()
+        .map(|id| {
+            let g = gate(InputGate::new(false));
+            c.add_gate(id, g.clone());
+            g
+        })
+        .collect()
+}
+
+fn add_clock(c: &mut Circuit) -> GateRef {
+    let clk = gate(ClockGate::new());
+    c.add_gate("clk", clk.clone());
+    clk
+}
+
+fn add_outputs(c: &mut Circuit, ids: &[String], gates: Vec<GateRef>) {
+    for (id, g) in ids.iter().zip(gates) {
+        c.add_gate(id, g);
+        c.add_output(id);
+    }
+}
+
+/// Register whose outputs are the wires `ids`. The next-state logic reads
+/// the wires; `close` then drives them from flip-flops `{id}.ff`.
+struct Register {
+    ids: Vec<String>,
+    q: Vec<GateRef>,
+}
+
+impl Register {
+    fn new(c: &mut Circuit, prefix: &str, width: usize) -> Self {
+        let ids = bus(prefix, width);
+        let q = ids.iter()
+            .map(|id| {
+                c.add_wire(id, Wire::new(id));
+                c.add_output(id);
+                c.gate(id).unwrap()
+            })
+            .collect();
+        Self { ids, q }
+    }
+
+    fn close(self, c: &mut Circuit, next: Vec<GateRef>, clock: &GateRef) -> Result<(), String> {
+        for (id, d) in self.ids.iter().zip(next) {
+            let ff = format!("{id}.ff");
+            c.add_gate(&ff, gate(Dflipflop::new(d, clock.clone())));
+            c.connect(&ff, id)?;
+        }
+        Ok(())
+    }
+}
+
+fn adder(name: &'static str, width: usize, lookahead: bool) -> Result<Example, String> {
+    let mut c = Circuit::new();
+    let (a, b) = (bus("a", width), bus("b", width));
+    let sum = bus("s", width);
+    add_inputs(&mut c, &a);
+    add_inputs(&mut c, &b);
+    add_inputs(&mut c, &["cin".to_string()]);
+
+    if lookahead {
+        c.add_carry_lookahead_adder(&refs(&a), &refs(&b), "cin", &refs(&sum), "cout")?;
+    } else {
+        c.add_ripple_carry_adder(&refs(&a), &refs(&b), "cin", &refs(&sum), "cout")?;
+    }
+    let outputs: Vec<String> = sum.into_iter().chain(["cout".to_string()]).collect();
+    for id in &outputs {
+        c.add_output(id);
+    }
+
+    Ok(Example {
+        name,
+        description: "Adds a and b (bit 0 first) plus cin.",
+        circuit: c,
+        inputs: a.into_iter().chain(b).chain(["cin".to_string()]).collect(),
+        outputs,
+    })
+}
+
+pub fn ripple_adder4() -> Example {
+    adder("4-bit ripple adder", 4, false).unwrap()
+}
+
+pub fn lookahead_adder8() -> Example {
+    adder("8-bit lookahead adder", 8, true).unwrap()
+}
+
+/// Synchronous 4-bit counter `q` with count enable `en` and synchronous
+/// clear `clr`; `tc` is high on 15 while counting.
+pub fn counter4() -> Example {
+    let mut c = Circuit::new();
+    let clk = add_clock(&mut c);
+    let inputs = vec!["en".to_string(), "clr".to_string()];
+    let pins = add_inputs(&mut c, &inputs);
+    let (en, clr) = (pins[0].clone(), pins[1].clone());
+
+    let q = Register::new(&mut c, "q", 4);
+    let zeros = vec![constant(Signal::Low); 4];
+    let inc = arith::ripple_carry_adder(&q.q, &zeros, en);
+    let clr_n = gate(NotGate::new(clr));
+    let next = inc.sum.into_iter().map(|s| gate(AndGate::new(s, clr_n.clone()))).collect();
+    let outputs: Vec<String> = q.ids.iter().cloned().chain(["tc".to_string()]).collect();
+    q.close(&mut c, next, &clk).unwrap();
+    add_outputs(&mut c, &["tc".to_string()], vec![inc.carry]);
+
+    Example {
+        name: "4-bit counter",
+        description: "Counts clock edges while en is high; clr resets it on the next edge.",
+        circuit: c,
+        inputs,
+        outputs,
+    }
+}
+
+/// Moore controller for a main road light. It stays green until a car
+/// waits on the side road (`req`), then runs amber, red, red+amber.
+pub fn traffic_light_table() -> StateTable {
+    StateTable::new(FsmKind::Moore, &["req"], &["red", "amber", "green"])
+        .state("green", "001")
+        .state("amber", "010")
+        .state("red", "100")
+        .state("red_amber", "110")
+        .transition("green", "1", "amber", "")
+        .transition("amber", "-", "red", "")
+        .transition("red", "-", "red_amber", "")
+        .transition("red_amber", "-", "green", "")
+}
+
+pub fn traffic_light() -> Example {
+    let mut c = Circuit::new();
+    add_clock(&mut c);
+    let inputs = vec!["req".to_string()];
+    add_inputs(&mut c, &inputs);
+    let outputs = vec!["red".to_string(), "amber".to_string(), "green".to_string()];
+    c.add_fsm("light", traffic_light_table(), "clk", &["req"], &refs(&outputs)).unwrap();
+    for id in &outputs {
+        c.add_output(id);
+    }
+
+    Example {
+        name: "Traffic light",
+        description: "State-table FSM; set req to let the side road through.",
+        circuit: c,
+        inputs,
+        outputs,
+    }
+}
+
+/// 8N1 serial transmitter sending one bit per clock cycle. A rising clock
+/// edge with `send` high while idle latches `d0..d7`; `tx` then carries the
+/// start bit, the data (bit 0 first) and the stop bit, and idles high.
+///
+/// The frame sits in a 10-bit shift register emptied from the top; since the
+/// stop bit is the last one set, the register is non-zero exactly while busy.
+pub fn uart_tx() -> Example {
+    const FRAME: usize = 10;
+
+    let mut c = Circuit::new();
+    let clk = add_clock(&mut c);
+    let data_ids = bus("d", 8);
+    let data = add_inputs(&mut c, &data_ids);
+    let send = add_inputs(&mut c, &["send".to_string()]).remove(0);
+
+    let sh = Register::new(&mut c, "sh", FRAME);
+    let busy = or_tree(&sh.q);
+    let load = gate(AndGate::new(send, gate(NotGate::new(busy.clone()))));
+
+    let frame: Vec<GateRef> = std::iter::once(constant(Signal::Low))
+        .chain(data)
+        .chain([constant(Signal::High)])
+        .collect();
+    let next = (0..FRAME)
+        .map(|i| {
+            let shifted = sh.q.get(i + 1).cloned().unwrap_or_else(|| constant(Signal::Low));
+            mux2(load.clone(), shifted, frame[i].clone())
+        })
+        .collect();
+    let tx = gate(OrGate::new(sh.q[0].clone(), gate(NotGate::new(busy.clone()))));
+    sh.close(&mut c, next, &clk).unwrap();
+    let outputs = vec!["tx".to_string(), "busy".to_string()];
+    add_outputs(&mut c, &outputs, vec![tx, busy]);
+
+    Example {
+        name: "UART transmitter",
+        description: "Set d0..d7, raise send for one clock and watch tx.",
+        circuit: c,
+        inputs: data_ids.into_iter().chain(["send".to_string()]).collect(),
+        outputs,
+    }
+}
+
+/// Instructions of the example CPU. Each is a 12-bit ROM word: the opcode
+/// in bits 8..11 and the operand in bits 0..7. Opcodes 0 to 7 are `AluOp`
+/// codes applied to the accumulator and the operand; jump targets use the
+/// low 4 operand bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instr {
+    Add(u8),
+    Sub(u8),
+    And(u8),
+    Or(u8),
+    Xor(u8),
+    Not,
+    Shl,
+    Shr,
+    /// Loads the operand into the accumulator.
+    Ldi(u8),
+    /// Copies the accumulator to the output register.
+    Out,
+    Jmp(u8),
+    /// Jumps if the accumulator is zero.
+    Jz(u8),
+    /// Jumps if the accumulator is not zero.
+    Jnz(u8),
+    /// Stops the program counter.
+    Hlt,
+}
+
+/// Address width of the CPU's program ROM.
+pub const CPU_ADDR_BITS: usize = 4;
+/// Width of a CPU instruction word.
+pub const CPU_WORD_BITS: usize = 12;
+
+impl Instr {
+    pub fn encode(self) -> u64 {
+        let (op, n) = match self {
+            Instr::Add(n) => (0, n),
+            Instr::Sub(n) => (1, n),
+            Instr::And(n) => (2, n),
+            Instr::Or(n) => (3, n),
+            Instr::Xor(n) => (4, n),
+            Instr::Not => (5, 0),
+            Instr::Shl => (6, 0),
+            Instr::Shr => (7, 0),
+            Instr::Ldi(n) => (8, n),
+            Instr::Out => (9, 0),
+            Instr::Jmp(a) => (10, a),
+            Instr::Jz(a) => (11, a),
+            Instr::Jnz(a) => (12, a),
+            Instr::Hlt => (15, 0),
+        };
+        (op << 8) | n as u64
+    }
+}
+
+/// Single-cycle 8-bit accumulator machine running `program` from a 16-word
+/// ROM (`rom`). Every rising clock edge executes one instruction. Registers:
+/// `pc0..3`, `acc0..7` and the output register `out0..7`; `halted` is high
+/// on a `Hlt`. The ROM word is visible as `ir0..11`.
+pub fn cpu(program: &[Instr]) -> Result<Example, String> {
+    if program.len() > 1 << CPU_ADDR_BITS {
+        return Err(format!("program has {} instructions, the ROM holds {}", program.len(), 1 << CPU_ADDR_BITS));
+    }
+    let mut c = Circuit::new();
+    let clk = add_clock(&mut c);
+
+    let pc = Register::new(&mut c, "pc", CPU_ADDR_BITS);
+    let acc = Register::new(&mut c, "acc", 8);
+    let out = Register::new(&mut c, "out", 8);
+
+    let words: Vec<u64> = program.iter().map(|i| i.encode()).collect();
+    let core = MemoryCore::with_contents(CPU_ADDR_BITS, CPU_WORD_BITS, &words);
+    let ir = bus("ir", CPU_WORD_BITS);
+    c.add_rom("rom", core, &refs(&pc.ids), None, &refs(&ir))?;
+    let ir: Vec<GateRef> = ir.iter().map(|id| c.gate(id).unwrap()).collect();
+    let (operand, opcode) = ir.split_at(8);
+
+    let opcode_n: Vec<GateRef> = opcode.iter().map(|b| gate(NotGate::new(b.clone()))).collect();
+    let decode = |code: usize| {
+        let lits: Vec<GateRef> = (0..4)
+            .map(|i| if code >> i & 1 == 1 { opcode[i].clone() } else { opcode_n[i].clone() })
+            .collect();
+        and_tree(&lits)
+    };
+    let is_alu = opcode_n[3].clone();
+    let (is_ldi, is_out, is_jmp, is_jz, is_jnz, is_hlt) = (decode(8), decode(9), decode(10), decode(11), decode(12), decode(15));
+
+    let alu_op: [GateRef; OPCODE_WIDTH] = [opcode[0].clone(), opcode[1].clone(), opcode[2].clone(), constant(Signal::Low)];
+    let alu = Alu::new(acc.q.clone(), operand.to_vec(), alu_op);
+    let next_acc = (0..8)
+        .map(|i| {
+            let loaded = mux2(is_ldi.clone(), acc.q[i].clone(), operand[i].clone());
+            mux2(is_alu.clone(), loaded, alu.result[i].clone())
+        })
+        .collect();
+    let next_out = (0..8).map(|i| mux2(is_out.clone(), out.q[i].clone(), acc.q[i].clone())).collect();
+
+    let zero = gate(NotGate::new(or_tree(&acc.q)));
+    let taken = or_tree(&[
+        is_jmp,
+        gate(AndGate::new(is_jz, zero.clone())),
+        gate(AndGate::new(is_jnz, gate(NotGate::new(zero)))),
+    ]);
+    let incremented = arith::ripple_carry_adder(&pc.q, &vec![constant(Signal::Low); CPU_ADDR_BITS], constant(Signal::High));
+    let next_pc = (0..CPU_ADDR_BITS)
+        .map(|i| {
+            let moved = mux2(taken.clone(), incremented.sum[i].clone(), operand[i].clone());
+            mux2(is_hlt.clone(), moved, pc.q[i].clone())
+        })
+        .collect();
+
+    let outputs: Vec<String> = pc.ids.iter().chain(&acc.ids).chain(&out.ids).cloned().chain(["halted".to_string()]).collect();
+    pc.close(&mut c, next_pc, &clk)?;
+    acc.close(&mut c, next_acc, &clk)?;
+    out.close(&mut c, next_out, &clk)?;
+    add_outputs(&mut c, &["halted".to_string()], vec![is_hlt]);
+
+    Ok(Example {
+        name: "8-bit CPU",
+        description: "Accumulator machine with a program ROM; the demo counts down from 10 on out.",
+        circuit: c,
+        inputs: Vec::new(),
+        outputs,
+    })
+}
+
+/// Program of the CPU example: shows 10, 9, ..., 0 on `out`, then halts.
+pub fn countdown_program() -> Vec<Instr> {
+    vec![
+        Instr::Ldi(10),
+        Instr::Out,
+        Instr::Sub(1),
+        Instr::Jnz(1),
+        Instr::Out,
+        Instr::Hlt,
+    ]
+}
+
+pub fn cpu_demo() -> Example {
+    cpu(&countdown_program()).unwrap()
+}
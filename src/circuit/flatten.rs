@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::param::Params;
+use crate::circuit::subcircuit::{self, InstanceInfo, SubcircuitDef};
+
+/// Separator replacing `.` in flat names.
+pub const SEPARATOR: &str = "__";
+
+/// Two-way map between hierarchical ids (`top.alu.add0.sum`) and the flat
+/// gate ids of a flattened design (`alu__add0__sum`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NameMap {
+    to_flat: HashMap<String, String>,
+    to_hier: HashMap<String, String>,
+}
+
+/// A design flattened into one `Circuit` without instances. `instances`
+/// keeps the hierarchy it came from, by hierarchical path.
+#[derive(Debug)]
+pub struct Flattened {
+    pub circuit: Circuit,
+    pub names: NameMap,
+    pub instances: BTreeMap<String, InstanceInfo>,
+}
+
+impl NameMap {
+    /// Records `hier` under a flat name derived from it, made unique with a
+    /// numeric suffix if needed, and returns that name.
+    fn insert(&mut self, root: &str, hier: &str) -> String {
+        let local = hier.strip_prefix(root).and_then(|s| s.strip_prefix('.')).unwrap_or(hier);
+        let base = mangle(local);
+        let flat = std::iter::once(base.clone())
+            .chain((1..).map(|n| format!("{base}_{n}")))
+            .find(|f| !self.to_hier.contains_key(f))
+            .unwrap();
+        self.to_flat.insert(hier.into(), flat.clone());
+        self.to_hier.insert(flat.clone(), hier.into());
+        flat
+    }
+
+    /// Flat id of hierarchical id `hier`.
+    pub fn flat(&self, hier: &str) -> Option<&str> {
+        self.to_flat.get(hier).map(String::as_str)
+    }
+
+    /// Hierarchical id of flat id `flat`.
+    pub fn hierarchical(&self, flat: &str) -> Option<&str> {
+        self.to_hier.get(flat).map(String::as_str)
+    }
+
+    /// Path of the instance holding flat gate `flat`, e.g. `top.alu.add0`.
+    pub fn instance(&self, flat: &str) -> Option<&str> {
+        self.hierarchical(flat)?.rsplit_once('.').map(|(path, _)| path)
+    }
+
+    /// `(flat, hierarchical)` pairs sorted by flat id.
+    pub fn pairs(&self) -> Vec<(&str, &str)> {
+        let mut pairs: Vec<(&str, &str)> = self.to_hier.iter().map(|(f, h)| (f.as_str(), h.as_str())).collect();
+        pairs.sort();
+        pairs
+    }
+
+    pub fn len(&self) -> usize {
+        self.to_hier.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_hier.is_empty()
+    }
+}
+
+/// Flat form of a hierarchical id relative to its top instance.
+pub fn mangle(path: &str) -> String {
+    path.replace('.', SEPARATOR)
+}
+
+/// Elaborates `module` with `params` as the top of a design and registers
+/// everything in a fresh `Circuit` under flat names. The top input ports
+/// become `InputGate`s and the top output ports registered outputs, both
+/// keeping their port names. Hierarchical ids start with the module name.
+pub fn flatten(defs: &HashMap<String, SubcircuitDef>, module: &str, params: &Params) -> Result<Flattened, String> {
+    let (inputs, outputs) = subcircuit::ports(defs, module, params)?;
+    let pins: Vec<GateRef> = inputs.iter().map(|_| Rc::new(RefCell::new(InputGate::new(false))) as GateRef).collect();
+    let built = subcircuit::elaborate(defs, module, module, params, pins.clone())?;
+
+    let mut circuit = Circuit::new();
+    let mut names = NameMap::default();
+    for (port, pin) in inputs.iter().zip(pins) {
+        let flat = names.insert(module, &format!("{module}.{port}"));
+        circuit.add_gate(flat, pin);
+    }
+    for (id, gate) in built.gates {
+        // the top input ports are the pins above
+        if names.flat(&id).is_none() {
+            let flat = names.insert(module, &id);
+            circuit.add_gate(flat, gate);
+        }
+    }
+    for port in &outputs {
+        circuit.add_output(names.flat(&format!("{module}.{port}")).unwrap());
+    }
+
+    Ok(Flattened { circuit, names, instances: built.instances.into_iter().collect() })
+}
@@ -0,0 +1,521 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::netlist::{GateKind, Saver, SharedKind};
+use crate::circuit::wire::Wire;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FsmKind {
+    /// Outputs depend on the state only.
+    Moore,
+    /// Outputs depend on the state and the current inputs.
+    Mealy,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsmState {
+    pub name: String,
+    /// Moore outputs, one `0`/`1` per output. Ignored by Mealy machines.
+    #[serde(default)]
+    pub outputs: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    pub from: String,
+    /// One `0`, `1` or `-` (don't care) per input.
+    pub when: String,
+    pub to: String,
+    /// Mealy outputs while this transition is selected.
+    #[serde(default)]
+    pub outputs: String,
+}
+
+/// State transition table. For a state and input combination the first
+/// matching transition in table order wins; with no match the machine stays
+/// put and a Mealy machine outputs zeros.
+///
+/// ```json
+/// { "kind": "Moore", "inputs": ["go"], "outputs": ["lamp"], "initial": "off",
+///   "states": [{ "name": "off", "outputs": "0" }, { "name": "on", "outputs": "1" }],
+///   "transitions": [{ "from": "off", "when": "1", "to": "on" },
+///                   { "from": "on", "when": "0", "to": "off" }] }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateTable {
+    pub kind: FsmKind,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub initial: String,
+    pub states: Vec<FsmState>,
+    pub transitions: Vec<Transition>,
+}
+
+/// Behavioural state machine clocked on the rising edge of `clock`.
+#[derive(Debug)]
+pub struct StateMachine {
+    table: StateTable,
+    inputs: Vec<GateRef>,
+    clock: GateRef,
+    state: Cell<usize>,
+    next: Cell<usize>,
+    last_clk: RefCell<Signal>,
+}
+
+/// How states are numbered in flip-flops when an FSM is synthesized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateEncoding {
+    /// State `i` is `i` in binary.
+    Binary,
+    /// State `i` is the Gray code of `i`, so neighbours differ in one bit.
+    Gray,
+    /// One flip-flop per state.
+    OneHot,
+}
+
+/// Gate-level FSM: `state` are the flip-flops (bit 0 first), `outputs` the
+/// output logic.
+#[derive(Debug)]
+pub struct SynthesizedFsm {
+    pub encoding: StateEncoding,
+    pub state: Vec<GateRef>,
+    pub outputs: Vec<GateRef>,
+}
+
+/// A machine and the gates driving its outputs.
+#[derive(Debug)]
+pub struct Fsm {
+    pub machine: Rc<StateMachine>,
+    pub outputs: Vec<GateRef>,
+}
+
+/// One output of a `StateMachine`. The port with no output evaluates to the
+/// clock, like other components that drive nothing of their own.
+#[derive(Debug)]
+pub struct FsmPort {
+    machine: Rc<StateMachine>,
+    output: Option<usize>,
+}
+
+fn bits(text: &str, len: usize, what: &str) -> Result<(), String> {
+    if text.len() != len || !text.chars().all(|c| c == '0' || c == '1') {
+        return Err(format!("{what} '{text}' must be {len} characters of 0/1"));
+    }
+    Ok(())
+}
+
+impl StateTable {
+    pub fn new(kind: FsmKind, inputs: &[&str], outputs: &[&str]) -> Self {
+        Self {
+            kind,
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            initial: String::new(),
+            states: Vec::new(),
+            transitions: Vec::new(),
+        }
+    }
+
+    /// Adds a state; the first one added becomes the initial state.
+    pub fn state(mut self, name: &str, outputs: &str) -> Self {
+        if self.states.is_empty() {
+            self.initial = name.into();
+        }
+        self.states.push(FsmState { name: name.into(), outputs: outputs.into() });
+        self
+    }
+
+    pub fn transition(mut self, from: &str, when: &str, to: &str, outputs: &str) -> Self {
+        self.transitions.push(Transition {
+            from: from.into(),
+            when: when.into(),
+            to: to.into(),
+            outputs: outputs.into(),
+        });
+        self
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let table: Self = serde_json::from_str(text).context("parsing state table")?;
+        table.check().map_err(anyhow::Error::msg)?;
+        Ok(table)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("loading {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text).with_context(|| format!("saving {}", path.display()))
+    }
+
+    pub fn state_index(&self, name: &str) -> Option<usize> {
+        self.states.iter().position(|s| s.name == name)
+    }
+
+    /// Checks names and the width of every condition and output string.
+    pub fn check(&self) -> Result<(), String> {
+        for (i, s) in self.states.iter().enumerate() {
+            if self.states[..i].iter().any(|o| o.name == s.name) {
+                return Err(format!("state '{}' defined twice", s.name));
+            }
+            if self.kind == FsmKind::Moore {
+                bits(&s.outputs, self.outputs.len(), &format!("outputs of state '{}'", s.name))?;
+            }
+        }
+        if self.state_index(&self.initial).is_none() {
+            return Err(format!("initial state '{}' is not defined", self.initial));
+        }
+
+        for t in &self.transitions {
+            for name in [&t.from, &t.to] {
+                if self.state_index(name).is_none() {
+                    return Err(format!("transition uses undefined state '{name}'"));
+                }
+            }
+            if t.when.len() != self.inputs.len() || !t.when.chars().all(|c| "01-".contains(c)) {
+                return Err(format!("condition '{}' must be {} characters of 0/1/-", t.when, self.inputs.len()));
+            }
+            if self.kind == FsmKind::Mealy {
+                bits(&t.outputs, self.outputs.len(), &format!("outputs of {} -> {}", t.from, t.to))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The transition taken from `state` on `inputs`, if any.
+    pub fn select(&self, state: usize, inputs: &[bool]) -> Option<&Transition> {
+        let name = &self.states[state].name;
+        self.transitions.iter().find(|t| {
+            &t.from == name
+                && t.when.chars().zip(inputs).all(|(c, &v)| c == '-' || (c == '1') == v)
+        })
+    }
+
+    /// Next state and outputs for `state` and `inputs`.
+    pub fn step(&self, state: usize, inputs: &[bool]) -> (usize, Vec<bool>) {
+        let t = self.select(state, inputs);
+        let next = t.and_then(|t| self.state_index(&t.to)).unwrap_or(state);
+        let outputs = match (self.kind, t) {
+            (FsmKind::Moore, _) => self.states[state].outputs.chars().map(|c| c == '1').collect(),
+            (FsmKind::Mealy, Some(t)) => t.outputs.chars().map(|c| c == '1').collect(),
+            (FsmKind::Mealy, None) => vec![false; self.outputs.len()],
+        };
+        (next, outputs)
+    }
+}
+
+impl StateEncoding {
+    pub const ALL: [StateEncoding; 3] = [StateEncoding::Binary, StateEncoding::Gray, StateEncoding::OneHot];
+
+    /// Flip-flops needed for `states` states.
+    pub fn width(self, states: usize) -> usize {
+        match self {
+            StateEncoding::OneHot => states,
+            _ => (usize::BITS - states.saturating_sub(1).leading_zeros()).max(1) as usize,
+        }
+    }
+
+    /// Code of state `index`, bit 0 first.
+    pub fn code(self, index: usize, states: usize) -> Vec<bool> {
+        let value = match self {
+            StateEncoding::Binary => index,
+            StateEncoding::Gray => index ^ (index >> 1),
+            StateEncoding::OneHot => return (0..states).map(|i| i == index).collect(),
+        };
+        (0..self.width(states)).map(|i| value >> i & 1 == 1).collect()
+    }
+
+    /// State whose code is `bits`, if any.
+    pub fn decode(self, bits: &[bool], states: usize) -> Option<usize> {
+        (0..states).find(|&i| self.code(i, states) == bits)
+    }
+}
+
+/// Builds `table` from `Dflipflop`s clocked by `clock` and AND/OR/NOT logic.
+///
+/// Transitions keep the table's first-match priority: a transition fires
+/// only if no earlier one from the same state matches. Unused binary and
+/// Gray codes lead to the code of state 0.
+pub fn synthesize(table: &StateTable, encoding: StateEncoding, clock: GateRef, inputs: Vec<GateRef>) -> Result<SynthesizedFsm, String> {
+    table.check()?;
+    if inputs.len() != table.inputs.len() {
+        return Err(format!("state table has {} inputs, got {}", table.inputs.len(), inputs.len()));
+    }
+    let not = |g: &GateRef| -> GateRef { Rc::new(RefCell::new(NotGate::new(g.clone()))) };
+
+    let n = table.states.len();
+    let width = encoding.width(n);
+    let q: Vec<Rc<RefCell<Wire>>> = (0..width)
+        .map(|i| Rc::new(RefCell::new(Wire::new(format!("q{i}")))))
+        .collect();
+    let q_ref: Vec<GateRef> = q.iter().map(|w| w.clone() as GateRef).collect();
+    let q_not: Vec<GateRef> = q_ref.iter().map(not).collect();
+    let in_not: Vec<GateRef> = inputs.iter().map(not).collect();
+
+    let decoded: Vec<GateRef> = (0..n)
+        .map(|s| match encoding {
+            StateEncoding::OneHot => q_ref[s].clone(),
+            _ => {
+                let lits: Vec<GateRef> = encoding.code(s, n).iter().enumerate()
+                    .map(|(i, &b)| if b { q_ref[i].clone() } else { q_not[i].clone() })
+                    .collect();
+                and_tree(&lits)
+            }
+        })
+        .collect();
+
+    // (fires, target state, transition) for every transition, then one
+    // "no match" term per state
+    let mut fired: Vec<(GateRef, usize, Option<&Transition>)> = Vec::new();
+    for (s, state) in table.states.iter().enumerate() {
+        let mut earlier: Vec<GateRef> = Vec::new();
+        for t in table.transitions.iter().filter(|t| t.from == state.name) {
+            let lits: Vec<GateRef> = t.when.chars().enumerate()
+                .filter(|(_, c)| *c != '-')
+                .map(|(i, c)| if c == '1' { inputs[i].clone() } else { in_not[i].clone() })
+                .collect();
+            let cond = and_tree(&lits);
+
+            let terms: Vec<GateRef> = [decoded[s].clone(), cond.clone()].into_iter().chain(earlier.clone()).collect();
+            fired.push((and_tree(&terms), table.state_index(&t.to).unwrap(), Some(t)));
+            earlier.push(not(&cond));
+        }
+        let stay: Vec<GateRef> = std::iter::once(decoded[s].clone()).chain(earlier).collect();
+        fired.push((and_tree(&stay), s, None));
+    }
+
+    let initial = encoding.code(table.state_index(&table.initial).unwrap(), n);
+    let state: Vec<GateRef> = (0..width)
+        .map(|bit| {
+            let terms: Vec<GateRef> = fired.iter()
+                .filter(|(_, to, _)| encoding.code(*to, n)[bit])
+                .map(|(g, _, _)| g.clone())
+                .collect();
+            let level = if initial[bit] { Signal::High } else { Signal::Low };
+            let ff: GateRef = Rc::new(RefCell::new(Dflipflop::with_state(or_tree(&terms), clock.clone(), level)));
+            q[bit].borrow_mut().connect(ff.clone());
+            ff
+        })
+        .collect();
+
+    let outputs = (0..table.outputs.len())
+        .map(|k| {
+            let terms: Vec<GateRef> = match table.kind {
+                FsmKind::Moore => table.states.iter().zip(&decoded)
+                    .filter(|(s, _)| s.outputs.as_bytes()[k] == b'1')
+                    .map(|(_, d)| d.clone())
+                    .collect(),
+                FsmKind::Mealy => fired.iter()
+                    .filter(|(_, _, t)| t.is_some_and(|t| t.outputs.as_bytes()[k] == b'1'))
+                    .map(|(g, _, _)| g.clone())
+                    .collect(),
+            };
+            or_tree(&terms)
+        })
+        .collect();
+
+    Ok(SynthesizedFsm { encoding, state, outputs })
+}
+
+/// Runs the behavioural machine and its synthesized form side by side for
+/// `steps` clock half-periods on seeded random inputs, comparing outputs and
+/// states after every step. Reports the first difference.
+pub fn check_synthesis(table: &StateTable, encoding: StateEncoding, steps: usize, seed: u64) -> Result<(), String> {
+    let mut c = Circuit::new();
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    let inputs: Vec<String> = table.inputs.iter().map(|name| format!("in.{name}")).collect();
+    for (i, id) in inputs.iter().enumerate() {
+        c.add_random(id, seed.wrapping_add(i as u64));
+    }
+    let in_ids: Vec<&str> = inputs.iter().map(String::as_str).collect();
+
+    let ids = |prefix: &str| table.outputs.iter().map(|o| format!("{prefix}.{o}")).collect::<Vec<_>>();
+    let (beh_out, gen_out) = (ids("ref"), ids("gen"));
+    fn refs(v: &[String]) -> Vec<&str> { v.iter().map(String::as_str).collect() }
+    c.add_fsm("ref", table.clone(), "clk", &in_ids, &refs(&beh_out))?;
+    c.add_synthesized_fsm("gen", table, encoding, "clk", &in_ids, &refs(&gen_out))?;
+
+    let machine = c.fsm("ref").unwrap();
+    let n = table.states.len();
+    let flops: Vec<GateRef> = (0..encoding.width(n)).map(|i| c.gate(&format!("gen.q{i}")).unwrap()).collect();
+
+    for step in 0..=steps {
+        if step > 0 {
+            c.step();
+        }
+        let bits: Vec<bool> = flops.iter().map(|f| f.borrow().eval().is_high()).collect();
+        if encoding.decode(&bits, n) != Some(machine.state()) {
+            return Err(format!("step {step}: state {} but flip-flops hold {bits:?}", machine.state_name()));
+        }
+        for (name, (a, b)) in table.outputs.iter().zip(beh_out.iter().zip(&gen_out)) {
+            let (a, b) = (c.gate(a).unwrap().borrow().eval(), c.gate(b).unwrap().borrow().eval());
+            if a != b {
+                return Err(format!("step {step}, state {}: output {name} is {a:?}, synthesized {b:?}", machine.state_name()));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl StateMachine {
+    fn input_levels(&self) -> Option<Vec<bool>> {
+        self.inputs.iter()
+            .map(|g| match g.borrow().eval() {
+                Signal::High => Some(true),
+                Signal::Low => Some(false),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn table(&self) -> &StateTable {
+        &self.table
+    }
+
+    /// Machine as saved by `Circuit::save`, in state number `state`.
+    pub(crate) fn restore(table: StateTable, inputs: Vec<GateRef>, clock: GateRef, state: usize, last_clk: Signal) -> Result<Self, String> {
+        table.check()?;
+        if inputs.len() != table.inputs.len() || state >= table.states.len() {
+            return Err("inputs or state do not match the state table".into());
+        }
+        Ok(Self { table, inputs, clock, state: Cell::new(state), next: Cell::new(state), last_clk: RefCell::new(last_clk) })
+    }
+
+    pub(crate) fn to_netlist(&self, cx: &mut Saver) -> SharedKind {
+        SharedKind::Machine {
+            table: self.table.clone(),
+            inputs: cx.names(&self.inputs),
+            clock: cx.name(&self.clock),
+            state: self.state.get(),
+            last_clk: *self.last_clk.borrow(),
+        }
+    }
+
+    pub fn state(&self) -> usize {
+        self.state.get()
+    }
+
+    pub fn state_name(&self) -> &str {
+        &self.table.states[self.state.get()].name
+    }
+
+    /// Forces the machine into state `name`.
+    pub fn set_state(&self, name: &str) -> Result<(), String> {
+        let i = self.table.state_index(name).ok_or_else(|| format!("no state '{name}'"))?;
+        self.state.set(i);
+        Ok(())
+    }
+
+    pub fn reset(&self) {
+        self.state.set(self.table.state_index(&self.table.initial).unwrap());
+    }
+
+    /// Current output levels. A Mealy machine with an input that is not a
+    /// clean 0/1 outputs `X`.
+    pub fn outputs(&self) -> Vec<Signal> {
+        let level = |b: bool| if b { Signal::High } else { Signal::Low };
+        match (self.table.kind, self.input_levels()) {
+            (FsmKind::Mealy, None) => vec![Signal::X; self.table.outputs.len()],
+            (_, levels) => {
+                let levels = levels.unwrap_or_else(|| vec![false; self.inputs.len()]);
+                self.table.step(self.state.get(), &levels).1.into_iter().map(level).collect()
+            }
+        }
+    }
+}
+
+impl Fsm {
+    pub fn new(table: StateTable, clock: GateRef, inputs: Vec<GateRef>) -> Result<Self, String> {
+        table.check()?;
+        if inputs.len() != table.inputs.len() {
+            return Err(format!("state table has {} inputs, got {}", table.inputs.len(), inputs.len()));
+        }
+        let initial = table.state_index(&table.initial).unwrap();
+        let machine = Rc::new(StateMachine {
+            inputs,
+            clock,
+            state: Cell::new(initial),
+            next: Cell::new(initial),
+            last_clk: RefCell::new(Signal::Low),
+            table,
+        });
+        let outputs = (0..machine.table.outputs.len())
+            .map(|i| Rc::new(RefCell::new(FsmPort { machine: machine.clone(), output: Some(i) })) as GateRef)
+            .collect();
+        Ok(Self { machine, outputs })
+    }
+
+    /// Gate standing for the machine itself.
+    pub fn body(&self) -> GateRef {
+        Rc::new(RefCell::new(FsmPort { machine: self.machine.clone(), output: None }))
+    }
+}
+
+impl FsmPort {
+    pub(crate) fn restore(machine: Rc<StateMachine>, output: Option<usize>) -> Result<Self, String> {
+        if output.is_some_and(|i| i >= machine.table.outputs.len()) {
+            return Err("state machine has fewer outputs".into());
+        }
+        Ok(Self { machine, output })
+    }
+}
+
+impl Gate for FsmPort {
+    fn eval(&self) -> Signal {
+        match self.output {
+            Some(i) => self.machine.outputs()[i],
+            None => self.machine.clock.borrow().eval(),
+        }
+    }
+
+    fn description(&self) -> String {
+        match self.output {
+            Some(i) => format!("Fsm({}).{}", self.machine.state_name(), self.machine.table.outputs[i]),
+            None => format!("Fsm({})", self.machine.state_name()),
+        }
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        let machine = cx.shared(&self.machine, |cx| self.machine.to_netlist(cx));
+        Some(GateKind::FsmPort { machine, output: self.output })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        self.machine.inputs.iter().chain([&self.machine.clock]).cloned().collect()
+    }
+
+    // all ports share one clock history, so only one of them steps the machine
+    fn clock_edge(&self) -> bool {
+        rising(&self.machine.clock, &self.machine.last_clk)
+    }
+
+    fn capture(&self) {
+        let m = &self.machine;
+        // an unknown input leaves the state alone
+        let next = match m.input_levels() {
+            Some(levels) => m.table.step(m.state.get(), &levels).0,
+            None => m.state.get(),
+        };
+        m.next.set(next);
+    }
+
+    fn commit(&self) {
+        self.machine.state.set(self.machine.next.get());
+    }
+}
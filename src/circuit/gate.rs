@@ -1,21 +1,96 @@
 use std::rc::Rc;
+use std::cell::Cell;
 use std::{cell::RefCell, fmt::Debug};
 use std::any::Any;
+use std::collections::{HashMap, HashSet};
 
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::netlist::{GateKind, Saver};
+use crate::circuit::wire::Wire;
+
+pub type GateRef = Rc<RefCell<dyn Gate>>;
+
+/// Wraps a gate for sharing as an input of others.
+pub(crate) fn gate<G: Gate + 'static>(g: G) -> GateRef {
+    Rc::new(RefCell::new(g))
+}
+
+thread_local! {
+    /// Set while `Circuit::settle` takes clock edges, see `scheduled`.
+    static SCHEDULING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` as the clock scheduler. A flip-flop read on its own takes a
+/// clock edge in `eval`; inside `f` it leaves the edge to `clock_edge`, so
+/// a gate reading its output meanwhile cannot consume that edge.
+pub(crate) fn scheduled<R>(f: impl FnOnce() -> R) -> R {
+    let outer = SCHEDULING.replace(true);
+    let result = f();
+    SCHEDULING.set(outer);
+    result
+}
+
+/// Reads `line` and remembers the level in `last`; true on a Low to High
+/// change since the previous call.
+pub(crate) fn rising(line: &GateRef, last: &RefCell<Signal>) -> bool {
+    let level = line.borrow().eval();
+    let prev = last.replace(level);
+    prev == Signal::Low && level == Signal::High
+}
+
+/// Reads `levels` as an unsigned number, bit 0 first. `None` if any of them
+/// is not a clean 0/1.
+pub fn pack(levels: &[Signal]) -> Option<u64> {
+    levels.iter().enumerate().try_fold(0u64, |acc, (i, s)| match s {
+        Signal::High => Some(acc | 1 << i),
+        Signal::Low => Some(acc),
+        _ => None,
+    })
+}
+
+/// The levels on `bus` as a number, see `pack`.
+pub(crate) fn bus_value(bus: &[GateRef]) -> Option<u64> {
+    pack(&bus.iter().map(|g| g.borrow().eval()).collect::<Vec<_>>())
+}
 
 pub trait Gate: Debug{
     fn eval(&self) -> Signal;
     fn description(&self) -> String;
 
     fn as_any(&mut self) -> &mut dyn Any;
+
+    /// Gates this one reads from, used to walk the circuit graph.
+    fn inputs(&self) -> Vec<GateRef> { Vec::new() }
+
+    // Clocked gates are updated by `Circuit::step` in three passes so every
+    // register sees the values from before the edge:
+    // `clock_edge` reads the clock and reports an active edge,
+    // `capture` samples the data inputs, `commit` exposes the new state.
+    fn clock_edge(&self) -> bool { false }
+    fn capture(&self) {}
+    fn commit(&self) {}
+
+    /// Moves a time source (clock, pattern generator, ...) on by one
+    /// simulation step. Called by `Circuit::step` before clocked gates settle.
+    fn advance(&self) {}
+
+    /// Pull resistors drive weakly: any other driver on the same `Wire` overrides them.
+    fn is_weak(&self) -> bool { false }
+
+    /// This gate with its state for `Circuit::save`, inputs named through
+    /// `cx`. `None` for gates that cannot be saved, such as closures.
+    fn to_netlist(&self, _cx: &mut Saver) -> Option<GateKind> { None }
 }
 
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Signal {
     Low,
     High,
     HiZ,
+    /// Unknown, e.g. two drivers fighting on one net.
+    X,
 }
 
 
@@ -59,6 +134,20 @@ pub struct TriStateGate{
     enable: Rc<RefCell<dyn Gate>>,
 }
 
+/// Weak driver holding a `Wire` at `level` while nothing else drives it.
+#[derive(Debug)]
+pub struct PullResistor {
+    level: Signal,
+}
+
+/// Open-drain (open-collector) output of `input`: pulls low when `input` is
+/// low and lets go (`HiZ`) when it is high. Several of them on one `Wire`
+/// with a pull-up form a wired-AND.
+#[derive(Debug)]
+pub struct OpenDrainGate {
+    input: Rc<RefCell<dyn Gate>>,
+}
+
 #[derive(Debug)]
 pub struct InputGate {
     signal: bool,
@@ -136,6 +225,7 @@ pub struct Dflipflop {
     clk: Rc<RefCell<dyn Gate>>,
     state: RefCell<Signal>,
     last_clk: RefCell<Signal>,
+    next: RefCell<Signal>,
 }
 
 #[derive(Debug)]
@@ -143,6 +233,27 @@ pub struct ClockGate{
     state: RefCell<Signal>,
 }
 
+/// Segment patterns for 0-F, bit 0 is segment `a` through bit 6 for `g`.
+pub const SEVEN_SEG_FONT: [u8; 16] = [
+    0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07,
+    0x7F, 0x6F, 0x77, 0x7C, 0x39, 0x5E, 0x79, 0x71,
+];
+
+/// One output of a `SevenSegDecoder`.
+#[derive(Debug)]
+pub struct SegmentGate {
+    inputs: [Rc<RefCell<dyn Gate>>; 4],
+    segment: usize,
+    hex: bool,
+}
+
+/// BCD or hex to seven-segment decoder. Inputs are bit 0 first,
+/// `segments` are `a` to `g`. In BCD mode 10-15 blank the display.
+#[derive(Debug)]
+pub struct SevenSegDecoder {
+    pub segments: [Rc<RefCell<dyn Gate>>; 7],
+}
+
 
 
 impl Signal {
@@ -153,8 +264,8 @@ impl Signal {
         match self {
             Signal::High => Signal::Low,
             Signal::Low => Signal::High,
-            Signal::HiZ => Signal::HiZ
-            
+            Signal::HiZ => Signal::HiZ,
+            Signal::X => Signal::X,
         }
     }
 }
@@ -175,6 +286,7 @@ impl SwitchGate {
 }
 
 impl ButtonGate {
+    pub(crate) fn restore(pressed: bool) -> Self { Self { input: pressed } }
     pub fn press(&mut self)  { self.input = true; }
     pub fn release(&mut self){ self.input = false; }
 }
@@ -193,6 +305,15 @@ impl TriStateGate {
     }
 }
 
+impl PullResistor {
+    pub fn up() -> Self { Self { level: Signal::High } }
+    pub fn down() -> Self { Self { level: Signal::Low } }
+}
+
+impl OpenDrainGate {
+    pub fn new(input: Rc<RefCell<dyn Gate>>) -> Self { Self { input } }
+}
+
 impl InputGate {
     pub fn new(s: bool) -> Self{
         Self {signal: s}
@@ -274,25 +395,69 @@ impl FullAdder {
 
 impl SRLatch{
     pub fn new(set: Rc<RefCell<dyn Gate>>, reset: Rc<RefCell<dyn Gate>>) -> Self {
-        Self { set: (set), reset: (reset), last_q: (RefCell::new(Signal::Low)) }
+        Self::restore(set, reset, Signal::Low)
+    }
+
+    pub(crate) fn restore(set: GateRef, reset: GateRef, q: Signal) -> Self {
+        Self { set, reset, last_q: RefCell::new(q) }
     }
 }
 
 impl Dlatch {
     pub fn new(d: Rc<RefCell<dyn Gate>>, enable: Rc<RefCell<dyn Gate>>) -> Self {
-        Self { d: (d), enable: (enable), state: (RefCell::new(Signal::Low)) }
+        Self::restore(d, enable, Signal::Low)
+    }
+
+    pub(crate) fn restore(d: GateRef, enable: GateRef, q: Signal) -> Self {
+        Self { d, enable, state: RefCell::new(q) }
     }
 }
 
 impl Dflipflop {
     pub fn new(d: Rc<RefCell<dyn Gate>>, clk: Rc<RefCell<dyn Gate>>) -> Self {
-        Self { d: (d), clk: (clk), state: (RefCell::new(Signal::Low)), last_clk: (RefCell::new(Signal::Low)) }
+        Self::with_state(d, clk, Signal::Low)
+    }
+
+    /// Flip-flop holding `state` until the first clock edge.
+    pub fn with_state(d: Rc<RefCell<dyn Gate>>, clk: Rc<RefCell<dyn Gate>>, state: Signal) -> Self {
+        Self::restore(d, clk, state, Signal::Low)
+    }
+
+    /// Flip-flop as saved by `Circuit::save`, with the clock level it last saw.
+    pub(crate) fn restore(d: GateRef, clk: GateRef, state: Signal, last_clk: Signal) -> Self {
+        Self { d, clk, state: RefCell::new(state), last_clk: RefCell::new(last_clk), next: RefCell::new(state) }
+    }
+}
+
+impl SegmentGate {
+    pub fn new(inputs: [Rc<RefCell<dyn Gate>>; 4], segment: usize, hex: bool) -> Self {
+        assert!(segment < 7, "segments are numbered 0 (a) to 6 (g)");
+        Self { inputs, segment, hex }
+    }
+}
+
+impl SevenSegDecoder {
+    pub fn new(inputs: [Rc<RefCell<dyn Gate>>; 4], hex: bool) -> Self {
+        let segments = std::array::from_fn(|seg| {
+            Rc::new(RefCell::new(SegmentGate::new(inputs.clone(), seg, hex))) as Rc<RefCell<dyn Gate>>
+        });
+        Self { segments }
+    }
+}
+
+impl Default for ClockGate {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl ClockGate {
     pub fn new() -> Self {
-        Self { state: RefCell::new(Signal::Low) }
+        Self::restore(Signal::Low)
+    }
+
+    pub(crate) fn restore(level: Signal) -> Self {
+        Self { state: RefCell::new(level) }
     }
 
     pub fn tick(&self) {
@@ -301,10 +466,94 @@ impl ClockGate {
     }
 }
 
+/// Folds `inputs` into a balanced tree of `AndGate`s.
+/// An empty slice yields `Const 1`.
+pub fn and_tree(inputs: &[GateRef]) -> GateRef {
+    match inputs.len() {
+        0 => Rc::new(RefCell::new(ConstGate::new(Signal::High))),
+        1 => inputs[0].clone(),
+        n => {
+            let (l, r) = inputs.split_at(n / 2);
+            Rc::new(RefCell::new(AndGate::new(and_tree(l), and_tree(r))))
+        }
+    }
+}
+
+/// Folds `inputs` into a balanced tree of `OrGate`s.
+/// An empty slice yields `Const 0`.
+pub fn or_tree(inputs: &[GateRef]) -> GateRef {
+    match inputs.len() {
+        0 => Rc::new(RefCell::new(ConstGate::new(Signal::Low))),
+        1 => inputs[0].clone(),
+        n => {
+            let (l, r) = inputs.split_at(n / 2);
+            Rc::new(RefCell::new(OrGate::new(or_tree(l), or_tree(r))))
+        }
+    }
+}
+
+fn gate_key(g: &GateRef) -> usize {
+    Rc::as_ptr(g) as *const () as usize
+}
+
+fn is_logic(g: &GateRef) -> bool {
+    let has_inputs = !g.borrow().inputs().is_empty();
+    has_inputs && !g.borrow_mut().as_any().is::<Wire>()
+}
+
+/// Every distinct gate reachable from `roots` through `Gate::inputs`.
+pub fn reachable(roots: &[GateRef]) -> Vec<GateRef> {
+    let mut seen = HashSet::new();
+    let mut stack: Vec<GateRef> = roots.to_vec();
+    let mut found = Vec::new();
+
+    while let Some(g) = stack.pop() {
+        if !seen.insert(gate_key(&g)) {
+            continue;
+        }
+        stack.extend(g.borrow().inputs());
+        found.push(g);
+    }
+    found
+}
+
+/// Number of distinct logic gates (anything with inputs, except `Wire`)
+/// reachable from `outputs`.
+pub fn gate_count(outputs: &[GateRef]) -> usize {
+    reachable(outputs).iter().filter(|g| is_logic(g)).count()
+}
+
+/// Longest chain of logic gates from any source to one of `outputs`.
+/// Wires add no depth and feedback edges are cut where they close a loop.
+pub fn logic_depth(outputs: &[GateRef]) -> usize {
+    fn visit(g: &GateRef, memo: &mut HashMap<usize, usize>, on_path: &mut HashSet<usize>) -> usize {
+        let key = gate_key(g);
+        if let Some(&d) = memo.get(&key) {
+            return d;
+        }
+        if !on_path.insert(key) {
+            return 0;
+        }
+
+        let inputs = g.borrow().inputs();
+        let below = inputs.iter().map(|i| visit(i, memo, on_path)).max().unwrap_or(0);
+        let depth = below + usize::from(is_logic(g));
+
+        on_path.remove(&key);
+        memo.insert(key, depth);
+        depth
+    }
+
+    let mut memo = HashMap::new();
+    let mut on_path = HashSet::new();
+    outputs.iter().map(|g| visit(g, &mut memo, &mut on_path)).max().unwrap_or(0)
+}
+
 #[inline]
 pub fn and(a: Signal, b: Signal) -> Signal {
     match (a,b) {
         (Signal::High, Signal::High) => Signal::High,
+        (Signal::X, _) | (_, Signal::X) => Signal::X,
         (Signal::HiZ, _) | (_, Signal::HiZ) => Signal::HiZ,
         _ => Signal::Low,
     }
@@ -314,6 +563,7 @@ pub fn and(a: Signal, b: Signal) -> Signal {
 pub fn or(a: Signal, b: Signal) -> Signal {
     match (a,b) {
         (Signal::Low, Signal::Low) => Signal::Low,
+        (Signal::X, _) | (_, Signal::X) => Signal::X,
         (Signal::HiZ, _) | (_, Signal::HiZ) => Signal::HiZ,
         _ => Signal::High,
     }
@@ -323,6 +573,7 @@ pub fn or(a: Signal, b: Signal) -> Signal {
 pub fn xor(a: Signal, b: Signal) -> Signal {
     use Signal::*;
     match (a,b) {
+        (X, _) | (_, X) => X,
         (HiZ, _) | (_, HiZ) => HiZ,
         (High, High) | (Low, Low) => Low,
         _ => High,
@@ -340,6 +591,7 @@ impl Gate for SwitchGate {
         format!("Switch {}", self.level)
     }
     fn as_any(&mut self) -> &mut dyn Any { self }
+    fn to_netlist(&self, _: &mut Saver) -> Option<GateKind> { Some(GateKind::Switch { level: self.level }) }
 }
 
 impl Gate for ButtonGate {
@@ -349,6 +601,7 @@ impl Gate for ButtonGate {
 
     fn description(&self) -> String { "Button".into()}
     fn as_any(&mut self) -> &mut dyn Any { self }
+    fn to_netlist(&self, _: &mut Saver) -> Option<GateKind> { Some(GateKind::Button { pressed: self.input }) }
 }
 
 // impl Gate for LowConstGate {
@@ -370,22 +623,21 @@ impl Gate for BufferGate {
     }
 
     fn as_any(&mut self) -> &mut dyn Any { self }
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> { Some(GateKind::Buffer { input: cx.name(&self.input) }) }
+    fn inputs(&self) -> Vec<GateRef> { vec![self.input.clone()] }
 }
 
 impl Gate for XnorGate {
     fn eval(&self) -> Signal {
-        let s = xor(self.signal_one.borrow().eval(), self.signal_two.borrow().eval());
-        match s {
-            Signal::High => Signal::Low,
-            Signal::Low => Signal::High,
-            Signal::HiZ => Signal::HiZ,
-        }
+        xor(self.signal_one.borrow().eval(), self.signal_two.borrow().eval()).invert()
     }
 
     fn description(&self) -> String {
         format!("Xnor({},{})", self.signal_one.borrow().description(), self.signal_two.borrow().description())
     }
     fn as_any(&mut self) -> &mut dyn Any { self }
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> { Some(GateKind::Xnor { a: cx.name(&self.signal_one), b: cx.name(&self.signal_two) }) }
+    fn inputs(&self) -> Vec<GateRef> { vec![self.signal_one.clone(), self.signal_two.clone()] }
 }
 
 impl Gate for TriStateGate {
@@ -400,6 +652,33 @@ impl Gate for TriStateGate {
         format!("TriStateGate({},{})", self.input.borrow().description(), self.enable.borrow().description())
     }
     fn as_any(&mut self) -> &mut dyn Any { self }
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> { Some(GateKind::TriState { input: cx.name(&self.input), enable: cx.name(&self.enable) }) }
+    fn inputs(&self) -> Vec<GateRef> { vec![self.input.clone(), self.enable.clone()] }
+}
+
+impl Gate for PullResistor {
+    fn eval(&self) -> Signal { self.level }
+    fn description(&self) -> String {
+        if self.level.is_high() { "PullUp".into() } else { "PullDown".into() }
+    }
+    fn as_any(&mut self) -> &mut dyn Any { self }
+    fn to_netlist(&self, _: &mut Saver) -> Option<GateKind> { Some(GateKind::Pull { level: self.level }) }
+    fn is_weak(&self) -> bool { true }
+}
+
+impl Gate for OpenDrainGate {
+    fn eval(&self) -> Signal {
+        match self.input.borrow().eval() {
+            Signal::High => Signal::HiZ,
+            other => other,
+        }
+    }
+    fn description(&self) -> String {
+        format!("OpenDrain({})", self.input.borrow().description())
+    }
+    fn as_any(&mut self) -> &mut dyn Any { self }
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> { Some(GateKind::OpenDrain { input: cx.name(&self.input) }) }
+    fn inputs(&self) -> Vec<GateRef> { vec![self.input.clone()] }
 }
 
 impl Gate for ConstGate {
@@ -409,9 +688,11 @@ impl Gate for ConstGate {
             Signal::High => "Const 1".into(),
             Signal::Low  => "Const 0".into(),
             Signal::HiZ  => "Const Z".into(),
+            Signal::X    => "Const X".into(),
         }
     }
     fn as_any(&mut self) -> &mut dyn Any { self }
+    fn to_netlist(&self, _: &mut Saver) -> Option<GateKind> { Some(GateKind::Const { level: self.level }) }
 }
 
 impl Gate for InputGate{
@@ -426,6 +707,10 @@ impl Gate for InputGate{
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, _: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Input { value: self.signal })
+    }
 }
 
 impl Gate for OutputGate {
@@ -440,6 +725,14 @@ impl Gate for OutputGate {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Output { input: cx.name(&self.input) })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.input.clone()]
+    }
 }
 
 impl Gate for AndGate {
@@ -456,6 +749,14 @@ impl Gate for AndGate {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::And { a: cx.name(&self.signal_one), b: cx.name(&self.signal_two) })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.signal_one.clone(), self.signal_two.clone()]
+    }
 }
 
 impl Gate for OrGate {
@@ -472,6 +773,14 @@ impl Gate for OrGate {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Or { a: cx.name(&self.signal_one), b: cx.name(&self.signal_two) })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.signal_one.clone(), self.signal_two.clone()]
+    }
 }
 
 impl Gate for NotGate {
@@ -487,6 +796,14 @@ impl Gate for NotGate {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Not { input: cx.name(&self.signal) })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.signal.clone()]
+    }
 }
 
 impl Gate for XorGate {
@@ -503,6 +820,14 @@ impl Gate for XorGate {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Xor { a: cx.name(&self.signal_one), b: cx.name(&self.signal_two) })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.signal_one.clone(), self.signal_two.clone()]
+    }
 }
 
 impl Gate for NorGate {
@@ -519,6 +844,14 @@ impl Gate for NorGate {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Nor { a: cx.name(&self.signal_one), b: cx.name(&self.signal_two) })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.signal_one.clone(), self.signal_two.clone()]
+    }
 }
 
 impl Gate for NandGate {
@@ -535,6 +868,14 @@ impl Gate for NandGate {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Nand { a: cx.name(&self.signal_one), b: cx.name(&self.signal_two) })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.signal_one.clone(), self.signal_two.clone()]
+    }
 }
 
 impl Gate for SRLatch {
@@ -563,6 +904,14 @@ impl Gate for SRLatch {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::SrLatch { set: cx.name(&self.set), reset: cx.name(&self.reset), q: *self.last_q.borrow() })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.set.clone(), self.reset.clone()]
+    }
 }
 
 impl Gate for Dlatch {
@@ -583,17 +932,24 @@ impl Gate for Dlatch {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::DLatch { d: cx.name(&self.d), enable: cx.name(&self.enable), q: *self.state.borrow() })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.d.clone(), self.enable.clone()]
+    }
 }
 
 impl Gate for Dflipflop {
     fn eval(&self) -> Signal {
-        let clk_val = self.clk.borrow().eval();
-        let last_clk = *self.last_clk.borrow();
-
-        if last_clk == Signal::Low && clk_val == Signal::High {
-            *self.state.borrow_mut() = self.d.borrow().eval();
+        // remember the clock before reading `d`, which may loop back here;
+        // under `Circuit::settle` the edge is left to `clock_edge`
+        if !SCHEDULING.get() && self.clock_edge() {
+            let d = self.d.borrow().eval();
+            *self.state.borrow_mut() = d;
         }
-        *self.last_clk.borrow_mut() = clk_val;
         *self.state.borrow()
     }
 
@@ -606,6 +962,26 @@ impl Gate for Dflipflop {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::DFlipFlop { d: cx.name(&self.d), clk: cx.name(&self.clk), q: *self.state.borrow(), last_clk: *self.last_clk.borrow() })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.d.clone(), self.clk.clone()]
+    }
+
+    fn clock_edge(&self) -> bool {
+        rising(&self.clk, &self.last_clk)
+    }
+
+    fn capture(&self) {
+        *self.next.borrow_mut() = self.d.borrow().eval();
+    }
+
+    fn commit(&self) {
+        *self.state.borrow_mut() = *self.next.borrow();
+    }
 }
 
 impl Gate for ClockGate {
@@ -623,4 +999,46 @@ impl Gate for ClockGate {
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
-}
\ No newline at end of file
+
+    fn to_netlist(&self, _: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Clock { level: *self.state.borrow() })
+    }
+
+    fn advance(&self) {
+        self.tick();
+    }
+}
+
+impl Gate for SegmentGate {
+    fn eval(&self) -> Signal {
+        let mut value = 0;
+        for (i, input) in self.inputs.iter().enumerate() {
+            match input.borrow().eval() {
+                Signal::High => value |= 1 << i,
+                Signal::Low => {}
+                other => return other,
+            }
+        }
+
+        if !self.hex && value > 9 {
+            return Signal::Low;
+        }
+        if SEVEN_SEG_FONT[value] >> self.segment & 1 == 1 { Signal::High } else { Signal::Low }
+    }
+
+    fn description(&self) -> String {
+        format!("Segment{}({})", (b'a' + self.segment as u8) as char, if self.hex { "hex" } else { "bcd" })
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Segment { inputs: self.inputs.each_ref().map(|g| cx.name(g)), segment: self.segment, hex: self.hex })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        self.inputs.to_vec()
+    }
+}
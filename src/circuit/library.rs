@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::subcircuit::SubcircuitDef;
+
+/// A file of subcircuit definitions shared between projects, e.g.
+///
+/// ```json
+/// { "name": "arith", "version": "1.2.0", "description": "Adders",
+///   "components": [ { "description": "1-bit half adder",
+///                     "ports": { "a": "addend", "sum": "a xor b" },
+///                     "definition": { "name": "half_adder", ... } } ] }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Library {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub components: Vec<Component>,
+}
+
+/// One definition of a library with its documentation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Component {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// Text per port, keyed by the port name as written in the definition
+    /// (`"d{0..W}"` documents the whole bus).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ports: BTreeMap<String, String>,
+    pub definition: SubcircuitDef,
+}
+
+impl Library {
+    pub fn new(name: &str, version: &str, description: &str) -> Self {
+        Self { name: name.into(), version: version.into(), description: description.into(), components: Vec::new() }
+    }
+
+    /// Adds `def` documented by `description` and `(port, text)` pairs.
+    pub fn component(mut self, def: SubcircuitDef, description: &str, ports: &[(&str, &str)]) -> Self {
+        self.components.push(Component {
+            description: description.into(),
+            ports: ports.iter().map(|(p, d)| (p.to_string(), d.to_string())).collect(),
+            definition: def,
+        });
+        self
+    }
+
+    /// The standard subcircuits as a library.
+    pub fn standard() -> Self {
+        Self::new("standard", env!("CARGO_PKG_VERSION"), "Subcircuits built into the simulator")
+            .component(SubcircuitDef::half_adder(), "1-bit half adder", &[("sum", "a xor b"), ("carry", "a and b")])
+            .component(SubcircuitDef::full_adder(), "1-bit full adder of two half adders", &[("cin", "carry in"), ("cout", "carry out")])
+            .component(
+                SubcircuitDef::register(),
+                "W-bit register with load enable",
+                &[("d{0..W}", "data in"), ("en", "load on the next rising clk edge"), ("q{0..W}", "stored value")],
+            )
+            .component(SubcircuitDef::parity(), "N-input xor tree", &[("odd", "high for an odd number of high inputs")])
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.version.trim().is_empty() {
+            return Err("library needs a name and a version".into());
+        }
+        for (i, c) in self.components.iter().enumerate() {
+            let def = &c.definition;
+            def.check()?;
+            if self.components[..i].iter().any(|o| o.definition.name == def.name) {
+                return Err(format!("'{}' defined twice in library '{}'", def.name, self.name));
+            }
+            if let Some(port) = c.ports.keys().find(|p| !def.inputs.contains(p) && !def.outputs.contains(p)) {
+                return Err(format!("'{}' documents unknown port '{port}'", def.name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Component> {
+        self.components.iter().find(|c| c.definition.name == name)
+    }
+
+    /// Names of the definitions in file order.
+    pub fn names(&self) -> Vec<&str> {
+        self.components.iter().map(|c| c.definition.name.as_str()).collect()
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let lib: Self = serde_json::from_str(text).context("parsing library")?;
+        lib.check().map_err(anyhow::Error::msg)?;
+        Ok(lib)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("loading {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text).with_context(|| format!("saving {}", path.display()))
+    }
+}
+
+impl Component {
+    /// Description followed by one line per documented port, for tooltips.
+    pub fn help(&self) -> String {
+        let mut text = self.description.clone();
+        for (port, doc) in &self.ports {
+            text.push_str(&format!("\n{port}: {doc}"));
+        }
+        text
+    }
+}
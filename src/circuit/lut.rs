@@ -0,0 +1,175 @@
+use std::any::Any;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::gate::*;
+use crate::circuit::netlist::{GateKind, Saver};
+
+/// Largest LUT, as in common FPGAs; its table fits in a `u64`.
+pub const MAX_LUT_INPUTS: usize = 6;
+
+/// Truth table of a `k`-input function. Bit `row` of `mask` is the output
+/// for the input combination `row`, input 0 being bit 0 of `row`.
+/// Serializes as its output column, e.g. `"0110"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct TruthTable {
+    inputs: usize,
+    mask: u64,
+}
+
+/// k-input gate computing any function given by a `TruthTable`.
+#[derive(Debug)]
+pub struct LutGate {
+    inputs: Vec<GateRef>,
+    table: TruthTable,
+}
+
+impl TruthTable {
+    pub fn new(inputs: usize, mask: u64) -> Result<Self, String> {
+        if inputs > MAX_LUT_INPUTS {
+            return Err(format!("a LUT has at most {MAX_LUT_INPUTS} inputs, got {inputs}"));
+        }
+        let rows = 1u128 << inputs;
+        if (mask as u128) >= 1 << rows {
+            return Err(format!("mask {mask:#x} has more than {rows} rows"));
+        }
+        Ok(Self { inputs, mask })
+    }
+
+    /// Table of `f` over every row.
+    pub fn from_fn(inputs: usize, f: impl Fn(usize) -> bool) -> Result<Self, String> {
+        let mut table = Self::new(inputs, 0)?;
+        for row in 0..table.rows() {
+            table.set(row, f(row));
+        }
+        Ok(table)
+    }
+
+    /// Parses the output column, row 0 first, e.g. `"0001"` for a 2-input AND.
+    /// Spaces and `_` are ignored.
+    pub fn parse(column: &str) -> Result<Self, String> {
+        let bits: Vec<bool> = column
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '_')
+            .map(|c| match c {
+                '0' => Ok(false),
+                '1' => Ok(true),
+                _ => Err(format!("invalid truth table character '{c}'")),
+            })
+            .collect::<Result<_, _>>()?;
+        if !bits.len().is_power_of_two() {
+            return Err(format!("{} rows is not a power of two", bits.len()));
+        }
+        let inputs = bits.len().trailing_zeros() as usize;
+        Self::from_fn(inputs, |row| bits[row])
+    }
+
+    pub fn inputs(&self) -> usize { self.inputs }
+    pub fn rows(&self) -> usize { 1 << self.inputs }
+    pub fn mask(&self) -> u64 { self.mask }
+
+    pub fn get(&self, row: usize) -> bool {
+        self.mask >> row & 1 == 1
+    }
+
+    pub fn set(&mut self, row: usize, value: bool) {
+        assert!(row < self.rows(), "row {row} out of range");
+        if value { self.mask |= 1 << row } else { self.mask &= !(1 << row) }
+    }
+
+    /// Output for `inputs`, bit 0 first. Inputs that are `HiZ` or `X` may
+    /// be either level; the result is `X` unless every choice agrees.
+    pub fn eval(&self, inputs: &[Signal]) -> Signal {
+        let mut row = 0;
+        let mut unknown = 0;
+        for (i, s) in inputs.iter().enumerate() {
+            match s {
+                Signal::High => row |= 1 << i,
+                Signal::Low => {}
+                _ => unknown |= 1 << i,
+            }
+        }
+
+        let first = self.get(row);
+        // walk every subset of the unknown bits
+        let mut sub: usize = unknown;
+        while sub != 0 {
+            if self.get(row | sub) != first {
+                return Signal::X;
+            }
+            sub = (sub - 1) & unknown;
+        }
+        if first { Signal::High } else { Signal::Low }
+    }
+}
+
+impl fmt::Display for TruthTable {
+    /// The output column, row 0 first (the format `parse` reads).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in 0..self.rows() {
+            f.write_str(if self.get(row) { "1" } else { "0" })?;
+        }
+        Ok(())
+    }
+}
+
+impl From<TruthTable> for String {
+    fn from(table: TruthTable) -> Self {
+        table.to_string()
+    }
+}
+
+impl TryFrom<String> for TruthTable {
+    type Error = String;
+
+    fn try_from(column: String) -> Result<Self, String> {
+        Self::parse(&column)
+    }
+}
+
+impl LutGate {
+    pub fn new(inputs: Vec<GateRef>, table: TruthTable) -> Result<Self, String> {
+        if inputs.len() != table.inputs() {
+            return Err(format!("LUT table has {} inputs, got {} gates", table.inputs(), inputs.len()));
+        }
+        Ok(Self { inputs, table })
+    }
+
+    pub fn table(&self) -> TruthTable {
+        self.table
+    }
+
+    /// Replaces the function; the new table must have the same number of inputs.
+    pub fn set_table(&mut self, table: TruthTable) -> Result<(), String> {
+        if table.inputs() != self.inputs.len() {
+            return Err(format!("LUT has {} inputs, table has {}", self.inputs.len(), table.inputs()));
+        }
+        self.table = table;
+        Ok(())
+    }
+}
+
+impl Gate for LutGate {
+    fn eval(&self) -> Signal {
+        let levels: Vec<Signal> = self.inputs.iter().map(|g| g.borrow().eval()).collect();
+        self.table.eval(&levels)
+    }
+
+    fn description(&self) -> String {
+        format!("Lut{}({})", self.inputs.len(), self.table)
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Lut { inputs: cx.names(&self.inputs), table: self.table })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        self.inputs.clone()
+    }
+}
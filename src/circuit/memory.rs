@@ -0,0 +1,538 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::gate::*;
+use crate::circuit::netlist::{GateKind, Loader, Saver, SharedKind};
+
+/// Word storage shared by the ports of a `Rom` or `Ram`.
+///
+/// Words are `data_width` bits wide and there are `2^addr_width` of them.
+/// `last_read`/`last_write` record the most recent access for viewers.
+#[derive(Debug, Clone)]
+pub struct MemoryCore {
+    addr_width: usize,
+    data_width: usize,
+    words: Vec<u64>,
+    pub last_read: Option<usize>,
+    pub last_write: Option<usize>,
+}
+
+/// On-disk formats understood by `MemoryCore::load_file`/`save_file`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryFormat {
+    /// Intel HEX records, byte addressed.
+    IntelHex,
+    /// Raw bytes, each word little-endian in `ceil(data_width / 8)` bytes.
+    Binary,
+    /// Whitespace separated hex words with optional `@addr` markers,
+    /// as read by Verilog's `$readmemh`.
+    HexText,
+}
+
+/// Pins of a memory block. `T` is a `GateRef` when building gates and a
+/// gate id when going through `Circuit::add_ram`.
+///
+/// Missing enables read as high. A memory without `write_enable` is read-only;
+/// with a `clock` it writes on the rising edge, otherwise whenever enabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemPins<T> {
+    pub addr: Vec<T>,
+    pub data_in: Vec<T>,
+    pub write_enable: Option<T>,
+    pub read_enable: Option<T>,
+    pub chip_select: Option<T>,
+    pub clock: Option<T>,
+}
+
+/// Read-only memory. `data` drives `HiZ` while `cs` is low.
+#[derive(Debug)]
+pub struct Rom {
+    pub data: Vec<GateRef>,
+    pub core: Rc<RefCell<MemoryCore>>,
+}
+
+/// Read/write memory. Reads are combinational; writes happen on the rising
+/// edge of `clock` (synchronous) or while enabled when there is no clock
+/// (asynchronous). `data` drives `HiZ` unless selected and read-enabled.
+#[derive(Debug)]
+pub struct Ram {
+    pub data: Vec<GateRef>,
+    pub core: Rc<RefCell<MemoryCore>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct MemShared {
+    core: Rc<RefCell<MemoryCore>>,
+    pins: MemPins<GateRef>,
+    last_clk: RefCell<Signal>,
+    pending: RefCell<Option<(usize, u64)>>,
+}
+
+/// One data output bit of a memory block.
+#[derive(Debug)]
+pub struct MemoryPort {
+    shared: Rc<MemShared>,
+    bit: usize,
+}
+
+impl<T> MemPins<T> {
+    pub fn rom(addr: Vec<T>, chip_select: Option<T>) -> Self {
+        Self { addr, data_in: Vec::new(), write_enable: None, read_enable: None, chip_select, clock: None }
+    }
+
+    pub fn map<U, E>(self, mut f: impl FnMut(T) -> Result<U, E>) -> Result<MemPins<U>, E> {
+        Ok(MemPins {
+            addr: self.addr.into_iter().map(&mut f).collect::<Result<_, _>>()?,
+            data_in: self.data_in.into_iter().map(&mut f).collect::<Result<_, _>>()?,
+            write_enable: self.write_enable.map(&mut f).transpose()?,
+            read_enable: self.read_enable.map(&mut f).transpose()?,
+            chip_select: self.chip_select.map(&mut f).transpose()?,
+            clock: self.clock.map(&mut f).transpose()?,
+        })
+    }
+}
+
+impl MemoryCore {
+    pub fn new(addr_width: usize, data_width: usize) -> Self {
+        assert!(addr_width <= 24, "address width {addr_width} is too large");
+        assert!((1..=64).contains(&data_width), "data width must be 1..=64 bits");
+        Self {
+            addr_width,
+            data_width,
+            words: vec![0; 1 << addr_width],
+            last_read: None,
+            last_write: None,
+        }
+    }
+
+    pub fn with_contents(addr_width: usize, data_width: usize, contents: &[u64]) -> Self {
+        let mut core = Self::new(addr_width, data_width);
+        for (addr, w) in contents.iter().enumerate() {
+            core.poke(addr, *w);
+        }
+        core
+    }
+
+    /// Core as saved by `Circuit::save`; missing words are zero.
+    pub(crate) fn restore(addr_width: usize, data_width: usize, words: &[u64], last_read: Option<usize>, last_write: Option<usize>) -> Result<Self, String> {
+        if addr_width > 24 || !(1..=64).contains(&data_width) {
+            return Err(format!("{addr_width}-bit address or {data_width}-bit data out of range"));
+        }
+        let mut core = Self::new(addr_width, data_width);
+        if words.len() > core.len() || words.iter().any(|w| *w > core.mask()) {
+            return Err(format!("contents do not fit {} words of {data_width} bits", core.len()));
+        }
+        core.words[..words.len()].copy_from_slice(words);
+        core.last_read = last_read.filter(|a| *a < core.len());
+        core.last_write = last_write.filter(|a| *a < core.len());
+        Ok(core)
+    }
+
+    pub(crate) fn to_netlist(&self) -> SharedKind {
+        let used = self.words.iter().rposition(|w| *w != 0).map_or(0, |i| i + 1);
+        SharedKind::Core {
+            addr_width: self.addr_width,
+            data_width: self.data_width,
+            words: self.words[..used].to_vec(),
+            last_read: self.last_read,
+            last_write: self.last_write,
+        }
+    }
+
+    pub fn addr_width(&self) -> usize { self.addr_width }
+    pub fn data_width(&self) -> usize { self.data_width }
+    pub fn len(&self) -> usize { self.words.len() }
+    pub fn is_empty(&self) -> bool { self.words.is_empty() }
+    pub fn words(&self) -> &[u64] { &self.words }
+
+    fn mask(&self) -> u64 {
+        if self.data_width == 64 { u64::MAX } else { (1 << self.data_width) - 1 }
+    }
+
+    fn bytes_per_word(&self) -> usize {
+        self.data_width.div_ceil(8)
+    }
+
+    /// Reads a word without recording the access.
+    pub fn peek(&self, addr: usize) -> u64 {
+        self.words[addr % self.words.len()]
+    }
+
+    /// Writes a word without recording the access.
+    pub fn poke(&mut self, addr: usize, value: u64) {
+        let len = self.words.len();
+        let mask = self.mask();
+        self.words[addr % len] = value & mask;
+    }
+
+    pub fn read(&mut self, addr: usize) -> u64 {
+        self.last_read = Some(addr % self.words.len());
+        self.peek(addr)
+    }
+
+    pub fn write(&mut self, addr: usize, value: u64) {
+        self.last_write = Some(addr % self.words.len());
+        self.poke(addr, value);
+    }
+
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|w| *w = 0);
+    }
+
+    /// Fills words from a little-endian byte image starting at byte `offset`.
+    fn load_bytes_at(&mut self, offset: usize, bytes: &[u8]) -> anyhow::Result<()> {
+        let bpw = self.bytes_per_word();
+        let capacity = self.words.len() * bpw;
+        if offset + bytes.len() > capacity {
+            bail!("image ends at byte {:#x}, memory holds {:#x} bytes", offset + bytes.len(), capacity);
+        }
+        for (i, b) in bytes.iter().enumerate() {
+            let pos = offset + i;
+            let (addr, shift) = (pos / bpw, (pos % bpw) * 8);
+            let word = (self.words[addr] & !(0xFF << shift)) | (*b as u64) << shift;
+            self.poke(addr, word);
+        }
+        Ok(())
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let bpw = self.bytes_per_word();
+        self.words.iter().flat_map(|w| w.to_le_bytes().into_iter().take(bpw)).collect()
+    }
+
+    pub fn load_binary(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.load_bytes_at(0, bytes)
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    pub fn load_intel_hex(&mut self, text: &str) -> anyhow::Result<()> {
+        let mut base = 0usize;
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let ctx = || format!("Intel HEX line {}", n + 1);
+
+            let Some(hex) = line.strip_prefix(':') else {
+                bail!("{}: record does not start with ':'", ctx());
+            };
+            if hex.len() % 2 != 0 || hex.len() < 10 {
+                bail!("{}: malformed record", ctx());
+            }
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .with_context(ctx)?;
+
+            let count = bytes[0] as usize;
+            if bytes.len() != count + 5 {
+                bail!("{}: byte count {} does not match record length", ctx(), count);
+            }
+            if bytes.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)) != 0 {
+                bail!("{}: checksum mismatch", ctx());
+            }
+
+            let offset = (bytes[1] as usize) << 8 | bytes[2] as usize;
+            let data = &bytes[4..4 + count];
+            match bytes[3] {
+                0x00 => self.load_bytes_at(base + offset, data).with_context(ctx)?,
+                0x01 => return Ok(()),
+                0x02 | 0x04 if count != 2 => bail!("{}: extended address record needs 2 data bytes", ctx()),
+                0x02 => base = ((data[0] as usize) << 8 | data[1] as usize) << 4,
+                0x04 => base = ((data[0] as usize) << 8 | data[1] as usize) << 16,
+                0x03 | 0x05 => {}
+                t => bail!("{}: unsupported record type {:02X}", ctx(), t),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_intel_hex(&self) -> String {
+        fn record(out: &mut String, kind: u8, offset: u16, data: &[u8]) {
+            let mut bytes = vec![data.len() as u8, (offset >> 8) as u8, offset as u8, kind];
+            bytes.extend_from_slice(data);
+            let sum = bytes.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+            bytes.push(sum.wrapping_neg());
+
+            out.push(':');
+            for b in bytes {
+                let _ = write!(out, "{b:02X}");
+            }
+            out.push('\n');
+        }
+
+        let mut out = String::new();
+        let bytes = self.to_bytes();
+        let mut upper = 0usize;
+        for (i, chunk) in bytes.chunks(16).enumerate() {
+            let addr = i * 16;
+            if addr >> 16 != upper {
+                upper = addr >> 16;
+                record(&mut out, 0x04, 0, &[(upper >> 8) as u8, upper as u8]);
+            }
+            record(&mut out, 0x00, addr as u16, chunk);
+        }
+        record(&mut out, 0x01, 0, &[]);
+        out
+    }
+
+    pub fn load_hex_text(&mut self, text: &str) -> anyhow::Result<()> {
+        let mut addr = 0usize;
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or("");
+            let line = line.split('#').next().unwrap_or("");
+
+            for token in line.split_whitespace() {
+                let ctx = || format!("hex text line {}: '{}'", n + 1, token);
+                if let Some(a) = token.strip_prefix('@') {
+                    addr = usize::from_str_radix(a, 16).with_context(ctx)?;
+                    continue;
+                }
+                if addr >= self.words.len() {
+                    bail!("{}: address {:#x} is out of range", ctx(), addr);
+                }
+                let word = u64::from_str_radix(&token.replace('_', ""), 16).with_context(ctx)?;
+                if word > self.mask() {
+                    bail!("{}: value does not fit in {} bits", ctx(), self.data_width);
+                }
+                self.poke(addr, word);
+                addr += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_hex_text(&self) -> String {
+        let digits = self.data_width.div_ceil(4);
+        let mut out = String::new();
+        for (i, row) in self.words.chunks(8).enumerate() {
+            let _ = write!(out, "@{:x}", i * 8);
+            for w in row {
+                let _ = write!(out, " {w:0digits$x}");
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn load_file(&mut self, path: impl AsRef<Path>, format: MemoryFormat) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let ctx = || format!("loading {}", path.display());
+        match format {
+            MemoryFormat::Binary => {
+                let bytes = std::fs::read(path).with_context(ctx)?;
+                self.load_binary(&bytes).with_context(ctx)
+            }
+            MemoryFormat::IntelHex => {
+                let text = std::fs::read_to_string(path).with_context(ctx)?;
+                self.load_intel_hex(&text).with_context(ctx)
+            }
+            MemoryFormat::HexText => {
+                let text = std::fs::read_to_string(path).with_context(ctx)?;
+                self.load_hex_text(&text).with_context(ctx)
+            }
+        }
+    }
+
+    pub fn save_file(&self, path: impl AsRef<Path>, format: MemoryFormat) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let result = match format {
+            MemoryFormat::Binary => std::fs::write(path, self.to_binary()),
+            MemoryFormat::IntelHex => std::fs::write(path, self.to_intel_hex()),
+            MemoryFormat::HexText => std::fs::write(path, self.to_hex_text()),
+        };
+        result.with_context(|| format!("saving {}", path.display()))
+    }
+}
+
+impl MemoryFormat {
+    /// Guesses the format from the file extension:
+    /// `.hex`/`.ihex`/`.ihx` are Intel HEX, `.bin`/`.rom` raw binary,
+    /// anything else hex text.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let ext = path.as_ref().extension().and_then(|e| e.to_str()).unwrap_or("");
+        match ext.to_ascii_lowercase().as_str() {
+            "hex" | "ihex" | "ihx" => MemoryFormat::IntelHex,
+            "bin" | "rom" => MemoryFormat::Binary,
+            _ => MemoryFormat::HexText,
+        }
+    }
+}
+
+impl MemShared {
+    fn enabled(pin: &Option<GateRef>) -> Option<bool> {
+        match pin.as_ref().map_or(Signal::High, |g| g.borrow().eval()) {
+            Signal::High => Some(true),
+            Signal::Low => Some(false),
+            _ => None,
+        }
+    }
+
+    fn write_request(&self) -> Option<(usize, u64)> {
+        self.pins.write_enable.as_ref()?;
+        if Self::enabled(&self.pins.chip_select)? && Self::enabled(&self.pins.write_enable)? {
+            let addr = bus_value(&self.pins.addr)? as usize;
+            let data = bus_value(&self.pins.data_in)?;
+            return Some((addr, data));
+        }
+        None
+    }
+
+    /// The address being read, `None` while the outputs float.
+    fn read_addr(&self) -> Option<usize> {
+        match Self::enabled(&self.pins.chip_select).zip(Self::enabled(&self.pins.read_enable)) {
+            Some((true, true)) => bus_value(&self.pins.addr).map(|a| a as usize),
+            _ => None,
+        }
+    }
+
+    /// A pure read of the core; writes and `last_read` are left to the
+    /// scheduler (`commit`, `advance`).
+    fn read_bit(&self, bit: usize) -> Signal {
+        match self.read_addr() {
+            Some(addr) if self.core.borrow().peek(addr) >> bit & 1 == 1 => Signal::High,
+            Some(_) => Signal::Low,
+            None => Signal::HiZ,
+        }
+    }
+
+    fn note_read(&self) {
+        if let Some(addr) = self.read_addr() {
+            self.core.borrow_mut().read(addr);
+        }
+    }
+}
+
+fn ports(core: &Rc<RefCell<MemoryCore>>, pins: MemPins<GateRef>) -> Vec<GateRef> {
+    let width = core.borrow().data_width();
+    assert_eq!(pins.addr.len(), core.borrow().addr_width(), "address bus width does not match memory");
+    if pins.write_enable.is_some() {
+        assert_eq!(pins.data_in.len(), width, "data bus width does not match memory");
+    }
+
+    let shared = Rc::new(MemShared {
+        core: core.clone(),
+        pins,
+        last_clk: RefCell::new(Signal::Low),
+        pending: RefCell::new(None),
+    });
+    (0..width)
+        .map(|bit| Rc::new(RefCell::new(MemoryPort { shared: shared.clone(), bit })) as GateRef)
+        .collect()
+}
+
+impl Rom {
+    pub fn new(core: Rc<RefCell<MemoryCore>>, addr: Vec<GateRef>, cs: Option<GateRef>) -> Self {
+        let data = ports(&core, MemPins::rom(addr, cs));
+        Self { data, core }
+    }
+}
+
+impl Ram {
+    pub fn new(core: Rc<RefCell<MemoryCore>>, pins: MemPins<GateRef>) -> Self {
+        assert!(pins.write_enable.is_some(), "RAM needs a write enable");
+        let data = ports(&core, pins);
+        Self { data, core }
+    }
+}
+
+/// Pins and clock history of a memory as saved by `Circuit::save`.
+pub(crate) fn restore_shared(cx: &mut Loader, core: usize, pins: &MemPins<String>, last_clk: Signal) -> Result<Rc<dyn Any>, String> {
+    let core = cx.shared::<RefCell<MemoryCore>>(core)?;
+    let pins = pins.clone().map(|id| cx.gate(&id))?;
+    let (addr_width, width) = (core.borrow().addr_width(), core.borrow().data_width());
+    if pins.addr.len() != addr_width || (pins.write_enable.is_some() && pins.data_in.len() != width) {
+        return Err("bus widths do not match the memory".into());
+    }
+    Ok(Rc::new(MemShared { core, pins, last_clk: RefCell::new(last_clk), pending: RefCell::new(None) }))
+}
+
+impl MemoryPort {
+    pub(crate) fn restore(shared: Rc<MemShared>, bit: usize) -> Result<Self, String> {
+        if bit >= shared.core.borrow().data_width() {
+            return Err(format!("memory has no data bit {bit}"));
+        }
+        Ok(Self { shared, bit })
+    }
+
+    pub fn core(&self) -> Rc<RefCell<MemoryCore>> {
+        self.shared.core.clone()
+    }
+}
+
+impl Gate for MemoryPort {
+    fn eval(&self) -> Signal {
+        self.shared.read_bit(self.bit)
+    }
+
+    fn description(&self) -> String {
+        let core = self.shared.core.borrow();
+        let kind = if self.shared.pins.write_enable.is_some() { "Ram" } else { "Rom" };
+        format!("{kind}({}x{}, bit {})", core.len(), core.data_width(), self.bit)
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        let s = &self.shared;
+        let memory = cx.shared(s, |cx| SharedKind::Memory {
+            core: cx.shared(&s.core, |_| s.core.borrow().to_netlist()),
+            pins: s.pins.clone().map(|g| Ok::<_, ()>(cx.name(&g))).unwrap(),
+            last_clk: *s.last_clk.borrow(),
+        });
+        Some(GateKind::MemoryPort { memory, bit: self.bit })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        let p = &self.shared.pins;
+        p.addr.iter()
+            .chain(&p.data_in)
+            .chain(&p.write_enable)
+            .chain(&p.read_enable)
+            .chain(&p.chip_select)
+            .chain(&p.clock)
+            .cloned()
+            .collect()
+    }
+
+    // every port shares one clock history, so only the first port to see
+    // an edge reports it and the write happens once; an asynchronous RAM
+    // reports one while a write would change the word
+    fn clock_edge(&self) -> bool {
+        let s = &self.shared;
+        match &s.pins.clock {
+            Some(clk) => rising(clk, &s.last_clk),
+            None => s.write_request().is_some_and(|(addr, data)| s.core.borrow().peek(addr) != data),
+        }
+    }
+
+    fn capture(&self) {
+        *self.shared.pending.borrow_mut() = self.shared.write_request();
+    }
+
+    fn commit(&self) {
+        if let Some((addr, data)) = self.shared.pending.borrow_mut().take() {
+            self.shared.core.borrow_mut().write(addr, data);
+        }
+        self.shared.note_read();
+    }
+
+    fn advance(&self) {
+        self.shared.note_read();
+    }
+}
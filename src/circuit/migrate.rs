@@ -0,0 +1,129 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::circuit::gate::Signal;
+use crate::circuit::netlist::{NetList, NETLIST_VERSION};
+
+/// JSON Schema of the current netlist format, for tools that check files
+/// without this crate.
+pub const NETLIST_SCHEMA: &str = include_str!("../../schema/netlist.schema.json");
+
+/// Upgrades a netlist by one version.
+type Step = fn(&mut Value) -> Result<(), String>;
+
+/// Upgrade from version `n` to `n + 1` at index `n`. Bumping
+/// `NETLIST_VERSION` without adding a step does not compile.
+const STEPS: [Step; NETLIST_VERSION as usize] = [v0_to_v1];
+
+/// Schema version of a saved netlist.
+///
+/// - 0: the first netlist, without a `version` field, holding gates as
+///   `{"And": ["a", "b"]}`. Files of the constant/input writer that came
+///   before it say `"version": 2` but keep their gates in an array; they are
+///   read as version 0 too.
+/// - 1: every gate kind with its state, shared components, libraries and
+///   subcircuits.
+pub fn version_of(doc: &Value) -> Result<u32, String> {
+    let obj = doc.as_object().ok_or("a netlist is a JSON object")?;
+    if obj.get("gates").is_some_and(Value::is_array) {
+        return Ok(0);
+    }
+    match obj.get("version") {
+        None => Ok(0),
+        Some(v) => v.as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("bad netlist version {v}")),
+    }
+}
+
+/// Brings `doc` up to `NETLIST_VERSION`, one version at a time.
+pub fn upgrade(mut doc: Value) -> Result<Value, String> {
+    let from = version_of(&doc)?;
+    if from > NETLIST_VERSION {
+        return Err(format!("netlist version {from} is newer than this build supports ({NETLIST_VERSION})"));
+    }
+    for (n, step) in STEPS.iter().enumerate().skip(from as usize) {
+        step(&mut doc).map_err(|e| format!("upgrading netlist from version {n}: {e}"))?;
+    }
+    Ok(doc)
+}
+
+/// Reads a netlist of any known version.
+pub fn read_netlist(doc: Value) -> Result<NetList, String> {
+    serde_json::from_value(upgrade(doc)?).map_err(|e| e.to_string())
+}
+
+/// Gates of the version 0 netlist.
+#[derive(Deserialize)]
+enum EarlyGate {
+    Const(bool),
+    Input(bool),
+    And([String; 2]),
+    Or([String; 2]),
+    Xor([String; 2]),
+    Nor([String; 2]),
+    Nand([String; 2]),
+    Not(String),
+    SRLatch { set: String, reset: String, q: bool },
+    DLatch { d: String, enable: String, q: bool },
+    DFlipFlop { d: String, clk: String, q: bool, last_clk: bool },
+    Clock(bool),
+    Wire(String),
+}
+
+/// Gates of the constant/input writer.
+#[derive(Deserialize)]
+#[serde(tag = "kind")]
+enum FirstGate {
+    Const { level: Signal },
+    Input { value: Signal },
+}
+
+fn level(high: bool) -> Signal {
+    if high { Signal::High } else { Signal::Low }
+}
+
+fn v0_to_v1(doc: &mut Value) -> Result<(), String> {
+    let obj = doc.as_object_mut().ok_or("a netlist is a JSON object")?;
+    let mut gates = Map::new();
+    match obj.remove("gates").unwrap_or_else(|| json!({})) {
+        Value::Array(list) => {
+            for entry in list {
+                let (id, gate): (String, FirstGate) = serde_json::from_value(entry).map_err(|e| e.to_string())?;
+                let kind = match gate {
+                    FirstGate::Const { level } => json!({ "kind": "Const", "level": level }),
+                    FirstGate::Input { value } => json!({ "kind": "Input", "value": value.is_high() }),
+                };
+                gates.insert(id, kind);
+            }
+        }
+        Value::Object(map) => {
+            for (id, gate) in map {
+                let gate: EarlyGate = serde_json::from_value(gate).map_err(|e| format!("gate '{id}': {e}"))?;
+                let kind = match gate {
+                    EarlyGate::Const(v) => json!({ "kind": "Const", "level": level(v) }),
+                    EarlyGate::Input(v) => json!({ "kind": "Input", "value": v }),
+                    EarlyGate::And([a, b]) => json!({ "kind": "And", "a": a, "b": b }),
+                    EarlyGate::Or([a, b]) => json!({ "kind": "Or", "a": a, "b": b }),
+                    EarlyGate::Xor([a, b]) => json!({ "kind": "Xor", "a": a, "b": b }),
+                    EarlyGate::Nor([a, b]) => json!({ "kind": "Nor", "a": a, "b": b }),
+                    EarlyGate::Nand([a, b]) => json!({ "kind": "Nand", "a": a, "b": b }),
+                    EarlyGate::Not(input) => json!({ "kind": "Not", "input": input }),
+                    EarlyGate::SRLatch { set, reset, q } => json!({ "kind": "SrLatch", "set": set, "reset": reset, "q": level(q) }),
+                    EarlyGate::DLatch { d, enable, q } => json!({ "kind": "DLatch", "d": d, "enable": enable, "q": level(q) }),
+                    EarlyGate::DFlipFlop { d, clk, q, last_clk } => {
+                        json!({ "kind": "DFlipFlop", "d": d, "clk": clk, "q": level(q), "last_clk": level(last_clk) })
+                    }
+                    EarlyGate::Clock(v) => json!({ "kind": "Clock", "level": level(v) }),
+                    EarlyGate::Wire(driver) => json!({ "kind": "Wire", "label": id, "drivers": [driver] }),
+                };
+                gates.insert(id, kind);
+            }
+        }
+        other => return Err(format!("gates must be a map or a list, not {other}")),
+    }
+    obj.insert("gates".into(), Value::Object(gates));
+    obj.entry("outputs").or_insert_with(|| json!([]));
+    obj.insert("version".into(), json!(1));
+    Ok(())
+}
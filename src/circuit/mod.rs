@@ -1,4 +1,25 @@
 pub mod gate;
 pub mod wire;
+#[allow(clippy::module_inception)]
 pub mod circuit;
-pub mod netlist;
\ No newline at end of file
+pub mod netlist;
+pub mod migrate;
+pub mod alu;
+pub mod arith;
+pub mod memory;
+pub mod display;
+pub mod terminal;
+pub mod stimulus;
+pub mod switch;
+pub mod lut;
+pub mod fsm;
+pub mod closure;
+pub mod examples;
+pub mod subcircuit;
+pub mod param;
+pub mod flatten;
+pub mod library;
+pub mod project;
+pub mod verilog;
+pub mod verilog_import;
+pub mod blif;
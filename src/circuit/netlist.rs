@@ -1,185 +1,354 @@
-// use std::collections::HashMap;
-// use std::collections::HashSet;
-// use serde::{Serialize, Deserialize};
-
-// pub type GateId = String;
-
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub enum GateKind {
-//     Const(bool),
-//     Input(bool),
-//     And([GateId; 2]),
-//     Or([GateId; 2]),
-//     Xor([GateId; 2]),
-//     Nor([GateId; 2]),
-//     Nand([GateId; 2]),
-//     Not(GateId),
-
-//     SRLatch {set: GateId, reset: GateId, q: bool},
-//     DLatch {d: GateId, enable: GateId, q: bool},
-//     DFlipFlop {d: GateId, clk: GateId, q: bool, last_clk: bool},
-//     Clock(bool),
-//     Wire(GateId),
-// }
-
-// #[derive(Default, Debug, Serialize, Deserialize)]
-// pub struct NetList {
-//     pub gates: HashMap<GateId, GateKind>,
-//     pub outputs: Vec<GateId>,
-// }
-
-// impl NetList {
-
-//     fn eval_gate_with_memo<'a>(
-//         &'a self,
-//         id: &'a GateId,
-//         memo: &mut HashMap<&'a GateId, bool>,
-//         visiting: &mut HashSet<&'a GateId>,
-//     ) -> bool {
-//         if let Some(&v) = memo.get(id) {
-//             return v;
-//         }
-
-//         if !visiting.insert(id) {
-
-//             return *memo.get(id).unwrap_or(&false);
-//         }
-
-//         let val = match &self.gates[id] {
-//             GateKind::Const(v) | GateKind::Input(v) | GateKind::Clock(v) => *v,
-//             GateKind::Wire(src) => self.eval_gate_with_memo(src, memo, visiting),
-//             GateKind::And([a,b])  => self.eval_gate_with_memo(a,memo,visiting) & self.eval_gate_with_memo(b,memo,visiting),
-//             GateKind::Or([a,b]) => self.eval_gate_with_memo(a,memo,visiting) | self.eval_gate_with_memo(b,memo,visiting),
-//             GateKind::Xor([a,b]) => self.eval_gate_with_memo(a,memo,visiting) ^ self.eval_gate_with_memo(b,memo,visiting),
-//             GateKind::Nand([a,b]) => !(self.eval_gate_with_memo(a,memo,visiting) & self.eval_gate_with_memo(b,memo,visiting)),
-//             GateKind::Nor([a,b]) => !(self.eval_gate_with_memo(a,memo,visiting) | self.eval_gate_with_memo(b,memo,visiting)),
-//             GateKind::Not(a) => !self.eval_gate_with_memo(a,memo,visiting),
-//             GateKind::DFlipFlop { q, ..} => *q,
-//             GateKind::DLatch { q, ..} => *q,
-//             GateKind::SRLatch { q, ..} => *q,
-//         };
-
-//         visiting.remove(id);
-//         memo.insert(id, val);
-//         val
-//     }
-
-//     fn eval_gate(&self, id: &GateId) -> bool {
-//         let mut memo = HashMap::new();
-//         let mut visiting = HashSet::new();
-//         self.eval_gate_with_memo(id, &mut memo, &mut visiting)
-//     }
-
-//     pub fn propagate(&mut self) {
-//         for _ in 0..10 {
-//             let mut changed = false;
-
-//             let updates: Vec<(GateId, bool, Option<bool>)> = self.gates.iter()
-//                 .filter_map(|(id, g)| {
-//                     let new_val = self.eval_gate(id);
-//                     match g {
-//                         GateKind::DFlipFlop {  last_clk, .. } => Some((id.clone(), new_val, Some(*last_clk))),
-//                         GateKind::DLatch    {  .. } => Some((id.clone(), new_val, None)),
-//                         GateKind::SRLatch   {  .. } => Some((id.clone(), new_val, None)),
-//                         _ => None
-//                     }
-//                 })
-//                 .collect();
-
-//                 for (id, v, _) in updates {
-//                     let clk_lvl = if let Some(GateKind::DFlipFlop { clk, .. }) = self.gates.get(&id) {
-//                         self.eval_gate(clk)
-//                     } else { false };
-                
-//                     if let Some(g) = self.gates.get_mut(&id) {
-//                         match g {
-//                             GateKind::DFlipFlop { q, last_clk, .. } => {
-//                                 changed |= *q != v || *last_clk != clk_lvl;
-//                                 *q       = v;
-//                                 *last_clk = clk_lvl;
-//                             }
-//                             GateKind::DLatch { q, ..  }
-//                           | GateKind::SRLatch { q, .. } => {
-//                                 changed |= *q != v;
-//                                 *q = v;
-//                             }
-//                             _ => {}
-//                         }
-//                     }
-//                 }
-
-//             if !changed { break; }
-//         }
-//     }
-
-//     pub fn set_input(&mut self, id: &str, value: bool) -> Result<(), String> {
-//         match self.gates.get_mut(id) {
-//             Some(GateKind::Input(v)) => {
-//                 if *v != value {
-//                     *v = value;
-//                     self.propagate();
-//                 }
-//                 Ok(())
-//             }
-//             Some(_) => Err(format!("Gate '{id}' is not of type Input")),
-//             None => Err(format!("Gate '{id}' does not exist"))
-//         }
-//     }
-
-//     pub fn read_outputs(&mut self) -> HashMap<GateId, bool> {
-//         self.propagate();
-//         self.outputs()
-//     }
-
-//     pub fn tick(&mut self, clk_id: &str) {
-//         if let Some(GateKind::Clock(v)) = self.gates.get_mut(clk_id) {
-//             *v = !*v
-//         }
-//     }
-
-//     pub fn outputs(&self) -> HashMap<GateId,bool> {
-//         self.outputs.iter()
-//             .map(|id| (id.clone(), self.eval_gate(id)))
-//             .collect()
-//     }
-
-//     pub fn add_const(&mut self, id: &str, v: bool) -> &mut Self{
-//         self.gates.insert(id.into(), GateKind::Const(v)); self
-//     }
-
-//     pub fn add_input(&mut self, id: &str, v: bool) -> &mut Self {
-//         self.gates.insert(id.into(), GateKind::Input(v)); self
-//     }
-
-//     pub fn add_not(&mut self, id: &str, src: &str) -> &mut Self{
-//         self.gates.insert(id.into(), GateKind::Not(src.into())); self
-//     }
-
-//     pub fn add_and(&mut self, id: &str, a: &str, b: &str) -> &mut Self {
-//         self.gates.insert(id.into(), GateKind::And([a.into(), b.into()])); self
-//     }
-
-//     pub fn add_xor(&mut self, id: &str, a: &str, b: &str) -> &mut Self {
-//         self.gates.insert(id.into(), GateKind::Xor([a.into(), b.into()])); self
-//     }
-
-//     pub fn add_halfadder(&mut self, a: &str, b: &str, sum_id: &str, carry_id: &str) -> &mut Self {
-//         self.add_xor(sum_id,a,b);
-//         self.add_and(carry_id,a,b);
-//         self
-//     }
-
-//     pub fn add_clock(&mut self, id: &str, init:bool) -> &mut Self {
-//         self.gates.insert(id.into(), GateKind::Clock(init)); self
-//     }
-
-//     pub fn add_dflipflop(&mut self, q: &str, clk: &str, init:bool) -> &mut Self {
-//         let d_not = format!("_not_{q}");
-//         self.gates.insert(
-//             q.into(),
-//             GateKind::DFlipFlop { d: (d_not.clone()), clk: (clk.into()), q: (init), last_clk: (false) }
-//         );
-//         self.add_not(&d_not, q);
-//         self
-//     }
-// }
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::display::{DisplayBus, PixelFormat};
+use crate::circuit::fsm::StateTable;
+use crate::circuit::gate::*;
+use crate::circuit::library::Library;
+use crate::circuit::lut::TruthTable;
+use crate::circuit::memory::MemPins;
+use crate::circuit::subcircuit::{InstanceInfo, SubcircuitDef};
+use crate::circuit::switch::Switch;
+use crate::circuit::wire::Wire;
+
+pub type GateId = String;
+
+/// Version written by `Circuit::to_netlist`; `migrate` upgrades older files.
+pub const NETLIST_VERSION: u32 = 1;
+
+/// One gate with its inputs named by id and its sequential state.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum GateKind {
+    /// A second id for gate `of`.
+    Alias { of: GateId },
+    Const { level: Signal },
+    Input { value: bool },
+    Switch { level: bool },
+    Button { pressed: bool },
+    Clock { level: Signal },
+    Pull { level: Signal },
+    Buffer { input: GateId },
+    Not { input: GateId },
+    OpenDrain { input: GateId },
+    Output { input: GateId },
+    TriState { input: GateId, enable: GateId },
+    And { a: GateId, b: GateId },
+    Or { a: GateId, b: GateId },
+    Xor { a: GateId, b: GateId },
+    Nand { a: GateId, b: GateId },
+    Nor { a: GateId, b: GateId },
+    Xnor { a: GateId, b: GateId },
+    SrLatch { set: GateId, reset: GateId, q: Signal },
+    DLatch { d: GateId, enable: GateId, q: Signal },
+    DFlipFlop { d: GateId, clk: GateId, q: Signal, last_clk: Signal },
+    Segment { inputs: [GateId; 4], segment: usize, hex: bool },
+    Lut { inputs: Vec<GateId>, table: TruthTable },
+    Wire { label: String, drivers: Vec<GateId> },
+    Pattern { pattern: String, hold: usize, repeat: bool, pos: usize },
+    OneShot { trigger: GateId, width: usize, retriggerable: bool, remaining: usize, last_trigger: Signal },
+    Random { seed: u64, state: u64 },
+    Display { frame: usize, bus: DisplayBus<GateId>, format: PixelFormat, last_write: Signal },
+    Tty { terminal: usize, data: Vec<GateId>, strobe: GateId, last_strobe: Signal },
+    /// Data bit `bit` of keyboard `keyboard`, or its `ready` line.
+    KeyboardPort { keyboard: usize, bit: Option<usize> },
+    MemoryPort { memory: usize, bit: usize },
+    /// Output `output` of machine `machine`, or the machine itself.
+    FsmPort { machine: usize, output: Option<usize> },
+    SwitchNode { network: usize, node: usize },
+}
+
+/// State shared by the ports of one component, referred to by index.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum SharedKind {
+    /// Memory words; trailing zero words are left out.
+    Core { addr_width: usize, data_width: usize, words: Vec<u64>, last_read: Option<usize>, last_write: Option<usize> },
+    Memory { core: usize, pins: MemPins<GateId>, last_clk: Signal },
+    Frame { width: usize, height: usize, pixels: Vec<[u8; 3]> },
+    Terminal { output: Vec<u8>, flushed: usize, input: Vec<u8> },
+    Keyboard { terminal: usize, ack: GateId, last_ack: Signal },
+    Machine { table: StateTable, inputs: Vec<GateId>, clock: GateId, state: usize, last_clk: Signal },
+    /// Switch network; `drivers` has one entry per node, `None` for the rails.
+    Network { nodes: Vec<String>, drivers: Vec<Option<GateId>>, switches: Vec<Switch>, charge: Vec<Signal> },
+}
+
+/// Serialized form of a whole `Circuit`. Gates that are not registered
+/// under an id, such as the inner gates of an adder, are kept in
+/// `internal` under generated `$n` ids.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetList {
+    pub version: u32,
+    pub gates: BTreeMap<GateId, GateKind>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub internal: BTreeMap<GateId, GateKind>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shared: Vec<SharedKind>,
+    pub outputs: Vec<GateId>,
+    /// Registry names of memories, displays, terminals and state machines,
+    /// as indices into `shared`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub memories: BTreeMap<String, usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub displays: BTreeMap<String, usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub terminals: BTreeMap<String, usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fsms: BTreeMap<String, usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub libraries: Vec<Library>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subcircuits: Vec<SubcircuitDef>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub instances: BTreeMap<String, InstanceInfo>,
+}
+
+fn key<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+/// Names gates and shared state while a circuit is written out.
+pub struct Saver {
+    names: HashMap<usize, GateId>,
+    taken: HashSet<GateId>,
+    queue: VecDeque<(GateId, GateRef)>,
+    internal: usize,
+    shared: HashMap<usize, usize>,
+    pub(crate) list: Vec<Option<SharedKind>>,
+}
+
+impl Saver {
+    /// `registered` in id order; a gate registered twice is named by its
+    /// first id.
+    pub(crate) fn new(registered: &[(&String, &GateRef)]) -> Self {
+        Self {
+            names: registered.iter().rev().map(|(id, g)| (key(g), id.to_string())).collect(),
+            taken: registered.iter().map(|(id, _)| id.to_string()).collect(),
+            queue: VecDeque::new(),
+            internal: 0,
+            shared: HashMap::new(),
+            list: Vec::new(),
+        }
+    }
+
+    /// Id of `g`. A gate seen for the first time gets a `$n` id and is
+    /// queued to be written to `NetList::internal`.
+    pub fn name(&mut self, g: &GateRef) -> GateId {
+        if let Some(id) = self.names.get(&key(g)) {
+            return id.clone();
+        }
+        let id = (self.internal..).map(|n| format!("${n}")).find(|id| !self.taken.contains(id)).unwrap();
+        self.internal = id[1..].parse::<usize>().unwrap() + 1;
+        self.taken.insert(id.clone());
+        self.names.insert(key(g), id.clone());
+        self.queue.push_back((id.clone(), g.clone()));
+        id
+    }
+
+    pub fn names(&mut self, gates: &[GateRef]) -> Vec<GateId> {
+        gates.iter().map(|g| self.name(g)).collect()
+    }
+
+    /// Index of the shared state behind `rc`, described by `make` the
+    /// first time it is seen.
+    pub fn shared<T: ?Sized>(&mut self, rc: &Rc<T>, make: impl FnOnce(&mut Self) -> SharedKind) -> usize {
+        if let Some(&i) = self.shared.get(&key(rc)) {
+            return i;
+        }
+        let i = self.list.len();
+        self.shared.insert(key(rc), i);
+        self.list.push(None);
+        self.list[i] = Some(make(self));
+        i
+    }
+
+    pub(crate) fn next_internal(&mut self) -> Option<(GateId, GateRef)> {
+        self.queue.pop_front()
+    }
+}
+
+/// Rebuilds gates and shared state from a `NetList`. Every gate is built
+/// once, after its inputs; wires are created first so feedback through
+/// them resolves.
+pub struct Loader<'a> {
+    net: &'a NetList,
+    gates: HashMap<GateId, GateRef>,
+    building: HashSet<GateId>,
+    shared: Vec<Option<Rc<dyn Any>>>,
+}
+
+impl<'a> Loader<'a> {
+    pub(crate) fn new(net: &'a NetList) -> Self {
+        let mut gates = HashMap::new();
+        for (id, kind) in net.gates.iter().chain(&net.internal) {
+            if let GateKind::Wire { label, .. } = kind {
+                gates.insert(id.clone(), Rc::new(RefCell::new(Wire::new(label))) as GateRef);
+            }
+        }
+        Self { net, gates, building: HashSet::new(), shared: vec![None; net.shared.len()] }
+    }
+
+    fn kind(&self, id: &str) -> Result<&'a GateKind, String> {
+        self.net.gates.get(id).or_else(|| self.net.internal.get(id)).ok_or_else(|| format!("no gate '{id}' in the netlist"))
+    }
+
+    pub fn gate(&mut self, id: &str) -> Result<GateRef, String> {
+        if let Some(g) = self.gates.get(id) {
+            return Ok(g.clone());
+        }
+        if !self.building.insert(id.to_string()) {
+            return Err(format!("gate '{id}' feeds back into itself without a wire"));
+        }
+        let g = self.kind(id)?.build(self).map_err(|e| format!("gate '{id}': {e}"))?;
+        self.building.remove(id);
+        self.gates.insert(id.to_string(), g.clone());
+        Ok(g)
+    }
+
+    pub fn gates(&mut self, ids: &[GateId]) -> Result<Vec<GateRef>, String> {
+        ids.iter().map(|id| self.gate(id)).collect()
+    }
+
+    /// Shared state `index`, built on first use.
+    pub fn shared<T: Any>(&mut self, index: usize) -> Result<Rc<T>, String> {
+        let kind = self.net.shared.get(index).ok_or_else(|| format!("no shared entry {index}"))?;
+        let rc = match &self.shared[index] {
+            Some(rc) => rc.clone(),
+            None => {
+                let rc = kind.build(self)?;
+                self.shared[index] = Some(rc.clone());
+                rc
+            }
+        };
+        rc.downcast::<T>().map_err(|_| format!("shared entry {index} is not a {}", kind.name()))
+    }
+
+    /// Connects the drivers of every wire once all gates exist.
+    pub(crate) fn finish(&mut self) -> Result<(), String> {
+        for (id, kind) in self.net.gates.iter().chain(&self.net.internal) {
+            if let GateKind::Wire { drivers, .. } = kind {
+                let drivers = self.gates(drivers)?;
+                let wire = self.gate(id)?;
+                let mut w = wire.borrow_mut();
+                let w = w.as_any().downcast_mut::<Wire>().unwrap();
+                for d in drivers {
+                    w.add_driver(d);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GateKind {
+    /// Ids of the gates this one reads directly. Inputs reached through
+    /// shared state, such as a memory's pins, are not included.
+    pub fn inputs(&self) -> Vec<&GateId> {
+        use GateKind::*;
+        match self {
+            Alias { of } => vec![of],
+            Buffer { input } | Not { input } | OpenDrain { input } | Output { input } => vec![input],
+            TriState { input, enable } => vec![input, enable],
+            And { a, b } | Or { a, b } | Xor { a, b } | Nand { a, b } | Nor { a, b } | Xnor { a, b } => vec![a, b],
+            SrLatch { set, reset, .. } => vec![set, reset],
+            DLatch { d, enable, .. } => vec![d, enable],
+            DFlipFlop { d, clk, .. } => vec![d, clk],
+            Segment { inputs, .. } => inputs.iter().collect(),
+            Lut { inputs, .. } => inputs.iter().collect(),
+            Wire { drivers, .. } => drivers.iter().collect(),
+            OneShot { trigger, .. } => vec![trigger],
+            Display { bus: DisplayBus::AddressData { x, y, data, write }, .. } => x.iter().chain(y).chain(data).chain([write]).collect(),
+            Display { bus: DisplayBus::RowColumn { row, columns, write }, .. } => row.iter().chain(columns).chain([write]).collect(),
+            Tty { data, strobe, .. } => data.iter().chain([strobe]).collect(),
+            Const { .. } | Input { .. } | Switch { .. } | Button { .. } | Clock { .. } | Pull { .. } | Pattern { .. } | Random { .. }
+            | KeyboardPort { .. } | MemoryPort { .. } | FsmPort { .. } | SwitchNode { .. } => Vec::new(),
+        }
+    }
+
+    fn build(&self, cx: &mut Loader) -> Result<GateRef, String> {
+        use crate::circuit::{display, fsm, lut, memory, stimulus, switch, terminal};
+        use GateKind::*;
+        Ok(match self {
+            Alias { of } => cx.gate(of)?,
+            Const { level } => gate(ConstGate::new(*level)),
+            Input { value } => gate(InputGate::new(*value)),
+            Switch { level } => gate(SwitchGate::new(*level)),
+            Button { pressed } => gate(ButtonGate::restore(*pressed)),
+            Clock { level } => gate(ClockGate::restore(*level)),
+            Pull { level } => gate(if level.is_high() { PullResistor::up() } else { PullResistor::down() }),
+            Buffer { input } => gate(BufferGate::new(cx.gate(input)?)),
+            Not { input } => gate(NotGate::new(cx.gate(input)?)),
+            OpenDrain { input } => gate(OpenDrainGate::new(cx.gate(input)?)),
+            Output { input } => gate(OutputGate::new(cx.gate(input)?)),
+            TriState { input, enable } => gate(TriStateGate::new(cx.gate(input)?, cx.gate(enable)?)),
+            And { a, b } => gate(AndGate::new(cx.gate(a)?, cx.gate(b)?)),
+            Or { a, b } => gate(OrGate::new(cx.gate(a)?, cx.gate(b)?)),
+            Xor { a, b } => gate(XorGate::new(cx.gate(a)?, cx.gate(b)?)),
+            Nand { a, b } => gate(NandGate::new(cx.gate(a)?, cx.gate(b)?)),
+            Nor { a, b } => gate(NorGate::new(cx.gate(a)?, cx.gate(b)?)),
+            Xnor { a, b } => gate(XnorGate::new(cx.gate(a)?, cx.gate(b)?)),
+            SrLatch { set, reset, q } => gate(SRLatch::restore(cx.gate(set)?, cx.gate(reset)?, *q)),
+            DLatch { d, enable, q } => gate(Dlatch::restore(cx.gate(d)?, cx.gate(enable)?, *q)),
+            DFlipFlop { d, clk, q, last_clk } => gate(Dflipflop::restore(cx.gate(d)?, cx.gate(clk)?, *q, *last_clk)),
+            Segment { inputs, segment, hex } => {
+                if *segment >= 7 {
+                    return Err(format!("no segment {segment}"));
+                }
+                let inputs = cx.gates(inputs)?.try_into().unwrap();
+                gate(SegmentGate::new(inputs, *segment, *hex))
+            }
+            Lut { inputs, table } => gate(lut::LutGate::new(cx.gates(inputs)?, *table)?),
+            Wire { .. } => unreachable!("wires are created up front"),
+            Pattern { pattern, hold, repeat, pos } => gate(stimulus::PatternGenerator::restore(pattern, *hold, *repeat, *pos)?),
+            OneShot { trigger, width, retriggerable, remaining, last_trigger } => {
+                gate(stimulus::OneShot::restore(cx.gate(trigger)?, *width, *retriggerable, *remaining, *last_trigger))
+            }
+            Random { seed, state } => gate(stimulus::RandomBit::restore(*seed, *state)),
+            Display { frame, bus, format, last_write } => gate(display::PixelDisplay::restore(cx, *frame, bus, *format, *last_write)?),
+            Tty { terminal, data, strobe, last_strobe } => {
+                gate(terminal::TtyOutput::restore(cx.shared(*terminal)?, cx.gates(data)?, cx.gate(strobe)?, *last_strobe)?)
+            }
+            KeyboardPort { keyboard, bit } => gate(terminal::KeyboardPort::restore(cx.shared(*keyboard)?, *bit)?),
+            MemoryPort { memory, bit } => gate(memory::MemoryPort::restore(cx.shared(*memory)?, *bit)?),
+            FsmPort { machine, output } => gate(fsm::FsmPort::restore(cx.shared(*machine)?, *output)?),
+            SwitchNode { network, node } => gate(switch::SwitchNode::restore(cx.shared(*network)?, *node)?),
+        })
+    }
+}
+
+impl SharedKind {
+    fn name(&self) -> &'static str {
+        match self {
+            SharedKind::Core { .. } => "Core",
+            SharedKind::Memory { .. } => "Memory",
+            SharedKind::Frame { .. } => "Frame",
+            SharedKind::Terminal { .. } => "Terminal",
+            SharedKind::Keyboard { .. } => "Keyboard",
+            SharedKind::Machine { .. } => "Machine",
+            SharedKind::Network { .. } => "Network",
+        }
+    }
+
+    fn build(&self, cx: &mut Loader) -> Result<Rc<dyn Any>, String> {
+        use crate::circuit::{display, fsm, memory, switch, terminal};
+        Ok(match self {
+            SharedKind::Core { addr_width, data_width, words, last_read, last_write } => {
+                Rc::new(RefCell::new(memory::MemoryCore::restore(*addr_width, *data_width, words, *last_read, *last_write)?))
+            }
+            SharedKind::Memory { core, pins, last_clk } => memory::restore_shared(cx, *core, pins, *last_clk)?,
+            SharedKind::Frame { width, height, pixels } => Rc::new(RefCell::new(display::FrameBuffer::restore(*width, *height, pixels)?)),
+            SharedKind::Terminal { output, flushed, input } => Rc::new(RefCell::new(terminal::Terminal::restore(output, *flushed, input)?)),
+            SharedKind::Keyboard { terminal, ack, last_ack } => terminal::restore_keyboard(cx, *terminal, ack, *last_ack)?,
+            SharedKind::Machine { table, inputs, clock, state, last_clk } => {
+                Rc::new(fsm::StateMachine::restore(table.clone(), cx.gates(inputs)?, cx.gate(clock)?, *state, *last_clk)?)
+            }
+            SharedKind::Network { nodes, drivers, switches, charge } => {
+                Rc::new(switch::SwitchNetwork::restore(cx, nodes, drivers, switches, charge)?)
+            }
+        })
+    }
+}
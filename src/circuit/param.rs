@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+/// Parameter and loop variable values by name.
+pub type Params = BTreeMap<String, i64>;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+const OPS: [&str; 11] = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "(", ")"];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let n = rest[..len].parse().map_err(|_| format!("number '{}' is too large", &rest[..len]))?;
+            tokens.push(Token::Num(n));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].into()));
+            len
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(format!("unexpected '{c}' in '{expr}'"));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Binding power of binary operators, loosest first.
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "|" => 1,
+        "&" => 2,
+        "<<" | ">>" => 3,
+        "+" | "-" => 4,
+        "*" | "/" | "%" => 5,
+        _ => return None,
+    })
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    vars: &'a Params,
+    expr: &'a str,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn atom(&mut self) -> Result<i64, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(n),
+            Some(Token::Name(name)) => self.vars.get(&name).copied().ok_or_else(|| format!("unknown parameter '{name}' in '{}'", self.expr)),
+            Some(Token::Op("-")) => Ok(self.atom()?.wrapping_neg()),
+            Some(Token::Op("(")) => {
+                let v = self.binary(0)?;
+                match self.next() {
+                    Some(Token::Op(")")) => Ok(v),
+                    _ => Err(format!("missing ')' in '{}'", self.expr)),
+                }
+            }
+            _ => Err(format!("malformed expression '{}'", self.expr)),
+        }
+    }
+
+    fn binary(&mut self, min: u8) -> Result<i64, String> {
+        let mut lhs = self.atom()?;
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos).cloned() {
+            let Some(prec) = precedence(op).filter(|p| *p > min) else {
+                break;
+            };
+            self.pos += 1;
+            let rhs = self.binary(prec)?;
+            let shift = u32::try_from(rhs).ok().filter(|s| *s < 64);
+            lhs = match op {
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err(format!("division by zero in '{}'", self.expr)),
+                "/" => lhs / rhs,
+                "%" => lhs % rhs,
+                "&" => lhs & rhs,
+                "|" => lhs | rhs,
+                "<<" => shift.map_or(0, |s| lhs << s),
+                _ => shift.map_or(if lhs < 0 { -1 } else { 0 }, |s| lhs >> s),
+            };
+        }
+        Ok(lhs)
+    }
+}
+
+/// Evaluates an integer expression over `vars`: decimal numbers, names,
+/// `+ - * / % << >> & |` with the usual precedence, unary minus and parentheses.
+pub fn eval(expr: &str, vars: &Params) -> Result<i64, String> {
+    let mut p = Parser { tokens: tokenize(expr)?, pos: 0, vars, expr };
+    let v = p.binary(0)?;
+    if p.pos != p.tokens.len() {
+        return Err(format!("unexpected text after '{expr}'"));
+    }
+    Ok(v)
+}
+
+/// Expands a name template. Each `{expr}` is replaced by its value, and one
+/// `{lo..hi}` part yields one name per value in `lo..hi`:
+/// `"d{0..W}"` with `W = 3` gives `d0`, `d1`, `d2`.
+pub fn expand(template: &str, vars: &Params) -> Result<Vec<String>, String> {
+    let mut parts = vec![String::new()];
+    let mut ranged = false;
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..].find('}').ok_or_else(|| format!("unclosed '{{' in '{template}'"))? + open;
+        let inner = &rest[open + 1..close];
+        let literal = &rest[..open];
+
+        let values: Vec<i64> = match inner.split_once("..") {
+            Some(_) if ranged => return Err(format!("more than one range in '{template}'")),
+            Some((lo, hi)) => {
+                ranged = true;
+                (eval(lo, vars)?..eval(hi, vars)?).collect()
+            }
+            None => vec![eval(inner, vars)?],
+        };
+        parts = parts.iter()
+            .flat_map(|p| values.iter().map(move |v| format!("{p}{literal}{v}")))
+            .collect();
+        rest = &rest[close + 1..];
+    }
+    Ok(parts.into_iter().map(|p| p + rest).collect())
+}
+
+/// `expand` for a template that must give exactly one name.
+pub fn substitute(template: &str, vars: &Params) -> Result<String, String> {
+    let mut names = expand(template, vars)?;
+    if names.len() != 1 {
+        return Err(format!("'{template}' must name exactly one signal"));
+    }
+    Ok(names.remove(0))
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::circuit::circuit::Circuit;
+use crate::circuit::migrate;
+use crate::circuit::netlist::{GateId, NetList};
+
+/// How many files `RecentFiles` remembers.
+pub const MAX_RECENT: usize = 8;
+
+/// An editor project: the circuit plus where its nodes sit on the canvas
+/// and which ports the drawn wires join.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    pub circuit: NetList,
+    #[serde(default)]
+    pub nodes: Vec<NodeLayout>,
+    #[serde(default)]
+    pub wires: Vec<WireLayout>,
+}
+
+/// A node of the canvas. Its label decides how the editor draws and
+/// handles it ("SW a0", "LAMP", "7SEG", ...).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeLayout {
+    pub id: String,
+    pub label: String,
+    /// The gate the node shows, e.g. its output or the first data pin.
+    pub gate: GateId,
+    pub pos: [f32; 2],
+    pub size: [f32; 2],
+    #[serde(default)]
+    pub ports: Vec<PortLayout>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortSide {
+    In,
+    Out,
+}
+
+/// A pin of a node, placed relative to its top-left corner.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PortLayout {
+    pub offset: [f32; 2],
+    pub side: PortSide,
+    pub gate: GateId,
+}
+
+/// A drawn wire, as (node id, port index) at each end.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WireLayout {
+    pub from: (String, usize),
+    pub to: (String, usize),
+}
+
+impl Project {
+    pub fn new(circuit: &Circuit, nodes: Vec<NodeLayout>, wires: Vec<WireLayout>) -> Result<Self, String> {
+        let project = Self { circuit: circuit.to_netlist()?, nodes, wires };
+        project.check()?;
+        Ok(project)
+    }
+
+    /// Checks that nodes name gates of the circuit and wires join ports
+    /// that exist.
+    pub fn check(&self) -> Result<(), String> {
+        let mut ids = BTreeSet::new();
+        for node in &self.nodes {
+            if !ids.insert(node.id.as_str()) {
+                return Err(format!("node '{}' appears twice", node.id));
+            }
+            let gates = std::iter::once(&node.gate).chain(node.ports.iter().map(|p| &p.gate));
+            if let Some(gate) = gates.into_iter().find(|g| !self.circuit.gates.contains_key(*g)) {
+                return Err(format!("node '{}' uses unknown gate '{gate}'", node.id));
+            }
+        }
+        for wire in &self.wires {
+            for (node, port) in [&wire.from, &wire.to] {
+                let ports = self.nodes.iter().find(|n| &n.id == node).map(|n| n.ports.len());
+                match ports {
+                    None => return Err(format!("wire ends at unknown node '{node}'")),
+                    Some(len) if *port >= len => return Err(format!("node '{node}' has no port {port}")),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the circuit of the project.
+    pub fn build(&self) -> Result<Circuit, String> {
+        self.check()?;
+        Circuit::from_netlist(&self.circuit)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Reads a project, upgrading a circuit saved by an older version.
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let mut doc: Value = serde_json::from_str(text).context("parsing project")?;
+        if let Some(circuit) = doc.get_mut("circuit") {
+            *circuit = migrate::upgrade(circuit.take()).map_err(anyhow::Error::msg)?;
+        }
+        let project: Self = serde_json::from_value(doc).context("parsing project")?;
+        project.check().map_err(anyhow::Error::msg)?;
+        Ok(project)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("loading {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = self.to_json()?;
+        std::fs::write(path, text).with_context(|| format!("saving {}", path.display()))
+    }
+}
+
+/// Recently opened or saved projects, most recent first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecentFiles {
+    paths: Vec<PathBuf>,
+}
+
+impl RecentFiles {
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Moves `path` to the front, forgetting the oldest beyond `MAX_RECENT`.
+    pub fn push(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        self.paths.retain(|p| *p != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(MAX_RECENT);
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+    }
+
+    /// The list stored at `path`; empty if there is none yet.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("loading {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text).with_context(|| format!("saving {}", path.display()))
+    }
+}
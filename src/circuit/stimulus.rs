@@ -0,0 +1,228 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+
+use crate::circuit::gate::*;
+use crate::circuit::netlist::{GateKind, Saver};
+
+/// Plays a fixed bit sequence, holding each bit for `hold` steps.
+/// After the last bit it starts over, or keeps the last bit when not repeating.
+#[derive(Debug)]
+pub struct PatternGenerator {
+    pattern: Vec<Signal>,
+    hold: usize,
+    repeat: bool,
+    pos: Cell<usize>,
+}
+
+/// Monostable: a rising `trigger` edge drives the output high for `width` steps.
+/// Edges while the pulse is running are ignored unless it is retriggerable,
+/// in which case they restart the pulse.
+#[derive(Debug)]
+pub struct OneShot {
+    trigger: GateRef,
+    width: usize,
+    retriggerable: bool,
+    remaining: Cell<usize>,
+    last_trigger: RefCell<Signal>,
+}
+
+/// Pseudo-random bit source (xorshift64). The same seed always gives the
+/// same sequence, so simulations can be replayed.
+#[derive(Debug)]
+pub struct RandomBit {
+    seed: u64,
+    state: Cell<u64>,
+}
+
+impl PatternGenerator {
+    pub fn new(pattern: Vec<Signal>, hold: usize, repeat: bool) -> Self {
+        assert!(!pattern.is_empty(), "a pattern needs at least one bit");
+        Self { pattern, hold: hold.max(1), repeat, pos: Cell::new(0) }
+    }
+
+    /// Parses a pattern like `"0110 1z"`: `0`, `1`, `z` for HiZ and `x` for X.
+    /// Spaces and `_` may be used as separators.
+    pub fn parse(pattern: &str, hold: usize, repeat: bool) -> Result<Self, String> {
+        let bits = pattern
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '_')
+            .map(|c| match c {
+                '0' => Ok(Signal::Low),
+                '1' => Ok(Signal::High),
+                'z' | 'Z' => Ok(Signal::HiZ),
+                'x' | 'X' => Ok(Signal::X),
+                _ => Err(format!("invalid pattern character '{c}'")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if bits.is_empty() {
+            return Err("empty pattern".into());
+        }
+        Ok(Self::new(bits, hold, repeat))
+    }
+
+    pub fn pattern_string(&self) -> String {
+        self.pattern
+            .iter()
+            .map(|s| match s {
+                Signal::Low => '0',
+                Signal::High => '1',
+                Signal::HiZ => 'z',
+                Signal::X => 'x',
+            })
+            .collect()
+    }
+
+    /// Goes back to the first bit.
+    pub fn restart(&self) {
+        self.pos.set(0);
+    }
+
+    /// Generator as saved by `Circuit::save`, `pos` steps into the pattern.
+    pub(crate) fn restore(pattern: &str, hold: usize, repeat: bool, pos: usize) -> Result<Self, String> {
+        let g = Self::parse(pattern, hold, repeat)?;
+        if pos > g.hold * g.pattern.len() {
+            return Err(format!("position {pos} is past the end of the pattern"));
+        }
+        g.pos.set(pos);
+        Ok(g)
+    }
+
+    fn index(&self) -> usize {
+        let i = self.pos.get() / self.hold;
+        if self.repeat { i % self.pattern.len() } else { i.min(self.pattern.len() - 1) }
+    }
+}
+
+impl OneShot {
+    pub fn new(trigger: GateRef, width: usize, retriggerable: bool) -> Self {
+        Self {
+            trigger,
+            width,
+            retriggerable,
+            remaining: Cell::new(0),
+            last_trigger: RefCell::new(Signal::Low),
+        }
+    }
+
+    pub(crate) fn restore(trigger: GateRef, width: usize, retriggerable: bool, remaining: usize, last_trigger: Signal) -> Self {
+        let g = Self::new(trigger, width, retriggerable);
+        g.remaining.set(remaining);
+        *g.last_trigger.borrow_mut() = last_trigger;
+        g
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.remaining.get() > 0
+    }
+}
+
+impl RandomBit {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        let seed = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+        Self { seed, state: Cell::new(seed) }
+    }
+
+    pub(crate) fn restore(seed: u64, state: u64) -> Self {
+        let g = Self::new(seed);
+        g.state.set(state);
+        g
+    }
+
+    /// Starts the sequence over from the seed.
+    pub fn reset(&self) {
+        self.state.set(self.seed);
+    }
+}
+
+impl Gate for PatternGenerator {
+    fn eval(&self) -> Signal {
+        self.pattern[self.index()]
+    }
+
+    fn description(&self) -> String {
+        format!("Pattern({} x{})", self.pattern_string(), self.hold)
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, _: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Pattern { pattern: self.pattern_string(), hold: self.hold, repeat: self.repeat, pos: self.pos.get() })
+    }
+
+    fn advance(&self) {
+        let end = self.hold * self.pattern.len();
+        let next = self.pos.get() + 1;
+        self.pos.set(if self.repeat { next % end } else { next.min(end) });
+    }
+}
+
+impl Gate for OneShot {
+    fn eval(&self) -> Signal {
+        if self.is_active() { Signal::High } else { Signal::Low }
+    }
+
+    fn description(&self) -> String {
+        format!("OneShot({} steps)", self.width)
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::OneShot {
+            trigger: cx.name(&self.trigger),
+            width: self.width,
+            retriggerable: self.retriggerable,
+            remaining: self.remaining.get(),
+            last_trigger: *self.last_trigger.borrow(),
+        })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.trigger.clone()]
+    }
+
+    fn clock_edge(&self) -> bool {
+        rising(&self.trigger, &self.last_trigger)
+    }
+
+    fn commit(&self) {
+        if self.retriggerable || !self.is_active() {
+            self.remaining.set(self.width);
+        }
+    }
+
+    fn advance(&self) {
+        self.remaining.set(self.remaining.get().saturating_sub(1));
+    }
+}
+
+impl Gate for RandomBit {
+    fn eval(&self) -> Signal {
+        if self.state.get() >> 63 == 1 { Signal::High } else { Signal::Low }
+    }
+
+    fn description(&self) -> String {
+        format!("Random(seed {:#x})", self.seed)
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, _: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Random { seed: self.seed, state: self.state.get() })
+    }
+
+    fn advance(&self) {
+        let mut x = self.state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state.set(x);
+    }
+}
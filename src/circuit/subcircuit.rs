@@ -0,0 +1,463 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::gate::*;
+use crate::circuit::lut::{LutGate, TruthTable};
+use crate::circuit::param::{self, Params};
+use crate::circuit::wire::Wire;
+
+/// What a cell of a `SubcircuitDef` is. Two-input gates accept more inputs,
+/// folded into a balanced tree of the same gate (inverted at the root for
+/// `Nand`/`Nor`/`Xnor`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum CellKind {
+    Const { level: Signal },
+    And,
+    Or,
+    Xor,
+    Nand,
+    Nor,
+    Xnor,
+    Not,
+    Buffer,
+    /// Inputs `[in, enable]`.
+    TriState,
+    OpenDrain,
+    /// Inputs `[d, clk]`.
+    Dff { init: Signal },
+    /// Inputs `[d, enable]`.
+    Dlatch,
+    /// Inputs `[set, reset]`.
+    SrLatch,
+    Lut { table: TruthTable },
+    /// Net with any number of drivers, e.g. a shared bus or a renamed signal.
+    Wire,
+    /// Another subcircuit; its input ports take the cell's inputs in order and
+    /// its output port `p` becomes the net `{cell}.{p}`. `params` are
+    /// expressions over the parameters of the enclosing subcircuit.
+    Instance {
+        module: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        params: BTreeMap<String, String>,
+    },
+}
+
+/// One cell of a subcircuit. Its output is the net named after it; `inputs`
+/// name nets of the same subcircuit: input ports, cells, or `{instance}.{port}`.
+/// Nets may be used before the cell driving them, so feedback is allowed.
+///
+/// Names and inputs are templates (see `param::expand`), so an input like
+/// `"x{0..N}"` stands for `N` nets.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CellDef {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: CellKind,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// Builds the cell this many times with `i` counting from 0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<String>,
+    /// Level of a `Const` or initial state of a `Dff` as an expression,
+    /// non-zero meaning high. Overrides `level`/`init`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// A parameter of a subcircuit and the value used when an instance does
+/// not set it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParamDef {
+    pub name: String,
+    pub default: i64,
+}
+
+/// Module and parameter values of one instance in a `Circuit`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceInfo {
+    pub module: String,
+    pub params: Params,
+}
+
+/// A reusable design with named input and output ports. Output ports name
+/// an input port or a cell of the subcircuit.
+///
+/// Every instantiation builds fresh gates, so instances keep their own state.
+/// Gates of an instance at `path` get the ids `{path}.{cell}` and its ports
+/// `{path}.{port}`, so nested signals read like `cpu.alu.add0.sum`.
+///
+/// With `params` the definition is a template expanded per instance: port
+/// and cell names may use them, e.g. an input `"d{0..W}"` for a `W`-bit bus.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SubcircuitDef {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<ParamDef>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub cells: Vec<CellDef>,
+}
+
+/// Gates and instances created by instantiating a subcircuit, ready to be
+/// registered in a `Circuit`.
+#[derive(Debug, Default)]
+pub struct Elaborated {
+    /// `(id, gate)` for every port, cell and nested port.
+    pub gates: Vec<(String, GateRef)>,
+    /// The instance and everything nested in it, by path.
+    pub instances: Vec<(String, InstanceInfo)>,
+    /// Output port gates, in port order.
+    pub outputs: Vec<GateRef>,
+}
+
+impl CellKind {
+    /// Allowed input counts, `None` meaning no upper bound.
+    fn arity(&self) -> (usize, Option<usize>) {
+        match self {
+            CellKind::Const { .. } => (0, Some(0)),
+            CellKind::And | CellKind::Or | CellKind::Xor | CellKind::Nand | CellKind::Nor | CellKind::Xnor => (2, None),
+            CellKind::Not | CellKind::Buffer | CellKind::OpenDrain => (1, Some(1)),
+            CellKind::TriState | CellKind::Dff { .. } | CellKind::Dlatch | CellKind::SrLatch => (2, Some(2)),
+            CellKind::Lut { table } => (table.inputs(), Some(table.inputs())),
+            CellKind::Wire | CellKind::Instance { .. } => (0, None),
+        }
+    }
+
+    /// Builds the gate of a primitive cell. `Instance` is handled by `elaborate`.
+    fn build(&self, name: &str, inputs: Vec<GateRef>) -> Result<GateRef, String> {
+        fn xor_tree(inputs: &[GateRef]) -> GateRef {
+            if inputs.len() == 1 {
+                return inputs[0].clone();
+            }
+            let (l, r) = inputs.split_at(inputs.len() / 2);
+            gate(XorGate::new(xor_tree(l), xor_tree(r)))
+        }
+        let two = |i: &[GateRef]| i.len() == 2;
+        let i = &inputs;
+
+        Ok(match self {
+            CellKind::Const { level } => gate(ConstGate::new(*level)),
+            CellKind::And => and_tree(i),
+            CellKind::Or => or_tree(i),
+            CellKind::Xor => xor_tree(i),
+            CellKind::Nand if two(i) => gate(NandGate::new(i[0].clone(), i[1].clone())),
+            CellKind::Nor if two(i) => gate(NorGate::new(i[0].clone(), i[1].clone())),
+            CellKind::Xnor if two(i) => gate(XnorGate::new(i[0].clone(), i[1].clone())),
+            CellKind::Nand => gate(NotGate::new(and_tree(i))),
+            CellKind::Nor => gate(NotGate::new(or_tree(i))),
+            CellKind::Xnor => gate(NotGate::new(xor_tree(i))),
+            CellKind::Not => gate(NotGate::new(i[0].clone())),
+            CellKind::Buffer => gate(BufferGate::new(i[0].clone())),
+            CellKind::TriState => gate(TriStateGate::new(i[0].clone(), i[1].clone())),
+            CellKind::OpenDrain => gate(OpenDrainGate::new(i[0].clone())),
+            CellKind::Dff { init } => gate(Dflipflop::with_state(i[0].clone(), i[1].clone(), *init)),
+            CellKind::Dlatch => gate(Dlatch::new(i[0].clone(), i[1].clone())),
+            CellKind::SrLatch => gate(SRLatch::new(i[0].clone(), i[1].clone())),
+            CellKind::Lut { table } => gate(LutGate::new(inputs, *table)?),
+            CellKind::Wire => {
+                let mut wire = Wire::new(name);
+                for d in inputs {
+                    wire.add_driver(d);
+                }
+                gate(wire)
+            }
+            CellKind::Instance { module, .. } => return Err(format!("instance of '{module}' is not a primitive")),
+        })
+    }
+}
+
+fn check_name(name: &str, what: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains('.') {
+        return Err(format!("{what} name '{name}' must be non-empty and contain no '.'"));
+    }
+    Ok(())
+}
+
+impl SubcircuitDef {
+    pub fn new(name: &str, inputs: &[&str], outputs: &[&str]) -> Self {
+        Self {
+            name: name.into(),
+            params: Vec::new(),
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            cells: Vec::new(),
+        }
+    }
+
+    pub fn param(mut self, name: &str, default: i64) -> Self {
+        self.params.push(ParamDef { name: name.into(), default });
+        self
+    }
+
+    pub fn cell(mut self, name: &str, kind: CellKind, inputs: &[&str]) -> Self {
+        self.cells.push(CellDef {
+            name: name.into(),
+            kind,
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            repeat: None,
+            value: None,
+        });
+        self
+    }
+
+    /// Sets `repeat` on the last cell.
+    pub fn repeat(mut self, count: &str) -> Self {
+        self.cells.last_mut().expect("no cell to repeat").repeat = Some(count.into());
+        self
+    }
+
+    /// Sets `value` on the last cell.
+    pub fn value(mut self, expr: &str) -> Self {
+        self.cells.last_mut().expect("no cell to set").value = Some(expr.into());
+        self
+    }
+
+    pub fn instance(self, name: &str, module: &str, inputs: &[&str]) -> Self {
+        self.instance_with(name, module, &[], inputs)
+    }
+
+    /// Instance setting parameters of `module` to expressions.
+    pub fn instance_with(self, name: &str, module: &str, params: &[(&str, &str)], inputs: &[&str]) -> Self {
+        let params = params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        self.cell(name, CellKind::Instance { module: module.into(), params }, inputs)
+    }
+
+    /// Half adder: `sum = a ^ b`, `carry = a & b`.
+    pub fn half_adder() -> Self {
+        Self::new("half_adder", &["a", "b"], &["sum", "carry"])
+            .cell("sum", CellKind::Xor, &["a", "b"])
+            .cell("carry", CellKind::And, &["a", "b"])
+    }
+
+    /// Full adder made of two `half_adder` instances.
+    pub fn full_adder() -> Self {
+        Self::new("full_adder", &["a", "b", "cin"], &["sum", "cout"])
+            .instance("ha0", "half_adder", &["a", "b"])
+            .instance("ha1", "half_adder", &["ha0.sum", "cin"])
+            .cell("sum", CellKind::Wire, &["ha1.sum"])
+            .cell("cout", CellKind::Or, &["ha0.carry", "ha1.carry"])
+    }
+
+    /// `W`-bit register loading `d` on rising `clk` edges while `en` is
+    /// high, starting at `INIT`.
+    pub fn register() -> Self {
+        Self::new("register", &["d{0..W}", "en", "clk"], &["q{0..W}"])
+            .param("W", 8)
+            .param("INIT", 0)
+            .cell("hold", CellKind::Not, &["en"])
+            .cell("keep{i}", CellKind::And, &["q{i}", "hold"]).repeat("W")
+            .cell("load{i}", CellKind::And, &["d{i}", "en"]).repeat("W")
+            .cell("next{i}", CellKind::Or, &["keep{i}", "load{i}"]).repeat("W")
+            .cell("q{i}", CellKind::Dff { init: Signal::Low }, &["next{i}", "clk"]).repeat("W").value("INIT >> i & 1")
+    }
+
+    /// `odd` is high when an odd number of the `N` inputs are.
+    pub fn parity() -> Self {
+        Self::new("parity", &["x{0..N}"], &["odd"])
+            .param("N", 4)
+            .cell("odd", CellKind::Xor, &["x{0..N}"])
+    }
+
+    /// Default parameter values with `overrides` applied.
+    pub fn params(&self, overrides: &Params) -> Result<Params, String> {
+        let mut vars: Params = self.params.iter().map(|p| (p.name.clone(), p.default)).collect();
+        for (name, v) in overrides {
+            if !vars.contains_key(name) {
+                return Err(format!("'{}' has no parameter '{name}'", self.name));
+            }
+            vars.insert(name.clone(), *v);
+        }
+        Ok(vars)
+    }
+
+    /// The definition for parameter values `vars` (see `params`): ports and
+    /// cells expanded, `value`s applied and instance parameters evaluated.
+    pub fn expand(&self, vars: &Params) -> Result<SubcircuitDef, String> {
+        let ports = |list: &[String]| -> Result<Vec<String>, String> {
+            list.iter().map(|t| param::expand(t, vars)).collect::<Result<Vec<_>, _>>().map(|v| v.concat())
+        };
+        let mut cells = Vec::new();
+        for cell in &self.cells {
+            let count = cell.repeat.as_deref().map_or(Ok(1), |e| param::eval(e, vars))?;
+            let mut scope = vars.clone();
+            for i in 0..count {
+                if cell.repeat.is_some() {
+                    scope.insert("i".into(), i);
+                }
+                let name = param::substitute(&cell.name, &scope)?;
+                let inputs = cell.inputs.iter()
+                    .map(|t| param::expand(t, &scope))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .concat();
+                let value = cell.value.as_deref().map(|e| param::eval(e, &scope)).transpose()?;
+                let level = |default: Signal| match value {
+                    Some(0) => Signal::Low,
+                    Some(_) => Signal::High,
+                    None => default,
+                };
+                let kind = match &cell.kind {
+                    CellKind::Const { level: l } => CellKind::Const { level: level(*l) },
+                    CellKind::Dff { init } => CellKind::Dff { init: level(*init) },
+                    CellKind::Instance { module, params } => CellKind::Instance {
+                        module: module.clone(),
+                        params: params.iter()
+                            .map(|(k, e)| Ok((k.clone(), param::eval(e, &scope)?.to_string())))
+                            .collect::<Result<_, String>>()?,
+                    },
+                    kind => kind.clone(),
+                };
+                cells.push(CellDef { name, kind, inputs, repeat: None, value: None });
+            }
+        }
+        Ok(SubcircuitDef {
+            name: self.name.clone(),
+            params: Vec::new(),
+            inputs: ports(&self.inputs)?,
+            outputs: ports(&self.outputs)?,
+            cells,
+        })
+    }
+
+    /// Checks names, input counts and output ports with the default
+    /// parameters. Instances are checked against their modules when elaborated.
+    pub fn check(&self) -> Result<(), String> {
+        check_name(&self.name, "subcircuit")?;
+        for (i, p) in self.params.iter().enumerate() {
+            check_name(&p.name, "parameter")?;
+            if self.params[..i].iter().any(|o| o.name == p.name) || p.name == "i" {
+                return Err(format!("parameter '{}' of '{}' is repeated or reserved", p.name, self.name));
+            }
+        }
+        self.expand(&self.params(&Params::new())?)?.check_expanded()
+    }
+
+    fn check_expanded(&self) -> Result<(), String> {
+        let mut seen: Vec<&str> = Vec::new();
+        for name in self.inputs.iter().chain(self.cells.iter().map(|c| &c.name)) {
+            check_name(name, "port or cell")?;
+            if seen.contains(&name.as_str()) {
+                return Err(format!("'{name}' defined twice in '{}'", self.name));
+            }
+            seen.push(name);
+        }
+        for cell in &self.cells {
+            let (min, max) = cell.kind.arity();
+            let n = cell.inputs.len();
+            if n < min || max.is_some_and(|max| n > max) {
+                return Err(format!("cell '{}' of '{}' cannot take {n} inputs", cell.name, self.name));
+            }
+        }
+        for out in &self.outputs {
+            if !seen.contains(&out.as_str()) {
+                return Err(format!("output '{out}' of '{}' is not an input or cell", self.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The subcircuits every editor session starts with.
+pub fn standard_library() -> Vec<SubcircuitDef> {
+    vec![
+        SubcircuitDef::half_adder(),
+        SubcircuitDef::full_adder(),
+        SubcircuitDef::register(),
+        SubcircuitDef::parity(),
+    ]
+}
+
+/// Builds an instance of `module` at `path` whose input ports are driven by
+/// `inputs`, with parameters `params` (others keep their defaults). `defs`
+/// holds every module it may use.
+pub fn elaborate(defs: &HashMap<String, SubcircuitDef>, module: &str, path: &str, params: &Params, inputs: Vec<GateRef>) -> Result<Elaborated, String> {
+    let mut out = Elaborated::default();
+    let ports = elaborate_into(defs, module, path, params, inputs, &mut out, &mut Vec::new())?;
+    out.outputs = ports.into_iter().map(|(_, g)| g).collect();
+    Ok(out)
+}
+
+/// Input and output port names of `module` for `params`.
+pub fn ports(defs: &HashMap<String, SubcircuitDef>, module: &str, params: &Params) -> Result<(Vec<String>, Vec<String>), String> {
+    let def = defs.get(module).ok_or_else(|| format!("no subcircuit '{module}'"))?;
+    let def = def.expand(&def.params(params)?)?;
+    Ok((def.inputs, def.outputs))
+}
+
+fn elaborate_into(
+    defs: &HashMap<String, SubcircuitDef>,
+    module: &str,
+    path: &str,
+    params: &Params,
+    inputs: Vec<GateRef>,
+    out: &mut Elaborated,
+    stack: &mut Vec<String>,
+) -> Result<Vec<(String, GateRef)>, String> {
+    let def = defs.get(module).ok_or_else(|| format!("no subcircuit '{module}' (instance '{path}')"))?;
+    if stack.iter().any(|m| m == module) {
+        return Err(format!("'{module}' instantiates itself (instance '{path}')"));
+    }
+    let params = def.params(params).map_err(|e| format!("instance '{path}': {e}"))?;
+    let def = def.expand(&params).map_err(|e| format!("instance '{path}': {e}"))?;
+    def.check_expanded().map_err(|e| format!("instance '{path}': {e}"))?;
+    if inputs.len() != def.inputs.len() {
+        return Err(format!("'{module}' has {} inputs, instance '{path}' connects {}", def.inputs.len(), inputs.len()));
+    }
+    stack.push(module.into());
+    out.instances.push((path.into(), InstanceInfo { module: module.into(), params }));
+
+    let mut nets: HashMap<String, GateRef> = HashMap::new();
+    // nets read before their cell is built, connected once it is
+    let mut pending: HashMap<String, Rc<RefCell<Wire>>> = HashMap::new();
+
+    for (port, driver) in def.inputs.iter().zip(inputs) {
+        let id = format!("{path}.{port}");
+        let mut wire = Wire::new(&id);
+        wire.connect(driver);
+        let wire = gate(wire);
+        out.gates.push((id, wire.clone()));
+        nets.insert(port.clone(), wire);
+    }
+
+    for cell in &def.cells {
+        let ins: Vec<GateRef> = cell.inputs.iter()
+            .map(|name| match nets.get(name) {
+                Some(g) => g.clone(),
+                None => pending.entry(name.clone())
+                    .or_insert_with(|| Rc::new(RefCell::new(Wire::new(format!("{path}.{name}")))))
+                    .clone(),
+            })
+            .collect();
+        let id = format!("{path}.{}", cell.name);
+
+        let defined: Vec<(String, GateRef)> = match &cell.kind {
+            CellKind::Instance { module, params } => {
+                let params = params.iter()
+                    .map(|(k, v)| Ok((k.clone(), param::eval(v, &Params::new())?)))
+                    .collect::<Result<Params, String>>()?;
+                let ports = elaborate_into(defs, module, &id, &params, ins, out, stack)?;
+                ports.into_iter().map(|(p, g)| (format!("{}.{p}", cell.name), g)).collect()
+            }
+            kind => {
+                let g = kind.build(&id, ins).map_err(|e| format!("cell '{id}': {e}"))?;
+                out.gates.push((id, g.clone()));
+                vec![(cell.name.clone(), g)]
+            }
+        };
+        for (net, g) in defined {
+            if let Some(wire) = pending.remove(&net) {
+                wire.borrow_mut().connect(g.clone());
+            }
+            nets.insert(net, g);
+        }
+    }
+
+    if let Some(net) = pending.keys().min() {
+        return Err(format!("'{module}' reads undefined net '{net}' (instance '{path}')"));
+    }
+    stack.pop();
+    Ok(def.outputs.iter().map(|p| (p.clone(), nets[p].clone())).collect())
+}
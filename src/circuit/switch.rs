@@ -0,0 +1,345 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::gate::*;
+use crate::circuit::netlist::{GateKind, Loader, Saver, SharedKind};
+
+pub const VDD: &str = "vdd";
+pub const GND: &str = "gnd";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwitchKind {
+    /// Conducts while its gate is high.
+    Nmos,
+    /// Conducts while its gate is low.
+    Pmos,
+}
+
+/// A transistor used as an ideal bidirectional switch between `a` and `b`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Switch {
+    pub kind: SwitchKind,
+    pub gate: usize,
+    pub a: usize,
+    pub b: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Conduction {
+    On,
+    Off,
+    Unknown,
+}
+
+/// Transistor netlist solved at switch level. Nodes are named; `vdd` and
+/// `gnd` always exist, other nodes are created on first use and can be
+/// driven from ordinary gates with `input`.
+///
+/// Conducting transistors join nodes into groups. A group takes the level of
+/// the rails and inputs it touches (`X` if they disagree). A group touching
+/// none keeps the charge its nodes held at the previous solve: one level if
+/// they agree, `X` when charges of both levels are shared, `HiZ` if it was
+/// never driven. A transistor whose gate is `X` or `HiZ` may or may not
+/// conduct, so groups it could join become `X` where that would matter.
+#[derive(Debug)]
+pub struct SwitchNetwork {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    drivers: Vec<Option<GateRef>>,
+    switches: Vec<Switch>,
+    charge: RefCell<Vec<Signal>>,
+}
+
+/// One node of a `SwitchNetwork` seen as a gate. Every read solves the
+/// whole network.
+#[derive(Debug)]
+pub struct SwitchNode {
+    net: Rc<SwitchNetwork>,
+    node: usize,
+}
+
+impl Default for SwitchNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SwitchNetwork {
+    pub fn new() -> Self {
+        let mut net = Self {
+            names: Vec::new(),
+            index: HashMap::new(),
+            drivers: Vec::new(),
+            switches: Vec::new(),
+            charge: RefCell::new(Vec::new()),
+        };
+        let vdd = net.node(VDD);
+        let gnd = net.node(GND);
+        net.drivers[vdd] = Some(Rc::new(RefCell::new(ConstGate::new(Signal::High))));
+        net.drivers[gnd] = Some(Rc::new(RefCell::new(ConstGate::new(Signal::Low))));
+        net
+    }
+
+    /// Index of node `name`, creating it if needed.
+    pub fn node(&mut self, name: &str) -> usize {
+        if let Some(&i) = self.index.get(name) {
+            return i;
+        }
+        let i = self.names.len();
+        self.names.push(name.into());
+        self.index.insert(name.into(), i);
+        self.drivers.push(None);
+        self.charge.get_mut().push(Signal::HiZ);
+        i
+    }
+
+    pub fn node_names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Drives node `name` from `gate`. A `HiZ` driver leaves the node floating.
+    pub fn input(&mut self, name: &str, gate: GateRef) -> Result<usize, String> {
+        let i = self.node(name);
+        if is_rail(i) {
+            return Err(format!("cannot drive the '{name}' rail"));
+        }
+        self.drivers[i] = Some(gate);
+        Ok(i)
+    }
+
+    pub fn nmos(&mut self, gate: &str, a: &str, b: &str) {
+        self.add_switch(SwitchKind::Nmos, gate, a, b);
+    }
+
+    pub fn pmos(&mut self, gate: &str, a: &str, b: &str) {
+        self.add_switch(SwitchKind::Pmos, gate, a, b);
+    }
+
+    /// NMOS and PMOS in parallel between `a` and `b`; passes both levels
+    /// while `n_gate` is high and `p_gate` low.
+    pub fn transmission_gate(&mut self, n_gate: &str, p_gate: &str, a: &str, b: &str) {
+        self.nmos(n_gate, a, b);
+        self.pmos(p_gate, a, b);
+    }
+
+    fn add_switch(&mut self, kind: SwitchKind, gate: &str, a: &str, b: &str) {
+        let (gate, a, b) = (self.node(gate), self.node(a), self.node(b));
+        self.switches.push(Switch { kind, gate, a, b });
+    }
+
+    /// Network as saved by `Circuit::save`. `nodes` starts with the rails.
+    pub(crate) fn restore(cx: &mut Loader, nodes: &[String], drivers: &[Option<String>], switches: &[Switch], charge: &[Signal]) -> Result<Self, String> {
+        let mut net = Self::new();
+        if nodes.get(..2) != Some(&net.names[..]) || drivers.len() != nodes.len() || charge.len() != nodes.len() {
+            return Err("malformed switch network".into());
+        }
+        for (name, driver) in nodes.iter().zip(drivers).skip(2) {
+            if net.index.contains_key(name) {
+                return Err(format!("node '{name}' is repeated"));
+            }
+            match driver {
+                Some(id) => net.input(name, cx.gate(id)?)?,
+                None => net.node(name),
+            };
+        }
+        if switches.iter().any(|s| [s.gate, s.a, s.b].iter().any(|n| *n >= nodes.len())) {
+            return Err("transistor on a missing node".into());
+        }
+        net.switches = switches.to_vec();
+        *net.charge.get_mut() = charge.to_vec();
+        Ok(net)
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> SharedKind {
+        SharedKind::Network {
+            nodes: self.names.clone(),
+            drivers: self.drivers.iter().enumerate()
+                .map(|(i, d)| d.as_ref().filter(|_| !is_rail(i)).map(|g| cx.name(g)))
+                .collect(),
+            switches: self.switches.clone(),
+            charge: self.charge.borrow().clone(),
+        }
+    }
+
+    pub fn switches(&self) -> &[Switch] {
+        &self.switches
+    }
+
+    fn conduction(kind: SwitchKind, gate: Signal) -> Conduction {
+        match (kind, gate) {
+            (SwitchKind::Nmos, Signal::High) | (SwitchKind::Pmos, Signal::Low) => Conduction::On,
+            (SwitchKind::Nmos, Signal::Low) | (SwitchKind::Pmos, Signal::High) => Conduction::Off,
+            _ => Conduction::Unknown,
+        }
+    }
+
+    /// Solves the network for the current inputs and stores the result as
+    /// the charge held by each node.
+    pub fn solve(&self) -> Vec<Signal> {
+        let n = self.names.len();
+        let sources: Vec<Option<Signal>> = self.drivers.iter()
+            .map(|d| d.as_ref().map(|g| g.borrow().eval()).filter(|s| *s != Signal::HiZ))
+            .collect();
+        let charge = self.charge.borrow().clone();
+
+        let mut values = charge.clone();
+        for (v, s) in values.iter_mut().zip(&sources) {
+            if let Some(s) = s {
+                *v = *s;
+            }
+        }
+
+        // transistor gates may hang off other nodes, so iterate to a fixed point
+        for _ in 0..2 * n + 4 {
+            let next = self.solve_pass(&values, &sources, &charge);
+            if next == values {
+                break;
+            }
+            values = next;
+        }
+
+        *self.charge.borrow_mut() = values.clone();
+        values
+    }
+
+    fn solve_pass(&self, values: &[Signal], sources: &[Option<Signal>], charge: &[Signal]) -> Vec<Signal> {
+        let n = self.names.len();
+        let mut groups = UnionFind::new(n);
+        let mut maybe = Vec::new();
+        let mut taps = Vec::new();
+        for s in &self.switches {
+            match Self::conduction(s.kind, values[s.gate]) {
+                // rails are ideal supplies: they feed a group but never join one
+                Conduction::On if is_rail(s.a) || is_rail(s.b) => {
+                    taps.extend([(s.a, s.b), (s.b, s.a)].into_iter().filter(|(r, _)| is_rail(*r)));
+                }
+                Conduction::On => groups.union(s.a, s.b),
+                Conduction::Unknown => maybe.push((s.a, s.b)),
+                Conduction::Off => {}
+            }
+        }
+
+        let merge = |acc: Option<Signal>, s: Signal| match acc {
+            None => Some(s),
+            Some(a) if a == s => Some(a),
+            _ => Some(Signal::X),
+        };
+        let mut driven: HashMap<usize, Option<Signal>> = HashMap::new();
+        let mut stored: HashMap<usize, Option<Signal>> = HashMap::new();
+        for i in 0..n {
+            let root = groups.find(i);
+            let d = driven.entry(root).or_default();
+            if let Some(s) = sources[i] {
+                *d = merge(*d, s);
+            }
+            let c = stored.entry(root).or_default();
+            if charge[i] != Signal::HiZ {
+                *c = merge(*c, charge[i]);
+            }
+        }
+
+        for (rail, node) in taps {
+            if !is_rail(node) {
+                let d = driven.get_mut(&groups.find(node)).unwrap();
+                *d = merge(*d, sources[rail].unwrap());
+            }
+        }
+
+        let mut level: HashMap<usize, Signal> = driven.iter()
+            .map(|(&root, d)| (root, d.or(stored[&root]).unwrap_or(Signal::HiZ)))
+            .collect();
+
+        // a switch that might conduct spoils whichever side it could change
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(a, b) in &maybe {
+                let (ra, rb) = (groups.find(a), groups.find(b));
+                if ra == rb || level[&ra] == level[&rb] {
+                    continue;
+                }
+                for (r, other) in [(ra, rb), (rb, ra)] {
+                    let weaker = !is_rail(r) && (driven[&r].is_none() || driven[&other].is_some());
+                    if weaker && level[&r] != Signal::X {
+                        level.insert(r, Signal::X);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        (0..n).map(|i| level[&groups.find(i)]).collect()
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        self.drivers.iter().flatten().cloned().collect()
+    }
+}
+
+fn is_rail(node: usize) -> bool {
+    node < 2
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self { parent: (0..n).collect() }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        self.parent[ra] = rb;
+    }
+}
+
+impl SwitchNode {
+    pub(crate) fn restore(net: Rc<SwitchNetwork>, node: usize) -> Result<Self, String> {
+        if node >= net.names.len() {
+            return Err(format!("switch network has no node {node}"));
+        }
+        Ok(Self { net, node })
+    }
+
+    pub fn new(net: Rc<SwitchNetwork>, name: &str) -> Result<Self, String> {
+        let node = *net.index.get(name).ok_or_else(|| format!("no node '{name}' in the switch network"))?;
+        Ok(Self { net, node })
+    }
+}
+
+impl Gate for SwitchNode {
+    fn eval(&self) -> Signal {
+        self.net.solve()[self.node]
+    }
+
+    fn description(&self) -> String {
+        format!("SwitchNode({}, {} transistors)", self.net.names[self.node], self.net.switches.len())
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        let network = cx.shared(&self.net, |cx| self.net.to_netlist(cx));
+        Some(GateKind::SwitchNode { network, node: self.node })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        self.net.inputs()
+    }
+}
@@ -0,0 +1,328 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::rc::Rc;
+use std::sync::mpsc;
+
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::netlist::{GateKind, Loader, Saver, SharedKind};
+
+/// Character streams shared by `TtyOutput` and `Keyboard` components.
+/// The GUI console and `run_headless` read and fill these.
+#[derive(Debug, Default)]
+pub struct Terminal {
+    output: Vec<u8>,
+    flushed: usize,
+    input: VecDeque<u8>,
+    /// Whether a `Keyboard` reads `input`.
+    keyboard: bool,
+}
+
+/// Appends the byte on `data` to its terminal on every rising `strobe` edge.
+#[derive(Debug)]
+pub struct TtyOutput {
+    terminal: Rc<RefCell<Terminal>>,
+    data: Vec<GateRef>,
+    strobe: GateRef,
+    last_strobe: RefCell<Signal>,
+    pending: RefCell<Option<u8>>,
+}
+
+/// Buffered keyboard. `data` shows the oldest unread key (zero when empty),
+/// `ready` is high while keys are buffered and a rising `ack` edge drops the
+/// oldest key.
+#[derive(Debug)]
+pub struct Keyboard {
+    pub data: Vec<GateRef>,
+    pub ready: GateRef,
+}
+
+#[derive(Debug)]
+pub(crate) struct KeyboardShared {
+    terminal: Rc<RefCell<Terminal>>,
+    ack: GateRef,
+    last_ack: RefCell<Signal>,
+    pop: RefCell<bool>,
+}
+
+/// One output of a `Keyboard`: a data bit, or `ready` when `bit` is `None`.
+#[derive(Debug)]
+pub struct KeyboardPort {
+    shared: Rc<KeyboardShared>,
+    bit: Option<usize>,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn output_text(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+
+    /// Bytes written since the previous call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        let new = self.output[self.flushed..].to_vec();
+        self.flushed = self.output.len();
+        new
+    }
+
+    pub fn write_byte(&mut self, b: u8) {
+        self.output.push(b);
+    }
+
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    pub fn has_keyboard(&self) -> bool {
+        self.keyboard
+    }
+
+    pub(crate) fn restore(output: &[u8], flushed: usize, input: &[u8]) -> Result<Self, String> {
+        if flushed > output.len() {
+            return Err(format!("{flushed} bytes flushed of {} written", output.len()));
+        }
+        Ok(Self { output: output.to_vec(), flushed, input: input.iter().copied().collect(), keyboard: false })
+    }
+
+    pub(crate) fn to_netlist(&self) -> SharedKind {
+        SharedKind::Terminal { output: self.output.clone(), flushed: self.flushed, input: self.input.iter().copied().collect() }
+    }
+
+    pub fn clear(&mut self) {
+        self.output.clear();
+        self.flushed = 0;
+        self.input.clear();
+    }
+}
+
+impl TtyOutput {
+    pub fn new(terminal: Rc<RefCell<Terminal>>, data: Vec<GateRef>, strobe: GateRef) -> Self {
+        assert!(data.len() <= 8, "a TTY takes at most 8 data bits");
+        Self {
+            terminal,
+            data,
+            strobe,
+            last_strobe: RefCell::new(Signal::Low),
+            pending: RefCell::new(None),
+        }
+    }
+}
+
+impl TtyOutput {
+    pub(crate) fn restore(terminal: Rc<RefCell<Terminal>>, data: Vec<GateRef>, strobe: GateRef, last_strobe: Signal) -> Result<Self, String> {
+        if data.len() > 8 {
+            return Err("a TTY takes at most 8 data bits".into());
+        }
+        let tty = Self::new(terminal, data, strobe);
+        *tty.last_strobe.borrow_mut() = last_strobe;
+        Ok(tty)
+    }
+}
+
+/// Keyboard state saved by `Circuit::save`, reading shared terminal `terminal`.
+pub(crate) fn restore_keyboard(cx: &mut Loader, terminal: usize, ack: &str, last_ack: Signal) -> Result<Rc<dyn Any>, String> {
+    let terminal: Rc<RefCell<Terminal>> = cx.shared(terminal)?;
+    terminal.borrow_mut().keyboard = true;
+    Ok(Rc::new(KeyboardShared {
+        terminal,
+        ack: cx.gate(ack)?,
+        last_ack: RefCell::new(last_ack),
+        pop: RefCell::new(false),
+    }))
+}
+
+impl KeyboardPort {
+    pub(crate) fn restore(shared: Rc<KeyboardShared>, bit: Option<usize>) -> Result<Self, String> {
+        if bit.is_some_and(|b| b >= 8) {
+            return Err("a keyboard has 8 data bits".into());
+        }
+        Ok(Self { shared, bit })
+    }
+}
+
+impl Keyboard {
+    pub fn new(terminal: Rc<RefCell<Terminal>>, ack: GateRef) -> Self {
+        terminal.borrow_mut().keyboard = true;
+        let shared = Rc::new(KeyboardShared {
+            terminal,
+            ack,
+            last_ack: RefCell::new(Signal::Low),
+            pop: RefCell::new(false),
+        });
+        let port = |bit| Rc::new(RefCell::new(KeyboardPort { shared: shared.clone(), bit })) as GateRef;
+
+        Self {
+            data: (0..8).map(|i| port(Some(i))).collect(),
+            ready: port(None),
+        }
+    }
+}
+
+impl Gate for TtyOutput {
+    fn eval(&self) -> Signal {
+        self.strobe.borrow().eval()
+    }
+
+    fn description(&self) -> String {
+        format!("Tty({} chars)", self.terminal.borrow().output.len())
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Tty {
+            terminal: cx.shared(&self.terminal, |_| self.terminal.borrow().to_netlist()),
+            data: cx.names(&self.data),
+            strobe: cx.name(&self.strobe),
+            last_strobe: *self.last_strobe.borrow(),
+        })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        self.data.iter().chain([&self.strobe]).cloned().collect()
+    }
+
+    fn clock_edge(&self) -> bool {
+        rising(&self.strobe, &self.last_strobe)
+    }
+
+    fn capture(&self) {
+        let byte = self.data.iter().enumerate().fold(0u8, |acc, (i, g)| {
+            acc | (g.borrow().eval().is_high() as u8) << i
+        });
+        *self.pending.borrow_mut() = Some(byte);
+    }
+
+    fn commit(&self) {
+        if let Some(b) = self.pending.borrow_mut().take() {
+            self.terminal.borrow_mut().write_byte(b);
+        }
+    }
+}
+
+impl Gate for KeyboardPort {
+    fn eval(&self) -> Signal {
+        let term = self.shared.terminal.borrow();
+        let high = match (self.bit, term.input.front()) {
+            (None, front) => front.is_some(),
+            (Some(bit), Some(key)) => key >> bit & 1 == 1,
+            (Some(_), None) => false,
+        };
+        if high { Signal::High } else { Signal::Low }
+    }
+
+    fn description(&self) -> String {
+        match self.bit {
+            Some(bit) => format!("Keyboard(bit {bit})"),
+            None => "Keyboard(ready)".into(),
+        }
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        let s = &self.shared;
+        let keyboard = cx.shared(s, |cx| SharedKind::Keyboard {
+            terminal: cx.shared(&s.terminal, |_| s.terminal.borrow().to_netlist()),
+            ack: cx.name(&s.ack),
+            last_ack: *s.last_ack.borrow(),
+        });
+        Some(GateKind::KeyboardPort { keyboard, bit: self.bit })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        vec![self.shared.ack.clone()]
+    }
+
+    // the ports share one ack history, so only one of them pops per edge
+    fn clock_edge(&self) -> bool {
+        rising(&self.shared.ack, &self.shared.last_ack)
+    }
+
+    fn capture(&self) {
+        *self.shared.pop.borrow_mut() = true;
+    }
+
+    fn commit(&self) {
+        if self.shared.pop.replace(false) {
+            self.shared.terminal.borrow_mut().input.pop_front();
+        }
+    }
+}
+
+/// Runs `circuit` for `steps` steps with terminal `name` mapped to the given
+/// streams: bytes read from `input` become key presses and every character
+/// the circuit prints is written to `output` as it appears.
+///
+/// Like a program reading stdin, a keyboard that has used up its keys waits
+/// for the next ones until `input` ends; a terminal without a keyboard never
+/// waits. `input` is read on a separate thread, which is not joined: it may
+/// still be blocked in `read` when this returns, and ends at the end of
+/// `input`, on a read error, or after its next read once nobody receives.
+/// Callers that run this more than once should pass an `input` that ends.
+pub fn run_headless<R, W>(circuit: &mut Circuit, name: &str, steps: usize, input: R, output: &mut W) -> anyhow::Result<()>
+where
+    R: Read + Send + 'static,
+    W: Write,
+{
+    let Some(terminal) = circuit.terminal(name) else {
+        anyhow::bail!("no terminal named '{name}' in the circuit");
+    };
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut input = input;
+        let mut buf = [0u8; 256];
+        loop {
+            match input.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let mut open = true;
+    for _ in 0..steps {
+        let mut keys = Vec::new();
+        let waiting = terminal.borrow().has_keyboard() && terminal.borrow().pending_input() == 0;
+        if open && waiting {
+            match rx.recv() {
+                Ok(chunk) => keys = chunk,
+                Err(_) => open = false,
+            }
+        }
+        keys.extend(rx.try_iter().flatten());
+        terminal.borrow_mut().push_input(&keys);
+
+        circuit.step();
+
+        let out = terminal.borrow_mut().take_output();
+        if !out.is_empty() {
+            output.write_all(&out)?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,468 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::{Signal, SEVEN_SEG_FONT};
+use crate::circuit::lut::TruthTable;
+use crate::circuit::netlist::{GateKind, NetList, SharedKind};
+use crate::circuit::param::Params;
+use crate::circuit::subcircuit::{CellDef, CellKind};
+use crate::circuit::switch::{SwitchKind, GND, VDD};
+
+/// Reserved words of IEEE 1364-2001, which nets must not be named.
+const KEYWORDS: &[&str] = &[
+    "always", "and", "assign", "automatic", "begin", "buf", "bufif0", "bufif1", "case", "casex", "casez", "cell",
+    "cmos", "config", "deassign", "default", "defparam", "design", "disable", "edge", "else", "end", "endcase",
+    "endconfig", "endfunction", "endgenerate", "endmodule", "endprimitive", "endspecify", "endtable", "endtask",
+    "event", "for", "force", "forever", "fork", "function", "generate", "genvar", "highz0", "highz1", "if", "ifnone",
+    "incdir", "include", "initial", "inout", "input", "instance", "integer", "join", "large", "liblist", "library",
+    "localparam", "macromodule", "medium", "module", "nand", "negedge", "nmos", "nor", "noshowcancelled", "not",
+    "notif0", "notif1", "or", "output", "parameter", "pmos", "posedge", "primitive", "pull0", "pull1", "pulldown",
+    "pullup", "pulsestyle_ondetect", "pulsestyle_onevent", "rcmos", "real", "realtime", "reg", "release", "repeat",
+    "rnmos", "rpmos", "rtran", "rtranif0", "rtranif1", "scalared", "showcancelled", "signed", "small", "specify",
+    "specparam", "strong0", "strong1", "supply0", "supply1", "table", "task", "time", "tran", "tranif0", "tranif1",
+    "tri", "tri0", "tri1", "triand", "trior", "trireg", "unsigned", "use", "vectored", "wait", "wand", "weak0",
+    "weak1", "while", "wire", "wor", "xnor", "xor",
+];
+
+/// One statement of a module, over nets named as in the circuit. Shared
+/// with the BLIF writer, which renders the same modules.
+pub(crate) enum Item {
+    Const(String, Signal),
+    /// Gate primitive, output first: `and (y, a, b);`.
+    Prim(&'static str, String, Vec<String>),
+    OpenDrain(String, String),
+    Pull(String, bool),
+    Dff { q: String, d: String, clk: String, init: Signal },
+    Dlatch { q: String, d: String, enable: String, init: Signal },
+    SrLatch { q: String, set: String, reset: String, init: Signal },
+    /// `table` indexed by `inputs`, input 0 being the low bit.
+    Table { out: String, inputs: Vec<String>, table: TruthTable },
+    Assign(String, String),
+    /// Bidirectional switch between two nets.
+    Tran { kind: SwitchKind, gate: String, a: String, b: String },
+    /// `ports` pairs a port of `module`, inputs then outputs as in its
+    /// `Module`, with the net it connects.
+    Instance { module: String, label: String, ports: Vec<(String, String)> },
+}
+
+impl Item {
+    fn nets(&self) -> Vec<&String> {
+        match self {
+            Item::Const(n, _) | Item::Pull(n, _) => vec![n],
+            Item::Prim(_, out, ins) => std::iter::once(out).chain(ins).collect(),
+            Item::OpenDrain(a, b) | Item::Assign(a, b) => vec![a, b],
+            Item::Dff { q, d, clk, .. } => vec![q, d, clk],
+            Item::Dlatch { q, d, enable, .. } => vec![q, d, enable],
+            Item::SrLatch { q, set, reset, .. } => vec![q, set, reset],
+            Item::Table { out, inputs, .. } => std::iter::once(out).chain(inputs).collect(),
+            Item::Tran { gate, a, b, .. } => vec![gate, a, b],
+            Item::Instance { ports, .. } => ports.iter().map(|(_, n)| n).collect(),
+        }
+    }
+
+    /// The net this item stores state in, with its initial level.
+    fn register(&self) -> Option<(&String, Signal)> {
+        match self {
+            Item::Dff { q, init, .. } | Item::Dlatch { q, init, .. } | Item::SrLatch { q, init, .. } => Some((q, *init)),
+            _ => None,
+        }
+    }
+}
+
+/// Verilog identifiers for net names, unique within a module.
+#[derive(Default)]
+struct Names {
+    idents: HashMap<String, String>,
+    used: HashSet<String>,
+}
+
+impl Names {
+    fn ident(&mut self, net: &str) -> String {
+        if let Some(id) = self.idents.get(net) {
+            return id.clone();
+        }
+        let id = self.fresh(net);
+        self.idents.insert(net.into(), id.clone());
+        id
+    }
+
+    /// A new identifier looking like `hint`.
+    fn fresh(&mut self, hint: &str) -> String {
+        let base = sanitize(hint);
+        let mut id = base.clone();
+        let mut n = 1;
+        while !self.used.insert(id.clone()) {
+            id = format!("{base}_{n}");
+            n += 1;
+        }
+        id
+    }
+}
+
+/// `fa.sum` becomes `fa__sum`, `$3` becomes `n3`.
+fn sanitize(name: &str) -> String {
+    let name = name.strip_prefix('$').map_or_else(|| name.to_string(), |n| format!("n{n}"));
+    let mut id: String = name.replace('.', "__")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if !id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        id.insert_str(0, "n_");
+    }
+    if KEYWORDS.contains(&id.as_str()) {
+        id.push('_');
+    }
+    id
+}
+
+fn literal(level: Signal) -> &'static str {
+    match level {
+        Signal::Low => "1'b0",
+        Signal::High => "1'b1",
+        Signal::HiZ => "1'bz",
+        Signal::X => "1'bx",
+    }
+}
+
+/// Port identifiers of a module; an output that is also an input gets a
+/// port of its own.
+fn port_idents(inputs: &[String], outputs: &[String]) -> (Names, Vec<String>, Vec<String>) {
+    let mut names = Names::default();
+    let ins = inputs.iter().map(|n| names.ident(n)).collect();
+    let outs = outputs.iter()
+        .map(|n| if inputs.contains(n) { names.fresh(n) } else { names.ident(n) })
+        .collect();
+    (names, ins, outs)
+}
+
+/// A module of the design: a subcircuit for some parameter values, or the
+/// top.
+pub(crate) struct Module {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub items: Vec<Item>,
+}
+
+fn render(module: &Module, modules: &BTreeMap<String, Module>) -> String {
+    let Module { name, inputs, outputs, items } = module;
+    let (mut names, in_ids, out_ids) = port_idents(inputs, outputs);
+    let regs: BTreeMap<&String, Signal> = items.iter().filter_map(Item::register).collect();
+
+    let mut ports: Vec<String> = in_ids.iter().map(|id| format!("input wire {id}")).collect();
+    for (net, id) in outputs.iter().zip(&out_ids) {
+        let kind = if regs.contains_key(net) && !inputs.contains(net) { "reg" } else { "wire" };
+        ports.push(format!("output {kind} {id}"));
+    }
+    let mut text = format!("module {name} (\n    {}\n);\n", ports.join(",\n    "));
+
+    let mut declared: HashSet<&String> = inputs.iter().chain(outputs).collect();
+    for net in items.iter().flat_map(Item::nets) {
+        if declared.insert(net) {
+            let kind = if regs.contains_key(net) { "reg" } else { "wire" };
+            writeln!(text, "    {kind} {};", names.ident(net)).unwrap();
+        }
+    }
+    for (net, init) in &regs {
+        if matches!(init, Signal::Low | Signal::High) {
+            writeln!(text, "    initial {} = {};", names.ident(net), literal(*init)).unwrap();
+        }
+    }
+    for (net, id) in outputs.iter().zip(&out_ids) {
+        if inputs.contains(net) {
+            writeln!(text, "    assign {id} = {};", names.ident(net)).unwrap();
+        }
+    }
+
+    for item in items {
+        let mut n = |net: &String| names.ident(net);
+        let line = match item {
+            Item::Const(out, level) => format!("assign {} = {};", n(out), literal(*level)),
+            Item::Prim(op, out, ins) => {
+                let args: Vec<String> = std::iter::once(out).chain(ins).map(&mut n).collect();
+                format!("{op} ({});", args.join(", "))
+            }
+            Item::OpenDrain(out, input) => format!("bufif0 ({}, 1'b0, {});", n(out), n(input)),
+            Item::Pull(net, up) => format!("{} ({});", if *up { "pullup" } else { "pulldown" }, n(net)),
+            Item::Dff { q, d, clk, .. } => format!("always @(posedge {}) {} <= {};", n(clk), n(q), n(d)),
+            Item::Dlatch { q, d, enable, .. } => format!("always @(*) if ({}) {} = {};", n(enable), n(q), n(d)),
+            Item::SrLatch { q, set, reset, .. } => {
+                let (q, s, r) = (n(q), n(set), n(reset));
+                format!("always @(*) if ({s} && !{r}) {q} = 1'b1; else if ({r} && !{s}) {q} = 1'b0;")
+            }
+            Item::Table { out, inputs, table } => {
+                let bits: String = (0..table.rows()).rev().map(|row| if table.get(row) { '1' } else { '0' }).collect();
+                let select: Vec<String> = inputs.iter().rev().map(&mut n).collect();
+                let out = n(out);
+                let param = names.fresh(&format!("{out}_table"));
+                writeln!(text, "    localparam [{}:0] {param} = {}'b{bits};", bits.len() - 1, bits.len()).unwrap();
+                format!("assign {out} = {param}[{{{}}}];", select.join(", "))
+            }
+            Item::Assign(out, src) => format!("assign {} = {};", n(out), n(src)),
+            Item::Tran { kind, gate, a, b } => {
+                let op = match kind { SwitchKind::Nmos => "tranif1", SwitchKind::Pmos => "tranif0" };
+                format!("{op} ({}, {}, {});", n(a), n(b), n(gate))
+            }
+            Item::Instance { module, label, ports } => {
+                let sub = &modules[module];
+                let (_, ins, outs) = port_idents(&sub.inputs, &sub.outputs);
+                let conns: Vec<String> = ins.iter().chain(&outs).zip(ports).map(|(port, (_, net))| format!(".{port}({})", n(net))).collect();
+                format!("{module} {} ({});", names.fresh(label), conns.join(", "))
+            }
+        };
+        writeln!(text, "    {line}").unwrap();
+    }
+    text.push_str("endmodule\n");
+    text
+}
+
+/// Name and ports of a module.
+struct Ports {
+    module: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+struct Exporter<'a> {
+    circuit: &'a Circuit,
+    /// Modules by name; `None` while one is being built.
+    modules: BTreeMap<String, Option<Module>>,
+}
+
+fn dedup(list: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    list.iter().filter(|n| seen.insert(*n)).cloned().collect()
+}
+
+impl Exporter<'_> {
+    /// Builds `module` with `params` unless it was already, returning its
+    /// ports. Parameterized subcircuits get one module per set of values,
+    /// e.g. `register_W4_INIT0`.
+    fn module(&mut self, module: &str, params: &Params) -> Result<Ports, String> {
+        let def = self.circuit.definition(module).ok_or_else(|| format!("no subcircuit '{module}'"))?;
+        let vars = def.params(params)?;
+        let mut name = def.name.clone();
+        for p in &def.params {
+            let v = vars[&p.name];
+            name.push_str(&format!("_{}{}{}", p.name, if v < 0 { "m" } else { "" }, v.abs()));
+        }
+        let def = def.expand(&vars)?;
+        let ports = Ports { module: sanitize(&name), inputs: def.inputs.clone(), outputs: dedup(&def.outputs) };
+
+        if !self.modules.contains_key(&ports.module) {
+            self.modules.insert(ports.module.clone(), None);
+            let mut items = Vec::new();
+            for cell in &def.cells {
+                items.push(self.cell(cell)?);
+            }
+            let module = Module { name: ports.module.clone(), inputs: ports.inputs.clone(), outputs: ports.outputs.clone(), items };
+            self.modules.insert(ports.module.clone(), Some(module));
+        }
+        Ok(ports)
+    }
+
+    fn cell(&mut self, cell: &CellDef) -> Result<Item, String> {
+        let out = cell.name.clone();
+        let ins = cell.inputs.clone();
+        let arg = |i: usize| ins[i].clone();
+        Ok(match &cell.kind {
+            CellKind::Const { level } => Item::Const(out, *level),
+            CellKind::And => Item::Prim("and", out, ins),
+            CellKind::Or => Item::Prim("or", out, ins),
+            CellKind::Xor => Item::Prim("xor", out, ins),
+            CellKind::Nand => Item::Prim("nand", out, ins),
+            CellKind::Nor => Item::Prim("nor", out, ins),
+            CellKind::Xnor => Item::Prim("xnor", out, ins),
+            CellKind::Not => Item::Prim("not", out, ins),
+            CellKind::Buffer => Item::Prim("buf", out, ins),
+            CellKind::TriState => Item::Prim("bufif1", out, ins),
+            CellKind::OpenDrain => Item::OpenDrain(out, arg(0)),
+            CellKind::Dff { init } => Item::Dff { q: out, d: arg(0), clk: arg(1), init: *init },
+            CellKind::Dlatch => Item::Dlatch { q: out, d: arg(0), enable: arg(1), init: Signal::X },
+            CellKind::SrLatch => Item::SrLatch { q: out, set: arg(0), reset: arg(1), init: Signal::X },
+            CellKind::Lut { table } => Item::Table { out, inputs: ins, table: *table },
+            CellKind::Wire => match ins.as_slice() {
+                [src] => Item::Assign(out, src.clone()),
+                _ => return Err(format!("cell '{}' joins {} drivers; only single-driver wires can be written", cell.name, ins.len())),
+            },
+            CellKind::Instance { module, params } => {
+                let params = params.iter()
+                    .map(|(k, v)| v.parse().map(|v| (k.clone(), v)).map_err(|_| format!("parameter {k} = '{v}' of '{}'", cell.name)))
+                    .collect::<Result<Params, String>>()?;
+                let sub = self.module(module, &params)?;
+                if sub.inputs.len() != ins.len() {
+                    return Err(format!("'{module}' has {} inputs, cell '{}' connects {}", sub.inputs.len(), cell.name, ins.len()));
+                }
+                let mut ports: Vec<(String, String)> = sub.inputs.iter().cloned().zip(ins).collect();
+                ports.extend(sub.outputs.iter().map(|p| (p.clone(), format!("{}.{p}", cell.name))));
+                Item::Instance { module: sub.module, label: cell.name.clone(), ports }
+            }
+        })
+    }
+
+    /// Items for gate `id` of the top module, plus nets it reads through
+    /// shared state.
+    fn gate(&self, net: &NetList, id: &str, kind: &GateKind, done: &mut HashSet<usize>) -> Result<(Vec<Item>, Vec<String>), String> {
+        use GateKind::*;
+        let id = id.to_string();
+        let prim = |op, ins: &[&String]| Item::Prim(op, id.clone(), ins.iter().map(|s| s.to_string()).collect());
+        let item = match kind {
+            Input { .. } | Switch { .. } | Button { .. } | Clock { .. } => return Ok((Vec::new(), Vec::new())),
+            Alias { of } => Item::Assign(id, of.clone()),
+            Const { level } => Item::Const(id, *level),
+            Pull { level } => Item::Pull(id, level.is_high()),
+            Buffer { input } | Output { input } => prim("buf", &[input]),
+            Not { input } => prim("not", &[input]),
+            OpenDrain { input } => Item::OpenDrain(id, input.clone()),
+            TriState { input, enable } => prim("bufif1", &[input, enable]),
+            And { a, b } => prim("and", &[a, b]),
+            Or { a, b } => prim("or", &[a, b]),
+            Xor { a, b } => prim("xor", &[a, b]),
+            Nand { a, b } => prim("nand", &[a, b]),
+            Nor { a, b } => prim("nor", &[a, b]),
+            Xnor { a, b } => prim("xnor", &[a, b]),
+            SrLatch { set, reset, q } => Item::SrLatch { q: id, set: set.clone(), reset: reset.clone(), init: *q },
+            DLatch { d, enable, q } => Item::Dlatch { q: id, d: d.clone(), enable: enable.clone(), init: *q },
+            DFlipFlop { d, clk, q, .. } => Item::Dff { q: id, d: d.clone(), clk: clk.clone(), init: *q },
+            Segment { inputs, segment, hex } => {
+                let table = TruthTable::from_fn(4, |row| (*hex || row <= 9) && SEVEN_SEG_FONT[row] >> segment & 1 == 1)?;
+                Item::Table { out: id, inputs: inputs.to_vec(), table }
+            }
+            Lut { inputs, table } => Item::Table { out: id, inputs: inputs.clone(), table: *table },
+            Wire { drivers, .. } => {
+                let items = drivers.iter()
+                    .map(|d| match net.gates.get(d).or_else(|| net.internal.get(d)) {
+                        // a pull resistor only weakly drives the net
+                        Some(Pull { level }) => Item::Pull(id.clone(), level.is_high()),
+                        _ => Item::Assign(id.clone(), d.clone()),
+                    })
+                    .collect();
+                return Ok((items, Vec::new()));
+            }
+            SwitchNode { network, node } => {
+                let Some(SharedKind::Network { nodes, drivers, switches, .. }) = net.shared.get(*network) else {
+                    return Err(format!("gate '{id}' names a missing switch network"));
+                };
+                let node_net = |i: usize| format!("#sw{network}.{}", nodes[i]);
+                let mut items = vec![Item::Assign(id, node_net(*node))];
+                let mut reads = Vec::new();
+                if done.insert(*network) {
+                    for (i, name) in nodes.iter().enumerate() {
+                        if name == VDD || name == GND {
+                            items.push(Item::Const(node_net(i), if name == VDD { Signal::High } else { Signal::Low }));
+                        } else if let Some(d) = &drivers[i] {
+                            items.push(Item::Assign(node_net(i), d.clone()));
+                            reads.push(d.clone());
+                        }
+                    }
+                    for s in switches {
+                        items.push(Item::Tran { kind: s.kind, gate: node_net(s.gate), a: node_net(s.a), b: node_net(s.b) });
+                    }
+                }
+                return Ok((items, reads));
+            }
+            other => {
+                let kind = serde_json::to_value(other).ok()
+                    .and_then(|v| v["kind"].as_str().map(String::from))
+                    .unwrap_or_default();
+                return Err(format!("gate '{id}' ({kind}) has no structural Verilog form"));
+            }
+        };
+        Ok((vec![item], Vec::new()))
+    }
+
+    fn top(&mut self, top: &str) -> Result<Module, String> {
+        let net = self.circuit.to_netlist()?;
+        let paths: Vec<&String> = net.instances.keys()
+            .filter(|p| !net.instances.keys().any(|q| p.starts_with(&format!("{q}."))))
+            .collect();
+        let owner = |id: &str| paths.iter().copied().find(|p| id.starts_with(&format!("{p}.")));
+
+        let mut items = Vec::new();
+        let mut stack: Vec<String> = Vec::new();
+        let mut ports_of: HashMap<&String, HashSet<String>> = HashMap::new();
+        for path in &paths {
+            let info = &net.instances[*path];
+            let ports = self.module(&info.module, &info.params)?;
+            let mut conns = Vec::new();
+            for port in &ports.inputs {
+                let wire = format!("{path}.{port}");
+                if let Some(GateKind::Wire { drivers, .. }) = net.gates.get(&wire) {
+                    for d in drivers {
+                        items.push(Item::Assign(wire.clone(), d.clone()));
+                        stack.push(d.clone());
+                    }
+                }
+                conns.push((port.clone(), wire));
+            }
+            conns.extend(ports.outputs.iter().map(|port| (port.clone(), format!("{path}.{port}"))));
+            ports_of.insert(path, ports.inputs.iter().chain(&ports.outputs).map(|p| format!("{path}.{p}")).collect());
+            items.push(Item::Instance { module: ports.module, label: path.to_string(), ports: conns });
+        }
+
+        // everything outside the instances that the design uses
+        stack.extend(net.outputs.iter().cloned());
+        stack.extend(net.gates.keys().filter(|id| owner(id).is_none()).cloned());
+        let mut reached = BTreeSet::new();
+        let mut networks = HashSet::new();
+        let mut gate_items = BTreeMap::new();
+        while let Some(id) = stack.pop() {
+            if !reached.insert(id.clone()) {
+                continue;
+            }
+            if let Some(path) = owner(&id) {
+                if !ports_of[path].contains(&id) {
+                    return Err(format!("'{id}' is inside instance '{path}'; only its ports can be used outside it"));
+                }
+                continue;
+            }
+            let kind = net.gates.get(&id).or_else(|| net.internal.get(&id)).ok_or_else(|| format!("no gate '{id}'"))?;
+            let (its, reads) = self.gate(&net, &id, kind, &mut networks)?;
+            gate_items.insert(id.clone(), its);
+            stack.extend(kind.inputs().into_iter().cloned());
+            stack.extend(reads);
+        }
+        items.extend(gate_items.into_values().flatten());
+
+        let inputs: Vec<String> = reached.iter()
+            .filter(|id| matches!(
+                net.gates.get(*id).or_else(|| net.internal.get(*id)),
+                Some(GateKind::Input { .. } | GateKind::Switch { .. } | GateKind::Button { .. } | GateKind::Clock { .. })
+            ))
+            .cloned()
+            .collect();
+        let name = sanitize(top);
+        if self.modules.contains_key(&name) {
+            return Err(format!("top module '{name}' has the name of a subcircuit module"));
+        }
+        Ok(Module { name, inputs, outputs: dedup(&net.outputs), items })
+    }
+}
+
+/// The modules of `circuit` with `top` as the top module: the top itself
+/// and the subcircuit modules it uses, by name.
+pub(crate) fn design(circuit: &Circuit, top: &str) -> Result<(Module, BTreeMap<String, Module>), String> {
+    let mut ex = Exporter { circuit, modules: BTreeMap::new() };
+    let top = ex.top(top)?;
+    Ok((top, ex.modules.into_iter().filter_map(|(name, m)| Some((name, m?))).collect()))
+}
+
+/// Structural Verilog for `circuit` with `top` as the top module.
+///
+/// Gates become primitives (`and`, `bufif1`, ...) or `assign`s, flip-flops
+/// `always @(posedge ...)` blocks and lookup tables constant selects.
+/// Subcircuit instances become instances of one module per definition and
+/// parameter values. Inputs, switches, buttons and clocks are the top
+/// module's inputs and the circuit's outputs its outputs. Transistor
+/// networks are written as `tranif0`/`tranif1` switches, which simulators
+/// accept but synthesis does not.
+///
+/// Behavioural parts (memories, displays, terminals, state machines,
+/// stimulus sources, closures) have no structural form and give an error.
+pub fn export(circuit: &Circuit, top: &str) -> Result<String, String> {
+    let (top, modules) = design(circuit, top)?;
+    let mut text: String = modules.values().map(|m| render(m, &modules) + "\n").collect();
+    text.push_str(&render(&top, &modules));
+    Ok(text)
+}
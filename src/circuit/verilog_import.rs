@@ -0,0 +1,1487 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::Signal;
+use crate::circuit::lut::{TruthTable, MAX_LUT_INPUTS};
+use crate::circuit::subcircuit::{CellDef, CellKind, SubcircuitDef};
+
+/// A problem in a Verilog source at a 1-based line and column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+type Pos = (usize, usize);
+
+fn diag(pos: Pos, message: impl Into<String>) -> Diagnostic {
+    Diagnostic { line: pos.0, col: pos.1, message: message.into() }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Number(String),
+    Sym(&'static str),
+    Eof,
+}
+
+struct Token {
+    tok: Tok,
+    pos: Pos,
+}
+
+/// Two-character symbols first so `<=` is not read as `<`.
+const SYMBOLS: &[&str] = &[
+    "<=", ">=", "==", "!=", "&&", "||", "~&", "~|", "~^", "^~", "<<", ">>", "(", ")", "[", "]", "{", "}", ",", ";",
+    ":", ".", "=", "~", "&", "|", "^", "!", "?", "@", "#", "*", "+", "-", "<", ">", "/", "%",
+];
+
+/// Widest literal, vector or replication read, so a typo in a size cannot
+/// exhaust memory.
+const MAX_WIDTH: usize = 4096;
+
+const PRIMITIVES: &[&str] = &["and", "or", "xor", "nand", "nor", "xnor", "not", "buf", "bufif0", "bufif1", "notif0", "notif1"];
+
+/// Keywords of constructs this reader does not take, reported where they
+/// start.
+const UNSUPPORTED: &[&str] = &[
+    "inout", "integer", "real", "time", "genvar", "generate", "function", "task", "specify", "defparam", "event",
+    "pullup", "pulldown", "tran", "tranif0", "tranif1", "rtran", "nmos", "pmos", "cmos", "primitive", "table",
+];
+
+fn lex(text: &str) -> Result<Vec<Token>, Diagnostic> {
+    let chars: Vec<char> = text.chars().collect();
+    let (mut i, mut line, mut col) = (0, 1, 1);
+    let mut toks = Vec::new();
+    // advances over `n` characters, keeping track of lines
+    let step = |i: &mut usize, line: &mut usize, col: &mut usize, n: usize| {
+        for _ in 0..n {
+            if chars.get(*i) == Some(&'\n') {
+                *line += 1;
+                *col = 1;
+            } else {
+                *col += 1;
+            }
+            *i += 1;
+        }
+    };
+    let starts = |i: usize, s: &str| s.chars().enumerate().all(|(k, c)| chars.get(i + k) == Some(&c));
+
+    while i < chars.len() {
+        let c = chars[i];
+        let pos = (line, col);
+        if c.is_whitespace() {
+            step(&mut i, &mut line, &mut col, 1);
+        } else if starts(i, "//") || c == '`' {
+            // comments and compiler directives such as `timescale
+            while i < chars.len() && chars[i] != '\n' {
+                step(&mut i, &mut line, &mut col, 1);
+            }
+        } else if starts(i, "/*") || (starts(i, "(*") && chars[i + 2..].iter().find(|c| !c.is_whitespace()) != Some(&')')) {
+            // block comments and attributes
+            let close = if c == '/' { "*/" } else { "*)" };
+            step(&mut i, &mut line, &mut col, 2);
+            while i < chars.len() && !starts(i, close) {
+                step(&mut i, &mut line, &mut col, 1);
+            }
+            if i >= chars.len() {
+                return Err(diag(pos, format!("unterminated '{}'", if c == '/' { "/*" } else { "(*" })));
+            }
+            step(&mut i, &mut line, &mut col, 2);
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let len = chars[i..].iter().take_while(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '$').count();
+            toks.push(Token { tok: Tok::Ident(chars[i..i + len].iter().collect()), pos });
+            step(&mut i, &mut line, &mut col, len);
+        } else if c == '\\' {
+            // escaped identifier; '.' would read as an instance path
+            let len = chars[i..].iter().take_while(|c| !c.is_whitespace()).count();
+            let name = chars[i + 1..i + len].iter().map(|&c| if matches!(c, '.' | '{' | '}') { '_' } else { c }).collect();
+            toks.push(Token { tok: Tok::Ident(name), pos });
+            step(&mut i, &mut line, &mut col, len);
+        } else if c.is_ascii_digit() || c == '\'' {
+            let mut len = chars[i..].iter().take_while(|c| c.is_ascii_digit() || **c == '_').count();
+            if chars.get(i + len) == Some(&'\'') {
+                len += 1;
+                if matches!(chars.get(i + len), Some('s' | 'S')) {
+                    len += 1;
+                }
+                if !matches!(chars.get(i + len), Some('b' | 'B' | 'o' | 'O' | 'd' | 'D' | 'h' | 'H')) {
+                    return Err(diag(pos, "expected a base after '"));
+                }
+                len += 1;
+                while chars.get(i + len).is_some_and(|c| *c == ' ' || *c == '\t') {
+                    len += 1;
+                }
+                len += chars[i + len..].iter().take_while(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '?').count();
+            }
+            toks.push(Token { tok: Tok::Number(chars[i..i + len].iter().filter(|c| !c.is_whitespace()).collect()), pos });
+            step(&mut i, &mut line, &mut col, len);
+        } else if let Some(sym) = SYMBOLS.iter().find(|s| starts(i, s)) {
+            toks.push(Token { tok: Tok::Sym(sym), pos });
+            step(&mut i, &mut line, &mut col, sym.len());
+        } else {
+            return Err(diag(pos, format!("unexpected character '{c}'")));
+        }
+    }
+    toks.push(Token { tok: Tok::Eof, pos: (line, col) });
+    Ok(toks)
+}
+
+/// Bits of a literal, least significant first.
+fn number(text: &str) -> Result<Vec<Signal>, String> {
+    let Some((size, rest)) = text.split_once('\'') else {
+        let value: u64 = text.replace('_', "").parse().map_err(|_| format!("bad number '{text}'"))?;
+        return Ok((0..32).map(|i| level(value >> i & 1 == 1)).collect());
+    };
+    let rest = rest.trim_start_matches(['s', 'S']);
+    let (base, digits) = rest.split_at(1);
+    let digits = digits.replace('_', "");
+    let per_digit = match base {
+        "b" | "B" => 1,
+        "o" | "O" => 3,
+        "h" | "H" => 4,
+        _ => 0,
+    };
+    let mut bits = Vec::new();
+    if per_digit == 0 {
+        let value: u64 = digits.parse().map_err(|_| format!("bad number '{text}'"))?;
+        bits.extend((0..64 - value.leading_zeros().min(63)).map(|i| level(value >> i & 1 == 1)));
+    } else {
+        for d in digits.chars().rev() {
+            let fill = match d {
+                'x' | 'X' => Some(Signal::X),
+                'z' | 'Z' | '?' => Some(Signal::HiZ),
+                _ => None,
+            };
+            let value = match fill {
+                Some(_) => 0,
+                None => d.to_digit(1 << per_digit).ok_or_else(|| format!("bad digit '{d}' in '{text}'"))?,
+            };
+            bits.extend((0..per_digit).map(|k| fill.unwrap_or(level(value >> k & 1 == 1))));
+        }
+    }
+    if bits.is_empty() {
+        return Err(format!("bad number '{text}'"));
+    }
+    if bits.len() > MAX_WIDTH {
+        return Err(format!("'{text}' is wider than {MAX_WIDTH} bits"));
+    }
+    if !size.is_empty() {
+        let size: usize = size.replace('_', "").parse().map_err(|_| format!("bad size in '{text}'"))?;
+        if size > MAX_WIDTH {
+            return Err(format!("size {size} of '{text}' is over {MAX_WIDTH} bits"));
+        }
+        if size == 0 {
+            return Err(format!("'{text}' has size zero"));
+        }
+        // x and z extend themselves, everything else zero-extends
+        let top = *bits.last().unwrap();
+        let pad = if matches!(top, Signal::X | Signal::HiZ) { top } else { Signal::Low };
+        bits.resize(size, pad);
+    }
+    Ok(bits)
+}
+
+fn level(high: bool) -> Signal {
+    if high { Signal::High } else { Signal::Low }
+}
+
+fn int_of(bits: &[Signal]) -> Option<i64> {
+    bits.iter().enumerate().try_fold(0i64, |acc, (i, b)| match b {
+        Signal::High if i < 63 => Some(acc | 1 << i),
+        Signal::Low => Some(acc),
+        _ => None,
+    })
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Ident(String, Pos),
+    Index(String, Box<Expr>, Pos),
+    /// `name[msb:lsb]`
+    Range(String, Box<Expr>, Box<Expr>, Pos),
+    Number(Vec<Signal>),
+    /// Parts as written, most significant first.
+    Concat(Vec<Expr>),
+    Repeat(Box<Expr>, Vec<Expr>, Pos),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+enum Stmt {
+    Block(Vec<Stmt>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    Assign(Expr, Expr, Pos),
+}
+
+enum Conns {
+    Ordered(Vec<Option<Expr>>),
+    Named(Vec<(String, Option<Expr>, Pos)>),
+}
+
+enum Item {
+    Assign(Expr, Expr, Pos),
+    Gate { prim: String, terms: Vec<Expr>, pos: Pos },
+    Instance { module: String, name: String, conns: Conns, pos: Pos },
+    /// `clock` is `(posedge, clock)` for clocked blocks.
+    Always { clock: Option<(bool, Expr)>, body: Stmt, pos: Pos },
+    Initial(Stmt, Pos),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dir {
+    Input,
+    Output,
+}
+
+struct Decl {
+    name: String,
+    pos: Pos,
+    dir: Option<Dir>,
+    range: Option<(i64, i64)>,
+}
+
+struct ModuleAst {
+    name: String,
+    pos: Pos,
+    header: Vec<(String, Pos)>,
+    decls: Vec<Decl>,
+    consts: Vec<(String, Expr, Pos)>,
+    items: Vec<Item>,
+}
+
+/// A port of a module, with its bit nets least significant first.
+struct Port {
+    name: String,
+    dir: Dir,
+    bits: Vec<String>,
+}
+
+fn bits_of(name: &str, range: Option<(i64, i64)>) -> Vec<String> {
+    match range {
+        None => vec![name.to_string()],
+        Some((msb, lsb)) => {
+            let step = if msb >= lsb { 1 } else { -1 };
+            (0..=(msb - lsb).abs()).map(|k| format!("{name}[{}]", lsb + k * step)).collect()
+        }
+    }
+}
+
+impl ModuleAst {
+    fn decl(&self, name: &str) -> Option<&Decl> {
+        self.decls.iter().find(|d| d.name == name)
+    }
+
+    fn ports(&self) -> Result<Vec<Port>, Diagnostic> {
+        self.header.iter()
+            .map(|(name, pos)| {
+                let decl = self.decl(name).filter(|d| d.dir.is_some())
+                    .ok_or_else(|| diag(*pos, format!("port '{name}' of '{}' is not declared input or output", self.name)))?;
+                Ok(Port { name: name.clone(), dir: decl.dir.unwrap(), bits: bits_of(name, decl.range) })
+            })
+            .collect()
+    }
+}
+
+struct Parser {
+    toks: Vec<Token>,
+    at: usize,
+    diags: Vec<Diagnostic>,
+}
+
+type Parsed<T> = Result<T, Diagnostic>;
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.toks[self.at].tok
+    }
+
+    fn pos(&self) -> Pos {
+        self.toks[self.at].pos
+    }
+
+    fn bump(&mut self) -> Tok {
+        let tok = self.toks[self.at].tok.clone();
+        if tok != Tok::Eof {
+            self.at += 1;
+        }
+        tok
+    }
+
+    /// Whether the next token is the symbol or keyword `s`.
+    fn is(&self, s: &str) -> bool {
+        match self.peek() {
+            Tok::Sym(sym) => *sym == s,
+            Tok::Ident(id) => id == s,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let found = self.is(s);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn expect(&mut self, s: &str) -> Parsed<()> {
+        if self.eat(s) { Ok(()) } else { self.unexpected(&format!("'{s}'")) }
+    }
+
+    fn unexpected<T>(&self, wanted: &str) -> Parsed<T> {
+        let found = match self.peek() {
+            Tok::Ident(s) | Tok::Number(s) => format!("'{s}'"),
+            Tok::Sym(s) => format!("'{s}'"),
+            Tok::Eof => "end of file".into(),
+        };
+        Err(diag(self.pos(), format!("expected {wanted}, found {found}")))
+    }
+
+    fn ident(&mut self) -> Parsed<(String, Pos)> {
+        let pos = self.pos();
+        match self.peek().clone() {
+            Tok::Ident(id) => {
+                self.bump();
+                Ok((id, pos))
+            }
+            _ => self.unexpected("a name"),
+        }
+    }
+
+    /// `[msb:lsb]` with literal bounds.
+    fn range(&mut self) -> Parsed<Option<(i64, i64)>> {
+        let pos = self.pos();
+        if !self.eat("[") {
+            return Ok(None);
+        }
+        let msb = self.int()?;
+        self.expect(":")?;
+        let lsb = self.int()?;
+        self.expect("]")?;
+        if msb.abs_diff(lsb) >= MAX_WIDTH as u64 {
+            return Err(diag(pos, format!("[{msb}:{lsb}] is over {MAX_WIDTH} bits wide")));
+        }
+        Ok(Some((msb, lsb)))
+    }
+
+    fn int(&mut self) -> Parsed<i64> {
+        let pos = self.pos();
+        match self.bump() {
+            Tok::Number(n) => number(&n).ok().and_then(|b| int_of(&b)).ok_or_else(|| diag(pos, format!("bad number '{n}'"))),
+            _ => Err(diag(pos, "only literal numbers are supported here")),
+        }
+    }
+
+    fn file(&mut self) -> Vec<ModuleAst> {
+        let mut modules = Vec::new();
+        while *self.peek() != Tok::Eof {
+            if self.is("module") {
+                match self.module() {
+                    Ok(m) => modules.push(m),
+                    Err(d) => {
+                        self.diags.push(d);
+                        while *self.peek() != Tok::Eof && !self.eat("endmodule") {
+                            self.bump();
+                        }
+                    }
+                }
+            } else {
+                let d = self.unexpected::<()>("'module'").unwrap_err();
+                self.diags.push(d);
+                while *self.peek() != Tok::Eof && !self.is("module") {
+                    self.bump();
+                }
+            }
+        }
+        modules
+    }
+
+    fn module(&mut self) -> Parsed<ModuleAst> {
+        self.expect("module")?;
+        let (name, pos) = self.ident()?;
+        let mut m = ModuleAst { name, pos, header: Vec::new(), decls: Vec::new(), consts: Vec::new(), items: Vec::new() };
+        if self.is("#") {
+            return Err(diag(self.pos(), "module parameters are not supported"));
+        }
+        if self.eat("(") {
+            // `(a, b, y)` or `(input a, output [3:0] y)`
+            let mut ansi: Option<(Dir, Option<(i64, i64)>)> = None;
+            while !self.eat(")") {
+                let dir_pos = self.pos();
+                let dir = if self.eat("input") {
+                    Some(Dir::Input)
+                } else if self.eat("output") {
+                    Some(Dir::Output)
+                } else if self.is("inout") {
+                    return Err(diag(dir_pos, "inout ports are not supported"));
+                } else {
+                    None
+                };
+                if let Some(dir) = dir {
+                    self.eat("wire");
+                    self.eat("reg");
+                    self.eat("signed");
+                    ansi = Some((dir, self.range()?));
+                }
+                let (port, pos) = self.ident()?;
+                if let Some((dir, range)) = ansi {
+                    m.decls.push(Decl { name: port.clone(), pos, dir: Some(dir), range });
+                }
+                m.header.push((port, pos));
+                if !self.is(")") {
+                    self.expect(",")?;
+                }
+            }
+        }
+        self.expect(";")?;
+
+        while !self.eat("endmodule") {
+            if *self.peek() == Tok::Eof {
+                return Err(diag(self.pos(), format!("module '{}' has no 'endmodule'", m.name)));
+            }
+            let start = self.at;
+            if let Err(d) = self.item(&mut m) {
+                self.diags.push(d);
+                self.at = start;
+                self.skip_item();
+            }
+        }
+        Ok(m)
+    }
+
+    /// Skips the item starting here: up to its `;`, or past the `end`
+    /// closing its block.
+    fn skip_item(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            if *self.peek() == Tok::Eof || (depth == 0 && self.is("endmodule")) {
+                return;
+            }
+            if self.is("begin") || self.is("case") || self.is("fork") {
+                depth += 1;
+            } else if self.is("end") || self.is("endcase") || self.is("join") {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    self.bump();
+                    // `else` continues the statement
+                    if !self.is("else") {
+                        return;
+                    }
+                    continue;
+                }
+            } else if depth == 0 && self.is(";") {
+                self.bump();
+                if !self.is("else") {
+                    return;
+                }
+                continue;
+            }
+            self.bump();
+        }
+    }
+
+    fn item(&mut self, m: &mut ModuleAst) -> Parsed<()> {
+        let pos = self.pos();
+        let keyword = match self.peek() {
+            Tok::Ident(id) => id.clone(),
+            _ => return self.unexpected("a module item"),
+        };
+        match keyword.as_str() {
+            "input" | "output" | "wire" | "reg" | "tri" | "supply0" | "supply1" => self.decl(m),
+            "localparam" | "parameter" => {
+                self.bump();
+                self.range()?;
+                loop {
+                    let (name, pos) = self.ident()?;
+                    self.expect("=")?;
+                    m.consts.push((name, self.expr()?, pos));
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect(";")
+            }
+            "assign" => {
+                self.bump();
+                loop {
+                    let pos = self.pos();
+                    let target = self.primary()?;
+                    self.expect("=")?;
+                    m.items.push(Item::Assign(target, self.expr()?, pos));
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect(";")
+            }
+            "always" => {
+                self.bump();
+                self.expect("@")?;
+                let clock = if self.eat("*") {
+                    None
+                } else {
+                    self.expect("(")?;
+                    let mut edges = Vec::new();
+                    loop {
+                        let pos = self.pos();
+                        if self.eat("*") {
+                        } else if self.eat("posedge") {
+                            edges.push((true, self.expr()?, pos));
+                        } else if self.eat("negedge") {
+                            edges.push((false, self.expr()?, pos));
+                        } else {
+                            self.expr()?;
+                        }
+                        if !self.eat("or") && !self.eat(",") {
+                            break;
+                        }
+                    }
+                    self.expect(")")?;
+                    if edges.len() > 1 {
+                        return Err(diag(edges[1].2, "only one clock edge per always block is supported; asynchronous set and reset are not"));
+                    }
+                    edges.pop().map(|(pos, clk, _)| (pos, clk))
+                };
+                let body = self.stmt()?;
+                m.items.push(Item::Always { clock, body, pos });
+                Ok(())
+            }
+            "initial" => {
+                self.bump();
+                let body = self.stmt()?;
+                m.items.push(Item::Initial(body, pos));
+                Ok(())
+            }
+            kw if PRIMITIVES.contains(&kw) => {
+                self.bump();
+                if self.is("(") && matches!(&self.toks[self.at + 1].tok, Tok::Ident(s) if s.ends_with('0') || s.ends_with('1')) {
+                    return Err(diag(self.pos(), "drive strengths are not supported"));
+                }
+                self.delay()?;
+                loop {
+                    let pos = self.pos();
+                    if matches!(self.peek(), Tok::Ident(_)) {
+                        self.bump();
+                    }
+                    self.expect("(")?;
+                    let mut terms = vec![self.expr()?];
+                    while self.eat(",") {
+                        terms.push(self.expr()?);
+                    }
+                    self.expect(")")?;
+                    m.items.push(Item::Gate { prim: kw.to_string(), terms, pos });
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect(";")
+            }
+            kw if UNSUPPORTED.contains(&kw) => Err(diag(pos, format!("'{kw}' is not supported"))),
+            _ => {
+                let (module, _) = self.ident()?;
+                if self.is("#") {
+                    return Err(diag(self.pos(), "parameter overrides are not supported"));
+                }
+                loop {
+                    let (name, pos) = self.ident()?;
+                    if self.is("[") {
+                        return Err(diag(self.pos(), "instance arrays are not supported"));
+                    }
+                    let conns = self.conns()?;
+                    m.items.push(Item::Instance { module: module.clone(), name, conns, pos });
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect(";")
+            }
+        }
+    }
+
+    /// Skips a `#n` or `#(...)` delay, which has no meaning here.
+    fn delay(&mut self) -> Parsed<()> {
+        if self.eat("#") {
+            if self.eat("(") {
+                while !self.eat(")") {
+                    if self.bump() == Tok::Eof {
+                        return self.unexpected("')'");
+                    }
+                }
+            } else {
+                self.bump();
+            }
+        }
+        Ok(())
+    }
+
+    fn decl(&mut self, m: &mut ModuleAst) -> Parsed<()> {
+        let (kind, _) = self.ident()?;
+        let dir = match kind.as_str() {
+            "input" => Some(Dir::Input),
+            "output" => Some(Dir::Output),
+            _ => None,
+        };
+        let mut reg = kind == "reg";
+        if dir.is_some() {
+            self.eat("wire");
+            reg = self.eat("reg");
+        }
+        self.eat("signed");
+        let range = self.range()?;
+        loop {
+            let (name, pos) = self.ident()?;
+            match m.decls.iter_mut().find(|d| d.name == name) {
+                Some(d) => {
+                    d.dir = d.dir.or(dir);
+                    d.range = d.range.or(range);
+                }
+                None => m.decls.push(Decl { name: name.clone(), pos, dir, range }),
+            }
+            let supply = match kind.as_str() {
+                "supply0" => Some(Signal::Low),
+                "supply1" => Some(Signal::High),
+                _ => None,
+            };
+            let init = if self.eat("=") { Some(self.expr()?) } else { supply.map(|s| Expr::Number(vec![s])) };
+            if let Some(init) = init {
+                let target = Expr::Ident(name, pos);
+                m.items.push(if reg { Item::Initial(Stmt::Assign(target, init, pos), pos) } else { Item::Assign(target, init, pos) });
+            }
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(";")
+    }
+
+    fn conns(&mut self) -> Parsed<Conns> {
+        self.expect("(")?;
+        if self.is(".") {
+            let mut named = Vec::new();
+            while self.eat(".") {
+                let (port, pos) = self.ident()?;
+                self.expect("(")?;
+                let expr = if self.is(")") { None } else { Some(self.expr()?) };
+                self.expect(")")?;
+                named.push((port, expr, pos));
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            self.expect(")")?;
+            return Ok(Conns::Named(named));
+        }
+        let mut ordered = Vec::new();
+        if !self.eat(")") {
+            loop {
+                ordered.push(if self.is(",") || self.is(")") { None } else { Some(self.expr()?) });
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            self.expect(")")?;
+        }
+        Ok(Conns::Ordered(ordered))
+    }
+
+    fn stmt(&mut self) -> Parsed<Stmt> {
+        let pos = self.pos();
+        if self.eat(";") {
+            return Ok(Stmt::Block(Vec::new()));
+        }
+        if self.eat("begin") {
+            if self.eat(":") {
+                self.ident()?;
+            }
+            let mut body = Vec::new();
+            while !self.eat("end") {
+                if *self.peek() == Tok::Eof {
+                    return self.unexpected("'end'");
+                }
+                body.push(self.stmt()?);
+            }
+            return Ok(Stmt::Block(body));
+        }
+        if self.eat("if") {
+            self.expect("(")?;
+            let cond = self.expr()?;
+            self.expect(")")?;
+            let then = Box::new(self.stmt()?);
+            let otherwise = if self.eat("else") { Some(Box::new(self.stmt()?)) } else { None };
+            return Ok(Stmt::If(cond, then, otherwise));
+        }
+        if let Tok::Ident(kw) = self.peek() {
+            if matches!(kw.as_str(), "case" | "casex" | "casez" | "for" | "while" | "repeat" | "forever" | "fork" | "wait") || kw.starts_with('$') {
+                return Err(diag(pos, format!("'{kw}' statements are not supported")));
+            }
+        }
+        if self.is("#") {
+            return Err(diag(pos, "delays are not supported"));
+        }
+        let target = self.primary()?;
+        if !self.eat("<=") {
+            self.expect("=")?;
+        }
+        self.delay()?;
+        let value = self.expr()?;
+        self.expect(";")?;
+        Ok(Stmt::Assign(target, value, pos))
+    }
+
+    fn expr(&mut self) -> Parsed<Expr> {
+        let cond = self.binary(0)?;
+        if self.eat("?") {
+            let a = self.expr()?;
+            self.expect(":")?;
+            let b = self.expr()?;
+            return Ok(Expr::Cond(Box::new(cond), Box::new(a), Box::new(b)));
+        }
+        Ok(cond)
+    }
+
+    /// Binary operators from loosest to tightest.
+    const LEVELS: &'static [&'static [&'static str]] = &[&["||"], &["&&"], &["|"], &["^", "~^", "^~"], &["&"], &["==", "!="]];
+
+    fn binary(&mut self, level: usize) -> Parsed<Expr> {
+        if level == Self::LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = Self::LEVELS[level].iter().find(|op| self.is(op)) {
+            self.bump();
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        if level == 0 {
+            if let Tok::Sym(op) = self.peek() {
+                if matches!(*op, "+" | "-" | "*" | "/" | "%" | "<" | ">" | "<=" | ">=" | "<<" | ">>") {
+                    return Err(diag(self.pos(), format!("operator '{op}' is not supported")));
+                }
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Parsed<Expr> {
+        for op in ["~&", "~|", "~^", "^~", "~", "!", "&", "|", "^"] {
+            if self.eat(op) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        if self.is("-") || self.is("+") {
+            return Err(diag(self.pos(), "arithmetic is not supported"));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Parsed<Expr> {
+        let pos = self.pos();
+        match self.peek().clone() {
+            Tok::Number(n) => {
+                self.bump();
+                Ok(Expr::Number(number(&n).map_err(|e| diag(pos, e))?))
+            }
+            Tok::Ident(name) if !name.starts_with('$') => {
+                self.bump();
+                if !self.eat("[") {
+                    return Ok(Expr::Ident(name, pos));
+                }
+                let index = self.expr()?;
+                let e = if self.eat(":") {
+                    Expr::Range(name, Box::new(index), Box::new(self.expr()?), pos)
+                } else {
+                    Expr::Index(name, Box::new(index), pos)
+                };
+                self.expect("]")?;
+                Ok(e)
+            }
+            Tok::Sym("(") => {
+                self.bump();
+                let e = self.expr()?;
+                self.expect(")")?;
+                Ok(e)
+            }
+            Tok::Sym("{") => {
+                self.bump();
+                let first = self.expr()?;
+                if self.is("{") {
+                    let Expr::Concat(parts) = self.primary()? else { unreachable!() };
+                    self.expect("}")?;
+                    return Ok(Expr::Repeat(Box::new(first), parts, pos));
+                }
+                let mut parts = vec![first];
+                while self.eat(",") {
+                    parts.push(self.expr()?);
+                }
+                self.expect("}")?;
+                Ok(Expr::Concat(parts))
+            }
+            Tok::Ident(name) => Err(diag(pos, format!("'{name}' is not supported"))),
+            _ => self.unexpected("an expression"),
+        }
+    }
+}
+
+const MUX: fn(usize) -> bool = |row| if row & 4 != 0 { row & 2 != 0 } else { row & 1 != 0 };
+
+/// Turns one module into a subcircuit. Every bit of a vector is a net of
+/// its own, `x[3]`; cells made for operators are named `$n`.
+struct Lower<'a> {
+    modules: &'a HashMap<String, ModuleAst>,
+    module: &'a ModuleAst,
+    /// Declared and implicit nets by name, with their range.
+    nets: HashMap<String, (Option<(i64, i64)>, Pos)>,
+    consts: HashMap<String, Vec<Signal>>,
+    inputs: Vec<String>,
+    cells: Vec<CellDef>,
+    drivers: BTreeMap<String, Vec<String>>,
+    /// Initial levels of registers, by bit.
+    inits: HashMap<String, Signal>,
+    /// Flip-flop cells and the bit they hold.
+    flops: Vec<(usize, String)>,
+    constants: [Option<String>; 4],
+    count: usize,
+}
+
+impl Lower<'_> {
+    fn cell(&mut self, kind: CellKind, inputs: Vec<String>) -> String {
+        self.count += 1;
+        let name = format!("${}", self.count);
+        self.cells.push(CellDef { name: name.clone(), kind, inputs, repeat: None, value: None });
+        name
+    }
+
+    fn constant(&mut self, level: Signal) -> String {
+        let k = level as usize;
+        if let Some(name) = &self.constants[k] {
+            return name.clone();
+        }
+        let name = self.cell(CellKind::Const { level }, Vec::new());
+        self.constants[k] = Some(name.clone());
+        name
+    }
+
+    fn is_constant(&self, net: &str, level: Signal) -> bool {
+        self.constants[level as usize].as_deref() == Some(net)
+    }
+
+    /// Zero-extends or truncates `bits` to `width`.
+    fn fit(&mut self, mut bits: Vec<String>, width: usize) -> Vec<String> {
+        if bits.len() < width {
+            let low = self.constant(Signal::Low);
+            bits.resize(width, low);
+        }
+        bits.truncate(width);
+        bits
+    }
+
+    /// One bit that is high when any of `bits` is.
+    fn any(&mut self, bits: Vec<String>) -> String {
+        if bits.len() == 1 { bits[0].clone() } else { self.cell(CellKind::Or, bits) }
+    }
+
+    fn net(&mut self, name: &str, pos: Pos) -> Vec<String> {
+        let (range, _) = *self.nets.entry(name.to_string()).or_insert((None, pos));
+        bits_of(name, range)
+    }
+
+    fn const_bits(&self, e: &Expr) -> Option<Vec<Signal>> {
+        match e {
+            Expr::Number(bits) => Some(bits.clone()),
+            Expr::Ident(name, _) => self.consts.get(name).cloned(),
+            Expr::Concat(parts) => {
+                let mut bits = Vec::new();
+                for part in parts.iter().rev() {
+                    bits.extend(self.const_bits(part)?);
+                }
+                Some(bits)
+            }
+            Expr::Unary("~", e) => Some(self.const_bits(e)?.into_iter().map(|s| s.invert()).collect()),
+            _ => None,
+        }
+    }
+
+    fn const_int(&self, e: &Expr, pos: Pos) -> Parsed<i64> {
+        self.const_bits(e).and_then(|b| int_of(&b)).ok_or_else(|| diag(pos, "expected a constant"))
+    }
+
+    /// Bits `hi` down to `lo` of `name`, least significant first.
+    fn select(&mut self, name: &str, hi: i64, lo: i64, pos: Pos) -> Parsed<Vec<String>> {
+        let (range, _) = *self.nets.get(name).ok_or_else(|| diag(pos, format!("'{name}' is not declared")))?;
+        let Some((msb, lsb)) = range else {
+            return Err(diag(pos, format!("'{name}' is not a vector")));
+        };
+        let inside = |i: i64| (msb.min(lsb)..=msb.max(lsb)).contains(&i);
+        if !inside(hi) || !inside(lo) {
+            return Err(diag(pos, format!("'{name}[{hi}:{lo}]' is outside [{msb}:{lsb}]")));
+        }
+        let step = if hi >= lo { 1 } else { -1 };
+        Ok((0..=(hi - lo).abs()).map(|k| format!("{name}[{}]", lo + k * step)).collect())
+    }
+
+    fn expr(&mut self, e: &Expr) -> Parsed<Vec<String>> {
+        if let Some(bits) = self.const_bits(e) {
+            return Ok(bits.into_iter().map(|b| self.constant(b)).collect());
+        }
+        Ok(match e {
+            Expr::Ident(name, pos) => self.net(name, *pos),
+            Expr::Index(name, index, pos) => {
+                if let Some(table) = self.consts.get(name).cloned() {
+                    // a constant looked up by a signal is a LUT
+                    let select = self.expr(index)?;
+                    if select.len() > MAX_LUT_INPUTS {
+                        return Err(diag(*pos, format!("'{name}' is indexed by {} bits; at most {MAX_LUT_INPUTS} are supported", select.len())));
+                    }
+                    let table = TruthTable::from_fn(select.len(), |row| table.get(row) == Some(&Signal::High)).map_err(|e| diag(*pos, e))?;
+                    return Ok(vec![self.cell(CellKind::Lut { table }, select)]);
+                }
+                let i = self.const_int(index, *pos).map_err(|_| diag(*pos, format!("'{name}' may only be indexed by a constant")))?;
+                self.select(name, i, i, *pos)?
+            }
+            Expr::Range(name, hi, lo, pos) => {
+                let (hi, lo) = (self.const_int(hi, *pos)?, self.const_int(lo, *pos)?);
+                self.select(name, hi, lo, *pos)?
+            }
+            Expr::Number(_) => unreachable!("constants are handled above"),
+            Expr::Concat(parts) => {
+                let mut bits = Vec::new();
+                for part in parts.iter().rev() {
+                    bits.extend(self.expr(part)?);
+                }
+                bits
+            }
+            Expr::Repeat(count, parts, pos) => {
+                let n = self.const_int(count, *pos)?;
+                if n < 1 {
+                    return Err(diag(*pos, format!("replication count {n} is not positive")));
+                }
+                let bits = self.expr(&Expr::Concat(parts.clone()))?;
+                if (n as usize).saturating_mul(bits.len()) > MAX_WIDTH {
+                    return Err(diag(*pos, format!("replication is over {MAX_WIDTH} bits wide")));
+                }
+                (0..n).flat_map(|_| bits.clone()).collect()
+            }
+            Expr::Unary(op, e) => {
+                let bits = self.expr(e)?;
+                match *op {
+                    "~" => bits.into_iter().map(|b| self.cell(CellKind::Not, vec![b])).collect(),
+                    "!" => {
+                        let any = self.any(bits);
+                        vec![self.cell(CellKind::Not, vec![any])]
+                    }
+                    _ if bits.len() == 1 => match *op {
+                        "&" | "|" | "^" => bits,
+                        _ => vec![self.cell(CellKind::Not, bits)],
+                    },
+                    _ => {
+                        let kind = match *op {
+                            "&" => CellKind::And,
+                            "|" => CellKind::Or,
+                            "^" => CellKind::Xor,
+                            "~&" => CellKind::Nand,
+                            "~|" => CellKind::Nor,
+                            _ => CellKind::Xnor,
+                        };
+                        vec![self.cell(kind, bits)]
+                    }
+                }
+            }
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (self.expr(a)?, self.expr(b)?);
+                match *op {
+                    "&&" | "||" => {
+                        let (a, b) = (self.any(a), self.any(b));
+                        vec![self.cell(if *op == "&&" { CellKind::And } else { CellKind::Or }, vec![a, b])]
+                    }
+                    "==" | "!=" => {
+                        let width = a.len().max(b.len());
+                        let (a, b) = (self.fit(a, width), self.fit(b, width));
+                        if width == 1 {
+                            let kind = if *op == "==" { CellKind::Xnor } else { CellKind::Xor };
+                            return Ok(vec![self.cell(kind, vec![a[0].clone(), b[0].clone()])]);
+                        }
+                        let same: Vec<String> = a.into_iter().zip(b).map(|(a, b)| self.cell(CellKind::Xnor, vec![a, b])).collect();
+                        vec![self.cell(if *op == "==" { CellKind::And } else { CellKind::Nand }, same)]
+                    }
+                    _ => {
+                        let width = a.len().max(b.len());
+                        let (a, b) = (self.fit(a, width), self.fit(b, width));
+                        let kind = match *op {
+                            "&" => CellKind::And,
+                            "|" => CellKind::Or,
+                            "^" => CellKind::Xor,
+                            _ => CellKind::Xnor,
+                        };
+                        a.into_iter().zip(b).map(|(a, b)| self.cell(kind.clone(), vec![a, b])).collect()
+                    }
+                }
+            }
+            Expr::Cond(c, a, b) => {
+                let c = self.expr(c)?;
+                let c = self.any(c);
+                let (a, b) = (self.expr(a)?, self.expr(b)?);
+                self.mux(c, a, b)
+            }
+        })
+    }
+
+    /// `sel ? a : b`, bit by bit.
+    fn mux(&mut self, sel: String, a: Vec<String>, b: Vec<String>) -> Vec<String> {
+        let width = a.len().max(b.len());
+        let (a, b) = (self.fit(a, width), self.fit(b, width));
+        let table = TruthTable::from_fn(3, MUX).unwrap();
+        a.into_iter().zip(b).map(|(a, b)| self.cell(CellKind::Lut { table }, vec![b, a, sel.clone()])).collect()
+    }
+
+    /// Bits an assignment or output connection drives.
+    fn target(&mut self, e: &Expr, pos: Pos) -> Parsed<Vec<String>> {
+        let bits = match e {
+            Expr::Ident(name, pos) | Expr::Index(name, _, pos) | Expr::Range(name, _, _, pos) => {
+                if self.consts.contains_key(name) {
+                    return Err(diag(*pos, format!("cannot assign to constant '{name}'")));
+                }
+                self.expr(e)?
+            }
+            Expr::Concat(parts) => {
+                let mut bits = Vec::new();
+                for part in parts.iter().rev() {
+                    bits.extend(self.target(part, pos)?);
+                }
+                bits
+            }
+            _ => return Err(diag(pos, "only nets, bit and part selects and concatenations can be assigned")),
+        };
+        if let Some(bit) = bits.iter().find(|b| self.inputs.contains(b)) {
+            return Err(diag(pos, format!("input '{bit}' cannot be driven")));
+        }
+        Ok(bits)
+    }
+
+    fn drive(&mut self, bits: Vec<String>, values: Vec<String>) {
+        let values = self.fit(values, bits.len());
+        for (bit, value) in bits.into_iter().zip(values) {
+            self.drivers.entry(bit).or_default().push(value);
+        }
+    }
+
+    /// Lowers the assignments of an always block into the next value of
+    /// every bit it assigns. `partial` collects bits left unassigned on
+    /// some path.
+    fn stmt(&mut self, s: &Stmt, env: &mut BTreeMap<String, String>, partial: &mut BTreeSet<String>) -> Parsed<()> {
+        match s {
+            Stmt::Block(body) => body.iter().try_for_each(|s| self.stmt(s, env, partial)),
+            Stmt::Assign(target, value, pos) => {
+                let bits = self.target(target, *pos)?;
+                let values = self.expr(value)?;
+                let values = self.fit(values, bits.len());
+                env.extend(bits.into_iter().zip(values));
+                Ok(())
+            }
+            Stmt::If(cond, then, otherwise) => {
+                let c = self.expr(cond)?;
+                let c = self.any(c);
+                let (mut t, mut e) = (env.clone(), env.clone());
+                self.stmt(then, &mut t, partial)?;
+                if let Some(otherwise) = otherwise {
+                    self.stmt(otherwise, &mut e, partial)?;
+                }
+                let keys: BTreeSet<String> = t.keys().chain(e.keys()).cloned().collect();
+                for bit in keys {
+                    let (a, b) = match (t.get(&bit), e.get(&bit)) {
+                        (Some(a), Some(b)) => (a.clone(), b.clone()),
+                        (Some(a), None) => {
+                            partial.insert(bit.clone());
+                            (a.clone(), bit.clone())
+                        }
+                        (None, Some(b)) => {
+                            partial.insert(bit.clone());
+                            (bit.clone(), b.clone())
+                        }
+                        (None, None) => unreachable!(),
+                    };
+                    let next = if a == b { a } else { self.mux(c.clone(), vec![a], vec![b]).remove(0) };
+                    env.insert(bit, next);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn item(&mut self, item: &Item) -> Parsed<()> {
+        match item {
+            Item::Assign(target, value, pos) => {
+                let bits = self.target(target, *pos)?;
+                let values = self.expr(value)?;
+                self.drive(bits, values);
+            }
+            Item::Gate { prim, terms, pos } => self.gate(prim, terms, *pos)?,
+            Item::Instance { module, name, conns, pos } => self.instance(module, name, conns, *pos)?,
+            Item::Always { clock: Some((posedge, clock)), body, pos } => {
+                let clk = self.expr(clock)?;
+                if clk.len() != 1 {
+                    return Err(diag(*pos, "the clock must be a single bit"));
+                }
+                let clk = if *posedge { clk[0].clone() } else { self.cell(CellKind::Not, clk) };
+                let mut env = BTreeMap::new();
+                self.stmt(body, &mut env, &mut BTreeSet::new())?;
+                for (bit, next) in env {
+                    let q = self.cell(CellKind::Dff { init: Signal::Low }, vec![next, clk.clone()]);
+                    self.flops.push((self.cells.len() - 1, bit.clone()));
+                    self.drivers.entry(bit).or_default().push(q);
+                }
+            }
+            Item::Always { clock: None, body, pos } => {
+                let mut body = body;
+                while let Stmt::Block(b) = body {
+                    match b.as_slice() {
+                        [only] => body = only,
+                        _ => break,
+                    }
+                }
+                // `if (en) q = d;` is a latch
+                if let Stmt::If(cond, then, None) = body {
+                    let mut assigns = vec![then.as_ref()];
+                    while let Some(Stmt::Block(b)) = assigns.last() {
+                        assigns = b.iter().collect();
+                    }
+                    if assigns.iter().all(|s| matches!(s, Stmt::Assign(..))) {
+                        let en = self.expr(cond)?;
+                        let en = self.any(en);
+                        for s in assigns {
+                            let Stmt::Assign(target, value, pos) = s else { unreachable!() };
+                            let bits = self.target(target, *pos)?;
+                            let values = self.expr(value)?;
+                            let values = self.fit(values, bits.len());
+                            for (bit, d) in bits.into_iter().zip(values) {
+                                let q = self.cell(CellKind::Dlatch, vec![d, en.clone()]);
+                                self.drivers.entry(bit).or_default().push(q);
+                            }
+                        }
+                        return Ok(());
+                    }
+                }
+                let mut env = BTreeMap::new();
+                let mut partial = BTreeSet::new();
+                self.stmt(body, &mut env, &mut partial)?;
+                if let Some(bit) = partial.iter().next() {
+                    return Err(diag(*pos, format!("'{bit}' keeps its value on some paths; the only latch supported is `if (en) q = d;`")));
+                }
+                for (bit, value) in env {
+                    self.drivers.entry(bit).or_default().push(value);
+                }
+            }
+            Item::Initial(body, pos) => self.initial(body, *pos)?,
+        }
+        Ok(())
+    }
+
+    fn initial(&mut self, s: &Stmt, pos: Pos) -> Parsed<()> {
+        match s {
+            Stmt::Block(body) => body.iter().try_for_each(|s| self.initial(s, pos)),
+            Stmt::Assign(target, value, at) => {
+                let levels = self.const_bits(value).ok_or_else(|| diag(*at, "initial values must be constants"))?;
+                let bits = self.target(target, *at)?;
+                for (k, bit) in bits.into_iter().enumerate() {
+                    self.inits.insert(bit, levels.get(k).copied().unwrap_or(Signal::Low));
+                }
+                Ok(())
+            }
+            Stmt::If(..) => Err(diag(pos, "initial blocks may only assign constants")),
+        }
+    }
+
+    fn gate(&mut self, prim: &str, terms: &[Expr], pos: Pos) -> Parsed<()> {
+        let mut ins = Vec::new();
+        for t in &terms[1.min(terms.len())..] {
+            let bits = self.expr(t)?;
+            if bits.len() != 1 {
+                return Err(diag(pos, format!("'{prim}' terminals must be single bits")));
+            }
+            ins.extend(bits);
+        }
+        let (outputs, value) = match prim {
+            "not" | "buf" => {
+                // several outputs, then the input
+                let Some(input) = ins.pop() else { return Err(diag(pos, format!("'{prim}' needs an input"))) };
+                let kind = if prim == "not" { CellKind::Not } else { CellKind::Buffer };
+                let value = self.cell(kind, vec![input]);
+                let mut outputs = vec![self.target(&terms[0], pos)?];
+                for t in &terms[1..terms.len() - 1] {
+                    outputs.push(self.target(t, pos)?);
+                }
+                (outputs, value)
+            }
+            "bufif0" | "bufif1" | "notif0" | "notif1" => {
+                if ins.len() != 2 {
+                    return Err(diag(pos, format!("'{prim}' takes 3 terminals")));
+                }
+                let (mut data, mut en) = (ins[0].clone(), ins[1].clone());
+                let value = if prim == "bufif0" && self.is_constant(&data, Signal::Low) {
+                    // pulls low while the control is low, otherwise floats
+                    self.cell(CellKind::OpenDrain, vec![en])
+                } else {
+                    if prim.starts_with("not") {
+                        data = self.cell(CellKind::Not, vec![data]);
+                    }
+                    if prim.ends_with('0') {
+                        en = self.cell(CellKind::Not, vec![en]);
+                    }
+                    self.cell(CellKind::TriState, vec![data, en])
+                };
+                (vec![self.target(&terms[0], pos)?], value)
+            }
+            _ => {
+                if ins.len() < 2 {
+                    return Err(diag(pos, format!("'{prim}' needs at least two inputs")));
+                }
+                let kind = match prim {
+                    "and" => CellKind::And,
+                    "or" => CellKind::Or,
+                    "xor" => CellKind::Xor,
+                    "nand" => CellKind::Nand,
+                    "nor" => CellKind::Nor,
+                    _ => CellKind::Xnor,
+                };
+                let value = self.cell(kind, ins);
+                (vec![self.target(&terms[0], pos)?], value)
+            }
+        };
+        for bits in outputs {
+            if bits.len() != 1 {
+                return Err(diag(pos, format!("'{prim}' terminals must be single bits")));
+            }
+            self.drive(bits, vec![value.clone()]);
+        }
+        Ok(())
+    }
+
+    fn instance(&mut self, module: &str, name: &str, conns: &Conns, pos: Pos) -> Parsed<()> {
+        let sub = self.modules.get(module).ok_or_else(|| diag(pos, format!("unknown module '{module}'")))?;
+        let ports = sub.ports()?;
+        if self.nets.contains_key(name) {
+            return Err(diag(pos, format!("'{name}' names both a net and an instance")));
+        }
+        let mut exprs: Vec<Option<&Expr>> = vec![None; ports.len()];
+        match conns {
+            Conns::Ordered(list) => {
+                if list.len() > ports.len() {
+                    return Err(diag(pos, format!("'{module}' has {} ports, instance '{name}' connects {}", ports.len(), list.len())));
+                }
+                for (slot, e) in exprs.iter_mut().zip(list) {
+                    *slot = e.as_ref();
+                }
+            }
+            Conns::Named(list) => {
+                for (port, e, at) in list {
+                    let k = ports.iter().position(|p| &p.name == port)
+                        .ok_or_else(|| diag(*at, format!("'{module}' has no port '{port}'")))?;
+                    exprs[k] = e.as_ref();
+                }
+            }
+        }
+        let mut inputs = Vec::new();
+        for (port, e) in ports.iter().zip(&exprs) {
+            match (port.dir, e) {
+                (Dir::Input, Some(e)) => {
+                    let bits = self.expr(e)?;
+                    inputs.extend(self.fit(bits, port.bits.len()));
+                }
+                (Dir::Input, None) => {
+                    let z = self.constant(Signal::HiZ);
+                    inputs.extend(port.bits.iter().map(|_| z.clone()));
+                }
+                (Dir::Output, Some(e)) => {
+                    let mut bits = self.target(e, pos)?;
+                    bits.truncate(port.bits.len());
+                    let values = port.bits.iter().map(|b| format!("{name}.{b}")).collect();
+                    self.drive(bits, values);
+                }
+                (Dir::Output, None) => {}
+            }
+        }
+        let kind = CellKind::Instance { module: module.into(), params: BTreeMap::new() };
+        self.cells.push(CellDef { name: name.into(), kind, inputs, repeat: None, value: None });
+        Ok(())
+    }
+
+    fn run(mut self, diags: &mut Vec<Diagnostic>) -> Option<SubcircuitDef> {
+        let m = self.module;
+        for decl in &m.decls {
+            self.nets.insert(decl.name.clone(), (decl.range, decl.pos));
+        }
+        for (name, value, pos) in &m.consts {
+            match self.const_bits(value) {
+                Some(bits) => {
+                    self.consts.insert(name.clone(), bits);
+                }
+                None => diags.push(diag(*pos, format!("'{name}' must be a constant"))),
+            }
+        }
+        let ports = match m.ports() {
+            Ok(ports) => ports,
+            Err(d) => {
+                diags.push(d);
+                return None;
+            }
+        };
+        let bits = |dir| ports.iter().filter(|p| p.dir == dir).flat_map(|p| p.bits.clone()).collect::<Vec<_>>();
+        self.inputs = bits(Dir::Input);
+        let outputs = bits(Dir::Output);
+        let before = diags.len();
+        for item in &m.items {
+            if let Err(d) = self.item(item) {
+                diags.push(d);
+            }
+        }
+        for (cell, bit) in &self.flops {
+            self.cells[*cell].kind = CellKind::Dff { init: self.inits.get(bit).copied().unwrap_or(Signal::Low) };
+        }
+
+        // nets read or exported must be driven
+        let read: BTreeSet<&String> = self.cells.iter().flat_map(|c| &c.inputs).chain(self.drivers.values().flatten()).chain(&outputs).collect();
+        for net in read {
+            let instance_port = net.contains('.');
+            if !net.starts_with('$') && !instance_port && !self.inputs.contains(net) && !self.drivers.contains_key(net) {
+                let base = net.split('[').next().unwrap_or(net);
+                let pos = self.nets.get(base).map_or(m.pos, |(_, pos)| *pos);
+                diags.push(diag(pos, format!("'{net}' is used but never driven in '{}'", m.name)));
+            }
+        }
+        if diags.len() > before {
+            return None;
+        }
+
+        // a cell driving exactly one net takes its name
+        let constants: Vec<&String> = self.constants.iter().flatten().collect();
+        let mut rename: HashMap<String, String> = HashMap::new();
+        for (net, srcs) in &self.drivers {
+            if let [src] = srcs.as_slice() {
+                let own = src.starts_with('$') && !constants.contains(&src);
+                if own && !rename.contains_key(src) && !rename.values().any(|n| n == net) {
+                    rename.insert(src.clone(), net.clone());
+                }
+            }
+        }
+        let renamed = |n: &String| rename.get(n).unwrap_or(n).clone();
+        let mut cells: Vec<CellDef> = self.cells.iter()
+            .map(|c| CellDef { name: renamed(&c.name), inputs: c.inputs.iter().map(renamed).collect(), ..c.clone() })
+            .collect();
+        for (net, srcs) in &self.drivers {
+            let srcs: Vec<String> = srcs.iter().map(renamed).collect();
+            if srcs != [net.clone()] {
+                cells.push(CellDef { name: net.clone(), kind: CellKind::Wire, inputs: srcs, repeat: None, value: None });
+            }
+        }
+        Some(SubcircuitDef { name: m.name.clone(), params: Vec::new(), inputs: self.inputs, outputs, cells })
+    }
+}
+
+/// Reads gate-level Verilog into a `Circuit`.
+///
+/// Takes modules with input and output ports, `wire`/`reg` declarations,
+/// `assign` with bitwise, logical, equality and `?:` operators, gate
+/// primitives, module instances, `always @(posedge clk)` blocks of
+/// (conditional) assignments, `if (en) q = d;` latches, `initial` values
+/// and `localparam` constants. Vectors are split into one net per bit,
+/// named `x[3]`.
+///
+/// Every module but the top becomes a subcircuit; the top one is built in
+/// the circuit itself with `Circuit::build_top`, its inputs becoming
+/// `InputGate`s. `top` picks it when the file holds several modules that no
+/// other instantiates.
+///
+/// Everything else, e.g. arithmetic, `case`, parameters or asynchronous
+/// resets, is reported with its line and column; the reader goes on to
+/// report as much as it can.
+pub fn import(text: &str, top: Option<&str>) -> Result<Circuit, Vec<Diagnostic>> {
+    let toks = lex(text).map_err(|d| vec![d])?;
+    let mut parser = Parser { toks, at: 0, diags: Vec::new() };
+    let parsed = parser.file();
+    let mut diags = parser.diags;
+
+    let mut modules = HashMap::new();
+    let mut order = Vec::new();
+    for m in parsed {
+        if modules.contains_key(&m.name) {
+            diags.push(diag(m.pos, format!("module '{}' is defined twice", m.name)));
+            continue;
+        }
+        order.push((m.name.clone(), m.pos));
+        modules.insert(m.name.clone(), m);
+    }
+    let used: BTreeSet<&String> = modules.values()
+        .flat_map(|m| &m.items)
+        .filter_map(|i| match i {
+            Item::Instance { module, .. } => Some(module),
+            _ => None,
+        })
+        .collect();
+    let top = match top {
+        Some(name) => modules.get(name).map(|m| (m.name.clone(), m.pos)).ok_or_else(|| format!("no module '{name}'")),
+        None => {
+            let tops: Vec<&(String, Pos)> = order.iter().filter(|(n, _)| !used.contains(n)).collect();
+            match tops.as_slice() {
+                [one] => Ok((*one).clone()),
+                [] => Err("no top module".into()),
+                many => Err(format!("several top modules: {}", many.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(", "))),
+            }
+        }
+    };
+    let top = match top {
+        Ok(top) => Some(top),
+        Err(e) => {
+            diags.push(diag((1, 1), e));
+            None
+        }
+    };
+
+    let mut defs = Vec::new();
+    for (name, _) in &order {
+        let lower = Lower {
+            modules: &modules,
+            module: &modules[name],
+            nets: HashMap::new(),
+            consts: HashMap::new(),
+            inputs: Vec::new(),
+            cells: Vec::new(),
+            drivers: BTreeMap::new(),
+            inits: HashMap::new(),
+            flops: Vec::new(),
+            constants: Default::default(),
+            count: 0,
+        };
+        defs.extend(lower.run(&mut diags));
+    }
+    let Some((top, top_pos)) = top else { return Err(diags) };
+    if !diags.is_empty() {
+        return Err(diags);
+    }
+
+    let mut circuit = Circuit::new();
+    let mut top_def = None;
+    for def in defs {
+        let pos = modules[&def.name].pos;
+        if def.name == top {
+            top_def = Some(def);
+        } else if let Err(e) = circuit.define(def) {
+            diags.push(diag(pos, e));
+        }
+    }
+    if let Some(def) = top_def {
+        if let Err(e) = circuit.build_top(&def) {
+            diags.push(diag(top_pos, e));
+        }
+    }
+    if diags.is_empty() { Ok(circuit) } else { Err(diags) }
+}
@@ -1,49 +1,94 @@
-use crate::circuit::gate::Gate;
+use crate::circuit::gate::{Gate, GateRef};
+use crate::circuit::netlist::{GateKind, Saver};
 use crate::circuit::gate::Signal;
 use std::any::Any;
 use std::cell::RefCell;
 use std::fmt::Debug;
 use std::rc::Rc;
 
+/// A net. It may have several drivers (tri-state buses, wired-AND of
+/// open-drain outputs, pull resistors); see `resolve` for how they combine.
+/// A net nobody drives floats at `HiZ`.
 #[derive(Debug)]
 pub struct Wire {
-    source: Option<Rc<RefCell<dyn Gate>>>,
+    drivers: Vec<Rc<RefCell<dyn Gate>>>,
     label: String,
 }
 
 impl Wire {
     pub fn new(label: impl Into<String>) -> Self {
         Self {
-            source: None,
+            drivers: Vec::new(),
             label: label.into(),
         }
     }
 
+    /// Makes `gate` the only driver of this wire.
     pub fn connect(&mut self, gate: Rc<RefCell<dyn Gate>>) {
-        self.source = Some(gate);
+        self.drivers = vec![gate];
     }
+
+    /// Adds `gate` next to the existing drivers.
+    pub fn add_driver(&mut self, gate: Rc<RefCell<dyn Gate>>) {
+        if !self.drivers.iter().any(|d| Rc::ptr_eq(d, &gate)) {
+            self.drivers.push(gate);
+        }
+    }
+
+    /// Removes `gate` from the drivers, returns whether it was one.
+    pub fn remove_driver(&mut self, gate: &Rc<RefCell<dyn Gate>>) -> bool {
+        let before = self.drivers.len();
+        self.drivers.retain(|d| !Rc::ptr_eq(d, gate));
+        self.drivers.len() != before
+    }
+}
+
+/// Level of a net with the given drivers. Strong drivers that are not `HiZ`
+/// win over pull resistors (`Gate::is_weak`); drivers of the same strength
+/// that disagree, or drive `X`, give `X`. With nothing driving, the net floats.
+pub fn resolve(drivers: &[Rc<RefCell<dyn Gate>>]) -> Signal {
+    let mut strong = None;
+    let mut weak = None;
+    for d in drivers {
+        let d = d.borrow();
+        let slot = if d.is_weak() { &mut weak } else { &mut strong };
+        *slot = match (*slot, d.eval()) {
+            (level, Signal::HiZ) => level,
+            (None, level) => Some(level),
+            (Some(a), b) if a == b => Some(a),
+            _ => Some(Signal::X),
+        };
+    }
+    strong.or(weak).unwrap_or(Signal::HiZ)
 }
 
 impl Gate for Wire {
     fn eval(&self) -> Signal {
-        self.source
-            .as_ref()
-            .map(|gate| gate.borrow().eval())
-            .unwrap_or(Signal::Low)
+        resolve(&self.drivers)
     }
 
     fn description(&self) -> String {
-        format!(
-            "Wire({}, connected: {})",
-            self.label,
-            self.source
-                .as_ref()
+        let drivers = if self.drivers.is_empty() {
+            "None".to_string()
+        } else {
+            self.drivers
+                .iter()
                 .map(|gate| gate.borrow().description())
-                .unwrap_or("None".to_string())
-        )
+                .collect::<Vec<_>>()
+                .join(" | ")
+        };
+        format!("Wire({}, connected: {})", self.label, drivers)
     }
 
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn to_netlist(&self, cx: &mut Saver) -> Option<GateKind> {
+        Some(GateKind::Wire { label: self.label.clone(), drivers: cx.names(&self.drivers) })
+    }
+
+    fn inputs(&self) -> Vec<GateRef> {
+        self.drivers.clone()
+    }
 }
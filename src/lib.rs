@@ -1,2 +1,3 @@
 pub mod circuit;
-pub mod tests;
\ No newline at end of file
+#[cfg(test)]
+pub mod tests;
@@ -4,16 +4,33 @@ use logic::circuit::gate::*;
 use std::rc::Rc;
 use std::cell::RefCell;
 use logic::circuit::wire::Wire;
+use logic::circuit::memory::{MemPins, MemoryCore, MemoryFormat};
+use logic::circuit::display::{DisplayBus, PixelFormat};
+use logic::circuit::switch::{SwitchNetwork, SwitchNode, GND, VDD};
+use logic::circuit::lut::{LutGate, TruthTable};
+use logic::circuit::fsm::StateTable;
+use logic::circuit::examples::{Example, EXAMPLES};
+use logic::circuit::param::Params;
+use logic::circuit::library::Library;
+use logic::circuit::terminal::run_headless;
+use logic::circuit::project::{NodeLayout, PortLayout, PortSide, Project, RecentFiles, WireLayout};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use anyhow::Context;
+
+const CONSOLE: &str = "console";
+const AUTOSAVE_EVERY: Duration = Duration::from_secs(30);
 
 type GateRef = Rc<RefCell<dyn Gate>>;
 
 macro_rules! register_gate {
     ($( $txt:literal => $spawn:ident ),* $(,)?) => {
-        pub fn palette(ui:&mut egui::Ui, app:&mut LogicApp) {
+        fn palette(ui:&mut egui::Ui, app:&mut LogicApp) {
             ui.heading("Palette");
             $(
                 if ui.button($txt).clicked() { app.$spawn(); }
             )*
+            library_palette(ui, app);
         }
     };
 }
@@ -48,23 +65,171 @@ struct LogicApp {
 
     to_delete_node: Option<usize>,
     to_delete_wire: Option<usize>,
+
+    mem_view: MemoryView,
+    console:  ConsoleView,
+    stimulus: StimulusSettings,
+    lut_editor: Option<String>,
+    fsm_path:   String,
+    fsm_status: String,
+    png_status: String,
+    lib_path:   String,
+    lib_status: String,
+    props:      Option<PropertyEdit>,
+    files:      ProjectFiles,
+}
+
+/// The open project file, recently used files and the autosave.
+struct ProjectFiles {
+    /// Where the recent files list and the autosave live.
+    dir:      PathBuf,
+    current:  Option<PathBuf>,
+    path:     String,
+    recent:   RecentFiles,
+    status:   String,
+    /// Autosave left by an earlier session, until restored or discarded.
+    recovery: Option<Project>,
+    last_autosave: Instant,
+    /// Project as last saved or autosaved, so unchanged work is not written.
+    autosaved: String,
+    /// Project as last opened or saved, to warn before unsaved work is lost.
+    saved:    String,
+    /// Replacement of the canvas waiting for unsaved work to be discarded.
+    confirm:  Option<Replace>,
+}
+
+/// What can take over the canvas.
+enum Replace {
+    New,
+    Open(PathBuf),
+    Example(fn() -> Example),
+}
+
+impl Default for ProjectFiles {
+    fn default() -> Self {
+        Self {
+            dir: state_dir(),
+            current: None,
+            path: "project.json".into(),
+            recent: RecentFiles::default(),
+            status: String::new(),
+            recovery: None,
+            last_autosave: Instant::now(),
+            autosaved: String::new(),
+            saved: String::new(),
+            confirm: None,
+        }
+    }
+}
+
+impl ProjectFiles {
+    /// Recent files and any autosave from the last session in `dir`.
+    fn open(dir: PathBuf) -> Self {
+        let files = Self { dir, ..Self::default() };
+        Self {
+            recent: RecentFiles::load(files.recent_path()).unwrap_or_default(),
+            recovery: Project::load(files.autosave_path()).ok(),
+            ..files
+        }
+    }
+
+    fn recent_path(&self) -> PathBuf { self.dir.join("recent.json") }
+    fn autosave_path(&self) -> PathBuf { self.dir.join("autosave.json") }
+
+    fn remember(&mut self, path: &Path) {
+        self.recent.push(path);
+        self.save_recent();
+    }
+
+    fn save_recent(&mut self) {
+        let saved = std::fs::create_dir_all(&self.dir).map_err(anyhow::Error::from)
+            .and_then(|_| self.recent.save(self.recent_path()));
+        if let Err(e) = saved {
+            self.status = format!("{e:#}");
+        }
+    }
+}
+
+/// Default home of the recent files list and the autosave.
+fn state_dir() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(std::env::temp_dir).join(".logic")
+}
+
+/// Parameters of the subcircuit instance open in the property panel.
+struct PropertyEdit {
+    path:   String,
+    params: Params,
+    status: String,
+}
+
+/// Settings used for newly placed stimulus sources.
+struct StimulusSettings {
+    pattern: String,
+    hold:    usize,
+    width:   usize,
+    seed:    u64,
+}
+
+impl Default for StimulusSettings {
+    fn default() -> Self {
+        Self { pattern: "0110".into(), hold: 1, width: 4, seed: 1 }
+    }
+}
+
+#[derive(Default)]
+struct ConsoleView {
+    open: bool,
+    line: String,
+}
+
+struct MemoryView {
+    open:     bool,
+    selected: Option<String>,
+    editing:  Option<(usize, String)>,
+    path:     String,
+    format:   MemoryFormat,
+    status:   String,
+}
+
+impl Default for MemoryView {
+    fn default() -> Self {
+        Self {
+            open: false,
+            selected: None,
+            editing: None,
+            path: "memory.hex".into(),
+            format: MemoryFormat::IntelHex,
+            status: String::new(),
+        }
+    }
+}
+
+/// First word of a node label: example circuits label their switches and
+/// lamps "SW a0", "LAMP sum0", ...
+fn label_kind(label: &str) -> &str {
+    label.split_whitespace().next().unwrap_or("")
 }
 
 trait Snap                { fn snap_to_grid(self, step:f32) -> Self; }
-trait ToPos2              { fn to_pos2(self) -> egui::Pos2; }
 
 impl Snap   for egui::Vec2 { fn snap_to_grid(self, s:f32) -> Self {
     egui::vec2((self.x/s).round()*s, (self.y/s).round()*s)
 }}
-impl ToPos2 for egui::Vec2 { fn to_pos2(self) -> egui::Pos2 { egui::pos2(self.x, self.y) }}
 
 
 
 
+/// Empty circuit with the standard library imported.
+fn new_circuit() -> Circuit {
+    let mut circuit = Circuit::new();
+    circuit.import_library(Library::standard()).expect("standard subcircuits are valid");
+    circuit
+}
+
 impl Default for LogicApp {
     fn default() -> Self {
         Self {
-            circuit: Circuit::new(),
+            circuit: new_circuit(),
             nodes:   Vec::new(),
             wires:   Vec::new(),
             pending_port: None,
@@ -72,12 +237,26 @@ impl Default for LogicApp {
             drag_offset: egui::Vec2::ZERO,
             to_delete_node: None,
             to_delete_wire: None,
+            mem_view: MemoryView::default(),
+            console: ConsoleView::default(),
+            stimulus: StimulusSettings::default(),
+            lut_editor: None,
+            fsm_path: "machine.json".into(),
+            fsm_status: String::new(),
+            png_status: String::new(),
+            lib_path: "library.json".into(),
+            lib_status: String::new(),
+            props: None,
+            files: ProjectFiles::default(),
         }
     }
 }
 
 fn new_input_wire(app: &mut LogicApp, hint: &str) -> String {
-    let wid   = format!("{hint}_w{}", app.nodes.len());
+    let wid = (0..)
+        .map(|n| format!("{hint}_w{n}"))
+        .find(|id| app.circuit.gate(id).is_none())
+        .unwrap();
     let wgate = Rc::new(RefCell::new(Wire::new(&wid)));
     app.circuit.add_gate(&wid, wgate);
     wid
@@ -177,6 +356,54 @@ impl LogicApp {
         });
     }
 
+    fn spawn_pattern(&mut self) {
+        let id = self.next_id();
+        let s = &self.stimulus;
+        if self.circuit.add_pattern(&id, &s.pattern, s.hold, true).is_err() {
+            return;
+        }
+        self.circuit.add_output(&id);
+
+        self.nodes.push(Node {
+            label: "PATTERN".into(), id: id.clone(),
+            gate: self.circuit.gate(&id).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(420.0, 140.0), egui::vec2(70.0, 30.0)),
+            ports: vec![Port { offset: egui::vec2(70.0, 15.0), kind: PortKind::Out, gate_id: id }],
+        });
+    }
+
+    fn spawn_one_shot(&mut self) {
+        let id = self.next_id();
+        let trigger = new_input_wire(self, &id);
+        if self.circuit.add_one_shot(&id, &trigger, self.stimulus.width, false).is_err() {
+            return;
+        }
+        self.circuit.add_output(&id);
+
+        self.nodes.push(Node {
+            label: "ONE-SHOT".into(), id: id.clone(),
+            gate: self.circuit.gate(&id).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(420.0, 180.0), egui::vec2(70.0, 30.0)),
+            ports: vec![
+                Port { offset: egui::vec2(0.0, 15.0), kind: PortKind::In, gate_id: trigger },
+                Port { offset: egui::vec2(70.0, 15.0), kind: PortKind::Out, gate_id: id },
+            ],
+        });
+    }
+
+    fn spawn_random(&mut self) {
+        let id = self.next_id();
+        self.circuit.add_random(&id, self.stimulus.seed);
+        self.circuit.add_output(&id);
+
+        self.nodes.push(Node {
+            label: "RANDOM".into(), id: id.clone(),
+            gate: self.circuit.gate(&id).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(420.0, 220.0), egui::vec2(70.0, 30.0)),
+            ports: vec![Port { offset: egui::vec2(70.0, 15.0), kind: PortKind::Out, gate_id: id }],
+        });
+    }
+
     fn spawn_button(&mut self) {
         let id   = self.next_id();
         let gate = Rc::new(RefCell::new(InputGate::new(false)));
@@ -215,7 +442,7 @@ impl LogicApp {
     fn spawn_buffer(&mut self){
         let base = self.next_id();
         let in_id = new_input_wire(self,&base);
-        let in_g  = self.circuit.gate(&in_id).unwrap();;
+        let in_g  = self.circuit.gate(&in_id).unwrap();
     
         let gate = Rc::new(RefCell::new(BufferGate::new(in_g)));
         self.circuit.add_gate(&base, gate.clone());
@@ -231,6 +458,41 @@ impl LogicApp {
         });
     }
 
+    fn spawn_open_drain(&mut self) {
+        let base = self.next_id();
+        let in_id = new_input_wire(self, &base);
+        if self.circuit.add_open_drain(&base, &in_id).is_err() {
+            return;
+        }
+        self.circuit.add_output(&base);
+
+        self.nodes.push(Node {
+            label: "OD".into(), id: base.clone(),
+            gate: self.circuit.gate(&base).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(260.0, 200.0), egui::vec2(60.0, 30.0)),
+            ports: vec![
+                Port { offset: egui::vec2(0.0, 15.0), kind: PortKind::In, gate_id: in_id },
+                Port { offset: egui::vec2(60.0, 15.0), kind: PortKind::Out, gate_id: base },
+            ],
+        });
+    }
+
+    fn spawn_pull(&mut self, pull: PullResistor) {
+        let id = self.next_id();
+        let label = if pull.eval().is_high() { "PULL-UP" } else { "PULL-DN" };
+        let gate = Rc::new(RefCell::new(pull));
+        self.circuit.add_gate(&id, gate.clone());
+
+        self.nodes.push(Node {
+            label: label.into(), id: id.clone(), gate,
+            rect: egui::Rect::from_min_size(egui::pos2(180.0, 40.0), egui::vec2(60.0, 30.0)),
+            ports: vec![Port { offset: egui::vec2(60.0, 15.0), kind: PortKind::Out, gate_id: id }],
+        });
+    }
+
+    fn spawn_pull_up(&mut self) { self.spawn_pull(PullResistor::up()); }
+    fn spawn_pull_down(&mut self) { self.spawn_pull(PullResistor::down()); }
+
     fn spawn_tri(&mut self){
         let base = self.next_id();
         let in_id = new_input_wire(self,&base);
@@ -279,13 +541,552 @@ impl LogicApp {
             });
         }
 
+    fn spawn_memory(&mut self, writable: bool) {
+        const ADDR_BITS: usize = 4;
+        const DATA_BITS: usize = 8;
+
+        let base = self.next_id();
+        let addr: Vec<String> = (0..ADDR_BITS).map(|_| new_input_wire(self, &base)).collect();
+        let cs = new_input_wire(self, &base);
+        let (data_in, we, clk) = if writable {
+            let data_in: Vec<String> = (0..DATA_BITS).map(|_| new_input_wire(self, &base)).collect();
+            (data_in, Some(new_input_wire(self, &base)), Some(new_input_wire(self, &base)))
+        } else {
+            (Vec::new(), None, None)
+        };
+        let data_out: Vec<String> = (0..DATA_BITS).map(|i| format!("{base}_q{i}")).collect();
+
+        let pins = MemPins {
+            addr: addr.iter().map(String::as_str).collect(),
+            data_in: data_in.iter().map(String::as_str).collect(),
+            write_enable: we.as_deref(),
+            read_enable: None,
+            chip_select: Some(cs.as_str()),
+            clock: clk.as_deref(),
+        };
+        let out_ids: Vec<&str> = data_out.iter().map(String::as_str).collect();
+        let core = MemoryCore::new(ADDR_BITS, DATA_BITS);
+        let added = if writable {
+            self.circuit.add_ram(&base, core, pins, &out_ids)
+        } else {
+            self.circuit.add_rom(&base, core, &pins.addr, pins.chip_select, &out_ids)
+        };
+        if added.is_err() {
+            return;
+        }
+        for id in &data_out {
+            self.circuit.add_output(id);
+        }
+
+        let inputs: Vec<String> = addr.into_iter()
+            .chain(data_in)
+            .chain(we)
+            .chain(std::iter::once(cs))
+            .chain(clk)
+            .collect();
+        let height = 12.0 * inputs.len().max(data_out.len()) as f32 + 8.0;
+
+        let mut ports: Vec<Port> = inputs.into_iter().enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(0.0, 10.0 + 12.0 * i as f32), kind: PortKind::In, gate_id })
+            .collect();
+        ports.extend(data_out.iter().enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(70.0, 10.0 + 12.0 * i as f32), kind: PortKind::Out, gate_id: gate_id.clone() }));
+
+        self.nodes.push(Node {
+            label: if writable { "RAM" } else { "ROM" }.into(),
+            id: base,
+            gate: self.circuit.gate(&data_out[0]).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(540.0, 60.0), egui::vec2(70.0, height)),
+            ports,
+        });
+    }
+
+    fn spawn_seven_seg(&mut self) {
+        let base = self.next_id();
+        let ports = (0..8)
+            .map(|i| Port {
+                offset: egui::vec2(0.0, 10.0 + 10.0 * i as f32),
+                kind: PortKind::In,
+                gate_id: new_input_wire(self, &base),
+            })
+            .collect::<Vec<_>>();
+        let gate = self.circuit.gate(&ports[0].gate_id).unwrap();
+
+        self.nodes.push(Node {
+            label: "7SEG".into(), id: base, gate,
+            rect: egui::Rect::from_min_size(egui::pos2(620.0, 100.0), egui::vec2(60.0, 90.0)),
+            ports,
+        });
+    }
+
+    fn spawn_seg_decoder(&mut self) {
+        let base = self.next_id();
+        let inputs: Vec<String> = (0..4).map(|_| new_input_wire(self, &base)).collect();
+        let segments: Vec<String> = "abcdefg".chars().map(|c| format!("{base}_{c}")).collect();
+
+        let in_ids: [&str; 4] = std::array::from_fn(|i| inputs[i].as_str());
+        let seg_ids: [&str; 7] = std::array::from_fn(|i| segments[i].as_str());
+        if self.circuit.add_seven_seg_decoder(in_ids, seg_ids, true).is_err() {
+            return;
+        }
+        for id in &segments {
+            self.circuit.add_output(id);
+        }
+
+        let mut ports: Vec<Port> = inputs.into_iter().enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(0.0, 20.0 + 14.0 * i as f32), kind: PortKind::In, gate_id })
+            .collect();
+        ports.extend(segments.iter().enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(70.0, 10.0 + 10.0 * i as f32), kind: PortKind::Out, gate_id: gate_id.clone() }));
+
+        self.nodes.push(Node {
+            label: "HEX>7".into(), id: base,
+            gate: self.circuit.gate(&segments[0]).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(540.0, 100.0), egui::vec2(70.0, 80.0)),
+            ports,
+        });
+    }
+
+    fn spawn_display(&mut self) {
+        const SIZE: usize = 32;
+        const ADDR_BITS: usize = 5;
+
+        let base = self.next_id();
+        let x: Vec<String> = (0..ADDR_BITS).map(|_| new_input_wire(self, &base)).collect();
+        let y: Vec<String> = (0..ADDR_BITS).map(|_| new_input_wire(self, &base)).collect();
+        let data: Vec<String> = (0..PixelFormat::Rgb111.bits()).map(|_| new_input_wire(self, &base)).collect();
+        let write = new_input_wire(self, &base);
+
+        fn refs(v: &[String]) -> Vec<&str> { v.iter().map(String::as_str).collect() }
+        let bus = DisplayBus::AddressData { x: refs(&x), y: refs(&y), data: refs(&data), write: write.as_str() };
+        if self.circuit.add_display(&base, SIZE, SIZE, bus, PixelFormat::Rgb111).is_err() {
+            return;
+        }
+
+        let ports = x.into_iter().chain(y).chain(data).chain(std::iter::once(write))
+            .enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(0.0, 10.0 + 10.0 * i as f32), kind: PortKind::In, gate_id })
+            .collect();
+
+        self.nodes.push(Node {
+            label: "PIXELS".into(), id: base.clone(),
+            gate: self.circuit.gate(&base).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(620.0, 220.0), egui::vec2(150.0, 150.0)),
+            ports,
+        });
+    }
+
+    fn spawn_tty(&mut self) {
+        let base = self.next_id();
+        let data: Vec<String> = (0..8).map(|_| new_input_wire(self, &base)).collect();
+        let strobe = new_input_wire(self, &base);
+
+        let data_ids: Vec<&str> = data.iter().map(String::as_str).collect();
+        if self.circuit.add_tty(&base, CONSOLE, &data_ids, &strobe).is_err() {
+            return;
+        }
+
+        let ports = data.into_iter().chain(std::iter::once(strobe))
+            .enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(0.0, 10.0 + 10.0 * i as f32), kind: PortKind::In, gate_id })
+            .collect();
+
+        self.nodes.push(Node {
+            label: "TTY".into(), id: base.clone(),
+            gate: self.circuit.gate(&base).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(620.0, 60.0), egui::vec2(60.0, 100.0)),
+            ports,
+        });
+    }
+
+    fn spawn_keyboard(&mut self) {
+        let base = self.next_id();
+        let ack = new_input_wire(self, &base);
+        let data: Vec<String> = (0..8).map(|i| format!("{base}_k{i}")).collect();
+        let ready = format!("{base}_ready");
+
+        let data_ids: Vec<&str> = data.iter().map(String::as_str).collect();
+        if self.circuit.add_keyboard(CONSOLE, &ack, &data_ids, &ready).is_err() {
+            return;
+        }
+        for id in data.iter().chain(std::iter::once(&ready)) {
+            self.circuit.add_output(id);
+        }
+
+        let mut ports = vec![Port { offset: egui::vec2(0.0, 50.0), kind: PortKind::In, gate_id: ack }];
+        ports.extend(data.into_iter().chain(std::iter::once(ready.clone())).enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(70.0, 10.0 + 10.0 * i as f32), kind: PortKind::Out, gate_id }));
+
+        self.nodes.push(Node {
+            label: "KEYBOARD".into(), id: base,
+            gate: self.circuit.gate(&ready).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(540.0, 220.0), egui::vec2(70.0, 100.0)),
+            ports,
+        });
+    }
+
+    fn spawn_lut(&mut self, k: usize) {
+        let base = self.next_id();
+        let inputs: Vec<String> = (0..k).map(|_| new_input_wire(self, &base)).collect();
+        let ids: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        // starts as a k-input AND; double-click the node to edit
+        let table = TruthTable::from_fn(k, |row| row == (1 << k) - 1).unwrap();
+        if self.circuit.add_lut(&base, &ids, table).is_err() {
+            return;
+        }
+        self.circuit.add_output(&base);
+
+        let height = 20.0 * k as f32 + 10.0;
+        let mut ports: Vec<Port> = inputs.into_iter().enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(0.0, 15.0 + 20.0 * i as f32), kind: PortKind::In, gate_id })
+            .collect();
+        ports.push(Port { offset: egui::vec2(60.0, height / 2.0), kind: PortKind::Out, gate_id: base.clone() });
+
+        self.nodes.push(Node {
+            label: format!("LUT{k}"), id: base.clone(),
+            gate: self.circuit.gate(&base).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(340.0, 200.0), egui::vec2(60.0, height)),
+            ports,
+        });
+    }
+
+    fn spawn_fsm(&mut self, table: StateTable) -> Result<(), String> {
+        let base = self.next_id();
+        let clk = new_input_wire(self, &base);
+        let inputs: Vec<String> = table.inputs.iter().map(|_| new_input_wire(self, &base)).collect();
+        let outputs: Vec<String> = table.outputs.iter().map(|name| format!("{base}_{name}")).collect();
+
+        let in_ids: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        let out_ids: Vec<&str> = outputs.iter().map(String::as_str).collect();
+        self.circuit.add_fsm(&base, table, &clk, &in_ids, &out_ids)?;
+        for id in &outputs {
+            self.circuit.add_output(id);
+        }
+
+        let rows = (inputs.len() + 1).max(outputs.len()).max(2);
+        let mut ports: Vec<Port> = std::iter::once(clk).chain(inputs).enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(0.0, 15.0 + 15.0 * i as f32), kind: PortKind::In, gate_id })
+            .collect();
+        ports.extend(outputs.into_iter().enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(100.0, 15.0 + 15.0 * i as f32), kind: PortKind::Out, gate_id }));
+
+        self.nodes.push(Node {
+            label: "FSM".into(), id: base.clone(),
+            gate: self.circuit.gate(&base).unwrap(),
+            rect: egui::Rect::from_min_size(egui::pos2(340.0, 260.0), egui::vec2(100.0, 15.0 * rows as f32 + 10.0)),
+            ports,
+        });
+        Ok(())
+    }
+
+    fn spawn_lut2(&mut self) { self.spawn_lut(2); }
+    fn spawn_lut3(&mut self) { self.spawn_lut(3); }
+    fn spawn_lut4(&mut self) { self.spawn_lut(4); }
+
+    fn spawn_ram(&mut self) { self.spawn_memory(true); }
+    fn spawn_rom(&mut self) { self.spawn_memory(false); }
+
 fn spawn_nand(&mut self){ self.spawn_binary("NAND", |a,b| Rc::new(RefCell::new(NandGate::new(a,b)))); }
 fn spawn_nor (&mut self){ self.spawn_binary("NOR" , |a,b| Rc::new(RefCell::new(NorGate ::new(a,b)))); }
 fn spawn_or  (&mut self){ self.spawn_binary("OR"  , |a,b| Rc::new(RefCell::new(OrGate  ::new(a,b)))); }
 fn spawn_xor (&mut self){ self.spawn_binary("XOR" , |a,b| Rc::new(RefCell::new(XorGate ::new(a,b)))); }
+fn spawn_cmos_nand(&mut self){ self.spawn_binary("CMOS NAND", cmos_nand); }
 fn spawn_xnor(&mut self){ self.spawn_binary("XNOR", |a,b| Rc::new(RefCell::new(XnorGate::new(a,b)))); }
 
     fn next_id(&self) -> String { format!("g{}", self.nodes.len()) }
+
+    fn spawn_instance(&mut self, module: &str) -> Result<(), String> {
+        let path = self.next_id();
+        let node = self.instance_node(&path, module, &Params::new(), egui::pos2(300.0, 300.0), &[])?;
+        for port in node.ports.iter().filter(|p| p.kind == PortKind::Out) {
+            self.circuit.add_output(&port.gate_id);
+        }
+        self.nodes.push(node);
+        Ok(())
+    }
+
+    /// Instantiates `module` at `path` and builds its node. Input ports
+    /// reuse the wires in `inputs` by position, extra ones get new wires.
+    fn instance_node(&mut self, path: &str, module: &str, params: &Params, pos: egui::Pos2, inputs: &[String]) -> Result<Node, String> {
+        let (ins, outs) = self.circuit.subcircuit_ports(module, params)?;
+        if ins.is_empty() && outs.is_empty() {
+            return Err(format!("'{module}' has no ports"));
+        }
+        let wires: Vec<String> = (0..ins.len())
+            .map(|i| inputs.get(i).cloned().unwrap_or_else(|| new_input_wire(self, path)))
+            .collect();
+        let ids: Vec<&str> = wires.iter().map(String::as_str).collect();
+        self.circuit.instantiate_with(module, path, params, &ids)?;
+
+        let outputs: Vec<String> = outs.iter().map(|p| format!("{path}.{p}")).collect();
+        let gate = self.circuit.gate(outputs.first().unwrap_or(&wires[0])).unwrap();
+        let rows = ins.len().max(outs.len());
+        let mut ports: Vec<Port> = wires.into_iter().enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(0.0, 15.0 + 15.0 * i as f32), kind: PortKind::In, gate_id })
+            .collect();
+        ports.extend(outputs.into_iter().enumerate()
+            .map(|(i, gate_id)| Port { offset: egui::vec2(100.0, 15.0 + 15.0 * i as f32), kind: PortKind::Out, gate_id }));
+
+        Ok(Node {
+            label: module.into(), id: path.into(), gate,
+            rect: egui::Rect::from_min_size(pos, egui::vec2(100.0, 15.0 * rows as f32 + 10.0)),
+            ports,
+        })
+    }
+
+    /// Rebuilds instance `path` with new parameters, keeping the wires of
+    /// ports that still exist. On failure the instance is left as it was.
+    fn rebuild_instance(&mut self, path: &str, params: &Params) -> Result<(), String> {
+        let idx = self.nodes.iter().position(|n| n.id == path).ok_or_else(|| format!("no node '{path}'"))?;
+        let module = self.circuit.instance_module(path).ok_or_else(|| format!("'{path}' is not an instance"))?.to_string();
+        let old_params = self.circuit.instance_params(path).cloned().unwrap_or_default();
+        let (pos, old_ports) = (self.nodes[idx].rect.min, std::mem::take(&mut self.nodes[idx].ports));
+        let inputs: Vec<String> = old_ports.iter().filter(|p| p.kind == PortKind::In).map(|p| p.gate_id.clone()).collect();
+
+        // loads of the old outputs must let go of the old gates
+        let port_gate = |nodes: &[Node], (nid, pidx): &(String, usize)| -> Option<String> {
+            let n = nodes.iter().find(|n| &n.id == nid)?;
+            let ports = if n.id == path { &old_ports } else { &n.ports };
+            ports.get(*pidx).map(|p| p.gate_id.clone())
+        };
+        let links: Vec<(String, String)> = self.wires.iter()
+            .filter(|w| w.from.0 == path)
+            .filter_map(|w| Some((port_gate(&self.nodes, &w.from)?, port_gate(&self.nodes, &w.to)?)))
+            .collect();
+        for (from, to) in &links {
+            let _ = self.circuit.disconnect(from, to);
+        }
+        self.circuit.remove_instance(path)?;
+
+        let (node, result) = match self.instance_node(path, &module, params, pos, &inputs) {
+            Ok(node) => (node, Ok(())),
+            Err(e) => (self.instance_node(path, &module, &old_params, pos, &inputs)?, Err(e)),
+        };
+        for port in node.ports.iter().filter(|p| p.kind == PortKind::Out) {
+            if !old_ports.iter().any(|o| o.gate_id == port.gate_id) {
+                self.circuit.add_output(&port.gate_id);
+            }
+        }
+
+        // keep the visual wires whose port survived, under its new index
+        let remap = |pidx: usize| old_ports.get(pidx)
+            .and_then(|old| node.ports.iter().position(|p| p.gate_id == old.gate_id));
+        self.wires.retain_mut(|w| {
+            for end in [&mut w.from, &mut w.to] {
+                if end.0 == path {
+                    match remap(end.1) {
+                        Some(i) => end.1 = i,
+                        None => return false,
+                    }
+                }
+            }
+            true
+        });
+        for (from, to) in links {
+            if node.ports.iter().any(|p| p.gate_id == from) {
+                let _ = self.circuit.add_driver(&from, &to);
+            }
+        }
+        self.nodes[idx] = node;
+        result
+    }
+
+    /// An empty canvas over `files`, with nothing to autosave yet.
+    fn with_files(files: ProjectFiles) -> Self {
+        let mut app = LogicApp { files, ..LogicApp::default() };
+        app.mark_clean();
+        app
+    }
+
+    /// Replaces the canvas with `example`: a switch per input, a lamp per
+    /// output, plus its clock and state machines.
+    fn load_example(&mut self, example: Example) {
+        const COLUMN: usize = 12;
+
+        // imported libraries stay available
+        let mut circuit = example.circuit;
+        for name in self.circuit.library_names() {
+            let lib = self.circuit.library(&name).unwrap().clone();
+            circuit.import_library(lib).expect("imported libraries are valid");
+        }
+        *self = LogicApp { circuit, files: std::mem::take(&mut self.files), ..LogicApp::default() };
+        self.files.current = None;
+        let row = |i: usize| 40.0 + 35.0 * (i % COLUMN) as f32;
+
+        if let Some(clk) = self.circuit.gate("clk") {
+            self.nodes.push(Node {
+                label: "CLK".into(), id: "clk".into(), gate: clk,
+                rect: egui::Rect::from_min_size(egui::pos2(40.0, 0.0), egui::vec2(60.0, 30.0)),
+                ports: vec![Port { offset: egui::vec2(60.0, 15.0), kind: PortKind::Out, gate_id: "clk".into() }],
+            });
+        }
+        for (i, id) in example.inputs.into_iter().enumerate() {
+            self.nodes.push(Node {
+                label: format!("SW {id}"), id: id.clone(),
+                gate: self.circuit.gate(&id).unwrap(),
+                rect: egui::Rect::from_min_size(egui::pos2(40.0 + 90.0 * (i / COLUMN) as f32, row(i)), egui::vec2(80.0, 30.0)),
+                ports: vec![Port { offset: egui::vec2(80.0, 15.0), kind: PortKind::Out, gate_id: id }],
+            });
+        }
+        for (i, id) in self.circuit.fsm_ids().into_iter().enumerate() {
+            self.nodes.push(Node {
+                label: "FSM".into(), id: id.clone(),
+                gate: self.circuit.gate(&id).unwrap(),
+                rect: egui::Rect::from_min_size(egui::pos2(260.0, row(i)), egui::vec2(100.0, 40.0)),
+                ports: Vec::new(),
+            });
+        }
+        for (i, id) in example.outputs.into_iter().enumerate() {
+            self.nodes.push(Node {
+                label: format!("LAMP {id}"), id: id.clone(),
+                gate: self.circuit.gate(&id).unwrap(),
+                rect: egui::Rect::from_min_size(egui::pos2(420.0 + 110.0 * (i / COLUMN) as f32, row(i)), egui::vec2(100.0, 30.0)),
+                ports: vec![Port { offset: egui::vec2(0.0, 15.0), kind: PortKind::In, gate_id: id }],
+            });
+        }
+        self.mark_clean();
+    }
+
+    /// The circuit with the canvas layout.
+    fn project(&self) -> Result<Project, String> {
+        let nodes = self.nodes.iter().map(|node| {
+            // the shown gate is registered under the node id or one of its pins
+            let gate = std::iter::once(&node.id).chain(node.ports.iter().map(|p| &p.gate_id))
+                .find(|id| self.circuit.gate(id).is_some_and(|g| Rc::ptr_eq(&g, &node.gate)))
+                .unwrap_or(&node.id);
+            NodeLayout {
+                id: node.id.clone(),
+                label: node.label.clone(),
+                gate: gate.clone(),
+                pos: node.rect.min.into(),
+                size: node.rect.size().into(),
+                ports: node.ports.iter().map(|p| PortLayout {
+                    offset: p.offset.into(),
+                    side: match p.kind { PortKind::In => PortSide::In, PortKind::Out => PortSide::Out },
+                    gate: p.gate_id.clone(),
+                }).collect(),
+            }
+        }).collect();
+        let wires = self.wires.iter()
+            .map(|w| WireLayout { from: w.from.clone(), to: w.to.clone() })
+            .collect();
+        Project::new(&self.circuit, nodes, wires)
+    }
+
+    /// Replaces the canvas with `project`, keeping the file state.
+    fn open_project(&mut self, project: &Project) -> Result<(), String> {
+        let circuit = project.build()?;
+        let nodes = project.nodes.iter().map(|n| Node {
+            id: n.id.clone(),
+            label: n.label.clone(),
+            gate: circuit.gate(&n.gate).expect("project gates were checked"),
+            rect: egui::Rect::from_min_size(n.pos.into(), n.size.into()),
+            ports: n.ports.iter().map(|p| Port {
+                offset: p.offset.into(),
+                kind: match p.side { PortSide::In => PortKind::In, PortSide::Out => PortKind::Out },
+                gate_id: p.gate.clone(),
+            }).collect(),
+        }).collect();
+        let wires = project.wires.iter()
+            .map(|w| WireVisual { from: w.from.clone(), to: w.to.clone() })
+            .collect();
+        *self = LogicApp { circuit, nodes, wires, files: std::mem::take(&mut self.files), ..LogicApp::default() };
+        Ok(())
+    }
+
+    fn new_project(&mut self) {
+        *self = LogicApp { files: std::mem::take(&mut self.files), ..LogicApp::default() };
+        self.files.current = None;
+        self.files.status.clear();
+        self.mark_clean();
+    }
+
+    fn open_file(&mut self, path: &Path) {
+        let opened = Project::load(path).map_err(|e| format!("{e:#}"))
+            .and_then(|project| self.open_project(&project));
+        match opened {
+            Ok(()) => {
+                self.files.current = Some(path.to_path_buf());
+                self.files.path = path.display().to_string();
+                self.files.status.clear();
+                self.files.remember(path);
+                self.mark_clean();
+            }
+            Err(e) => {
+                if !path.exists() {
+                    self.files.recent.remove(path);
+                    self.files.save_recent();
+                }
+                self.files.status = e;
+            }
+        }
+    }
+
+    fn save_file(&mut self, path: PathBuf) {
+        let saved = self.project()
+            .and_then(|project| project.save(&path).map_err(|e| format!("{e:#}")));
+        match saved {
+            Ok(()) => {
+                self.files.path = path.display().to_string();
+                self.files.status.clear();
+                self.files.remember(&path);
+                self.files.current = Some(path);
+                self.mark_clean();
+                let _ = std::fs::remove_file(self.files.autosave_path());
+            }
+            Err(e) => self.files.status = e,
+        }
+    }
+
+    /// Takes the current project as the baseline for autosaving.
+    fn mark_clean(&mut self) {
+        self.files.saved = self.project_json();
+        self.files.autosaved = self.files.saved.clone();
+    }
+
+    fn project_json(&self) -> String {
+        self.project().ok()
+            .and_then(|p| p.to_json().ok())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the canvas, or asks first if that would lose unsaved work.
+    fn replace(&mut self, what: Replace) {
+        if self.project_json() != self.files.saved {
+            self.files.confirm = Some(what);
+        } else {
+            self.replace_now(what);
+        }
+    }
+
+    fn replace_now(&mut self, what: Replace) {
+        match what {
+            Replace::New => self.new_project(),
+            Replace::Open(path) => self.open_file(&path),
+            Replace::Example(build) => self.load_example(build()),
+        }
+    }
+
+    /// Writes the project to the recovery file if it changed since the
+    /// last save, at most every `AUTOSAVE_EVERY` unless `now`.
+    fn autosave(&mut self, now: bool) {
+        // an unanswered recovery keeps its file
+        if self.files.recovery.is_some() || (!now && self.files.last_autosave.elapsed() < AUTOSAVE_EVERY) {
+            return;
+        }
+        self.files.last_autosave = Instant::now();
+        let Ok(json) = self.project().and_then(|p| p.to_json().map_err(|e| e.to_string())) else {
+            return;
+        };
+        if json != self.files.autosaved {
+            let written = std::fs::create_dir_all(&self.files.dir)
+                .and_then(|_| std::fs::write(self.files.autosave_path(), &json));
+            match written {
+                Ok(()) => self.files.autosaved = json,
+                Err(e) => self.files.status = format!("autosave: {e}"),
+            }
+        }
+    }
 }
 
 
@@ -295,6 +1096,9 @@ register_gate! {
     "Switch"  => spawn_switch,
     "Button"  => spawn_button,
     "Clock"   => spawn_clock,
+    "Pattern" => spawn_pattern,
+    "One-shot" => spawn_one_shot,
+    "Random bit" => spawn_random,
 
     "Buffer"  => spawn_buffer,
     "NOT"     => spawn_not,
@@ -305,8 +1109,24 @@ register_gate! {
     "XOR"     => spawn_xor,
     "XNOR"    => spawn_xnor,
     "TRI-State"=> spawn_tri,
+    "CMOS NAND (4T)" => spawn_cmos_nand,
+    "LUT 2" => spawn_lut2,
+    "LUT 3" => spawn_lut3,
+    "LUT 4" => spawn_lut4,
+    "Open-drain" => spawn_open_drain,
+    "Pull-up" => spawn_pull_up,
+    "Pull-down" => spawn_pull_down,
 
     "Lamp"    => spawn_lamp,
+    "7-Segment" => spawn_seven_seg,
+    "Hex → 7-Seg" => spawn_seg_decoder,
+    "Pixel display 32x32" => spawn_display,
+
+    "TTY" => spawn_tty,
+    "Keyboard" => spawn_keyboard,
+
+    "RAM 16x8" => spawn_ram,
+    "ROM 16x8" => spawn_rom,
 }
 
 
@@ -316,16 +1136,498 @@ impl LogicApp {
 }
 
 
+/// Two-input NAND built from four transistors.
+fn cmos_nand(a: GateRef, b: GateRef) -> GateRef {
+    let mut net = SwitchNetwork::new();
+    net.input("a", a).unwrap();
+    net.input("b", b).unwrap();
+    net.pmos("a", VDD, "out");
+    net.pmos("b", VDD, "out");
+    net.nmos("a", "out", "mid");
+    net.nmos("b", "mid", GND);
+    Rc::new(RefCell::new(SwitchNode::new(Rc::new(net), "out").unwrap()))
+}
+
+const WORDS_PER_ROW: usize = 8;
+
+/// Segments `a`..`g` of a digit drawn inside `r`, plus the decimal point.
+fn seven_seg_shapes(r: egui::Rect) -> ([egui::Rect; 7], egui::Pos2) {
+    let m = 12.0;
+    let t = 4.0;
+    let (l, rt) = (r.left() + m, r.right() - m);
+    let (top, bot) = (r.top() + 8.0, r.bottom() - 12.0);
+    let mid = (top + bot) / 2.0;
+    let h = |y: f32| egui::Rect::from_min_max(egui::pos2(l + t, y - t / 2.0), egui::pos2(rt - t, y + t / 2.0));
+    let v = |x: f32, y0: f32, y1: f32| egui::Rect::from_min_max(egui::pos2(x - t / 2.0, y0 + t), egui::pos2(x + t / 2.0, y1 - t));
+
+    ([
+        h(top),
+        v(rt, top, mid),
+        v(rt, mid, bot),
+        h(bot),
+        v(l, mid, bot),
+        v(l, top, mid),
+        h(mid),
+    ], egui::pos2(rt + 5.0, bot))
+}
+
+fn memory_panel(ui: &mut egui::Ui, app: &mut LogicApp) {
+    let ids = app.circuit.memory_ids();
+    let view = &mut app.mem_view;
+    if view.selected.as_ref().is_none_or(|s| !ids.contains(s)) {
+        view.selected = ids.first().cloned();
+        view.editing = None;
+    }
+
+    let Some(selected) = view.selected.clone() else {
+        ui.label("No RAM or ROM in the circuit.");
+        return;
+    };
+    let core = app.circuit.memory(&selected).unwrap();
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label("Memory")
+            .selected_text(&selected)
+            .show_ui(ui, |ui| {
+                for id in &ids {
+                    ui.selectable_value(&mut view.selected, Some(id.clone()), id);
+                }
+            });
+        let c = core.borrow();
+        ui.label(format!("{} x {} bit", c.len(), c.data_width()));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("File");
+        ui.text_edit_singleline(&mut view.path);
+        egui::ComboBox::from_id_source("mem_format")
+            .selected_text(format!("{:?}", view.format))
+            .show_ui(ui, |ui| {
+                for f in [MemoryFormat::IntelHex, MemoryFormat::Binary, MemoryFormat::HexText] {
+                    ui.selectable_value(&mut view.format, f, format!("{f:?}"));
+                }
+            });
+        if ui.button("Import").clicked() {
+            view.status = match core.borrow_mut().load_file(&view.path, view.format) {
+                Ok(()) => format!("Loaded {}", view.path),
+                Err(e) => format!("{e:#}"),
+            };
+        }
+        if ui.button("Export").clicked() {
+            view.status = match core.borrow().save_file(&view.path, view.format) {
+                Ok(()) => format!("Saved {}", view.path),
+                Err(e) => format!("{e:#}"),
+            };
+        }
+    });
+    if !view.status.is_empty() {
+        ui.label(&view.status);
+    }
+    ui.horizontal(|ui| {
+        ui.colored_label(egui::Color32::LIGHT_BLUE, "last read");
+        ui.colored_label(egui::Color32::GOLD, "last write");
+        ui.label("click a word to edit");
+    });
+    ui.separator();
+
+    let (digits, bytes, len, last_read, last_write) = {
+        let c = core.borrow();
+        (c.data_width().div_ceil(4), c.data_width().div_ceil(8), c.len(), c.last_read, c.last_write)
+    };
+
+    egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+        egui::Grid::new("mem_grid").striped(true).show(ui, |ui| {
+            for row in (0..len).step_by(WORDS_PER_ROW) {
+                ui.monospace(format!("{row:04x}:"));
+
+                let mut ascii = String::new();
+                for addr in row..(row + WORDS_PER_ROW).min(len) {
+                    let word = core.borrow().peek(addr);
+                    ascii.extend(word.to_le_bytes().iter().take(bytes).map(|b| {
+                        if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' }
+                    }));
+
+                    if let Some((edit_addr, buf)) = view.editing.as_mut().filter(|(a, _)| *a == addr) {
+                        let resp = ui.add(egui::TextEdit::singleline(buf)
+                            .desired_width(8.0 * digits as f32 + 8.0)
+                            .font(egui::TextStyle::Monospace));
+                        resp.request_focus();
+                        if resp.lost_focus() {
+                            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                match u64::from_str_radix(buf.trim(), 16) {
+                                    Ok(v) => core.borrow_mut().poke(*edit_addr, v),
+                                    Err(e) => view.status = format!("bad value '{buf}': {e}"),
+                                }
+                            }
+                            view.editing = None;
+                        }
+                        continue;
+                    }
+
+                    let mut text = egui::RichText::new(format!("{word:0digits$x}")).monospace();
+                    if last_write == Some(addr) {
+                        text = text.background_color(egui::Color32::GOLD).color(egui::Color32::BLACK);
+                    } else if last_read == Some(addr) {
+                        text = text.background_color(egui::Color32::LIGHT_BLUE).color(egui::Color32::BLACK);
+                    }
+                    if ui.add(egui::Label::new(text).sense(egui::Sense::click())).clicked() {
+                        view.editing = Some((addr, format!("{word:x}")));
+                    }
+                }
+                ui.monospace(ascii);
+                ui.end_row();
+            }
+        });
+    });
+}
+
+/// Truth table editor for the LUT node in `app.lut_editor`.
+fn lut_panel(ui: &mut egui::Ui, app: &mut LogicApp) {
+    let Some(gate) = app.lut_editor.as_ref().and_then(|id| app.circuit.gate(id)) else {
+        return;
+    };
+    let mut g = gate.borrow_mut();
+    let Some(lut) = g.as_any().downcast_mut::<LutGate>() else {
+        return;
+    };
+    let mut table = lut.table();
+    let k = table.inputs();
+
+    egui::Grid::new("lut_rows").striped(true).show(ui, |ui| {
+        for i in (0..k).rev() {
+            ui.strong(format!("in{i}"));
+        }
+        ui.strong("out");
+        ui.end_row();
+
+        for row in 0..table.rows() {
+            for i in (0..k).rev() {
+                ui.monospace(if row >> i & 1 == 1 { "1" } else { "0" });
+            }
+            let mut out = table.get(row);
+            if ui.checkbox(&mut out, "").changed() {
+                table.set(row, out);
+            }
+            ui.end_row();
+        }
+    });
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        if ui.button("All 0").clicked() { table = TruthTable::new(k, 0).unwrap(); }
+        if ui.button("Invert").clicked() { table = TruthTable::from_fn(k, |r| !table.get(r)).unwrap(); }
+    });
+    ui.monospace(format!("column {table}  mask {:#x}", table.mask()));
+    let _ = lut.set_table(table);
+}
+
+/// Parameters of the selected subcircuit instance; Apply rebuilds it.
+fn property_panel(ui: &mut egui::Ui, app: &mut LogicApp) {
+    let Some(edit) = app.props.as_mut() else {
+        return;
+    };
+    let Some(module) = app.circuit.instance_module(&edit.path) else {
+        app.props = None;
+        return;
+    };
+    ui.label(format!("{} : {module}", edit.path));
+    if edit.params.is_empty() {
+        ui.label("No parameters.");
+        return;
+    }
+    egui::Grid::new("params").show(ui, |ui| {
+        for (name, value) in edit.params.iter_mut() {
+            ui.label(name.as_str());
+            ui.add(egui::DragValue::new(value));
+            ui.end_row();
+        }
+    });
+    if ui.button("Apply").clicked() {
+        let (path, params) = (edit.path.clone(), edit.params.clone());
+        let status = app.rebuild_instance(&path, &params).err().unwrap_or_default();
+        if let Some(edit) = app.props.as_mut() {
+            edit.status = status;
+        }
+    }
+    if let Some(edit) = app.props.as_ref().filter(|e| !e.status.is_empty()) {
+        ui.colored_label(egui::Color32::RED, &edit.status);
+    }
+}
+
+/// Output of the shared `console` terminal and a line editor feeding its keyboard.
+fn console_panel(ui: &mut egui::Ui, app: &mut LogicApp) {
+    let Some(term) = app.circuit.terminal(CONSOLE) else {
+        ui.label("Add a TTY or keyboard to use the console.");
+        return;
+    };
+
+    let text = term.borrow().output_text();
+    egui::ScrollArea::vertical()
+        .max_height(240.0)
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            ui.add(egui::Label::new(egui::RichText::new(text).monospace()).wrap(true));
+        });
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        let edit = ui.text_edit_singleline(&mut app.console.line);
+        let enter = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if ui.button("Send").clicked() || enter {
+            let mut line = std::mem::take(&mut app.console.line);
+            line.push('\n');
+            term.borrow_mut().push_input(line.as_bytes());
+            edit.request_focus();
+        }
+        if ui.button("Clear").clicked() {
+            term.borrow_mut().clear();
+        }
+    });
+    ui.label(format!("{} key(s) waiting", term.borrow().pending_input()));
+}
+
+/// Palette sections for imported libraries, then definitions outside of
+/// any library, and the import controls.
+fn library_palette(ui: &mut egui::Ui, app: &mut LogicApp) {
+    let mut spawn = None;
+    let mut listed = Vec::new();
+    for name in app.circuit.library_names() {
+        let lib = app.circuit.library(&name).unwrap();
+        ui.collapsing(format!("{} {}", lib.name, lib.version), |ui| {
+            if !lib.description.is_empty() {
+                ui.label(&lib.description);
+            }
+            for c in &lib.components {
+                if ui.button(&c.definition.name).on_hover_text(c.help()).clicked() {
+                    spawn = Some(c.definition.name.clone());
+                }
+            }
+        });
+        listed.extend(lib.names().into_iter().map(String::from));
+    }
+    let others: Vec<String> = app.circuit.definition_names().into_iter().filter(|n| !listed.contains(n)).collect();
+    if !others.is_empty() {
+        ui.collapsing("Subcircuits", |ui| {
+            for name in others {
+                if ui.button(&name).clicked() {
+                    spawn = Some(name);
+                }
+            }
+        });
+    }
+    if let Some(name) = spawn {
+        app.lib_status = app.spawn_instance(&name).err().unwrap_or_default();
+    }
+
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut app.lib_path);
+        if ui.button("Import library").clicked() {
+            app.lib_status = match Library::load(&app.lib_path) {
+                Ok(lib) => app.circuit.import_library(lib).err().unwrap_or_default(),
+                Err(e) => format!("{e:#}"),
+            };
+        }
+    });
+    if !app.lib_status.is_empty() {
+        ui.colored_label(egui::Color32::RED, &app.lib_status);
+    }
+}
+
+fn file_menu(ui: &mut egui::Ui, app: &mut LogicApp) {
+    ui.menu_button("File", |ui| {
+        if ui.button("New").clicked() {
+            app.replace(Replace::New);
+            ui.close_menu();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Path");
+            ui.text_edit_singleline(&mut app.files.path);
+        });
+        if ui.button("Open").clicked() {
+            app.replace(Replace::Open(PathBuf::from(&app.files.path)));
+            ui.close_menu();
+        }
+        if ui.add_enabled(app.files.current.is_some(), egui::Button::new("Save")).clicked() {
+            if let Some(path) = app.files.current.clone() {
+                app.save_file(path);
+            }
+            ui.close_menu();
+        }
+        if ui.button("Save As").clicked() {
+            app.save_file(PathBuf::from(&app.files.path));
+            ui.close_menu();
+        }
+        if ui.button("Export Verilog").clicked() {
+            let path = PathBuf::from(&app.files.path).with_extension("v");
+            let top = path.file_stem().and_then(|s| s.to_str()).unwrap_or("top").to_string();
+            match app.circuit.save_verilog(&path, &top) {
+                Ok(()) => app.files.status.clear(),
+                Err(e) => app.files.status = format!("{e:#}"),
+            }
+            ui.close_menu();
+        }
+        if ui.button("Export BLIF").clicked() {
+            let path = PathBuf::from(&app.files.path).with_extension("blif");
+            let top = path.file_stem().and_then(|s| s.to_str()).unwrap_or("top").to_string();
+            match app.circuit.save_blif(&path, &top) {
+                Ok(()) => app.files.status.clear(),
+                Err(e) => app.files.status = format!("{e:#}"),
+            }
+            ui.close_menu();
+        }
+        ui.menu_button("Open Recent", |ui| {
+            if app.files.recent.paths().is_empty() {
+                ui.label("No recent files");
+            }
+            for path in app.files.recent.paths().to_vec() {
+                if ui.button(path.display().to_string()).clicked() {
+                    app.replace(Replace::Open(path));
+                    ui.close_menu();
+                }
+            }
+        });
+    });
+}
+
 impl eframe::App for LogicApp {
     fn update(&mut self, ctx:&egui::Context, _: &mut eframe::Frame) {
-
+        // let strobes driven by switches reach clocked parts between ticks
+        self.circuit.settle();
+
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                file_menu(ui, self);
+                ui.menu_button("Examples", |ui| {
+                    for (name, build) in EXAMPLES {
+                        if ui.button(name).clicked() {
+                            self.replace(Replace::Example(build));
+                            ui.close_menu();
+                        }
+                    }
+                });
+                if let Some(path) = &self.files.current {
+                    ui.label(path.display().to_string());
+                }
+                if !self.files.status.is_empty() {
+                    ui.colored_label(egui::Color32::RED, &self.files.status);
+                }
+            });
+        });
 
         egui::SidePanel::left("palette").show(ctx, |ui| {
             palette(ui, self);
             ui.separator();
         
+            ui.collapsing("Stimulus settings", |ui| {
+                let s = &mut self.stimulus;
+                ui.horizontal(|ui| { ui.label("Pattern"); ui.text_edit_singleline(&mut s.pattern); });
+                ui.add(egui::DragValue::new(&mut s.hold).clamp_range(1..=64).prefix("hold "));
+                ui.add(egui::DragValue::new(&mut s.width).clamp_range(1..=64).prefix("pulse "));
+                ui.add(egui::DragValue::new(&mut s.seed).prefix("seed "));
+            });
             if ui.button("Tick clock").clicked() { self.circuit.step(); }
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.fsm_path);
+                if ui.button("Load FSM").clicked() {
+                    self.fsm_status = match StateTable::load(&self.fsm_path) {
+                        Ok(table) => self.spawn_fsm(table).err().unwrap_or_default(),
+                        Err(e) => format!("{e:#}"),
+                    };
+                }
+            });
+            if !self.fsm_status.is_empty() {
+                ui.colored_label(egui::Color32::RED, &self.fsm_status);
+            }
+            ui.checkbox(&mut self.mem_view.open, "Memory viewer");
+            ui.checkbox(&mut self.console.open, "Console");
+            if ui.button("Save displays as PNG").clicked() {
+                self.png_status = self.circuit.display_ids().iter()
+                    .filter_map(|id| {
+                        let frame = self.circuit.display(id).unwrap();
+                        let saved = frame.borrow().save_png(format!("{id}.png"));
+                        saved.err().map(|e| format!("{e:#}"))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+            if !self.png_status.is_empty() {
+                ui.colored_label(egui::Color32::RED, &self.png_status);
+            }
         });
+
+        if let Some(project) = self.files.recovery.clone() {
+            egui::Window::new("Recover")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("Unsaved work from the last session was found.");
+                    ui.horizontal(|ui| {
+                        if ui.button("Restore").clicked() {
+                            self.files.recovery = None;
+                            if let Err(e) = self.open_project(&project) {
+                                self.files.status = e;
+                            }
+                            self.files.current = None;
+                            self.mark_clean();
+                            // restored work is still not in any file
+                            self.files.saved.clear();
+                        }
+                        if ui.button("Discard").clicked() {
+                            self.files.recovery = None;
+                            let _ = std::fs::remove_file(self.files.autosave_path());
+                        }
+                    });
+                });
+        }
+        if self.files.confirm.is_some() {
+            egui::Window::new("Unsaved changes")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("The current project has unsaved changes.");
+                    ui.horizontal(|ui| {
+                        if ui.button("Discard").clicked() {
+                            if let Some(what) = self.files.confirm.take() {
+                                self.replace_now(what);
+                            }
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.files.confirm = None;
+                        }
+                    });
+                });
+        }
+        self.autosave(false);
+
+        let mut mem_open = self.mem_view.open;
+        egui::Window::new("Memory")
+            .open(&mut mem_open)
+            .default_width(420.0)
+            .show(ctx, |ui| memory_panel(ui, self));
+        self.mem_view.open = mem_open;
+
+        let mut lut_open = self.lut_editor.is_some();
+        egui::Window::new("Truth table")
+            .open(&mut lut_open)
+            .show(ctx, |ui| lut_panel(ui, self));
+        if !lut_open {
+            self.lut_editor = None;
+        }
+
+        let mut props_open = self.props.is_some();
+        egui::Window::new("Properties")
+            .open(&mut props_open)
+            .show(ctx, |ui| property_panel(ui, self));
+        if !props_open {
+            self.props = None;
+        }
+
+        let mut console_open = self.console.open;
+        egui::Window::new("Console")
+            .open(&mut console_open)
+            .default_width(360.0)
+            .show(ctx, |ui| console_panel(ui, self));
+        self.console.open = console_open;
         
 
 
@@ -350,17 +1652,48 @@ impl eframe::App for LogicApp {
 
                 let rect_screen = node.rect.translate(canvas_offset);
 
-                let base_color = if node.label == "LAMP" {
+                let base_color = if label_kind(&node.label) == "LAMP" {
                     match node.gate.borrow().eval() {
                         Signal::High => egui::Color32::GREEN,
                         Signal::Low  => egui::Color32::RED,
                         Signal::HiZ  => egui::Color32::DARK_GRAY,
+                        Signal::X    => egui::Color32::from_rgb(255, 140, 0),
                     }
                 } else { egui::Color32::DARK_GRAY };
 
                 painter.rect_filled(rect_screen, 4.0, base_color);
-                painter.text(rect_screen.center(), egui::Align2::CENTER_CENTER,
-                             &node.label, egui::FontId::monospace(12.0), egui::Color32::WHITE);
+                if node.label == "7SEG" {
+                    let lit: Vec<bool> = node.ports.iter()
+                        .map(|p| self.circuit.gate(&p.gate_id).is_some_and(|g| g.borrow().eval().is_high()))
+                        .collect();
+                    let colour = |on: bool| if on { egui::Color32::RED } else { egui::Color32::from_rgb(60, 20, 20) };
+                    let (segments, dp) = seven_seg_shapes(rect_screen);
+                    for (seg, on) in segments.iter().zip(&lit) {
+                        painter.rect_filled(*seg, 1.0, colour(*on));
+                    }
+                    painter.circle_filled(dp, 2.5, colour(lit[7]));
+                } else if let Some(frame) = self.circuit.display(&node.id) {
+                    let frame = frame.borrow();
+                    let area = rect_screen.shrink2(egui::vec2(14.0, 11.0));
+                    let px = egui::vec2(area.width() / frame.width() as f32, area.height() / frame.height() as f32);
+                    painter.rect_filled(area, 0.0, egui::Color32::BLACK);
+                    for y in 0..frame.height() {
+                        for x in 0..frame.width() {
+                            let [r, g, b] = frame.pixel(x, y);
+                            if [r, g, b] != [0, 0, 0] {
+                                let min = area.min + egui::vec2(x as f32 * px.x, y as f32 * px.y);
+                                painter.rect_filled(egui::Rect::from_min_size(min, px), 0.0, egui::Color32::from_rgb(r, g, b));
+                            }
+                        }
+                    }
+                } else if let Some(fsm) = self.circuit.fsm(&node.id) {
+                    painter.text(rect_screen.center(), egui::Align2::CENTER_CENTER,
+                                 format!("{}\n[{}]", node.label, fsm.state_name()),
+                                 egui::FontId::monospace(12.0), egui::Color32::WHITE);
+                } else {
+                    painter.text(rect_screen.center(), egui::Align2::CENTER_CENTER,
+                                 &node.label, egui::FontId::monospace(12.0), egui::Color32::WHITE);
+                }
 
 
                              let resp = ui.interact(
@@ -382,14 +1715,24 @@ impl eframe::App for LogicApp {
                 if resp.secondary_clicked() {
                     self.to_delete_node = Some(idx); 
                 }
+                if resp.double_clicked() && node.gate.borrow_mut().as_any().is::<LutGate>() {
+                    self.lut_editor = Some(node.id.clone());
+                }
+                if resp.double_clicked() {
+                    if let Some(params) = self.circuit.instance_params(&node.id) {
+                        self.props = Some(PropertyEdit { path: node.id.clone(), params: params.clone(), status: String::new() });
+                    }
+                }
                 
 
                 for (pidx, port) in node.ports.iter().enumerate() {
                     let pin_pos = rect_screen.min + port.offset;
-                    painter.circle_filled(pin_pos, 4.0, match node.gate.borrow().eval() {
+                    let pin_gate = self.circuit.gate(&port.gate_id).unwrap_or_else(|| node.gate.clone());
+                    painter.circle_filled(pin_pos, 4.0, match pin_gate.borrow().eval() {
                         Signal::High => egui::Color32::GREEN,
                         Signal::Low  => egui::Color32::RED,
                         Signal::HiZ  => egui::Color32::GRAY,
+                        Signal::X    => egui::Color32::from_rgb(255, 140, 0),
                     });
 
                     let pin_resp = ui.interact(
@@ -410,7 +1753,7 @@ impl eframe::App for LogicApp {
                     }
                 }
 
-                if node.label == "SW" {
+                if label_kind(&node.label) == "SW" {
                     let rect_screen = node.rect.translate(canvas_offset);
                     let resp = ui.interact(
                         rect_screen,
@@ -463,6 +1806,7 @@ impl eframe::App for LogicApp {
                 let id = self.nodes[idx].id.clone();
                 self.wires.retain(|w| w.from.0 != id && w.to.0 != id);
                 self.circuit.remove_gate(&id);  
+                let _ = self.circuit.remove_instance(&id);
                 self.nodes.swap_remove(idx);
             }
 
@@ -476,7 +1820,8 @@ impl eframe::App for LogicApp {
                                         .find(|n| n.id==aid).unwrap().ports[aidx].gate_id.clone();
                         let to_gate   = self.nodes.iter()
                                         .find(|n| n.id==nid).unwrap().ports[pidx].gate_id.clone();
-                        let _ = self.circuit.connect(&from_gate,&to_gate);
+                        // several outputs may share one input net, e.g. a pull-up next to open-drain drivers
+                        let _ = self.circuit.add_driver(&from_gate,&to_gate);
                     }
                 }
             }
@@ -507,7 +1852,13 @@ impl eframe::App for LogicApp {
 
 
             if let Some(idx) = self.to_delete_wire.take() {
-                self.wires.swap_remove(idx);
+                let w = self.wires.swap_remove(idx);
+                let port_gate = |(nid, pidx): &(String, usize)| self.nodes.iter()
+                    .find(|n| &n.id == nid)
+                    .map(|n| n.ports[*pidx].gate_id.clone());
+                if let (Some(from), Some(to)) = (port_gate(&w.from), port_gate(&w.to)) {
+                    let _ = self.circuit.disconnect(&from, &to);
+                }
             }
 
 
@@ -515,17 +1866,166 @@ impl eframe::App for LogicApp {
 
         ctx.request_repaint();  
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // kept for the next start unless the work was saved
+        self.autosave(true);
+    }
 }
 
 
 
 
 
+const USAGE: &str = "usage: logic [--headless <file> [--terminal <name>] [--steps <n>]]";
+
+/// Steps run by `--headless` without `--steps`.
+const HEADLESS_STEPS: usize = 100_000;
+
+/// `--headless`: runs a saved circuit or project with one of its terminals
+/// on `input` and `output` instead of opening the editor.
+fn run_cli<R, W>(args: &[String], input: R, output: &mut W) -> anyhow::Result<()>
+where
+    R: std::io::Read + Send + 'static,
+    W: std::io::Write,
+{
+    let (mut file, mut terminal, mut steps) = (None, None, HEADLESS_STEPS);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow::anyhow!("{arg} needs a value\n{USAGE}"));
+        match arg.as_str() {
+            "--headless" => file = Some(PathBuf::from(value()?)),
+            "--terminal" => terminal = Some(value()?.clone()),
+            "--steps" => {
+                let n = value()?;
+                steps = n.parse().map_err(|_| anyhow::anyhow!("--steps takes a number, not '{n}'"))?;
+            }
+            _ => anyhow::bail!("unknown argument '{arg}'\n{USAGE}"),
+        }
+    }
+    let Some(path) = file else { anyhow::bail!("{USAGE}") };
+
+    let text = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let is_project = serde_json::from_str::<serde_json::Value>(&text).is_ok_and(|doc| doc.get("circuit").is_some());
+    let mut circuit = if is_project {
+        Project::from_json(&text).and_then(|p| p.build().map_err(anyhow::Error::msg))
+    } else {
+        Circuit::from_json(&text)
+    }.with_context(|| format!("loading {}", path.display()))?;
+
+    let terminal = match terminal {
+        Some(name) => name,
+        None => match circuit.terminal_ids().as_slice() {
+            [only] => only.clone(),
+            [] => anyhow::bail!("{} has no terminal", path.display()),
+            names => anyhow::bail!("{} has several terminals, pick one with --terminal: {}", path.display(), names.join(", ")),
+        },
+    };
+    run_headless(&mut circuit, &terminal, steps, input, output)
+}
+
 fn main() -> eframe::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = run_cli(&args, std::io::stdin(), &mut std::io::stdout()) {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     eframe::run_native(
         "Logic",
         eframe::NativeOptions::default(),
-        Box::new(|_cc| Box::new(LogicApp::default())),
+        Box::new(|_cc| Box::new(LogicApp::with_files(ProjectFiles::open(state_dir())))),
     )
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("logic_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_untouched_session_writes_no_autosave() {
+        let dir = scratch_dir("untouched");
+        let mut app = LogicApp::with_files(ProjectFiles::open(dir.clone()));
+        app.autosave(true);
+        assert!(!app.files.autosave_path().exists(), "{}", app.files.status);
+
+        app.spawn_switch();
+        app.autosave(true);
+        assert!(app.files.autosave_path().exists(), "{}", app.files.status);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_headless_cli() {
+        let dir = scratch_dir("cli");
+        std::fs::create_dir_all(&dir).unwrap();
+        const KEYS: [&str; 8] = ["k0", "k1", "k2", "k3", "k4", "k5", "k6", "k7"];
+        let mut c = Circuit::new();
+        c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+        c.add_wire("ack", Wire::new("ack"));
+        c.add_keyboard("con", "ack", &KEYS, "ready").unwrap();
+        let strobe = AndGate::new(c.gate("ready").unwrap(), c.gate("clk").unwrap());
+        c.add_gate("strobe", Rc::new(RefCell::new(strobe)));
+        c.connect("strobe", "ack").unwrap();
+        c.add_tty("tty", "con", &KEYS, "strobe").unwrap();
+        let path = dir.join("echo.json");
+        c.save(&path).unwrap();
+
+        let run = |args: &[&str], keys: &[u8]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let mut out = Vec::new();
+            run_cli(&args, std::io::Cursor::new(keys.to_vec()), &mut out).map(|()| out).map_err(|e| format!("{e:#}"))
+        };
+        let file = path.to_str().unwrap();
+        assert_eq!(run(&["--headless", file, "--steps", "40"], b"hello\n").unwrap(), b"hello\n");
+        assert_eq!(run(&["--steps", "40", "--headless", file, "--terminal", "con"], b"hi").unwrap(), b"hi");
+        for (args, err) in [
+            (&["--headless", file, "--terminal", "tty2"][..], "no terminal named 'tty2'"),
+            (&["--headless", file, "--steps", "many"], "--steps takes a number"),
+            (&["--headless"], "--headless needs a value"),
+            (&["--verbose"], "unknown argument '--verbose'"),
+            (&[], "usage"),
+        ] {
+            let e = run(args, b"").unwrap_err();
+            assert!(e.to_lowercase().contains(&err.to_lowercase()), "{args:?}: {e}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replacing_unsaved_work_asks_first() {
+        let dir = scratch_dir("unsaved");
+        let mut app = LogicApp::with_files(ProjectFiles::open(dir.clone()));
+        app.replace(Replace::Example(EXAMPLES[0].1));
+        assert!(app.files.confirm.is_none(), "nothing to lose");
+        assert!(!app.nodes.is_empty());
+
+        app.spawn_switch();
+        let nodes = app.nodes.len();
+        app.autosave(true);
+        for what in [Replace::New, Replace::Open(dir.join("missing.json")), Replace::Example(EXAMPLES[1].1)] {
+            app.replace(what);
+            assert!(app.files.confirm.take().is_some(), "autosaved work is still unsaved");
+            assert_eq!(app.nodes.len(), nodes);
+        }
+
+        let path = dir.join("saved.json");
+        app.save_file(path.clone());
+        assert_eq!(app.files.status, "");
+        app.replace(Replace::New);
+        assert!(app.files.confirm.is_none());
+        assert!(app.nodes.is_empty());
+        app.replace(Replace::Open(path));
+        assert_eq!(app.nodes.len(), nodes);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use crate::circuit::alu::{Alu, AluOp};
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::tests::{drive, read};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const A: [&str; 4] = ["a0", "a1", "a2", "a3"];
+const B: [&str; 4] = ["b0", "b1", "b2", "b3"];
+const OP: [&str; 4] = ["op0", "op1", "op2", "op3"];
+const R: [&str; 4] = ["r0", "r1", "r2", "r3"];
+const FLAGS: [&str; 4] = ["carry", "zero", "neg", "ovf"];
+
+fn alu_circuit() -> Circuit {
+    let mut circuit = Circuit::new();
+    for id in A.iter().chain(B.iter()).chain(OP.iter()) {
+        circuit.add_gate(*id, Rc::new(RefCell::new(InputGate::new(false))));
+    }
+    circuit.add_alu(&A, &B, OP, &R, FLAGS).unwrap();
+    for id in R.iter().chain(FLAGS.iter()) {
+        circuit.add_output(*id);
+    }
+    circuit
+}
+
+fn run(circuit: &mut Circuit, op: AluOp, a: u8, b: u8) -> (u8, [bool; 4]) {
+    drive(circuit, &A, a.into());
+    drive(circuit, &B, b.into());
+    drive(circuit, &OP, op.code().into());
+
+    let out = circuit.eval();
+    (read(circuit, &R) as u8, FLAGS.map(|id| out[id]))
+}
+
+#[test]
+fn test_alu_exhaustive_4bit() {
+    let mut circuit = alu_circuit();
+
+    for op in AluOp::ALL {
+        for a in 0..16u8 {
+            for b in 0..16u8 {
+                let (r, [carry, zero, neg, ovf]) = run(&mut circuit, op, a, b);
+
+                let (full, expected_carry) = match op {
+                    AluOp::Add => (a as u16 + b as u16, a as u16 + b as u16 > 15),
+                    AluOp::Sub | AluOp::Cmp => ((a as u16).wrapping_sub(b as u16), a >= b),
+                    AluOp::And => ((a & b) as u16, false),
+                    AluOp::Or  => ((a | b) as u16, false),
+                    AluOp::Xor => ((a ^ b) as u16, false),
+                    AluOp::Not => ((!a) as u16, false),
+                    AluOp::Shl => ((a << 1) as u16, a & 8 != 0),
+                    AluOp::Shr => ((a >> 1) as u16, a & 1 != 0),
+                };
+                let value = (full & 0xF) as u8;
+                let expected_r = if op == AluOp::Cmp { 0 } else { value };
+
+                let sa = ((a << 4) as i8) >> 4;
+                let sb = ((b << 4) as i8) >> 4;
+                let expected_ovf = match op {
+                    AluOp::Add => !(-8..=7).contains(&(sa + sb)),
+                    AluOp::Sub | AluOp::Cmp => !(-8..=7).contains(&(sa - sb)),
+                    _ => false,
+                };
+
+                let ctx = format!("{op:?} a={a} b={b}");
+                assert_eq!(r, expected_r, "result {ctx}");
+                assert_eq!(carry, expected_carry, "carry {ctx}");
+                assert_eq!(zero, value == 0, "zero {ctx}");
+                assert_eq!(neg, value & 8 != 0, "negative {ctx}");
+                assert_eq!(ovf, expected_ovf, "overflow {ctx}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_alu_unused_opcode() {
+    let mut circuit = alu_circuit();
+    drive(&mut circuit, &A, 0b1011);
+    drive(&mut circuit, &B, 0b0110);
+    drive(&mut circuit, &OP, 0b1111);
+
+    let out = circuit.eval();
+    assert!(R.iter().all(|id| !out[*id]));
+    assert!(out["zero"]);
+    assert!(!out["carry"] && !out["neg"] && !out["ovf"]);
+}
+
+#[test]
+fn test_alu_single_bit() {
+    let t = Rc::new(RefCell::new(ConstGate::new(Signal::High)));
+    let f = Rc::new(RefCell::new(ConstGate::new(Signal::Low)));
+    let bits = AluOp::Add.bits().map(|b| -> GateRef { if b { t.clone() } else { f.clone() } });
+
+    let alu = Alu::new(vec![t.clone()], vec![t.clone()], bits);
+    assert_eq!(alu.result[0].borrow().eval(), Signal::Low);
+    assert_eq!(alu.carry.borrow().eval(), Signal::High);
+    assert_eq!(alu.zero.borrow().eval(), Signal::High);
+}
+
+#[test]
+fn test_add_alu_width_mismatch() {
+    let mut circuit = alu_circuit();
+    let err = circuit.add_alu(&A, &B[..3], OP, &R, FLAGS);
+    assert!(err.is_err());
+
+    let err = circuit.add_alu(&["missing"], &["b0"], OP, &["x"], FLAGS);
+    assert!(err.is_err());
+}
@@ -0,0 +1,144 @@
+use crate::circuit::arith::*;
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::tests::{drive, ids, inputs, read};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn adder_circuit(kind: &str) -> (Circuit, Vec<String>, Vec<String>, Vec<String>) {
+    let mut c = Circuit::new();
+    let a = inputs(&mut c, "a", 5);
+    let b = inputs(&mut c, "b", 5);
+    c.add_gate("cin", Rc::new(RefCell::new(InputGate::new(false))));
+
+    // carry out is the sixth sum bit
+    let sum: Vec<String> = (0..6).map(|i| format!("s{i}")).collect();
+    let (a_ids, b_ids, s_ids) = (ids(&a), ids(&b), ids(&sum));
+    match kind {
+        "ripple" => c.add_ripple_carry_adder(&a_ids, &b_ids, "cin", &s_ids[..5], "s5"),
+        "lookahead" => c.add_carry_lookahead_adder(&a_ids, &b_ids, "cin", &s_ids[..5], "s5"),
+        "select" => c.add_carry_select_adder(&a_ids, &b_ids, "cin", &s_ids[..5], "s5", 2),
+        _ => unreachable!(),
+    }
+    .unwrap();
+    for id in &sum {
+        c.add_output(id);
+    }
+    (c, a, b, sum)
+}
+
+#[test]
+fn test_adders_exhaustive() {
+    for kind in ["ripple", "lookahead", "select"] {
+        let (mut c, a, b, sum) = adder_circuit(kind);
+        for x in 0..32u64 {
+            for y in 0..32 {
+                for cin in [false, true] {
+                    drive(&mut c, &a, x);
+                    drive(&mut c, &b, y);
+                    c.set_input_bool("cin", cin).unwrap();
+                    assert_eq!(read(&c, &sum), x + y + cin as u64, "{kind} {x}+{y}+{cin}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_adder_architectures_differ() {
+    let (ripple, ..) = adder_circuit("ripple");
+    let (lookahead, ..) = adder_circuit("lookahead");
+
+    assert!(lookahead.depth() < ripple.depth());
+    assert!(lookahead.gate_count() > ripple.gate_count());
+    assert_eq!(ripple.gate_count(), 5 * 5);
+}
+
+#[test]
+fn test_subtractor() {
+    let mut c = Circuit::new();
+    let a = inputs(&mut c, "a", 4);
+    let b = inputs(&mut c, "b", 4);
+    let diff: Vec<String> = (0..4).map(|i| format!("d{i}")).collect();
+    c.add_subtractor(&ids(&a), &ids(&b), &ids(&diff), "nb").unwrap();
+    for id in &diff {
+        c.add_output(id);
+    }
+    c.add_output("nb");
+
+    for x in 0..16u64 {
+        for y in 0..16 {
+            drive(&mut c, &a, x);
+            drive(&mut c, &b, y);
+            assert_eq!(read(&c, &diff), x.wrapping_sub(y) & 0xF);
+            assert_eq!(c.eval()["nb"], x >= y);
+        }
+    }
+}
+
+#[test]
+fn test_multiplier() {
+    let mut c = Circuit::new();
+    let a = inputs(&mut c, "a", 4);
+    let b = inputs(&mut c, "b", 3);
+    let p: Vec<String> = (0..7).map(|i| format!("p{i}")).collect();
+    c.add_multiplier(&ids(&a), &ids(&b), &ids(&p)).unwrap();
+    for id in &p {
+        c.add_output(id);
+    }
+
+    for x in 0..16u64 {
+        for y in 0..8 {
+            drive(&mut c, &a, x);
+            drive(&mut c, &b, y);
+            assert_eq!(read(&c, &p), x * y, "{x}*{y}");
+        }
+    }
+
+    assert!(c.add_multiplier(&ids(&a), &ids(&b), &ids(&p[..6])).is_err());
+}
+
+#[test]
+fn test_comparators() {
+    let mut c = Circuit::new();
+    let a = inputs(&mut c, "a", 3);
+    let b = inputs(&mut c, "b", 3);
+    c.add_equality_comparator(&ids(&a), &ids(&b), "same").unwrap();
+    c.add_magnitude_comparator(&ids(&a), &ids(&b), ["lt", "eq", "gt"]).unwrap();
+    for id in ["same", "lt", "eq", "gt"] {
+        c.add_output(id);
+    }
+
+    for x in 0..8u64 {
+        for y in 0..8 {
+            drive(&mut c, &a, x);
+            drive(&mut c, &b, y);
+            let out = c.eval();
+            assert_eq!(out["same"], x == y);
+            assert_eq!(out["eq"], x == y);
+            assert_eq!(out["lt"], x < y);
+            assert_eq!(out["gt"], x > y);
+        }
+    }
+}
+
+#[test]
+fn test_mux2() {
+    let t: GateRef = Rc::new(RefCell::new(ConstGate::new(Signal::High)));
+    let f: GateRef = Rc::new(RefCell::new(ConstGate::new(Signal::Low)));
+
+    assert_eq!(mux2(f.clone(), t.clone(), f.clone()).borrow().eval(), Signal::High);
+    assert_eq!(mux2(t.clone(), t.clone(), f.clone()).borrow().eval(), Signal::Low);
+}
+
+#[test]
+fn test_bus_width_errors() {
+    let mut c = Circuit::new();
+    let a = inputs(&mut c, "a", 3);
+    let b = inputs(&mut c, "b", 2);
+    c.add_gate("cin", Rc::new(RefCell::new(InputGate::new(false))));
+
+    assert!(c.add_ripple_carry_adder(&ids(&a), &ids(&b), "cin", &["s0", "s1", "s2"], "co").is_err());
+    assert!(c.add_carry_select_adder(&ids(&a), &ids(&a), "cin", &["s0", "s1", "s2"], "co", 0).is_err());
+    assert!(c.add_equality_comparator(&ids(&a), &["a0", "a1", "nope"], "eq").is_err());
+}
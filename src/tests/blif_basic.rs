@@ -0,0 +1,223 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::netlist::GateKind;
+use crate::circuit::subcircuit::SubcircuitDef;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn level(c: &Circuit, id: &str) -> Signal {
+    c.gate(id).unwrap_or_else(|| panic!("no gate '{id}'")).borrow().eval()
+}
+
+fn set(c: &mut Circuit, id: &str, v: bool) {
+    c.set_input_bool(id, v).unwrap();
+    c.settle();
+}
+
+/// ABC-style output: covers, a wide off-set cover, a latch and a subcircuit.
+const DESIGN: &str = "
+# written by hand
+.model top
+.inputs a b c d e f g h \\
+        clk
+.outputs maj wide q s co
+.default_input_arrival 0 0
+.names a b c maj
+11- 1
+1-1 1
+-11 1
+.names a b c d e f g h wide
+00000000 0
+.latch maj q re clk 1
+.subckt half_adder x=a y=b s=s c=co
+.end
+
+.model half_adder
+.inputs x y
+.outputs s c
+.names x y s
+10 1
+01 1
+.names x y c
+11 1
+.end
+";
+
+#[test]
+fn test_read_covers_latches_and_subckts() {
+    let mut c = Circuit::from_blif(DESIGN).unwrap();
+    assert_eq!(c.instance_module("half_adder_0"), Some("half_adder"));
+    let netlist = c.to_netlist().unwrap();
+    assert!(matches!(netlist.gates["maj"], GateKind::Lut { .. }));
+    assert!(!matches!(netlist.gates["wide"], GateKind::Lut { .. }), "more than 6 inputs");
+    assert!(level(&c, "q").is_high(), "initial value");
+
+    for n in 0..8u8 {
+        for (id, v) in ["a", "b", "c"].iter().zip([n & 1, n & 2, n & 4]) {
+            set(&mut c, id, v != 0);
+        }
+        let ones = n.count_ones();
+        assert_eq!(level(&c, "maj").is_high(), ones >= 2, "{n:03b}");
+        assert_eq!(level(&c, "wide").is_high(), n != 0, "{n:03b}");
+        assert_eq!(level(&c, "s").is_high(), (n & 1 != 0) != (n & 2 != 0), "{n:03b}");
+        assert_eq!(level(&c, "co").is_high(), n & 3 == 3, "{n:03b}");
+    }
+    set(&mut c, "h", true);
+    set(&mut c, "a", false);
+    set(&mut c, "b", false);
+    assert!(level(&c, "wide").is_high());
+    set(&mut c, "clk", true);
+    assert!(!level(&c, "q").is_high(), "latched maj");
+}
+
+#[test]
+fn test_latch_without_clock_and_wide_sop() {
+    let text = "
+.model m
+.inputs a b c d e f g
+.outputs y q
+.names a b c d e f g y
+1111111 1
+0-----0 1
+.latch y q 0
+.end
+";
+    let mut c = Circuit::from_blif(text).unwrap();
+    assert!(c.gate("clk").is_some(), "global clock input");
+    assert!(level(&c, "y").is_high(), "a = g = 0");
+    set(&mut c, "g", true);
+    assert!(!level(&c, "y").is_high());
+    for id in ["a", "b", "c", "d", "e", "f"] {
+        set(&mut c, id, true);
+    }
+    assert!(level(&c, "y").is_high());
+    assert!(!level(&c, "q").is_high());
+    set(&mut c, "clk", true);
+    assert!(level(&c, "q").is_high());
+}
+
+#[test]
+fn test_round_trip() {
+    let mut c = Circuit::new();
+    for id in ["a", "b", "cin", "en"] {
+        c.add_gate(id, Rc::new(RefCell::new(InputGate::new(false))));
+    }
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.define(SubcircuitDef::half_adder()).unwrap();
+    c.define(SubcircuitDef::full_adder()).unwrap();
+    c.instantiate("full_adder", "fa", &["a", "b", "cin"]).unwrap();
+    let (sum, en, clk) = (c.gate("fa.sum").unwrap(), c.gate("en").unwrap(), c.gate("clk").unwrap());
+    c.add_gate("ff", Rc::new(RefCell::new(Dflipflop::new(sum.clone(), clk))));
+    c.add_gate("latch", Rc::new(RefCell::new(Dlatch::new(sum, en))));
+    c.add_lut("maj", &["a", "b", "cin"], crate::circuit::lut::TruthTable::from_fn(3, |r| (r as u32).count_ones() >= 2).unwrap()).unwrap();
+    for out in ["fa.sum", "fa.cout", "ff", "latch", "maj"] {
+        c.add_output(out);
+    }
+
+    let text = c.to_blif("top").unwrap();
+    assert!(text.starts_with(".model top\n"), "{text}");
+    assert!(text.contains(".subckt full_adder "), "{text}");
+    assert!(text.contains(".latch "), "{text}");
+    let mut back = Circuit::from_blif(&text).unwrap_or_else(|e| panic!("{e}\n{text}"));
+    assert_eq!(back.instance_module("full_adder_0.half_adder_0"), Some("half_adder"));
+    for n in 0..16u8 {
+        for (id, v) in ["a", "b", "cin", "en"].iter().zip([n & 1, n & 2, n & 4, n & 8]) {
+            set(&mut c, id, v != 0);
+            set(&mut back, id, v != 0);
+        }
+        for clk in [true, false] {
+            set(&mut back, "clk", clk);
+            c.step();
+        }
+        for (orig, port) in [("fa.sum", "fa_sum"), ("fa.cout", "fa_cout"), ("ff", "ff"), ("latch", "latch"), ("maj", "maj")] {
+            assert_eq!(level(&back, port), level(&c, orig), "{orig} at {n:04b}");
+        }
+    }
+}
+
+#[test]
+fn test_errors() {
+    let mut c = Circuit::new();
+    c.add_gate("a", Rc::new(RefCell::new(InputGate::new(false))));
+    c.add_gate("en", Rc::new(RefCell::new(InputGate::new(false))));
+    let (a, en) = (c.gate("a").unwrap(), c.gate("en").unwrap());
+    c.add_gate("drv", Rc::new(RefCell::new(TriStateGate::new(a, en))));
+    c.add_output("drv");
+    let err = c.to_blif("top").unwrap_err();
+    assert!(err.contains("tri-state 'drv'") && err.contains("no BLIF form"), "{err}");
+
+    let err = Circuit::from_blif(".model m\n.inputs a\n.outputs y\n.gate and2 A=a B=a O=y\n.end\n").unwrap_err();
+    assert!(err.starts_with("line 4:") && err.contains(".gate"), "{err}");
+    let err = Circuit::from_blif(".model m\n.outputs y\n.names ghost y\n1 1\n.end\n").unwrap_err();
+    assert!(err.contains("'ghost' is never driven"), "{err}");
+    let err = Circuit::from_blif(".model m\n.inputs a\n.subckt nothing x=a\n.end\n").unwrap_err();
+    assert!(err.starts_with("line 3:") && err.contains("unknown model 'nothing'"), "{err}");
+    let err = Circuit::from_blif(".model m\n.inputs a\n.outputs y\n.names a y\n1 1\n0 0\n.end\n").unwrap_err();
+    assert!(err.starts_with("line 6:"), "{err}");
+    assert!(Circuit::load_blif("/nonexistent/top.blif").is_err());
+}
+
+#[test]
+fn test_global_clock_reaches_nested_models() {
+    let text = "
+.model top
+.inputs d
+.outputs q
+.subckt mid a=d y=q
+.end
+
+.model mid
+.inputs a
+.outputs y
+.subckt leaf x=a z=y
+.end
+
+.model leaf
+.inputs x
+.outputs z
+.latch x z 0
+.end
+";
+    let mut c = Circuit::from_blif(text).unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(c.instance_module("mid_0.leaf_0"), Some("leaf"));
+    set(&mut c, "d", true);
+    assert!(!level(&c, "q").is_high());
+    set(&mut c, "clk", true);
+    assert!(level(&c, "q").is_high(), "clk drives the latch two models down");
+}
+
+#[test]
+fn test_names_kept_apart() {
+    let text = "
+.model top
+.inputs a.b a_b
+.outputs y z
+.names a.b a_b y
+10 1
+.subckt sub.x p.q=a.b p_q=a_b o.r=z
+.end
+
+.model sub.x
+.inputs p.q p_q
+.outputs o.r
+.names p.q p_q o.r
+01 1
+.end
+
+.model sub_x
+.inputs i
+.outputs o
+.names i o
+1 1
+.end
+";
+    let mut c = Circuit::from_blif(text).unwrap_or_else(|e| panic!("{e}"));
+    assert!(c.gate("a_b").is_some() && c.gate("a_b_1").is_some());
+    assert_eq!(c.instance_module("sub_x_1_0"), Some("sub_x_1"));
+    for (ab, a_b) in [(false, false), (true, false), (false, true), (true, true)] {
+        set(&mut c, "a_b_1", ab);
+        set(&mut c, "a_b", a_b);
+        assert_eq!(level(&c, "y").is_high(), ab && !a_b, "{ab} {a_b}");
+        assert_eq!(level(&c, "z").is_high(), !ab && a_b, "{ab} {a_b}");
+    }
+}
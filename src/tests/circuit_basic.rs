@@ -124,3 +124,106 @@ fn test_4bit_adder() {
     assert_eq!(out.get("sum3"), Some(&true));
     assert_eq!(out.get("cout"), Some(&false));
 }
+
+#[test]
+fn test_step_clocks_registers_together() {
+    let mut circuit = Circuit::new();
+
+    let clk = Rc::new(RefCell::new(ClockGate::new()));
+    let d = Rc::new(RefCell::new(InputGate::new(true)));
+    let q0 = Rc::new(RefCell::new(Dflipflop::new(d.clone(), clk.clone())));
+    let q1 = Rc::new(RefCell::new(Dflipflop::new(q0.clone(), clk.clone())));
+
+    circuit.add_gate("clk", clk);
+    circuit.add_gate("d", d);
+    circuit.add_gate("q0", q0);
+    circuit.add_gate("q1", q1);
+    circuit.add_output("q0");
+    circuit.add_output("q1");
+
+    circuit.step();
+    let out = circuit.eval();
+    assert!(out["q0"]);
+    assert!(!out["q1"]);
+
+    circuit.step();
+    circuit.step();
+    let out = circuit.eval();
+    assert!(out["q0"]);
+    assert!(out["q1"]);
+}
+
+#[test]
+fn test_toggle_flip_flop_feedback() {
+    let mut circuit = Circuit::new();
+
+    let clk = Rc::new(RefCell::new(ClockGate::new()));
+    let d = Rc::new(RefCell::new(Wire::new("d")));
+    let q = Rc::new(RefCell::new(Dflipflop::new(d.clone(), clk.clone())));
+    d.borrow_mut().connect(Rc::new(RefCell::new(NotGate::new(q.clone()))));
+
+    circuit.add_gate("clk", clk);
+    circuit.add_gate("q", q);
+    circuit.add_output("q");
+
+    for cycle in 1..=4 {
+        circuit.step();
+        circuit.step();
+        assert_eq!(circuit.eval()["q"], cycle % 2 == 1);
+    }
+}
+
+#[test]
+fn test_flop_clocking_another_flop_keeps_its_edge() {
+    // `gates` is a HashMap, so each fresh circuit visits them in another order
+    for _ in 0..200 {
+        let mut circuit = Circuit::new();
+
+        let clk = Rc::new(RefCell::new(ClockGate::new()));
+        let one = Rc::new(RefCell::new(ConstGate::new(Signal::High)));
+        let q1 = Rc::new(RefCell::new(Dflipflop::new(one.clone(), clk.clone())));
+        let q2 = Rc::new(RefCell::new(Dflipflop::new(q1.clone(), clk.clone())));
+        let q3 = Rc::new(RefCell::new(Dflipflop::new(one, q1.clone())));
+
+        circuit.add_gate("clk", clk);
+        circuit.add_gate("q1", q1);
+        circuit.add_gate("q2", q2);
+        circuit.add_gate("q3", q3);
+        for id in ["q1", "q2", "q3"] {
+            circuit.add_output(id);
+        }
+
+        circuit.step();
+        let out = circuit.eval();
+        assert!(out["q1"]);
+        assert!(!out["q2"], "q2 samples q1 from before the edge");
+        assert!(out["q3"], "q1 rising clocks q3");
+
+        circuit.step();
+        circuit.step();
+        assert!(circuit.eval()["q2"]);
+    }
+}
+
+#[test]
+fn test_flip_flop_latches_on_eval_and_settle() {
+    let mut circuit = Circuit::new();
+    let d = Rc::new(RefCell::new(InputGate::new(false)));
+    let clk = Rc::new(RefCell::new(InputGate::new(false)));
+    circuit.add_gate("q", Rc::new(RefCell::new(Dflipflop::new(d.clone(), clk.clone()))));
+    circuit.add_gate("d", d);
+    circuit.add_gate("clk", clk);
+    circuit.add_output("q");
+
+    // without a scheduler, reading the flip-flop takes the edge
+    circuit.set_input_bool("d", true).unwrap();
+    circuit.set_input_bool("clk", true).unwrap();
+    assert!(circuit.eval()["q"]);
+
+    circuit.set_input_bool("clk", false).unwrap();
+    circuit.settle();
+    circuit.set_input_bool("d", false).unwrap();
+    circuit.set_input_bool("clk", true).unwrap();
+    circuit.settle();
+    assert!(!circuit.eval()["q"], "settle took the edge");
+}
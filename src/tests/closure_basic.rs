@@ -0,0 +1,84 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::closure::*;
+use crate::circuit::gate::*;
+use crate::tests::{drive, ids, inputs, read};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn test_reference_multiplier_matches_gates() {
+    let mut c = Circuit::new();
+    let a = inputs(&mut c, "a", 3);
+    let b = inputs(&mut c, "b", 3);
+    let gates: Vec<String> = (0..6).map(|i| format!("p{i}")).collect();
+    let model: Vec<String> = (0..6).map(|i| format!("m{i}")).collect();
+
+    c.add_multiplier(&ids(&a), &ids(&b), &ids(&gates)).unwrap();
+    let ab: Vec<&str> = ids(&a).into_iter().chain(ids(&b)).collect();
+    c.add_closure_gate("mul", &ab, &ids(&model), |levels| match (pack(&levels[..3]), pack(&levels[3..])) {
+        (Some(x), Some(y)) => unpack(x * y, 6),
+        _ => vec![Signal::X; 6],
+    })
+    .unwrap();
+
+    for x in 0..8u64 {
+        for y in 0..8 {
+            drive(&mut c, &a, x);
+            drive(&mut c, &b, y);
+            assert_eq!(read(&c, &model), x * y);
+            assert_eq!(read(&c, &gates), read(&c, &model), "{x} * {y}");
+        }
+    }
+    assert_eq!(c.gate("m0").unwrap().borrow().description(), "mul[0]");
+}
+
+#[test]
+fn test_clocked_closure_counter() {
+    let mut c = Circuit::new();
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.add_gate("en", Rc::new(RefCell::new(InputGate::new(true))));
+
+    let counter = ClosureGate::clocked(
+        "counter",
+        c.gate("clk").unwrap(),
+        vec![c.gate("en").unwrap()],
+        4,
+        0u64,
+        |n, levels| if levels[0].is_high() { (n + 1) % 16 } else { *n },
+        |n, _| unpack(*n, 4),
+    );
+    let q: Vec<String> = (0..4).map(|i| format!("q{i}")).collect();
+    c.add_closure(&ids(&q), counter).unwrap();
+
+    for expected in 1..=5 {
+        c.step();
+        assert_eq!(read(&c, &q), expected);
+        c.step();
+    }
+    c.set_input_bool("en", false).unwrap();
+    c.step();
+    assert_eq!(read(&c, &q), 5);
+}
+
+#[test]
+fn test_closure_gate_errors() {
+    let mut c = Circuit::new();
+    inputs(&mut c, "a", 1);
+    assert!(c.add_closure_gate("f", &["nope"], &["y"], |l| l.to_vec()).is_err());
+
+    // a short result leaves the missing outputs unknown
+    c.add_closure_gate("short", &["a0"], &["y0", "y1"], |l| l.to_vec()).unwrap();
+    assert_eq!(c.gate("y0").unwrap().borrow().eval(), Signal::Low);
+    assert_eq!(c.gate("y1").unwrap().borrow().eval(), Signal::X);
+
+    let gate = ClosureGate::new("g", Vec::new(), 2, |_| Vec::new());
+    assert!(c.add_closure(&["z"], gate).is_err());
+}
+
+#[test]
+fn test_pack_unpack() {
+    assert_eq!(pack(&unpack(0b1011, 4)), Some(0b1011));
+    assert_eq!(unpack(0b1011, 2), [Signal::High, Signal::High]);
+    assert_eq!(pack(&[Signal::High, Signal::HiZ]), None);
+    assert_eq!(pack(&[]), Some(0));
+}
@@ -0,0 +1,128 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::display::*;
+use crate::circuit::gate::*;
+use crate::tests::{drive, ids, inputs};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn strobe(circuit: &mut Circuit) {
+    circuit.set_input_bool("we", true).unwrap();
+    circuit.settle();
+    circuit.set_input_bool("we", false).unwrap();
+    circuit.settle();
+}
+
+/// Decodes the uncompressed PNGs written by `FrameBuffer::to_png`.
+fn decode_png(png: &[u8]) -> (u32, u32, Vec<u8>) {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let (mut pos, mut size, mut idat) = (8, (0, 0), Vec::new());
+    while pos < png.len() {
+        let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &png[pos + 4..pos + 8];
+        let data = &png[pos + 8..pos + 8 + len];
+        match kind {
+            b"IHDR" => size = (
+                u32::from_be_bytes(data[..4].try_into().unwrap()),
+                u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            ),
+            b"IDAT" => idat.extend_from_slice(data),
+            _ => {}
+        }
+        pos += 12 + len;
+    }
+
+    let mut raw = Vec::new();
+    let mut p = 2;
+    loop {
+        let last = idat[p] & 1 == 1;
+        let len = u16::from_le_bytes([idat[p + 1], idat[p + 2]]) as usize;
+        raw.extend_from_slice(&idat[p + 5..p + 5 + len]);
+        p += 5 + len;
+        if last {
+            break;
+        }
+    }
+    (size.0, size.1, raw)
+}
+
+#[test]
+fn test_address_data_display() {
+    let mut c = Circuit::new();
+    let x = inputs(&mut c, "x", 2);
+    let y = inputs(&mut c, "y", 2);
+    let d = inputs(&mut c, "d", 3);
+    c.add_gate("we", Rc::new(RefCell::new(InputGate::new(false))));
+
+    let bus = DisplayBus::AddressData { x: ids(&x), y: ids(&y), data: ids(&d), write: "we" };
+    c.add_display("lcd", 4, 3, bus, PixelFormat::Rgb111).unwrap();
+
+    drive(&mut c, &x, 3);
+    drive(&mut c, &y, 1);
+    drive(&mut c, &d, 0b011);
+    let frame = c.display("lcd").unwrap();
+    assert_eq!(frame.borrow().pixel(3, 1), [0, 0, 0]);
+
+    strobe(&mut c);
+    assert_eq!(frame.borrow().pixel(3, 1), [255, 255, 0]);
+
+    // out of range rows are ignored
+    drive(&mut c, &y, 3);
+    strobe(&mut c);
+    assert_eq!(frame.borrow().pixel(3, 2), [0, 0, 0]);
+}
+
+#[test]
+fn test_row_column_display_png() {
+    let mut c = Circuit::new();
+    let row = inputs(&mut c, "r", 1);
+    let cols = inputs(&mut c, "c", 3);
+    c.add_gate("we", Rc::new(RefCell::new(InputGate::new(false))));
+
+    let bus = DisplayBus::RowColumn { row: ids(&row), columns: ids(&cols), write: "we" };
+    c.add_display("matrix", 3, 2, bus, PixelFormat::Mono).unwrap();
+
+    drive(&mut c, &cols, 0b101);
+    strobe(&mut c);
+    drive(&mut c, &row, 1);
+    drive(&mut c, &cols, 0b010);
+    strobe(&mut c);
+
+    let frame = c.display("matrix").unwrap().borrow().clone();
+    let (w, h, raw) = decode_png(&frame.to_png());
+    assert_eq!((w, h), (3, 2));
+    assert_eq!(raw, [
+        0, 255, 255, 255, 0, 0, 0, 255, 255, 255,
+        0, 0, 0, 0, 255, 255, 255, 0, 0, 0,
+    ]);
+
+    let path = std::env::temp_dir().join(format!("logic_display_{}.png", std::process::id()));
+    frame.save_png(&path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), frame.to_png());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_display_bus_checks() {
+    let mut c = Circuit::new();
+    let x = inputs(&mut c, "x", 2);
+    c.add_gate("we", Rc::new(RefCell::new(InputGate::new(false))));
+
+    let bus = DisplayBus::AddressData { x: ids(&x), y: ids(&x), data: ids(&x), write: "we" };
+    assert!(c.add_display("a", 4, 4, bus, PixelFormat::Mono).is_err());
+
+    let bus = DisplayBus::RowColumn { row: ids(&x[..1]), columns: ids(&x), write: "we" };
+    assert!(c.add_display("b", 2, 4, bus, PixelFormat::Mono).is_err());
+
+    let bus = DisplayBus::RowColumn { row: vec!["nope"], columns: ids(&x), write: "we" };
+    assert!(c.add_display("c", 2, 2, bus, PixelFormat::Mono).is_err());
+}
+
+#[test]
+fn test_large_frame_png_blocks() {
+    let mut frame = FrameBuffer::new(200, 200);
+    frame.set_pixel(199, 199, [1, 2, 3]);
+    let (w, h, raw) = decode_png(&frame.to_png());
+    assert_eq!((w, h), (200, 200));
+    assert_eq!(raw.len(), 200 * 601);
+    assert_eq!(&raw[raw.len() - 3..], &[1, 2, 3]);
+}
@@ -0,0 +1,167 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::examples::*;
+use crate::circuit::gate::Signal;
+
+fn set_bus(c: &mut Circuit, prefix: &str, width: usize, value: u64) {
+    for i in 0..width {
+        c.set_input_bool(&format!("{prefix}{i}"), value >> i & 1 == 1).unwrap();
+    }
+}
+
+fn read_bus(c: &Circuit, prefix: &str, width: usize) -> u64 {
+    (0..width)
+        .filter(|i| c.gate(&format!("{prefix}{i}")).unwrap().borrow().eval().is_high())
+        .fold(0, |acc, i| acc | 1 << i)
+}
+
+fn level(c: &Circuit, id: &str) -> Signal {
+    c.gate(id).unwrap().borrow().eval()
+}
+
+/// One full clock period, i.e. one rising edge.
+fn cycle(c: &mut Circuit) {
+    c.step();
+    c.step();
+}
+
+#[test]
+fn test_every_example_builds() {
+    for (name, _) in EXAMPLES {
+        let ex = example(name).unwrap();
+        assert_eq!(ex.name, name);
+        for id in ex.inputs.iter().chain(&ex.outputs) {
+            assert!(ex.circuit.gate(id).is_some(), "{name}: no gate '{id}'");
+        }
+    }
+    assert!(example("nope").is_none());
+}
+
+#[test]
+fn test_example_adders() {
+    for (mut ex, width) in [(ripple_adder4(), 4), (lookahead_adder8(), 8)] {
+        let c = &mut ex.circuit;
+        let max = 1u64 << width;
+        for (a, b, cin) in [(0, 0, 0), (3, 5, 1), (max - 1, 1, 0), (max - 1, max - 1, 1), (max / 2, max / 3, 0)] {
+            set_bus(c, "a", width, a);
+            set_bus(c, "b", width, b);
+            c.set_input_bool("cin", cin == 1).unwrap();
+            let total = read_bus(c, "s", width) | (level(c, "cout").is_high() as u64) << width;
+            assert_eq!(total, a + b + cin, "{a} + {b} + {cin}");
+        }
+    }
+}
+
+#[test]
+fn test_example_counter() {
+    let mut ex = counter4();
+    let c = &mut ex.circuit;
+    cycle(c);
+    assert_eq!(read_bus(c, "q", 4), 0, "disabled");
+
+    c.set_input_bool("en", true).unwrap();
+    for n in 1..=15 {
+        cycle(c);
+        assert_eq!(read_bus(c, "q", 4), n);
+    }
+    assert_eq!(level(c, "tc"), Signal::High);
+    cycle(c);
+    assert_eq!(read_bus(c, "q", 4), 0, "wraps");
+
+    cycle(c);
+    cycle(c);
+    c.set_input_bool("clr", true).unwrap();
+    assert_eq!(read_bus(c, "q", 4), 2);
+    cycle(c);
+    assert_eq!(read_bus(c, "q", 4), 0);
+}
+
+#[test]
+fn test_example_traffic_light() {
+    let mut ex = traffic_light();
+    let c = &mut ex.circuit;
+    let lights = |c: &Circuit| ["red", "amber", "green"].map(|id| level(c, id).is_high());
+
+    cycle(c);
+    cycle(c);
+    assert_eq!(lights(c), [false, false, true], "waits for a request");
+
+    c.set_input_bool("req", true).unwrap();
+    let mut seen = Vec::new();
+    for _ in 0..4 {
+        cycle(c);
+        seen.push(c.fsm("light").unwrap().state_name().to_string());
+    }
+    assert_eq!(seen, ["amber", "red", "red_amber", "green"]);
+    assert_eq!(lights(c), [false, false, true]);
+}
+
+#[test]
+fn test_example_uart_frame() {
+    let mut ex = uart_tx();
+    let c = &mut ex.circuit;
+    assert_eq!(level(c, "tx"), Signal::High, "idles high");
+
+    let byte = 0b1010_0110;
+    set_bus(c, "d", 8, byte);
+    c.set_input_bool("send", true).unwrap();
+    let mut line = Vec::new();
+    for _ in 0..12 {
+        cycle(c);
+        line.push(level(c, "tx").is_high());
+        // holding send does not restart the frame
+        if line.len() == 3 {
+            c.set_input_bool("send", false).unwrap();
+        }
+    }
+
+    let expected: Vec<bool> = std::iter::once(false)
+        .chain((0..8).map(|i| byte >> i & 1 == 1))
+        .chain([true, true, true])
+        .collect();
+    assert_eq!(line, expected);
+    assert_eq!(level(c, "busy"), Signal::Low);
+}
+
+#[test]
+fn test_example_cpu_countdown() {
+    let mut ex = cpu_demo();
+    let c = &mut ex.circuit;
+    let mut shown = vec![read_bus(c, "out", 8)];
+    for _ in 0..64 {
+        if level(c, "halted").is_high() {
+            break;
+        }
+        cycle(c);
+        let out = read_bus(c, "out", 8);
+        if shown.last() != Some(&out) {
+            shown.push(out);
+        }
+    }
+    assert_eq!(level(c, "halted"), Signal::High);
+    assert_eq!(shown, std::iter::once(0).chain((0..=10).rev()).collect::<Vec<u64>>());
+}
+
+#[test]
+fn test_example_cpu_alu_program() {
+    // ((3 << 2 | 1) ^ 0xF0) >> 1
+    let program = [
+        Instr::Ldi(3),
+        Instr::Shl,
+        Instr::Shl,
+        Instr::Or(1),
+        Instr::Xor(0xF0),
+        Instr::Shr,
+        Instr::Jz(0),
+        Instr::Out,
+        Instr::Hlt,
+    ];
+    let mut ex = cpu(&program).unwrap();
+    let c = &mut ex.circuit;
+    for _ in 0..program.len() + 2 {
+        cycle(c);
+    }
+    assert_eq!(read_bus(c, "out", 8), 0b0111_1110);
+    assert_eq!(read_bus(c, "pc", 4), 8, "stays on Hlt");
+    assert!(cpu(&[Instr::Hlt; 17]).is_err());
+}
+
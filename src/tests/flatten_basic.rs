@@ -0,0 +1,90 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::flatten::*;
+use crate::circuit::gate::*;
+use crate::circuit::param::Params;
+use crate::circuit::subcircuit::*;
+use std::collections::HashMap;
+
+fn library(defs: &[SubcircuitDef]) -> HashMap<String, SubcircuitDef> {
+    defs.iter().map(|d| (d.name.clone(), d.clone())).collect()
+}
+
+fn level(c: &Circuit, id: &str) -> Signal {
+    c.gate(id).unwrap_or_else(|| panic!("no gate '{id}'")).borrow().eval()
+}
+
+#[test]
+fn test_flatten_full_adder() {
+    let defs = library(&[SubcircuitDef::half_adder(), SubcircuitDef::full_adder()]);
+    let mut flat = flatten(&defs, "full_adder", &Params::new()).unwrap();
+    assert!(flat.circuit.instance_paths().is_empty());
+    assert_eq!(flat.instances.keys().collect::<Vec<_>>(), ["full_adder", "full_adder.ha0", "full_adder.ha1"]);
+
+    let c = &mut flat.circuit;
+    for n in 0..8u8 {
+        let bits = [n & 1 == 1, n & 2 != 0, n & 4 != 0];
+        for (id, v) in ["a", "b", "cin"].iter().zip(bits) {
+            c.set_input_bool(id, v).unwrap();
+        }
+        let total = bits.iter().filter(|b| **b).count();
+        assert_eq!(level(c, "sum").is_high(), total & 1 == 1, "{n:03b}");
+        assert_eq!(level(c, "cout").is_high(), total >= 2, "{n:03b}");
+    }
+}
+
+#[test]
+fn test_name_map_both_ways() {
+    let defs = library(&[SubcircuitDef::half_adder(), SubcircuitDef::full_adder()]);
+    let flat = flatten(&defs, "full_adder", &Params::new()).unwrap();
+    let names = &flat.names;
+
+    assert_eq!(names.flat("full_adder.ha1.carry"), Some("ha1__carry"));
+    assert_eq!(names.hierarchical("ha1__carry"), Some("full_adder.ha1.carry"));
+    assert_eq!(names.instance("ha1__carry"), Some("full_adder.ha1"));
+    assert_eq!(names.hierarchical("cin"), Some("full_adder.cin"));
+    assert_eq!(names.instance("cout"), Some("full_adder"));
+    assert_eq!(names.hierarchical("nope"), None);
+
+    // every flat id is a gate of the circuit and maps back to itself
+    for (f, h) in names.pairs() {
+        assert!(flat.circuit.gate(f).is_some(), "{f}");
+        assert!(!f.contains('.'), "{f}");
+        assert_eq!(names.flat(h), Some(f));
+    }
+}
+
+#[test]
+fn test_mangled_names_stay_unique() {
+    // a cell whose own name collides with a mangled nested id
+    let top = SubcircuitDef::new("top", &["a", "b"], &["y"])
+        .instance("ha", "half_adder", &["a", "b"])
+        .cell("ha__sum", CellKind::Not, &["ha.sum"])
+        .cell("y", CellKind::Or, &["ha__sum", "ha.carry"]);
+    let defs = library(&[SubcircuitDef::half_adder(), top]);
+    let flat = flatten(&defs, "top", &Params::new()).unwrap();
+
+    let a = flat.names.flat("top.ha.sum").unwrap();
+    let b = flat.names.flat("top.ha__sum").unwrap();
+    assert_ne!(a, b);
+    assert_eq!(mangle("x.y.z"), "x__y__z");
+}
+
+#[test]
+fn test_flatten_with_params_and_errors() {
+    let mut c = Circuit::new();
+    c.define(SubcircuitDef::parity()).unwrap();
+    let params: Params = [("N".to_string(), 3)].into();
+    let mut flat = c.flatten("parity", &params).unwrap();
+    assert_eq!(flat.names.hierarchical("x2"), Some("parity.x2"));
+
+    for n in 0..8u32 {
+        for i in 0..3 {
+            flat.circuit.set_input_bool(&format!("x{i}"), n >> i & 1 == 1).unwrap();
+        }
+        assert_eq!(level(&flat.circuit, "odd").is_high(), n.count_ones() % 2 == 1, "{n:03b}");
+    }
+
+    assert!(c.flatten("missing", &Params::new()).is_err());
+    let bad: Params = [("K".to_string(), 1)].into();
+    assert!(c.flatten("parity", &bad).is_err());
+}
@@ -0,0 +1,206 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::fsm::*;
+use crate::circuit::gate::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Moore traffic light: advances while `go` is high.
+fn traffic_light() -> StateTable {
+    StateTable::new(FsmKind::Moore, &["go"], &["red", "amber", "green"])
+        .state("red", "100")
+        .state("red_amber", "110")
+        .state("green", "001")
+        .state("amber", "010")
+        .transition("red", "1", "red_amber", "")
+        .transition("red_amber", "1", "green", "")
+        .transition("green", "1", "amber", "")
+        .transition("amber", "1", "red", "")
+}
+
+/// Mealy detector: `hit` is high while the input is 1 for the second cycle running.
+fn ones_detector() -> StateTable {
+    StateTable::new(FsmKind::Mealy, &["x"], &["hit"])
+        .state("idle", "")
+        .state("one", "")
+        .transition("idle", "1", "one", "0")
+        .transition("one", "1", "one", "1")
+        .transition("one", "0", "idle", "0")
+}
+
+fn machine_circuit(table: StateTable, input: &str, outputs: &[&str]) -> Circuit {
+    let mut c = Circuit::new();
+    c.add_gate(input, Rc::new(RefCell::new(InputGate::new(false))));
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.add_fsm("fsm", table, "clk", &[input], outputs).unwrap();
+    for id in outputs {
+        c.add_output(*id);
+    }
+    c
+}
+
+fn cycle(c: &mut Circuit) {
+    c.step();
+    c.step();
+}
+
+#[test]
+fn test_moore_traffic_light() {
+    let mut c = machine_circuit(traffic_light(), "go", &["r", "a", "g"]);
+    let fsm = c.fsm("fsm").unwrap();
+    let lamps = |c: &Circuit| {
+        let out = c.eval();
+        (out["r"], out["a"], out["g"])
+    };
+
+    assert_eq!(fsm.state_name(), "red");
+    cycle(&mut c);
+    assert_eq!(fsm.state_name(), "red");
+
+    c.set_input_bool("go", true).unwrap();
+    let mut seen = Vec::new();
+    for _ in 0..4 {
+        cycle(&mut c);
+        seen.push((fsm.state_name().to_string(), lamps(&c)));
+    }
+    assert_eq!(seen, [
+        ("red_amber".into(), (true, true, false)),
+        ("green".into(), (false, false, true)),
+        ("amber".into(), (false, true, false)),
+        ("red".into(), (true, false, false)),
+    ]);
+    assert_eq!(c.gate("fsm").unwrap().borrow().description(), "Fsm(red)");
+}
+
+#[test]
+fn test_mealy_outputs_follow_inputs() {
+    let ghost = ones_detector().transition("ghost", "-", "idle", "0");
+    assert!(ghost.check().is_err());
+
+    let mut c = machine_circuit(ones_detector(), "x", &["hit"]);
+    let fsm = c.fsm("fsm").unwrap();
+
+    c.set_input_bool("x", true).unwrap();
+    assert!(!c.eval()["hit"]);
+    cycle(&mut c);
+    assert_eq!(fsm.state_name(), "one");
+    assert!(c.eval()["hit"]);
+
+    // Mealy output drops as soon as the input does, before the clock
+    c.set_input_bool("x", false).unwrap();
+    assert!(!c.eval()["hit"]);
+    cycle(&mut c);
+    assert_eq!(fsm.state_name(), "idle");
+
+    fsm.set_state("one").unwrap();
+    assert!(fsm.set_state("nope").is_err());
+    fsm.reset();
+    assert_eq!(fsm.state(), 0);
+}
+
+#[test]
+fn test_first_matching_transition_wins() {
+    let table = StateTable::new(FsmKind::Moore, &["a", "b"], &[])
+        .state("s0", "")
+        .state("s1", "")
+        .state("s2", "")
+        .transition("s0", "1-", "s1", "")
+        .transition("s0", "-1", "s2", "");
+
+    assert_eq!(table.step(0, &[true, true]).0, 1);
+    assert_eq!(table.step(0, &[false, true]).0, 2);
+    assert_eq!(table.step(0, &[false, false]).0, 0);
+}
+
+#[test]
+fn test_state_table_json() {
+    let table = traffic_light();
+    let text = serde_json::to_string(&table).unwrap();
+    assert_eq!(StateTable::from_json(&text).unwrap(), table);
+
+    let path = std::env::temp_dir().join(format!("logic_fsm_{}.json", std::process::id()));
+    table.save(&path).unwrap();
+    assert_eq!(StateTable::load(&path).unwrap(), table);
+    std::fs::remove_file(&path).unwrap();
+
+    let doc = r#"{ "kind": "Moore", "inputs": ["go"], "outputs": ["lamp"], "initial": "off",
+        "states": [{ "name": "off", "outputs": "0" }, { "name": "on", "outputs": "1" }],
+        "transitions": [{ "from": "off", "when": "1", "to": "on" },
+                        { "from": "on", "when": "0", "to": "off" }] }"#;
+    let table = StateTable::from_json(doc).unwrap();
+    assert_eq!(table.step(0, &[true]), (1, vec![false]));
+
+    assert!(StateTable::from_json(&doc.replace("\"when\": \"1\"", "\"when\": \"12\"")).is_err());
+    assert!(StateTable::from_json(&doc.replace("\"initial\": \"off\"", "\"initial\": \"x\"")).is_err());
+    assert!(StateTable::from_json(&doc.replace("\"outputs\": \"1\"", "\"outputs\": \"11\"")).is_err());
+}
+
+#[test]
+fn test_add_fsm_errors() {
+    let mut c = Circuit::new();
+    c.add_gate("go", Rc::new(RefCell::new(InputGate::new(false))));
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    assert!(c.add_fsm("f", traffic_light(), "clk", &["go"], &["r"]).is_err());
+    assert!(c.add_fsm("f", traffic_light(), "clk", &[], &["r", "a", "g"]).is_err());
+    assert!(c.add_fsm("f", traffic_light(), "nope", &["go"], &["r", "a", "g"]).is_err());
+    assert!(c.fsm("f").is_none());
+}
+
+#[test]
+fn test_state_encodings() {
+    assert_eq!(StateEncoding::Binary.width(5), 3);
+    assert_eq!(StateEncoding::Gray.width(1), 1);
+    assert_eq!(StateEncoding::OneHot.width(5), 5);
+
+    assert_eq!(StateEncoding::Binary.code(3, 4), [true, true]);
+    assert_eq!(StateEncoding::Gray.code(3, 4), [false, true]);
+    assert_eq!(StateEncoding::OneHot.code(2, 4), [false, false, true, false]);
+    for enc in StateEncoding::ALL {
+        for s in 0..5 {
+            assert_eq!(enc.decode(&enc.code(s, 5), 5), Some(s));
+        }
+    }
+    // Gray neighbours differ in one bit
+    for s in 0..7 {
+        let (a, b) = (StateEncoding::Gray.code(s, 8), StateEncoding::Gray.code(s + 1, 8));
+        assert_eq!(a.iter().zip(&b).filter(|(x, y)| x != y).count(), 1);
+    }
+}
+
+#[test]
+fn test_synthesis_matches_behaviour() {
+    // initial state is not state 0, and "busy" has overlapping conditions
+    let mut arbiter = StateTable::new(FsmKind::Moore, &["req", "stop"], &["busy"])
+        .state("idle", "0")
+        .state("busy", "1")
+        .state("done", "0")
+        .transition("idle", "1-", "busy", "")
+        .transition("busy", "-1", "done", "")
+        .transition("busy", "0-", "idle", "")
+        .transition("done", "--", "idle", "");
+    arbiter.initial = "done".into();
+
+    for table in [traffic_light(), ones_detector(), arbiter] {
+        for enc in StateEncoding::ALL {
+            check_synthesis(&table, enc, 200, 0xC0FFEE).unwrap_or_else(|e| panic!("{enc:?}: {e}"));
+        }
+    }
+}
+
+#[test]
+fn test_synthesized_traffic_light() {
+    let mut c = Circuit::new();
+    c.add_gate("go", Rc::new(RefCell::new(InputGate::new(true))));
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.add_synthesized_fsm("tl", &traffic_light(), StateEncoding::OneHot, "clk", &["go"], &["r", "a", "g"]).unwrap();
+    for id in ["r", "a", "g", "tl.q0", "tl.q3"] {
+        c.add_output(id);
+    }
+
+    assert!(c.eval()["r"] && c.eval()["tl.q0"]);
+    for _ in 0..3 {
+        cycle(&mut c);
+    }
+    let out = c.eval();
+    assert!(out["a"] && !out["r"] && out["tl.q3"]);
+    assert!(c.gate("tl.q4").is_none());
+}
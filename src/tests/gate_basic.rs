@@ -192,6 +192,28 @@ fn test_d_flip_flop() {
     assert_eq!(ff.eval(), Signal::Low);
 }
 
+#[test]
+fn test_d_flip_flop_scheduled() {
+    let d = Rc::new(RefCell::new(InputGate::new(true)));
+    let clk = Rc::new(RefCell::new(InputGate::new(false)));
+    let ff = Dflipflop::new(d.clone(), clk.clone());
+    assert!(!ff.clock_edge());
+
+    // the scheduler takes the edge in three passes; reads in between see
+    // the old level
+    clk.borrow_mut().set_signal(true);
+    scheduled(|| {
+        assert!(ff.clock_edge());
+        assert_eq!(ff.eval(), Signal::Low);
+        ff.capture();
+        d.borrow_mut().set_signal(false);
+        assert_eq!(ff.eval(), Signal::Low);
+        ff.commit();
+    });
+    assert_eq!(ff.eval(), Signal::High);
+    assert!(!ff.clock_edge(), "the edge was taken once");
+}
+
 #[test]
 fn test_clock() {
     let clk = ClockGate::new();
@@ -203,3 +225,33 @@ fn test_clock() {
     clk.tick();
     assert_eq!(clk.eval(), Signal::Low);
 }
+
+#[test]
+fn test_seven_seg_decoder() {
+    let bits: [Rc<RefCell<InputGate>>; 4] = std::array::from_fn(|_| Rc::new(RefCell::new(InputGate::new(false))));
+    let inputs = bits.clone().map(|b| b as GateRef);
+    let hex = SevenSegDecoder::new(inputs.clone(), true);
+    let bcd = SevenSegDecoder::new(inputs, false);
+
+    let pattern = |dec: &SevenSegDecoder| {
+        dec.segments.iter().enumerate().fold(0u8, |acc, (i, s)| acc | (s.borrow().eval().is_high() as u8) << i)
+    };
+
+    for (value, font) in SEVEN_SEG_FONT.iter().enumerate() {
+        for (i, b) in bits.iter().enumerate() {
+            b.borrow_mut().set_signal(value >> i & 1 == 1);
+        }
+        assert_eq!(pattern(&hex), *font);
+        assert_eq!(pattern(&bcd), if value < 10 { *font } else { 0 });
+    }
+    assert_eq!(SEVEN_SEG_FONT[8], 0x7F);
+    assert_eq!(hex.segments[0].borrow().description(), "Segmenta(hex)");
+}
+
+#[test]
+fn test_seven_seg_decoder_hiz() {
+    let z: GateRef = Rc::new(RefCell::new(ConstGate::new(Signal::HiZ)));
+    let low: GateRef = Rc::new(RefCell::new(ConstGate::new(Signal::Low)));
+    let dec = SevenSegDecoder::new([low.clone(), z, low.clone(), low], true);
+    assert_eq!(dec.segments[3].borrow().eval(), Signal::HiZ);
+}
@@ -0,0 +1,73 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::library::*;
+use crate::circuit::subcircuit::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn majority() -> SubcircuitDef {
+    SubcircuitDef::new("majority", &["a", "b", "c"], &["y"])
+        .cell("ab", CellKind::And, &["a", "b"])
+        .cell("bc", CellKind::And, &["b", "c"])
+        .cell("ca", CellKind::And, &["c", "a"])
+        .cell("y", CellKind::Or, &["ab", "bc", "ca"])
+}
+
+fn voting() -> Library {
+    Library::new("voting", "0.3.1", "Voters for redundant logic")
+        .component(majority(), "2-of-3 majority", &[("y", "high when two inputs are")])
+}
+
+#[test]
+fn test_library_json_round_trip() {
+    let lib = voting();
+    let text = serde_json::to_string_pretty(&lib).unwrap();
+    assert!(text.contains("\"version\": \"0.3.1\""));
+    assert_eq!(Library::from_json(&text).unwrap(), lib);
+
+    let path = std::env::temp_dir().join("logic_library_basic.json");
+    Library::standard().save(&path).unwrap();
+    let loaded = Library::load(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(loaded.names(), ["half_adder", "full_adder", "register", "parity"]);
+    assert_eq!(loaded.get("register").unwrap().ports["en"], "load on the next rising clk edge");
+    assert!(loaded.get("full_adder").unwrap().help().starts_with("1-bit full adder"));
+}
+
+#[test]
+fn test_import_library() {
+    let mut c = Circuit::new();
+    for id in ["a", "b", "x"] {
+        c.add_gate(id, Rc::new(RefCell::new(InputGate::new(false))));
+    }
+    c.import_library(voting()).unwrap();
+    c.import_library(Library::standard()).unwrap();
+    assert_eq!(c.library_names(), ["standard", "voting"]);
+    assert_eq!(c.library("voting").unwrap().description, "Voters for redundant logic");
+
+    c.instantiate("majority", "m", &["a", "b", "x"]).unwrap();
+    c.instantiate("half_adder", "ha", &["a", "b"]).unwrap();
+    c.set_input_bool("a", true).unwrap();
+    assert!(!c.gate("m.y").unwrap().borrow().eval().is_high());
+    c.set_input_bool("x", true).unwrap();
+    assert!(c.gate("m.y").unwrap().borrow().eval().is_high());
+    assert!(c.gate("ha.sum").unwrap().borrow().eval().is_high());
+}
+
+#[test]
+fn test_invalid_libraries() {
+    let bad_port = Library::new("v", "1", "").component(majority(), "", &[("z", "no such port")]);
+    assert!(bad_port.check().is_err());
+    let twice = voting().component(majority(), "again", &[]);
+    assert!(twice.check().is_err());
+    assert!(Library::new("v", " ", "").check().is_err());
+
+    // nothing is defined from a rejected library
+    let mut c = Circuit::new();
+    assert!(c.import_library(twice).is_err());
+    assert!(c.definition("majority").is_none());
+    assert!(c.library_names().is_empty());
+
+    assert!(Library::from_json("{\"name\": \"v\"}").is_err());
+    assert!(Library::load("/nonexistent/library.json").is_err());
+}
@@ -0,0 +1,83 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::lut::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const IN: [&str; 3] = ["a", "b", "c"];
+
+fn add_inputs(circuit: &mut Circuit) {
+    for id in IN {
+        circuit.add_gate(id, Rc::new(RefCell::new(InputGate::new(false))));
+    }
+}
+
+#[test]
+fn test_lut_matches_majority() {
+    let mut c = Circuit::new();
+    add_inputs(&mut c);
+    let majority = TruthTable::from_fn(3, |row| row.count_ones() >= 2).unwrap();
+    assert_eq!(majority.to_string(), "00010111");
+    c.add_lut("maj", &IN, majority).unwrap();
+    c.add_output("maj");
+
+    for row in 0..8usize {
+        for (i, id) in IN.iter().enumerate() {
+            c.set_input_bool(id, row >> i & 1 == 1).unwrap();
+        }
+        assert_eq!(c.eval()["maj"], row.count_ones() >= 2, "row {row}");
+    }
+}
+
+#[test]
+fn test_truth_table_parse_and_mask() {
+    let xor = TruthTable::parse("01_10").unwrap();
+    assert_eq!((xor.inputs(), xor.mask()), (2, 0b0110));
+    assert_eq!(TruthTable::new(2, 0b0110).unwrap(), xor);
+
+    assert!(TruthTable::parse("010").is_err());
+    assert!(TruthTable::parse("01a0").is_err());
+    assert!(TruthTable::new(1, 0b100).is_err());
+    assert!(TruthTable::new(7, 0).is_err());
+
+    let all = TruthTable::new(6, u64::MAX).unwrap();
+    assert!(all.get(63));
+}
+
+#[test]
+fn test_lut_unknown_inputs() {
+    // a AND b: a low input decides the result whatever b is
+    let and = TruthTable::parse("0001").unwrap();
+    assert_eq!(and.eval(&[Signal::Low, Signal::HiZ]), Signal::Low);
+    assert_eq!(and.eval(&[Signal::High, Signal::X]), Signal::X);
+    assert_eq!(and.eval(&[Signal::High, Signal::High]), Signal::High);
+}
+
+#[test]
+fn test_truth_table_serde() {
+    let table = TruthTable::parse("1000 0001").unwrap();
+    let json = serde_json::to_string(&table).unwrap();
+    assert_eq!(json, "\"10000001\"");
+    assert_eq!(serde_json::from_str::<TruthTable>(&json).unwrap(), table);
+    assert!(serde_json::from_str::<TruthTable>("\"101\"").is_err());
+}
+
+#[test]
+fn test_lut_set_table() {
+    let mut c = Circuit::new();
+    add_inputs(&mut c);
+    let and2 = TruthTable::parse("0001").unwrap();
+    assert!(c.add_lut("bad", &IN, and2).is_err());
+    c.add_lut("f", &IN[..2], and2).unwrap();
+    c.add_output("f");
+    c.set_input_bool("a", true).unwrap();
+    assert!(!c.eval()["f"]);
+
+    let gate = c.gate("f").unwrap();
+    let mut g = gate.borrow_mut();
+    let lut = g.as_any().downcast_mut::<LutGate>().unwrap();
+    lut.set_table(TruthTable::parse("0111").unwrap()).unwrap();
+    assert!(lut.set_table(TruthTable::parse("01").unwrap()).is_err());
+    drop(g);
+    assert!(c.eval()["f"]);
+}
@@ -0,0 +1,185 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::memory::*;
+use crate::tests::{drive, read};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn input(circuit: &mut Circuit, id: &str) {
+    circuit.add_gate(id, Rc::new(RefCell::new(InputGate::new(false))));
+}
+
+const ADDR: [&str; 3] = ["a0", "a1", "a2"];
+const DIN: [&str; 4] = ["d0", "d1", "d2", "d3"];
+const DOUT: [&str; 4] = ["q0", "q1", "q2", "q3"];
+
+fn ram_circuit(clocked: bool) -> Circuit {
+    let mut c = Circuit::new();
+    for id in ADDR.iter().chain(DIN.iter()).chain(["we", "cs"].iter()) {
+        input(&mut c, id);
+    }
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+
+    let pins = MemPins {
+        addr: ADDR.to_vec(),
+        data_in: DIN.to_vec(),
+        write_enable: Some("we"),
+        read_enable: None,
+        chip_select: Some("cs"),
+        clock: clocked.then_some("clk"),
+    };
+    c.add_ram("ram", MemoryCore::new(3, 4), pins, &DOUT).unwrap();
+    for id in DOUT {
+        c.add_output(id);
+    }
+    c
+}
+
+#[test]
+fn test_async_ram() {
+    let mut c = ram_circuit(false);
+    c.set_input_bool("cs", true).unwrap();
+
+    for addr in 0..8 {
+        drive(&mut c, &ADDR, addr);
+        drive(&mut c, &DIN, 15 - addr);
+        c.set_input_bool("we", true).unwrap();
+        assert_eq!(read(&c, &DOUT), 0, "reading does not write");
+        c.settle();
+        assert_eq!(read(&c, &DOUT), 15 - addr);
+        c.set_input_bool("we", false).unwrap();
+    }
+
+    let core = c.memory("ram").unwrap();
+    drive(&mut c, &DIN, 0);
+    for addr in (0..8).rev() {
+        drive(&mut c, &ADDR, addr);
+        assert_eq!(read(&c, &DOUT), 15 - addr);
+    }
+    assert_eq!(core.borrow().last_read, Some(7), "reads are recorded by the scheduler only");
+
+    c.step();
+    assert_eq!(core.borrow().words(), &[15, 14, 13, 12, 11, 10, 9, 8]);
+    assert_eq!(core.borrow().last_read, Some(0));
+    assert_eq!(core.borrow().last_write, Some(7));
+}
+
+#[test]
+fn test_sync_ram_writes_on_rising_edge() {
+    let mut c = ram_circuit(true);
+    c.set_input_bool("cs", true).unwrap();
+    c.set_input_bool("we", true).unwrap();
+    drive(&mut c, &ADDR, 5);
+    drive(&mut c, &DIN, 9);
+
+    // reading does not write without a clock edge
+    assert_eq!(read(&c, &DOUT), 0);
+
+    c.step();
+    assert_eq!(read(&c, &DOUT), 9);
+
+    // falling edge does nothing
+    drive(&mut c, &DIN, 3);
+    c.step();
+    assert_eq!(read(&c, &DOUT), 9);
+
+    c.step();
+    assert_eq!(read(&c, &DOUT), 3);
+}
+
+#[test]
+fn test_chip_select_floats_outputs() {
+    let mut c = ram_circuit(false);
+    let q0 = c.gate("q0").unwrap();
+
+    assert_eq!(q0.borrow().eval(), Signal::HiZ);
+    c.set_input_bool("cs", true).unwrap();
+    assert_eq!(q0.borrow().eval(), Signal::Low);
+}
+
+#[test]
+fn test_rom() {
+    let mut c = Circuit::new();
+    for id in ADDR {
+        input(&mut c, id);
+    }
+    let core = MemoryCore::with_contents(3, 4, &[1, 2, 4, 8, 3, 5, 7, 15]);
+    c.add_rom("rom", core, &ADDR, None, &DOUT).unwrap();
+    for id in DOUT {
+        c.add_output(id);
+    }
+
+    for (addr, expected) in [1, 2, 4, 8, 3, 5, 7, 15].into_iter().enumerate() {
+        drive(&mut c, &ADDR, addr as u64);
+        assert_eq!(read(&c, &DOUT), expected);
+    }
+
+    let err = c.add_rom("bad", MemoryCore::new(4, 4), &ADDR, None, &DOUT);
+    assert!(err.is_err());
+}
+
+#[test]
+fn test_intel_hex_roundtrip() {
+    let mut core = MemoryCore::new(5, 12);
+    for addr in 0..32 {
+        core.poke(addr, (addr as u64 * 0x55) & 0xFFF);
+    }
+    let text = core.to_intel_hex();
+    assert!(text.ends_with(":00000001FF\n"));
+
+    let mut back = MemoryCore::new(5, 12);
+    back.load_intel_hex(&text).unwrap();
+    assert_eq!(back.words(), core.words());
+}
+
+#[test]
+fn test_intel_hex_errors() {
+    let mut core = MemoryCore::new(4, 8);
+    core.load_intel_hex(":0400000001020304F2\n:00000001FF\n").unwrap();
+    assert_eq!(&core.words()[..5], &[1, 2, 3, 4, 0]);
+
+    assert!(core.load_intel_hex(":0400000001020304F3\n").is_err());
+    assert!(core.load_intel_hex("0400000001020304F2\n").is_err());
+    assert!(core.load_intel_hex(":0400F00001020304F2\n").is_err());
+    let err = core.load_intel_hex(":0100000212EB\n").unwrap_err().to_string();
+    assert!(err.contains("extended address record needs 2 data bytes"), "{err}");
+}
+
+#[test]
+fn test_hex_text_and_binary() {
+    let mut core = MemoryCore::new(3, 8);
+    core.load_hex_text("// header\nde ad\n@6 be # tail\nef\n").unwrap();
+    assert_eq!(core.words(), &[0xde, 0xad, 0, 0, 0, 0, 0xbe, 0xef]);
+
+    let mut back = MemoryCore::new(3, 8);
+    back.load_hex_text(&core.to_hex_text()).unwrap();
+    assert_eq!(back.words(), core.words());
+
+    let mut bin = MemoryCore::new(3, 8);
+    bin.load_binary(&core.to_binary()).unwrap();
+    assert_eq!(bin.words(), core.words());
+
+    assert!(core.load_hex_text("1ff").is_err());
+    assert!(core.load_hex_text("@8 00").is_err());
+    assert!(core.load_binary(&[0; 9]).is_err());
+}
+
+#[test]
+fn test_memory_files() {
+    let dir = std::env::temp_dir().join(format!("logic_memory_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let core = MemoryCore::with_contents(4, 16, &[0x1234, 0xBEEF, 0x0042]);
+    for name in ["image.hex", "image.bin", "image.mem"] {
+        let path = dir.join(name);
+        let format = MemoryFormat::from_path(&path);
+        core.save_file(&path, format).unwrap();
+
+        let mut back = MemoryCore::new(4, 16);
+        back.load_file(&path, format).unwrap();
+        assert_eq!(back.words(), core.words(), "{name}");
+    }
+    assert_eq!(std::fs::read(dir.join("image.bin")).unwrap()[..4], [0x34, 0x12, 0xEF, 0xBE]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
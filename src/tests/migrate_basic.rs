@@ -0,0 +1,133 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::Signal;
+use crate::circuit::migrate::*;
+use crate::circuit::netlist::NETLIST_VERSION;
+use crate::circuit::project::Project;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+/// The first netlist format: no version, externally tagged gates.
+const EARLY: &str = r#"{
+    "gates": {
+        "a":   { "Input": true },
+        "one": { "Const": true },
+        "and": { "And": ["a", "one"] },
+        "inv": { "Not": "and" },
+        "clk": { "Clock": false },
+        "ff":  { "DFlipFlop": { "d": "inv", "clk": "clk", "q": true, "last_clk": false } },
+        "sr":  { "SRLatch": { "set": "a", "reset": "one", "q": false } },
+        "net": { "Wire": "ff" }
+    },
+    "outputs": ["and", "net"]
+}"#;
+
+/// A version 1 file as written by this crate; it must keep loading.
+const V1: &str = r#"{
+    "version": 1,
+    "gates": {
+        "a":   { "kind": "Input", "value": true },
+        "b":   { "kind": "Switch", "level": false },
+        "q":   { "kind": "Wire", "label": "q", "drivers": ["ff"] },
+        "ff":  { "kind": "DFlipFlop", "d": "$0", "clk": "a", "q": "High", "last_clk": "High" },
+        "xor": { "kind": "Xor", "a": "a", "b": "b" }
+    },
+    "internal": { "$0": { "kind": "Not", "input": "q" } },
+    "outputs": ["xor", "q"]
+}"#;
+
+fn level(c: &Circuit, id: &str) -> Signal {
+    c.gate(id).unwrap().borrow().eval()
+}
+
+#[test]
+fn test_early_netlist_is_upgraded() {
+    let doc: Value = serde_json::from_str(EARLY).unwrap();
+    assert_eq!(version_of(&doc), Ok(0));
+    let up = upgrade(doc).unwrap();
+    assert_eq!(version_of(&up), Ok(NETLIST_VERSION));
+    assert_eq!(up["gates"]["net"], json!({ "kind": "Wire", "label": "net", "drivers": ["ff"] }));
+    assert_eq!(up["gates"]["ff"]["q"], json!("High"));
+
+    let c = Circuit::from_json(EARLY).unwrap();
+    assert_eq!(level(&c, "and"), Signal::High);
+    assert_eq!(level(&c, "inv"), Signal::Low);
+    assert_eq!(level(&c, "net"), Signal::High);
+    assert_eq!(level(&c, "sr"), Signal::Low);
+    assert_eq!(c.to_netlist().unwrap().outputs, ["and", "net"]);
+}
+
+#[test]
+fn test_first_writer_files_load() {
+    let text = r#"{ "version": 2, "outputs": ["k"], "gates": [
+        ["k", { "kind": "Const", "level": "HiZ" }],
+        ["s", { "kind": "Input", "value": "High" }]
+    ] }"#;
+    assert_eq!(version_of(&serde_json::from_str(text).unwrap()), Ok(0));
+    let mut c = Circuit::from_json(text).unwrap();
+    assert_eq!(level(&c, "k"), Signal::HiZ);
+    assert_eq!(level(&c, "s"), Signal::High);
+    c.set_input_bool("s", false).unwrap();
+    assert_eq!(level(&c, "s"), Signal::Low);
+}
+
+#[test]
+fn test_current_files_survive() {
+    let doc: Value = serde_json::from_str(V1).unwrap();
+    assert_eq!(upgrade(doc.clone()).unwrap(), doc);
+    let mut c = Circuit::from_json(V1).unwrap();
+    assert_eq!(level(&c, "xor"), Signal::High);
+    assert_eq!(level(&c, "q"), Signal::High);
+    for clk in [false, true] {
+        c.set_input_bool("a", clk).unwrap();
+        c.settle();
+    }
+    assert_eq!(level(&c, "q"), Signal::Low);
+
+    // projects upgrade the circuit they hold
+    let project = format!(r#"{{ "circuit": {EARLY}, "nodes": [] }}"#);
+    let project = Project::from_json(&project).unwrap();
+    assert_eq!(project.circuit.version, NETLIST_VERSION);
+}
+
+#[test]
+fn test_bad_versions() {
+    let err = upgrade(json!({ "version": NETLIST_VERSION + 1, "gates": {} })).unwrap_err();
+    assert!(err.contains("newer"), "{err}");
+    assert!(upgrade(json!({ "version": "one", "gates": {} })).is_err());
+    assert!(upgrade(json!([])).is_err());
+    let err = Circuit::from_json(r#"{ "gates": { "x": { "Mux": ["a"] } } }"#).unwrap_err();
+    assert!(format!("{err:#}").contains("'x'"), "{err:#}");
+}
+
+#[test]
+fn test_schema_matches_netlist() {
+    let schema: Value = serde_json::from_str(NETLIST_SCHEMA).unwrap();
+    assert_eq!(schema["properties"]["version"]["const"], json!(NETLIST_VERSION));
+    let kinds = |def: &str| -> BTreeSet<String> {
+        schema["$defs"][def]["oneOf"].as_array().unwrap().iter()
+            .map(|k| k["properties"]["kind"]["const"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let (gate_kinds, shared_kinds) = (kinds("gate"), kinds("shared"));
+
+    let net = serde_json::to_value(super::netlist_basic::everything().to_netlist().unwrap()).unwrap();
+    let top: BTreeSet<&String> = schema["properties"].as_object().unwrap().keys().collect();
+    for key in net.as_object().unwrap().keys() {
+        assert!(top.contains(key), "schema lacks '{key}'");
+    }
+    for section in ["gates", "internal"] {
+        for gate in net[section].as_object().unwrap().values() {
+            let kind = gate["kind"].as_str().unwrap();
+            assert!(gate_kinds.contains(kind), "schema lacks gate kind {kind}");
+            let props = &schema["$defs"]["gate"]["oneOf"].as_array().unwrap().iter()
+                .find(|k| k["properties"]["kind"]["const"] == kind).unwrap()["properties"];
+            for field in gate.as_object().unwrap().keys() {
+                assert!(props.get(field).is_some(), "schema lacks {kind}.{field}");
+            }
+        }
+    }
+    for shared in net["shared"].as_array().unwrap() {
+        let kind = shared["kind"].as_str().unwrap();
+        assert!(shared_kinds.contains(kind), "schema lacks shared kind {kind}");
+    }
+}
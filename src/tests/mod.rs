@@ -1,5 +1,60 @@
-// pub mod netlist_basic;
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::{pack, InputGate, Signal};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Adds `width` low inputs `{prefix}0`, `{prefix}1`, ... and returns their ids.
+pub fn inputs(circuit: &mut Circuit, prefix: &str, width: usize) -> Vec<String> {
+    (0..width)
+        .map(|i| {
+            let id = format!("{prefix}{i}");
+            circuit.add_gate(&id, Rc::new(RefCell::new(InputGate::new(false))));
+            id
+        })
+        .collect()
+}
+
+/// Borrows `names` as the `&str` ids the builders take.
+pub fn ids(names: &[String]) -> Vec<&str> {
+    names.iter().map(String::as_str).collect()
+}
+
+/// Sets inputs `ids` to the bits of `value`, `ids[0]` taking the low bit.
+pub fn drive<S: AsRef<str>>(circuit: &mut Circuit, ids: &[S], value: u64) {
+    for (i, id) in ids.iter().enumerate() {
+        circuit.set_input_bool(id.as_ref(), value >> i & 1 == 1).unwrap();
+    }
+}
+
+/// The levels of gates `ids` as a number, `ids[0]` being the low bit.
+/// Panics unless each is high or low.
+pub fn read<S: AsRef<str>>(circuit: &Circuit, ids: &[S]) -> u64 {
+    let levels: Vec<Signal> = ids.iter().map(|id| circuit.gate(id.as_ref()).unwrap().borrow().eval()).collect();
+    pack(&levels).unwrap_or_else(|| panic!("{levels:?} is not a number"))
+}
+
+pub mod netlist_basic;
 pub mod circuit_basic;
 pub mod gate_basic;
 
 pub mod wire_basic;
+pub mod alu_basic;
+pub mod arith_basic;
+pub mod memory_basic;
+pub mod display_basic;
+pub mod terminal_basic;
+pub mod stimulus_basic;
+pub mod switch_basic;
+pub mod lut_basic;
+pub mod fsm_basic;
+pub mod closure_basic;
+pub mod examples_basic;
+pub mod subcircuit_basic;
+pub mod param_basic;
+pub mod flatten_basic;
+pub mod library_basic;
+pub mod project_basic;
+pub mod migrate_basic;
+pub mod verilog_basic;
+pub mod verilog_import_basic;
+pub mod blif_basic;
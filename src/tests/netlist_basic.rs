@@ -1,68 +1,184 @@
-// #[warn(unused_imports)]
-// use crate::circuit::netlist::{GateKind, NetList};
-// #[test]
-// fn const_and_not() {
-//     let mut nl = NetList::default();
-
-//     nl.gates.insert("a".into(), GateKind::Const(true));
-//     nl.gates.insert("b".into(), GateKind::Not("a".into()));
-//     nl.outputs.push("b".into());
-
-//     nl.propagate();
-//     let out = nl.outputs();
-//     assert_eq!(out["b"], false)
-// }
-
-// #[test]
-// fn ha_netlist() {
-//     let mut nl = NetList::default();
-
-//     nl.add_const("a", true).add_const("b", true);
-
-//     nl.add_halfadder("a", "b", "sum", "carry");
-//     nl.outputs.extend(["sum".into(), "carry".into()]);
-
-//     nl.propagate();
-//     let o = nl.outputs();
-
-//     assert_eq!(o["sum"], false);
-//     assert_eq!(o["carry"], true);
-
-// }
-
-// #[test]
-// fn dff_counter_stable() {
-//     let mut n = NetList::default();
-//     n.add_const("clk_low", false)
-//      .add_clock("clk",  false)      // zegar
-//      .add_dflipflop("q", "clk", false);
-
-//     n.outputs.push("q".into());
-
-//     for _ in 0..32 {
-//         n.tick("clk");
-//         n.propagate();
-//         println!("q = {}", n.outputs()["q"]);
-//     }
-// }
-
-// #[test]
-// fn interactive_io(){
-//     let mut nl = NetList::default();
-//     nl.add_input("A", false)
-//         .add_input("B", false)
-//         .add_and("R", "A", "B")
-//         .outputs.push("R".into());
-
-//     assert_eq!(nl.read_outputs()["R"],false);
-
-//     nl.set_input("A",  true).unwrap();
-//     assert_eq!(nl.read_outputs()["R"], false);
-
-//     nl.set_input("B", true).unwrap();
-//     assert_eq!(nl.read_outputs()["R"], true);
-
-//     assert!(nl.set_input("R", false).is_err());
-//     assert!(matches!(nl.set_input("R", false), Err(_)));
-
-// }
+use crate::circuit::circuit::Circuit;
+use crate::circuit::display::{DisplayBus, PixelFormat};
+use crate::circuit::fsm::{FsmKind, StateTable};
+use crate::circuit::gate::*;
+use crate::circuit::library::Library;
+use crate::circuit::lut::TruthTable;
+use crate::circuit::memory::{MemPins, MemoryCore};
+use crate::circuit::netlist::*;
+use crate::circuit::switch::{SwitchNetwork, GND, VDD};
+use crate::circuit::wire::Wire;
+use std::rc::Rc;
+
+/// A circuit using every kind of gate and shared component.
+pub(super) fn everything() -> Circuit {
+    let mut c = Circuit::new();
+    c.import_library(Library::standard()).unwrap();
+    for id in ["a", "b", "en"] {
+        c.add_gate(id, gate(InputGate::new(false)));
+    }
+    let (a, b, en) = (c.gate("a").unwrap(), c.gate("b").unwrap(), c.gate("en").unwrap());
+    c.add_gate("alias", a.clone());
+    c.add_gate("clk", gate(ClockGate::new()));
+    c.add_gate("sw", gate(SwitchGate::new(true)));
+    c.add_gate("btn", gate(ButtonGate::restore(true)));
+    c.add_gate("k", gate(ConstGate::new(Signal::HiZ)));
+
+    c.add_gate("and", gate(AndGate::new(a.clone(), b.clone())));
+    c.add_gate("or", gate(OrGate::new(a.clone(), b.clone())));
+    c.add_gate("xor", gate(XorGate::new(a.clone(), b.clone())));
+    c.add_gate("nand", gate(NandGate::new(a.clone(), b.clone())));
+    c.add_gate("nor", gate(NorGate::new(a.clone(), b.clone())));
+    c.add_gate("xnor", gate(XnorGate::new(a.clone(), b.clone())));
+    c.add_gate("not", gate(NotGate::new(a.clone())));
+    c.add_gate("buf", gate(BufferGate::new(b.clone())));
+    c.add_gate("out", gate(OutputGate::new(b.clone())));
+    c.add_gate("tri", gate(TriStateGate::new(a.clone(), en.clone())));
+    c.add_gate("sr", gate(SRLatch::new(a.clone(), b.clone())));
+    c.add_gate("dl", gate(Dlatch::new(a.clone(), en.clone())));
+
+    // wired-AND bus and a toggle flip-flop fed back through a wire
+    c.add_wire("bus", Wire::new("bus"));
+    c.add_open_drain("od", "a").unwrap();
+    c.add_driver("od", "bus").unwrap();
+    c.add_pull_up("pu", "bus").unwrap();
+    c.add_wire("q", Wire::new("q"));
+    let q = c.gate("q").unwrap();
+    c.add_gate("ff", gate(Dflipflop::new(gate(NotGate::new(q.clone())), c.gate("clk").unwrap())));
+    c.connect("ff", "q").unwrap();
+
+    c.add_pattern("pat", "01x1z", 2, true).unwrap();
+    c.add_one_shot("os", "pat", 3, false).unwrap();
+    c.add_random("rnd", 42);
+    c.add_lut("lut", &["a", "b", "q"], TruthTable::parse("01101001").unwrap()).unwrap();
+    c.add_seven_seg_decoder(["a", "b", "q", "clk"], ["sa", "sb", "sc", "sd", "se", "sf", "sg"], true).unwrap();
+    c.add_halfadder("a", "b", "hs", "hc").unwrap();
+    c.add_alu(&["a", "b"], &["b", "q"], ["a", "b", "en", "clk"], &["r0", "r1"], ["cf", "zf", "nf", "vf"]).unwrap();
+
+    c.add_rom("rom", MemoryCore::with_contents(1, 2, &[2, 1]), &["q"], Some("en"), &["rom0", "rom1"]).unwrap();
+    let pins = MemPins { addr: vec!["a"], data_in: vec!["b", "q"], write_enable: Some("en"), read_enable: None, chip_select: None, clock: Some("clk") };
+    c.add_ram("ram", MemoryCore::new(1, 2), pins, &["ram0", "ram1"]).unwrap();
+
+    let table = StateTable::new(FsmKind::Moore, &["go"], &["lamp"])
+        .state("off", "0")
+        .state("on", "1")
+        .transition("off", "1", "on", "")
+        .transition("on", "0", "off", "");
+    c.add_fsm("fsm", table, "clk", &["q"], &["lamp"]).unwrap();
+
+    let bus = DisplayBus::AddressData { x: vec!["a"], y: vec!["q"], data: vec!["b"], write: "clk" };
+    c.add_display("lcd", 2, 2, bus, PixelFormat::Mono).unwrap();
+    c.add_tty("tty", "con", &["a", "q"], "clk").unwrap();
+    let keys: Vec<String> = (0..8).map(|i| format!("key{i}")).collect();
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    c.add_keyboard("con", "q", &keys, "ready").unwrap();
+    c.terminal("con").unwrap().borrow_mut().push_input(b"hello");
+
+    let mut net = SwitchNetwork::new();
+    net.input("a", a).unwrap();
+    net.input("b", b).unwrap();
+    net.pmos("a", VDD, "y");
+    net.pmos("b", VDD, "y");
+    net.nmos("a", "y", "mid");
+    net.nmos("b", "mid", GND);
+    c.add_switch_network(net, &[("y", "cmos")]).unwrap();
+
+    c.instantiate("full_adder", "fa", &["a", "b", "q"]).unwrap();
+    for id in ["and", "bus", "lut", "r0", "cf", "ram0", "lamp", "cmos", "fa.sum", "tty"] {
+        c.add_output(id);
+    }
+    c
+}
+
+fn levels(c: &Circuit, ids: &[String]) -> Vec<Signal> {
+    ids.iter().map(|id| c.gate(id).unwrap().borrow().eval()).collect()
+}
+
+#[test]
+fn test_round_trip_is_exact() {
+    let mut c = everything();
+    c.set_input_bool("a", true).unwrap();
+    c.set_input_bool("en", true).unwrap();
+    for _ in 0..5 {
+        c.step();
+    }
+
+    let json = c.to_json().unwrap();
+    let mut loaded = Circuit::from_json(&json).unwrap();
+    assert_eq!(loaded.to_json().unwrap(), json);
+    assert_eq!(loaded.definition_names(), c.definition_names());
+    assert_eq!(loaded.instance_paths(), c.instance_paths());
+    assert_eq!(loaded.library_names(), ["standard"]);
+    let pending = |c: &Circuit| c.terminal("con").unwrap().borrow().pending_input();
+    assert_eq!(pending(&loaded), pending(&c));
+    assert!(pending(&c) > 0);
+    assert_eq!(loaded.memory("rom").unwrap().borrow().words(), [2, 1]);
+
+    // both copies keep running in lockstep
+    let ids: Vec<String> = c.to_netlist().unwrap().gates.keys().cloned().collect();
+    for n in 0..24 {
+        for (id, v) in [("a", n % 3 == 0), ("b", n % 5 < 2), ("en", n % 4 != 1)] {
+            c.set_input_bool(id, v).unwrap();
+            loaded.set_input_bool(id, v).unwrap();
+        }
+        c.step();
+        loaded.step();
+        assert_eq!(levels(&loaded, &ids), levels(&c, &ids), "step {n}");
+    }
+    assert_eq!(loaded.to_json().unwrap(), c.to_json().unwrap());
+    assert_eq!(loaded.display("lcd").unwrap().borrow().clone(), c.display("lcd").unwrap().borrow().clone());
+    assert_eq!(loaded.terminal("con").unwrap().borrow().output(), c.terminal("con").unwrap().borrow().output());
+}
+
+#[test]
+fn test_shared_state_and_aliases_stay_shared() {
+    let c = Circuit::from_json(&everything().to_json().unwrap()).unwrap();
+    let net = c.to_netlist().unwrap();
+    assert_eq!(net.gates["alias"], GateKind::Alias { of: "a".into() });
+    assert!(Rc::ptr_eq(&c.gate("alias").unwrap(), &c.gate("a").unwrap()));
+    assert!(net.internal.keys().all(|id| id.starts_with('$')));
+
+    // the RAM's registry entry and its ports use one core
+    c.memory("ram").unwrap().borrow_mut().poke(1, 3);
+    let mut c = c;
+    c.set_input_bool("a", true).unwrap();
+    assert_eq!(c.gate("ram0").unwrap().borrow().eval(), Signal::High);
+    assert_eq!(c.gate("ram1").unwrap().borrow().eval(), Signal::High);
+}
+
+#[test]
+fn test_save_and_load_file() {
+    let c = everything();
+    let path = std::env::temp_dir().join("logic_netlist_basic.json");
+    c.save(&path).unwrap();
+    let loaded = Circuit::load(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(loaded.to_json().unwrap(), c.to_json().unwrap());
+    assert!(Circuit::load("/nonexistent/circuit.json").is_err());
+}
+
+#[test]
+fn test_unsaveable_and_malformed() {
+    let mut c = Circuit::new();
+    c.add_gate("a", gate(InputGate::new(false)));
+    c.add_closure_gate("f", &["a"], &["fa"], |x| x.to_vec()).unwrap();
+    let err = c.to_netlist().unwrap_err();
+    assert!(err.contains("'fa'"), "{err}");
+
+    let mut net = NetList { version: NETLIST_VERSION, ..NetList::default() };
+    net.gates.insert("n".into(), GateKind::Not { input: "missing".into() });
+    assert!(Circuit::from_netlist(&net).is_err(), "unknown input");
+
+    net.gates.insert("n".into(), GateKind::Not { input: "m".into() });
+    net.gates.insert("m".into(), GateKind::Not { input: "n".into() });
+    let err = Circuit::from_netlist(&net).unwrap_err();
+    assert!(err.contains("without a wire"), "{err}");
+
+    net.gates.clear();
+    net.outputs.push("y".into());
+    assert!(Circuit::from_netlist(&net).is_err(), "unknown output");
+    net.outputs.clear();
+    net.version = 99;
+    assert!(Circuit::from_netlist(&net).is_err(), "future version");
+    assert!(Circuit::from_json("{\"version\": 1}").is_err());
+}
@@ -0,0 +1,43 @@
+use crate::circuit::param::*;
+
+fn vars(pairs: &[(&str, i64)]) -> Params {
+    pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+}
+
+#[test]
+fn test_eval_precedence() {
+    let v = vars(&[("W", 8), ("i", 3)]);
+    assert_eq!(eval("W", &v), Ok(8));
+    assert_eq!(eval("W - 1 - i", &v), Ok(4));
+    assert_eq!(eval("2 + 3 * 4", &v), Ok(14));
+    assert_eq!(eval("(2 + 3) * 4", &v), Ok(20));
+    assert_eq!(eval("-W / 3", &v), Ok(-2));
+    assert_eq!(eval("W % 3", &v), Ok(2));
+    assert!(eval("0xF", &v).is_err());
+    assert_eq!(eval("5 >> i - 2 & 1", &v), Ok(0), "shift binds tighter than &");
+    assert_eq!(eval("1 << W | 1", &v), Ok(257));
+    assert_eq!(eval("1 << 70", &v), Ok(0));
+}
+
+#[test]
+fn test_eval_errors() {
+    let v = vars(&[("N", 4)]);
+    for bad in ["", "N +", "(N", "N)", "M", "N / 0", "N % (N - 4)", "N $ 2", "99999999999999999999"] {
+        assert!(eval(bad, &v).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn test_expand_templates() {
+    let v = vars(&[("W", 3), ("i", 1)]);
+    assert_eq!(expand("d{0..W}", &v).unwrap(), ["d0", "d1", "d2"]);
+    assert_eq!(expand("q{i}_{i + 1}", &v).unwrap(), ["q1_2"]);
+    assert_eq!(expand("b{W - 1..W + 1}x", &v).unwrap(), ["b2x", "b3x"]);
+    assert_eq!(expand("clk", &v).unwrap(), ["clk"]);
+    assert!(expand("d{0..0}", &v).unwrap().is_empty());
+
+    assert!(expand("d{0..W}{0..W}", &v).is_err());
+    assert!(expand("d{W", &v).is_err());
+    assert_eq!(substitute("y{i}", &v).unwrap(), "y1");
+    assert!(substitute("y{0..W}", &v).is_err());
+}
@@ -0,0 +1,92 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::project::*;
+use crate::circuit::wire::Wire;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A switch wired to a NOT gate whose output lights a lamp.
+fn inverter() -> (Circuit, Vec<NodeLayout>, Vec<WireLayout>) {
+    let mut c = Circuit::new();
+    c.add_gate("g0", Rc::new(RefCell::new(InputGate::new(true))));
+    c.add_wire("g1_w0", Wire::new("g1_w0"));
+    let w = c.gate("g1_w0").unwrap();
+    c.add_gate("g1", Rc::new(RefCell::new(NotGate::new(w))));
+    c.add_wire("g2", Wire::new("g2"));
+    c.add_driver("g0", "g1_w0").unwrap();
+    c.add_driver("g1", "g2").unwrap();
+    c.add_output("g1");
+
+    let port = |x: f32, side, gate: &str| PortLayout { offset: [x, 15.0], side, gate: gate.into() };
+    let node = |id: &str, label: &str, x: f32, ports| NodeLayout {
+        id: id.into(), label: label.into(), gate: id.into(), pos: [x, 40.0], size: [60.0, 30.0], ports,
+    };
+    let nodes = vec![
+        node("g0", "SW", 20.0, vec![port(60.0, PortSide::Out, "g0")]),
+        node("g1", "NOT", 120.0, vec![port(0.0, PortSide::In, "g1_w0"), port(60.0, PortSide::Out, "g1")]),
+        node("g2", "LAMP", 220.0, vec![port(0.0, PortSide::In, "g2")]),
+    ];
+    let wires = vec![
+        WireLayout { from: ("g0".into(), 0), to: ("g1".into(), 0) },
+        WireLayout { from: ("g1".into(), 1), to: ("g2".into(), 0) },
+    ];
+    (c, nodes, wires)
+}
+
+#[test]
+fn test_project_round_trip() {
+    let (c, nodes, wires) = inverter();
+    let project = Project::new(&c, nodes, wires).unwrap();
+    let path = std::env::temp_dir().join("logic_project_basic.json");
+    project.save(&path).unwrap();
+    let loaded = Project::load(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(loaded, project);
+    assert_eq!(loaded.nodes[1].pos, [120.0, 40.0]);
+    assert_eq!(loaded.nodes[2].label, "LAMP");
+
+    let mut built = loaded.build().unwrap();
+    assert_eq!(built.gate("g2").unwrap().borrow().eval(), Signal::Low);
+    built.set_input_bool("g0", false).unwrap();
+    assert_eq!(built.gate("g2").unwrap().borrow().eval(), Signal::High);
+}
+
+#[test]
+fn test_project_layout_is_checked() {
+    let (c, mut nodes, wires) = inverter();
+    let mut bad_wires = wires.clone();
+    bad_wires.push(WireLayout { from: ("g1".into(), 2), to: ("g2".into(), 0) });
+    let err = Project::new(&c, nodes.clone(), bad_wires).unwrap_err();
+    assert!(err.contains("no port 2"), "{err}");
+
+    nodes[0].gate = "missing".into();
+    let err = Project::new(&c, nodes.clone(), wires.clone()).unwrap_err();
+    assert!(err.contains("'missing'"), "{err}");
+
+    nodes[0] = nodes[1].clone();
+    assert!(Project::new(&c, nodes, wires).is_err(), "duplicate node");
+    assert!(Project::from_json("{\"nodes\": []}").is_err(), "no circuit");
+    assert!(Project::load("/nonexistent/project.json").is_err());
+}
+
+#[test]
+fn test_recent_files() {
+    let mut recent = RecentFiles::default();
+    for n in 0..MAX_RECENT + 2 {
+        recent.push(format!("p{n}.json"));
+    }
+    recent.push("p4.json");
+    assert_eq!(recent.paths().len(), MAX_RECENT);
+    assert_eq!(recent.paths()[0].to_str(), Some("p4.json"));
+    assert_eq!(recent.paths()[1].to_str(), Some("p9.json"));
+    assert_eq!(recent.paths().iter().filter(|p| p.to_str() == Some("p4.json")).count(), 1);
+    recent.remove("p9.json".as_ref());
+    assert_eq!(recent.paths()[1].to_str(), Some("p8.json"));
+
+    let path = std::env::temp_dir().join("logic_recent_basic.json");
+    std::fs::remove_file(&path).ok();
+    assert_eq!(RecentFiles::load(&path).unwrap(), RecentFiles::default());
+    recent.save(&path).unwrap();
+    assert_eq!(RecentFiles::load(&path).unwrap(), recent);
+    std::fs::remove_file(&path).ok();
+}
@@ -0,0 +1,98 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::stimulus::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn trace(circuit: &mut Circuit, id: &str, steps: usize) -> String {
+    let gate = circuit.gate(id).unwrap();
+    (0..steps)
+        .map(|_| {
+            let bit = if gate.borrow().eval().is_high() { '1' } else { '0' };
+            circuit.step();
+            bit
+        })
+        .collect()
+}
+
+#[test]
+fn test_pattern_generator() {
+    let play = |pattern: &str, hold: usize, repeat: bool, steps: usize| {
+        let mut c = Circuit::new();
+        c.add_pattern("p", pattern, hold, repeat).unwrap();
+        trace(&mut c, "p", steps)
+    };
+    assert_eq!(play("0110", 1, true, 9), "011001100");
+    assert_eq!(play("10", 3, true, 8), "11100011");
+    assert_eq!(play("1_0 1", 1, false, 6), "101111");
+
+    let mut c = Circuit::new();
+    assert!(c.add_pattern("bad", "01q", 1, true).is_err());
+    assert!(c.add_pattern("empty", " _ ", 1, true).is_err());
+}
+
+#[test]
+fn test_pattern_hiz_and_restart() {
+    let gen = PatternGenerator::parse("1z", 1, true).unwrap();
+    assert_eq!(gen.pattern_string(), "1z");
+    gen.advance();
+    assert_eq!(gen.eval(), Signal::HiZ);
+    gen.restart();
+    assert_eq!(gen.eval(), Signal::High);
+}
+
+#[test]
+fn test_one_shot() {
+    let mut c = Circuit::new();
+    c.add_pattern("trig", "0100 0000 0101 0000", 1, false).unwrap();
+    c.add_one_shot("pulse", "trig", 3, false).unwrap();
+
+    assert_eq!(trace(&mut c, "pulse", 16), "0111000001110000");
+
+    let mut c = Circuit::new();
+    c.add_pattern("trig", "0100 0000 0101 0000", 1, false).unwrap();
+    c.add_one_shot("retrig", "trig", 3, true).unwrap();
+    assert_eq!(trace(&mut c, "retrig", 16), "0111000001111100");
+
+    assert!(c.add_one_shot("x", "missing", 1, false).is_err());
+}
+
+#[test]
+fn test_random_bit_is_reproducible() {
+    let mut c = Circuit::new();
+    c.add_random("a", 42);
+    c.add_random("b", 42);
+    c.add_random("c", 7);
+
+    let a = trace(&mut c, "a", 64);
+    let b = trace(&mut c, "b", 64);
+    let other = trace(&mut c, "c", 64);
+    assert_ne!(a, other);
+    assert!(a.contains('0') && a.contains('1'));
+
+    // "b" was stepped 64 times while "a" was traced
+    let fresh = RandomBit::new(42);
+    let replay: String = (0..128)
+        .map(|_| {
+            let bit = if fresh.eval().is_high() { '1' } else { '0' };
+            fresh.advance();
+            bit
+        })
+        .collect();
+    assert_eq!(&replay[..64], a);
+    assert_eq!(&replay[64..], b);
+
+    fresh.reset();
+    assert_eq!(fresh.eval().is_high(), a.starts_with('1'));
+}
+
+#[test]
+fn test_pattern_clocks_register() {
+    let mut c = Circuit::new();
+    c.add_pattern("d", "1100", 2, true).unwrap();
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    let ff = Dflipflop::new(c.gate("d").unwrap(), c.gate("clk").unwrap());
+    c.add_gate("q", Rc::new(RefCell::new(ff)));
+
+    assert_eq!(trace(&mut c, "q", 12), "011110000111");
+}
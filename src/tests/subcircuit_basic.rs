@@ -0,0 +1,220 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::subcircuit::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn with_inputs(ids: &[&str]) -> Circuit {
+    let mut c = Circuit::new();
+    for id in ids {
+        c.add_gate(*id, Rc::new(RefCell::new(InputGate::new(false))));
+    }
+    c
+}
+
+fn level(c: &Circuit, id: &str) -> Signal {
+    c.gate(id).unwrap_or_else(|| panic!("no gate '{id}'")).borrow().eval()
+}
+
+fn adders(c: &mut Circuit) {
+    c.define(SubcircuitDef::half_adder()).unwrap();
+    c.define(SubcircuitDef::full_adder()).unwrap();
+}
+
+/// Toggle flip-flop: `q` flips on every rising `clk` edge while `t` is high.
+fn toggle() -> SubcircuitDef {
+    SubcircuitDef::new("toggle", &["t", "clk"], &["q"])
+        .cell("next", CellKind::Xor, &["q", "t"])
+        .cell("q", CellKind::Dff { init: Signal::Low }, &["next", "clk"])
+}
+
+#[test]
+fn test_full_adder_instance() {
+    let mut c = with_inputs(&["a", "b", "cin"]);
+    adders(&mut c);
+    c.instantiate("full_adder", "fa", &["a", "b", "cin"]).unwrap();
+
+    for n in 0..8u8 {
+        let bits = [n & 1 == 1, n & 2 != 0, n & 4 != 0];
+        for (id, v) in ["a", "b", "cin"].iter().zip(bits) {
+            c.set_input_bool(id, v).unwrap();
+        }
+        let total = bits.iter().filter(|b| **b).count();
+        assert_eq!(level(&c, "fa.sum").is_high(), total & 1 == 1, "{n:03b}");
+        assert_eq!(level(&c, "fa.cout").is_high(), total >= 2, "{n:03b}");
+    }
+    assert_eq!(c.instance_module("fa"), Some("full_adder"));
+    assert_eq!(c.instance_module("fa.ha1"), Some("half_adder"));
+    assert_eq!(c.instance_paths(), ["fa", "fa.ha0", "fa.ha1"]);
+}
+
+#[test]
+fn test_instances_keep_their_own_state() {
+    let mut c = with_inputs(&["on", "off"]);
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.define(toggle()).unwrap();
+    c.instantiate("toggle", "t0", &["on", "clk"]).unwrap();
+    c.instantiate("toggle", "t1", &["off", "clk"]).unwrap();
+    c.set_input_bool("on", true).unwrap();
+
+    let mut seen = Vec::new();
+    for _ in 0..3 {
+        c.step();
+        seen.push((level(&c, "t0.q"), level(&c, "t1.q")));
+        c.step();
+    }
+    use Signal::*;
+    assert_eq!(seen, [(High, Low), (Low, Low), (High, Low)]);
+}
+
+#[test]
+fn test_deep_hierarchy_paths() {
+    let mut c = with_inputs(&["x0", "x1", "y0", "y1"]);
+    adders(&mut c);
+    c.define(
+        SubcircuitDef::new("alu", &["a0", "a1", "b0", "b1"], &["s0", "s1", "carry"])
+            .cell("zero", CellKind::Const { level: Signal::Low }, &[])
+            .instance("add0", "full_adder", &["a0", "b0", "zero"])
+            .instance("add1", "full_adder", &["a1", "b1", "add0.cout"])
+            .cell("s0", CellKind::Wire, &["add0.sum"])
+            .cell("s1", CellKind::Wire, &["add1.sum"])
+            .cell("carry", CellKind::Wire, &["add1.cout"]),
+    )
+    .unwrap();
+    c.define(
+        SubcircuitDef::new("cpu", &["a0", "a1", "b0", "b1"], &["s0", "s1", "carry"])
+            .instance("alu", "alu", &["a0", "a1", "b0", "b1"])
+            .cell("s0", CellKind::Wire, &["alu.s0"])
+            .cell("s1", CellKind::Wire, &["alu.s1"])
+            .cell("carry", CellKind::Wire, &["alu.carry"]),
+    )
+    .unwrap();
+    c.instantiate("cpu", "cpu", &["x0", "x1", "y0", "y1"]).unwrap();
+
+    // 3 + 1 = 4
+    for id in ["x0", "x1", "y0"] {
+        c.set_input_bool(id, true).unwrap();
+    }
+    assert_eq!(level(&c, "cpu.alu.add0.sum"), Signal::Low);
+    assert_eq!(level(&c, "cpu.alu.add0.cout"), Signal::High);
+    assert_eq!(level(&c, "cpu.alu.add1.ha0.carry"), Signal::Low);
+    assert_eq!([level(&c, "cpu.s0"), level(&c, "cpu.s1"), level(&c, "cpu.carry")], [Signal::Low, Signal::Low, Signal::High]);
+    assert_eq!(c.instance_module("cpu.alu.add1.ha1"), Some("half_adder"));
+
+    c.remove_instance("cpu.alu").unwrap();
+    assert!(c.gate("cpu.alu.add0.sum").is_none());
+    assert!(c.instance_module("cpu.alu.add0").is_none());
+    assert!(c.gate("cpu.s0").is_some());
+}
+
+#[test]
+fn test_subcircuit_errors() {
+    let mut c = with_inputs(&["a", "b"]);
+    adders(&mut c);
+
+    assert!(c.instantiate("nope", "x", &["a"]).is_err());
+    assert!(c.instantiate("half_adder", "x", &["a"]).is_err(), "too few inputs");
+    c.instantiate("half_adder", "x", &["a", "b"]).unwrap();
+    assert!(c.instantiate("half_adder", "x", &["a", "b"]).is_err(), "path in use");
+
+    let looped = SubcircuitDef::new("loop", &["a"], &["y"]).instance("inner", "loop", &["a"]).cell("y", CellKind::Wire, &["inner.y"]);
+    c.define(looped).unwrap();
+    assert!(c.instantiate("loop", "l", &["a"]).unwrap_err().contains("instantiates itself"));
+    assert!(c.gate("l.a").is_none(), "nothing registered on failure");
+
+    let dangling = SubcircuitDef::new("dangling", &["a"], &["y"]).cell("y", CellKind::Not, &["ghost"]);
+    c.define(dangling).unwrap();
+    assert!(c.instantiate("dangling", "d", &["a"]).unwrap_err().contains("ghost"));
+
+    let bad = [
+        SubcircuitDef::new("twice", &["a"], &["a"]).cell("a", CellKind::Not, &["a"]),
+        SubcircuitDef::new("arity", &["a"], &["y"]).cell("y", CellKind::Not, &["a", "a"]),
+        SubcircuitDef::new("port", &["a"], &["y"]),
+        SubcircuitDef::new("dot.ted", &[], &[]),
+    ];
+    for def in bad {
+        assert!(c.define(def).is_err());
+    }
+}
+
+#[test]
+fn test_subcircuit_json_round_trip() {
+    let def = toggle();
+    let json = serde_json::to_string(&def).unwrap();
+    assert!(json.contains(r#""kind":"Dff","init":"Low""#), "{json}");
+    let back: SubcircuitDef = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, def);
+}
+
+fn params(pairs: &[(&str, i64)]) -> crate::circuit::param::Params {
+    pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+}
+
+#[test]
+fn test_parameterized_register() {
+    let mut c = with_inputs(&["en", "d0", "d1", "d2", "d3"]);
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.define(SubcircuitDef::register()).unwrap();
+    let d = ["d0", "d1", "d2", "d3", "en", "clk"];
+    c.instantiate_with("register", "r4", &params(&[("W", 4), ("INIT", 0b1010)]), &d).unwrap();
+    c.instantiate_with("register", "r2", &params(&[("W", 2)]), &["d0", "d1", "en", "clk"]).unwrap();
+
+    let value = |c: &Circuit, path: &str, w: usize| (0..w).filter(|i| level(c, &format!("{path}.q{i}")).is_high()).fold(0, |a, i| a | 1 << i);
+    assert_eq!(value(&c, "r4", 4), 0b1010, "initial value");
+    assert!(c.gate("r2.q2").is_none());
+
+    for (id, v) in [("d0", true), ("d1", true), ("d2", false), ("d3", true)] {
+        c.set_input_bool(id, v).unwrap();
+    }
+    c.step();
+    c.step();
+    assert_eq!(value(&c, "r4", 4), 0b1010, "holds without en");
+    c.set_input_bool("en", true).unwrap();
+    c.step();
+    assert_eq!(value(&c, "r4", 4), 0b1011);
+    assert_eq!(value(&c, "r2", 2), 0b11);
+
+    let p = c.instance_params("r4").unwrap();
+    assert_eq!((p["W"], p["INIT"]), (4, 0b1010));
+    assert_eq!(c.instance_params("r2").unwrap()["INIT"], 0, "defaults are recorded");
+}
+
+#[test]
+fn test_parity_tree_and_nested_params() {
+    let ids: Vec<String> = (0..6).map(|i| format!("x{i}")).collect();
+    let refs: Vec<&str> = ids.iter().map(String::as_str).collect();
+    let mut c = with_inputs(&refs);
+    c.define(SubcircuitDef::parity()).unwrap();
+    // a wrapper passing its own width down
+    c.define(
+        SubcircuitDef::new("checker", &["in{0..M}"], &["ok"])
+            .param("M", 2)
+            .instance_with("tree", "parity", &[("N", "M")], &["in{0..M}"])
+            .cell("ok", CellKind::Not, &["tree.odd"]),
+    )
+    .unwrap();
+    c.instantiate_with("checker", "chk", &params(&[("M", 6)]), &refs).unwrap();
+    assert_eq!(c.instance_params("chk.tree").unwrap()["N"], 6);
+    assert_eq!(c.subcircuit_ports("parity", &params(&[("N", 3)])).unwrap().0, ["x0", "x1", "x2"]);
+
+    for n in 0..64u32 {
+        for (i, id) in ids.iter().enumerate() {
+            c.set_input_bool(id, n >> i & 1 == 1).unwrap();
+        }
+        assert_eq!(level(&c, "chk.ok").is_high(), n.count_ones() % 2 == 0, "{n:06b}");
+    }
+
+    assert!(c.instantiate_with("parity", "p", &params(&[("K", 2)]), &refs[..4]).is_err(), "unknown parameter");
+    assert!(c.instantiate_with("parity", "p", &params(&[("N", 3)]), &refs[..4]).is_err(), "width mismatch");
+    assert!(c.instantiate_with("parity", "p", &params(&[("N", 1)]), &refs[..1]).is_err(), "xor needs 2 inputs");
+    assert!(c.define(SubcircuitDef::new("bad", &["a{0..W}"], &[]).param("W", 2).param("W", 3)).is_err());
+    assert!(c.define(SubcircuitDef::new("bad", &["a{0..V}"], &[])).is_err(), "unknown name");
+}
+
+#[test]
+fn test_parameterized_json_round_trip() {
+    let def = SubcircuitDef::register();
+    let json = serde_json::to_string(&def).unwrap();
+    assert!(json.contains(r#""repeat":"W""#) && json.contains(r#""value":"INIT >> i & 1""#), "{json}");
+    assert_eq!(serde_json::from_str::<SubcircuitDef>(&json).unwrap(), def);
+}
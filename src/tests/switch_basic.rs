@@ -0,0 +1,130 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::switch::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn input(circuit: &mut Circuit, id: &str) -> GateRef {
+    let gate: GateRef = Rc::new(RefCell::new(InputGate::new(false)));
+    circuit.add_gate(id, gate.clone());
+    gate
+}
+
+fn level(s: Signal) -> GateRef {
+    Rc::new(RefCell::new(ConstGate::new(s)))
+}
+
+#[test]
+fn test_cmos_nand_matches_nand_gate() {
+    let mut c = Circuit::new();
+    let a = input(&mut c, "a");
+    let b = input(&mut c, "b");
+
+    let mut net = SwitchNetwork::new();
+    net.input("a", a.clone()).unwrap();
+    net.input("b", b.clone()).unwrap();
+    net.pmos("a", VDD, "out");
+    net.pmos("b", VDD, "out");
+    net.nmos("a", "out", "mid");
+    net.nmos("b", "mid", GND);
+    c.add_switch_network(net, &[("out", "cmos")]).unwrap();
+    c.add_gate("ref", Rc::new(RefCell::new(NandGate::new(a, b))));
+
+    let cmos = c.gate("cmos").unwrap();
+    let reference = c.gate("ref").unwrap();
+    for v in 0..4 {
+        c.set_input_bool("a", v & 1 == 1).unwrap();
+        c.set_input_bool("b", v & 2 == 2).unwrap();
+        assert_eq!(cmos.borrow().eval(), reference.borrow().eval(), "inputs {v:02b}");
+    }
+}
+
+#[test]
+fn test_inverter_chain_and_unknown_gate() {
+    let a = Rc::new(RefCell::new(ConstGate::new(Signal::Low)));
+    let mut net = SwitchNetwork::new();
+    net.input("a", a.clone()).unwrap();
+    for (i, o) in [("a", "n1"), ("n1", "n2")] {
+        net.pmos(i, VDD, o);
+        net.nmos(i, o, GND);
+    }
+    let net = Rc::new(net);
+    let n2 = SwitchNode::new(net.clone(), "n2").unwrap();
+    assert_eq!(n2.eval(), Signal::Low);
+
+    a.borrow_mut().set_level(Signal::High);
+    assert_eq!(n2.eval(), Signal::High);
+
+    // both transistors may conduct, so the output is unknown
+    a.borrow_mut().set_level(Signal::X);
+    assert_eq!(net.solve()[net.node_names().iter().position(|n| n == "n1").unwrap()], Signal::X);
+    assert_eq!(n2.eval(), Signal::X);
+
+    assert!(SwitchNode::new(net, "nope").is_err());
+}
+
+#[test]
+fn test_transmission_gate_and_charge_storage() {
+    let en = Rc::new(RefCell::new(ConstGate::new(Signal::High)));
+    let en_n = Rc::new(RefCell::new(ConstGate::new(Signal::Low)));
+    let d = Rc::new(RefCell::new(ConstGate::new(Signal::High)));
+
+    let mut net = SwitchNetwork::new();
+    net.input("en", en.clone()).unwrap();
+    net.input("en_n", en_n.clone()).unwrap();
+    net.input("d", d.clone()).unwrap();
+    net.transmission_gate("en", "en_n", "d", "store");
+    let net = Rc::new(net);
+    let store = SwitchNode::new(net, "store").unwrap();
+
+    assert_eq!(store.eval(), Signal::High);
+    d.borrow_mut().set_level(Signal::Low);
+    assert_eq!(store.eval(), Signal::Low);
+
+    // closing the gate leaves the node holding its charge
+    en.borrow_mut().set_level(Signal::Low);
+    en_n.borrow_mut().set_level(Signal::High);
+    d.borrow_mut().set_level(Signal::High);
+    assert_eq!(store.eval(), Signal::Low);
+}
+
+#[test]
+fn test_charge_sharing_gives_x() {
+    let load = Rc::new(RefCell::new(ConstGate::new(Signal::High)));
+    let share = Rc::new(RefCell::new(ConstGate::new(Signal::Low)));
+
+    let mut net = SwitchNetwork::new();
+    net.input("load", load.clone()).unwrap();
+    net.input("share", share.clone()).unwrap();
+    net.nmos("load", VDD, "p");
+    net.nmos("load", GND, "q");
+    net.nmos("share", "p", "q");
+    let net = Rc::new(net);
+    let p = SwitchNode::new(net.clone(), "p").unwrap();
+    let q = SwitchNode::new(net.clone(), "q").unwrap();
+
+    assert_eq!((p.eval(), q.eval()), (Signal::High, Signal::Low));
+
+    load.borrow_mut().set_level(Signal::Low);
+    assert_eq!((p.eval(), q.eval()), (Signal::High, Signal::Low));
+
+    share.borrow_mut().set_level(Signal::High);
+    assert_eq!((p.eval(), q.eval()), (Signal::X, Signal::X));
+}
+
+#[test]
+fn test_floating_and_fighting_nodes() {
+    let mut net = SwitchNetwork::new();
+    net.input("on", level(Signal::High)).unwrap();
+    net.input("z", level(Signal::HiZ)).unwrap();
+    net.nmos("on", VDD, "short");
+    net.nmos("on", GND, "short");
+    net.nmos("z", "z", "float");
+    assert!(net.input(VDD, level(Signal::Low)).is_err());
+
+    let values = net.solve();
+    let at = |name: &str| values[net.node_names().iter().position(|n| n == name).unwrap()];
+    assert_eq!(at("short"), Signal::X);
+    assert_eq!(at("float"), Signal::HiZ);
+    assert_eq!(at(VDD), Signal::High);
+}
@@ -0,0 +1,101 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::terminal::*;
+use crate::circuit::wire::Wire;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const KEYS: [&str; 8] = ["k0", "k1", "k2", "k3", "k4", "k5", "k6", "k7"];
+
+/// Keyboard wired straight into a TTY, strobed on every clock high phase
+/// while a key is waiting.
+fn echo_circuit() -> Circuit {
+    let mut c = Circuit::new();
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.add_wire("ack", Wire::new("ack"));
+    c.add_keyboard("con", "ack", &KEYS, "ready").unwrap();
+
+    let strobe = AndGate::new(c.gate("ready").unwrap(), c.gate("clk").unwrap());
+    c.add_gate("strobe", Rc::new(RefCell::new(strobe)));
+    c.connect("strobe", "ack").unwrap();
+    c.add_tty("tty", "con", &KEYS, "strobe").unwrap();
+    c
+}
+
+#[test]
+fn test_keyboard_outputs() {
+    let mut c = echo_circuit();
+    for id in KEYS.iter().chain(["ready"].iter()) {
+        c.add_output(*id);
+    }
+    assert!(!c.eval()["ready"]);
+
+    c.terminal("con").unwrap().borrow_mut().push_input(b"A");
+    let out = c.eval();
+    assert!(out["ready"]);
+    let key = KEYS.iter().enumerate().fold(0u8, |acc, (i, id)| acc | (out[*id] as u8) << i);
+    assert_eq!(key, b'A');
+}
+
+#[test]
+fn test_echo() {
+    let mut c = echo_circuit();
+    let term = c.terminal("con").unwrap();
+    term.borrow_mut().push_input(b"hi!");
+
+    for _ in 0..4 {
+        c.step();
+    }
+    assert_eq!(term.borrow().output(), b"hi");
+    assert_eq!(term.borrow().pending_input(), 1);
+
+    for _ in 0..10 {
+        c.step();
+    }
+    assert_eq!(term.borrow().output_text(), "hi!");
+    assert_eq!(term.borrow().pending_input(), 0);
+}
+
+#[test]
+fn test_take_output() {
+    let mut term = Terminal::new();
+    term.write_byte(b'a');
+    assert_eq!(term.take_output(), b"a");
+    term.write_byte(b'b');
+    assert_eq!(term.take_output(), b"b");
+    assert!(term.take_output().is_empty());
+    assert_eq!(term.output(), b"ab");
+}
+
+#[test]
+fn test_run_headless() {
+    let mut c = echo_circuit();
+    let mut stdout = Vec::new();
+    run_headless(&mut c, "con", 20, std::io::Cursor::new(b"ok\n".to_vec()), &mut stdout).unwrap();
+    assert_eq!(stdout, b"ok\n");
+    assert_eq!(c.terminal("con").unwrap().borrow().output_text(), "ok\n");
+
+    // keys typed ahead of the run come first, and an ended input stops waiting
+    c.terminal("con").unwrap().borrow_mut().push_input(b"a");
+    stdout.clear();
+    run_headless(&mut c, "con", 20, std::io::Cursor::new(b"bc".to_vec()), &mut stdout).unwrap();
+    assert_eq!(stdout, b"abc");
+    run_headless(&mut c, "con", 20, std::io::empty(), &mut stdout).unwrap();
+    assert_eq!(stdout, b"abc");
+
+    let mut back = Circuit::from_json(&c.to_json().unwrap()).unwrap();
+    assert!(back.terminal("con").unwrap().borrow().has_keyboard());
+    stdout.clear();
+    run_headless(&mut back, "con", 20, std::io::Cursor::new(b"!".to_vec()), &mut stdout).unwrap();
+    assert_eq!(stdout, b"!");
+
+    assert!(run_headless(&mut c, "missing", 1, std::io::empty(), &mut stdout).is_err());
+}
+
+#[test]
+fn test_add_tty_errors() {
+    let mut c = echo_circuit();
+    assert!(c.add_tty("t2", "con", &["k0"; 9], "strobe").is_err());
+    assert!(c.add_tty("t2", "con", &KEYS, "missing").is_err());
+    assert!(c.add_keyboard("con", "ack", &KEYS[..7], "r").is_err());
+}
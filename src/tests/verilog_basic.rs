@@ -0,0 +1,185 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::subcircuit::*;
+use crate::circuit::wire::Wire;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+fn with_inputs(ids: &[&str]) -> Circuit {
+    let mut c = Circuit::new();
+    for id in ids {
+        c.add_gate(*id, Rc::new(RefCell::new(InputGate::new(false))));
+    }
+    c
+}
+
+/// Module name to its text, checking every module is closed.
+fn modules(text: &str) -> BTreeMap<String, String> {
+    assert_eq!(text.matches("\nmodule ").count() + text.starts_with("module ") as usize, text.matches("endmodule").count());
+    text.split("endmodule")
+        .filter_map(|m| {
+            let m = m.trim_start();
+            let name = m.strip_prefix("module ")?.split_whitespace().next()?;
+            Some((name.to_string(), m.to_string()))
+        })
+        .collect()
+}
+
+/// Reads `text` back and checks it behaves like `c` for every combination
+/// of `inputs`, each port named in `c` and then in `text`. With `clk` the
+/// two are also clocked through both levels each time.
+fn assert_round_trip(c: &mut Circuit, text: &str, inputs: &[&str], outputs: &[(&str, &str)], clk: Option<&str>) {
+    let mut back = Circuit::from_verilog(text, None).unwrap_or_else(|e| panic!("{e}\n{text}"));
+    for n in 0..1u32 << inputs.len() {
+        for (i, id) in inputs.iter().enumerate() {
+            c.set_input_bool(id, n >> i & 1 == 1).unwrap();
+            back.set_input_bool(id, n >> i & 1 == 1).unwrap();
+        }
+        for level in clk.iter().flat_map(|clk| [(clk, true), (clk, false)]) {
+            back.set_input_bool(level.0, level.1).unwrap();
+            back.settle();
+            c.step();
+        }
+        c.settle();
+        back.settle();
+        let (got, want) = (back.eval(), c.eval());
+        for (orig, port) in outputs {
+            assert_eq!(got[*port], want[*orig], "{orig} at {n:b} in\n{text}");
+        }
+    }
+}
+
+#[test]
+fn test_flat_gates() {
+    let mut c = with_inputs(&["a", "b", "en"]);
+    let (a, b, en) = (c.gate("a").unwrap(), c.gate("b").unwrap(), c.gate("en").unwrap());
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    let clk = c.gate("clk").unwrap();
+    c.add_gate("and", Rc::new(RefCell::new(AndGate::new(a.clone(), b.clone()))));
+    c.add_gate("tri", Rc::new(RefCell::new(TriStateGate::new(a.clone(), en))));
+    c.add_gate("ff", Rc::new(RefCell::new(Dflipflop::new(c.gate("and").unwrap(), clk))));
+    c.add_gate("unused", Rc::new(RefCell::new(OrGate::new(a, b))));
+    c.add_wire("bus", Wire::new("bus"));
+    c.add_driver("tri", "bus").unwrap();
+    c.add_pull_down("pd", "bus").unwrap();
+    c.add_output("ff");
+    c.add_output("bus");
+
+    let text = c.to_verilog("top").unwrap();
+    let m = &modules(&text)["top"];
+    for line in [
+        "input wire a,", "input wire clk,", "output reg ff,", "output wire bus",
+        "and (and_, a, b);", "bufif1 (tri_, a, en);", "always @(posedge clk) ff <= and_;",
+        "assign bus = tri_;", "pulldown (bus);", "or (unused, a, b);",
+    ] {
+        assert!(m.contains(line), "missing '{line}' in\n{m}");
+    }
+}
+
+#[test]
+fn test_tri_state_bus_reads_back() {
+    // the reader takes no pull resistors, so one driver is always enabled
+    let mut c = with_inputs(&["a", "b", "sel"]);
+    let (a, b, sel) = (c.gate("a").unwrap(), c.gate("b").unwrap(), c.gate("sel").unwrap());
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.add_gate("nsel", Rc::new(RefCell::new(NotGate::new(sel.clone()))));
+    c.add_gate("ta", Rc::new(RefCell::new(TriStateGate::new(a, sel))));
+    c.add_gate("tb", Rc::new(RefCell::new(TriStateGate::new(b, c.gate("nsel").unwrap()))));
+    c.add_wire("bus", Wire::new("bus"));
+    c.add_driver("ta", "bus").unwrap();
+    c.add_driver("tb", "bus").unwrap();
+    c.add_gate("ff", Rc::new(RefCell::new(Dflipflop::new(c.gate("bus").unwrap(), c.gate("clk").unwrap()))));
+    c.add_output("bus");
+    c.add_output("ff");
+
+    let text = c.to_verilog("top").unwrap();
+    assert!(text.contains("bufif1 (ta, a, sel);"), "{text}");
+    assert_round_trip(&mut c, &text, &["a", "b", "sel"], &[("bus", "bus"), ("ff", "ff")], Some("clk"));
+}
+
+#[test]
+fn test_hierarchy_becomes_modules() {
+    let mut c = with_inputs(&["a", "b", "cin"]);
+    c.define(SubcircuitDef::half_adder()).unwrap();
+    c.define(SubcircuitDef::full_adder()).unwrap();
+    c.instantiate("full_adder", "fa", &["a", "b", "cin"]).unwrap();
+    c.add_output("fa.sum");
+    c.add_output("fa.cout");
+
+    let text = c.to_verilog("adder").unwrap();
+    let mods = modules(&text);
+    assert_eq!(mods.keys().collect::<Vec<_>>(), ["adder", "full_adder", "half_adder"]);
+    assert!(text.trim_end().ends_with("endmodule") && text.rfind("module adder") > text.rfind("module full_adder"));
+    assert!(mods["full_adder"].contains("half_adder ha0 ("), "{}", mods["full_adder"]);
+    assert!(mods["half_adder"].contains("xor ("), "{}", mods["half_adder"]);
+    let top = &mods["adder"];
+    assert!(top.contains("full_adder fa ("), "{top}");
+    assert!(top.contains("assign fa__a = a;"), "{top}");
+    assert!(top.contains("output wire fa__sum"), "{top}");
+    assert_round_trip(&mut c, &text, &["a", "b", "cin"], &[("fa.sum", "fa__sum"), ("fa.cout", "fa__cout")], None);
+
+    // the top module may not take a subcircuit's name
+    assert!(c.to_verilog("half_adder").is_err());
+}
+
+#[test]
+fn test_parameters_specialize_modules() {
+    let mut c = with_inputs(&["en", "d0", "d1", "d2", "d3"]);
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.define(SubcircuitDef::register()).unwrap();
+    let params = |w| [("W".to_string(), w)].into_iter().collect();
+    c.instantiate_with("register", "r4", &params(4), &["d0", "d1", "d2", "d3", "en", "clk"]).unwrap();
+    c.instantiate_with("register", "r2", &params(2), &["d0", "d1", "en", "clk"]).unwrap();
+    c.add_output("r2.q1");
+
+    let mods = modules(&c.to_verilog("top").unwrap());
+    assert!(mods.contains_key("register_W4_INIT0"), "{:?}", mods.keys());
+    assert!(mods.contains_key("register_W2_INIT0"), "{:?}", mods.keys());
+    assert!(mods["register_W4_INIT0"].contains("always @(posedge"));
+    assert!(!mods["register_W2_INIT0"].contains("q2"));
+    assert!(mods["top"].contains("register_W4_INIT0 r4 ("));
+}
+
+#[test]
+fn test_export_errors() {
+    let mut c = with_inputs(&["a", "b"]);
+    c.add_gate("cin", Rc::new(RefCell::new(InputGate::new(false))));
+    c.define(SubcircuitDef::half_adder()).unwrap();
+    c.define(SubcircuitDef::full_adder()).unwrap();
+    c.instantiate("full_adder", "fa", &["a", "b", "cin"]).unwrap();
+    c.add_gate("peek", Rc::new(RefCell::new(NotGate::new(c.gate("fa.ha0.sum").unwrap()))));
+    c.add_output("peek");
+    let err = c.to_verilog("top").unwrap_err();
+    assert!(err.contains("inside instance 'fa'"), "{err}");
+
+    let mut c = with_inputs(&["a"]);
+    c.add_closure_gate("f", &["a"], &["y"], |ins| vec![ins[0]]).unwrap();
+    c.add_output("y");
+    assert!(c.to_verilog("top").is_err());
+}
+
+#[test]
+fn test_keyword_named_nets() {
+    let mut c = with_inputs(&["wait", "signed", "edge"]);
+    let (wait, signed, edge) = (c.gate("wait").unwrap(), c.gate("signed").unwrap(), c.gate("edge").unwrap());
+    c.add_gate("table", Rc::new(RefCell::new(AndGate::new(wait, signed))));
+    c.add_gate("wor", Rc::new(RefCell::new(OrGate::new(c.gate("table").unwrap(), edge))));
+    c.add_output("wor");
+
+    let text = c.to_verilog("top").unwrap();
+    let m = &modules(&text)["top"];
+    for line in ["input wire wait_,", "input wire signed_,", "output wire wor_", "and (table_, wait_, signed_);"] {
+        assert!(m.contains(line), "missing '{line}' in\n{m}");
+    }
+
+    let mut back = Circuit::from_verilog(&text, None).unwrap_or_else(|e| panic!("{e}\n{text}"));
+    for n in 0..8u8 {
+        for (id, v) in ["wait", "signed", "edge"].iter().zip([n & 1, n & 2, n & 4]) {
+            c.set_input_bool(id, v != 0).unwrap();
+            back.set_input_bool(&format!("{id}_"), v != 0).unwrap();
+        }
+        assert_eq!(back.eval()["wor_"], c.eval()["wor"], "{n:03b}");
+    }
+}
+
@@ -0,0 +1,219 @@
+use crate::circuit::circuit::Circuit;
+use crate::circuit::gate::*;
+use crate::circuit::subcircuit::SubcircuitDef;
+use crate::circuit::verilog_import::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn level(c: &Circuit, id: &str) -> Signal {
+    c.gate(id).unwrap_or_else(|| panic!("no gate '{id}'")).borrow().eval()
+}
+
+fn set(c: &mut Circuit, id: &str, v: bool) {
+    c.set_input_bool(id, v).unwrap();
+    c.settle();
+}
+
+/// A textbook full adder: ports declared in the body, implicit wires.
+const FULL_ADDER: &str = "
+// full adder from primitives
+module full_adder(a, b, cin, sum, cout);
+    input a, b, cin;
+    output sum, cout;
+    xor x1(s1, a, b);        /* s1 is implicit */
+    xor x2(sum, s1, cin);
+    and a1(c1, a, b), a2(c2, s1, cin);
+    or  #1 o1(cout, c1, c2);
+endmodule
+";
+
+#[test]
+fn test_textbook_full_adder() {
+    let mut c = Circuit::from_verilog(FULL_ADDER, None).unwrap();
+    for n in 0..8u8 {
+        let bits = [n & 1 == 1, n & 2 != 0, n & 4 != 0];
+        for (id, v) in ["a", "b", "cin"].iter().zip(bits) {
+            set(&mut c, id, v);
+        }
+        let total = bits.iter().filter(|b| **b).count();
+        assert_eq!(level(&c, "sum").is_high(), total & 1 == 1, "{n:03b}");
+        assert_eq!(level(&c, "cout").is_high(), total >= 2, "{n:03b}");
+    }
+    assert_eq!(c.to_netlist().unwrap().outputs, ["sum", "cout"]);
+}
+
+#[test]
+fn test_vectors_registers_and_instances() {
+    let text = "
+module reg4 (input clk, input en, input [3:0] d, output reg [3:0] q);
+    initial q = 4'b1010;
+    always @(posedge clk) begin
+        if (en) q <= d;
+    end
+endmodule
+
+module top (input clk, input load, input inv, input [3:0] x, output [3:0] y, output zero, output pick);
+    wire [3:0] qs;
+    reg4 r (.clk(clk), .en(load), .d(inv ? ~x : x), .q(qs));
+    assign y = qs;
+    assign zero = ~|qs;
+    assign pick = qs[3] & qs[1];
+endmodule
+";
+    let mut c = Circuit::from_verilog(text, None).unwrap();
+    assert_eq!(c.instance_module("r"), Some("reg4"));
+    let value = |c: &Circuit| (0..4).filter(|i| level(c, &format!("y[{i}]")).is_high()).fold(0, |a, i| a | 1 << i);
+    assert_eq!(value(&c), 0b1010, "initial value");
+    assert!(level(&c, "pick").is_high());
+
+    for (i, v) in [true, true, false, false].into_iter().enumerate() {
+        set(&mut c, &format!("x[{i}]"), v);
+    }
+    set(&mut c, "clk", true);
+    set(&mut c, "clk", false);
+    assert_eq!(value(&c), 0b1010, "holds without load");
+
+    set(&mut c, "load", true);
+    set(&mut c, "clk", true);
+    set(&mut c, "clk", false);
+    assert_eq!(value(&c), 0b0011);
+    set(&mut c, "inv", true);
+    set(&mut c, "clk", true);
+    assert_eq!(value(&c), 0b1100);
+    assert!(!level(&c, "zero").is_high());
+
+    set(&mut c, "clk", false);
+    for i in 0..4 {
+        set(&mut c, &format!("x[{i}]"), true);
+    }
+    set(&mut c, "clk", true);
+    assert!(level(&c, "zero").is_high());
+}
+
+#[test]
+fn test_yosys_style_netlist() {
+    let text = r#"
+(* top = 1 *)
+module \decoder (sel, en, y, \out.ok );
+    (* src = "dec.v:1" *)
+    input [1:0] sel;
+    input en;
+    output [3:0] y;
+    output \out.ok ;
+    wire [1:0] _0_;
+    localparam [3:0] ONE_HOT = 4'b0110;
+    assign _0_ = { sel[0], sel[1] };
+    assign y = { 4 { en } } & { _0_ == 2'b11, _0_ == 2'b01, _0_ == 2'b10, _0_ == 2'b00 };
+    assign \out.ok = ONE_HOT[sel];
+    bufif1 (y_bus, en, sel[0]);
+endmodule
+"#;
+    let mut c = Circuit::from_verilog(text, None).unwrap();
+    set(&mut c, "en", true);
+    for n in 0..4usize {
+        set(&mut c, "sel[0]", n & 1 == 1);
+        set(&mut c, "sel[1]", n & 2 != 0);
+        for k in 0..4 {
+            assert_eq!(level(&c, &format!("y[{k}]")).is_high(), k == n, "sel {n}, y[{k}]");
+        }
+        assert_eq!(level(&c, "out_ok").is_high(), 0b0110 >> n & 1 == 1, "sel {n}");
+        assert_eq!(level(&c, "y_bus"), if n & 1 == 1 { Signal::High } else { Signal::HiZ });
+    }
+}
+
+#[test]
+fn test_exported_verilog_reads_back() {
+    let mut c = Circuit::new();
+    for id in ["a", "b", "cin", "en"] {
+        c.add_gate(id, Rc::new(RefCell::new(InputGate::new(false))));
+    }
+    c.add_gate("clk", Rc::new(RefCell::new(ClockGate::new())));
+    c.define(SubcircuitDef::half_adder()).unwrap();
+    c.define(SubcircuitDef::full_adder()).unwrap();
+    c.instantiate("full_adder", "fa", &["a", "b", "cin"]).unwrap();
+    let (sum, en, clk) = (c.gate("fa.sum").unwrap(), c.gate("en").unwrap(), c.gate("clk").unwrap());
+    c.add_gate("ff", Rc::new(RefCell::new(Dflipflop::new(sum.clone(), clk))));
+    c.add_gate("latch", Rc::new(RefCell::new(Dlatch::new(sum.clone(), en.clone()))));
+    c.add_gate("drv", Rc::new(RefCell::new(TriStateGate::new(sum, en))));
+    c.add_lut("maj", &["a", "b", "cin"], crate::circuit::lut::TruthTable::from_fn(3, |r| (r as u32).count_ones() >= 2).unwrap()).unwrap();
+    for out in ["fa.sum", "fa.cout", "ff", "latch", "drv", "maj"] {
+        c.add_output(out);
+    }
+
+    let text = c.to_verilog("top").unwrap();
+    let mut back = Circuit::from_verilog(&text, None).unwrap_or_else(|e| panic!("{e}\n{text}"));
+    assert_eq!(back.instance_module("fa"), Some("full_adder"));
+    assert_eq!(back.instance_module("fa.ha0"), Some("half_adder"));
+    for n in 0..16u8 {
+        for (id, v) in ["a", "b", "cin", "en"].iter().zip([n & 1, n & 2, n & 4, n & 8]) {
+            set(&mut c, id, v != 0);
+            set(&mut back, id, v != 0);
+        }
+        for clk in [true, false] {
+            set(&mut back, "clk", clk);
+            c.step();
+        }
+        for (orig, port) in [("fa.sum", "fa__sum"), ("fa.cout", "fa__cout"), ("latch", "latch"), ("drv", "drv"), ("maj", "maj")] {
+            assert_eq!(level(&back, port), level(&c, orig), "{orig} at {n:04b}");
+        }
+    }
+}
+
+#[test]
+fn test_diagnostics() {
+    let text = "module m (input a, input b, input clk, input rst, output y, output z, output reg q, output w);
+    assign y = a + b;
+    always @(posedge clk or posedge rst) q <= a;
+    always @(*) begin
+        case (a) 1'b0: q = b; endcase
+    end
+    nothing u1 (a, z);
+    assign w = ghost;
+endmodule
+";
+    let diags = import(text, None).unwrap_err();
+    let at: Vec<(usize, usize)> = diags.iter().map(|d| (d.line, d.col)).collect();
+    assert_eq!(&at[..3], [(2, 18), (3, 29), (5, 9)], "{diags:?}");
+    assert!(diags[0].message.contains("'+'"), "{}", diags[0]);
+    assert!(diags[1].message.contains("asynchronous"), "{}", diags[1]);
+    assert!(diags[2].message.contains("'case'"), "{}", diags[2]);
+    assert!(diags.iter().any(|d| d.message.contains("unknown module 'nothing'") && d.line == 7), "{diags:?}");
+    assert!(diags.iter().any(|d| d.message.contains("'ghost'")), "{diags:?}");
+    assert_eq!(diags[0].to_string(), format!("2:18: {}", diags[0].message));
+
+    let err = Circuit::from_verilog("module a; endmodule\nmodule b; endmodule", None).unwrap_err();
+    assert!(err.contains("several top modules: a, b"), "{err}");
+    assert!(Circuit::from_verilog("module a; endmodule\nmodule b; endmodule", Some("b")).is_ok());
+    let err = Circuit::from_verilog("module a(input x, output y);\n  assign x = y;\nendmodule", None).unwrap_err();
+    assert!(err.starts_with("2:10:") && err.contains("input 'x'"), "{err}");
+    assert!(Circuit::from_verilog("module a(output y);\n  assign y = 1'b1;\n", None).unwrap_err().contains("endmodule"));
+    assert!(Circuit::load_verilog("/nonexistent/top.v", None).is_err());
+}
+
+#[test]
+fn test_oversized_widths() {
+    let diags = import("module m(output y);\n    assign y = 99999999999'b0;\nendmodule\n", None).unwrap_err();
+    assert_eq!((diags[0].line, diags[0].col), (2, 16), "{diags:?}");
+    assert!(diags[0].message.contains("4096 bits"), "{}", diags[0]);
+
+    let diags = import("module m(a, y);\n    input [4000000000:0] a;\n    output y;\n    assign y = a[0];\nendmodule\n", None).unwrap_err();
+    assert_eq!((diags[0].line, diags[0].col), (2, 11), "{diags:?}");
+    assert!(diags[0].message.contains("[4000000000:0]"), "{}", diags[0]);
+
+    let diags = import("module m(input a, output [3:0] y);\n    assign y = {100000{a}};\nendmodule\n", None).unwrap_err();
+    assert_eq!(diags[0].line, 2, "{diags:?}");
+    assert!(diags[0].message.contains("replication"), "{}", diags[0]);
+
+    assert!(Circuit::from_verilog("module m(output [4095:0] y);\n    assign y = 4096'h0;\nendmodule\n", None).is_ok());
+}
+
+#[test]
+fn test_zero_widths() {
+    let diags = import("module m(output y);\n    assign y = 0'b1;\nendmodule\n", None).unwrap_err();
+    assert_eq!((diags[0].line, diags[0].col), (2, 16), "{diags:?}");
+    assert!(diags[0].message.contains("size zero"), "{}", diags[0]);
+
+    let diags = import("module m(input a, output [1:0] y);\n    assign y = {a, {0{a}}};\nendmodule\n", None).unwrap_err();
+    assert_eq!((diags[0].line, diags[0].col), (2, 20), "{diags:?}");
+    assert!(diags[0].message.contains("replication count 0"), "{}", diags[0]);
+}
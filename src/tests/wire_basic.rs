@@ -1,7 +1,8 @@
+use crate::circuit::circuit::Circuit;
 use crate::circuit::{gate::Gate, wire::Wire};
 use std::rc::Rc;
 use std::cell::RefCell;
-use crate::circuit::gate::{Signal, ConstGate};
+use crate::circuit::gate::*;
 
 
 #[test]
@@ -9,10 +10,84 @@ fn test_wire(){
     let const_gate = Rc::new(RefCell::new(ConstGate::new(Signal::High)));
     let mut wire = Wire::new("w1");
 
-    assert_eq!(wire.eval(), Signal::Low);
+    assert_eq!(wire.eval(), Signal::HiZ);
     assert_eq!(wire.description(),"Wire(w1, connected: None)");
 
     wire.connect(const_gate.clone());
     assert_eq!(wire.eval(), Signal::High);
     assert_eq!(wire.description(), "Wire(w1, connected: Const 1)")
 }
+
+#[test]
+fn test_pulls_resolve_floating_wire() {
+    let mut c = Circuit::new();
+    c.add_gate("en", Rc::new(RefCell::new(InputGate::new(false))));
+    c.add_gate("d", Rc::new(RefCell::new(InputGate::new(false))));
+    let tri = TriStateGate::new(c.gate("d").unwrap(), c.gate("en").unwrap());
+    c.add_gate("tri", Rc::new(RefCell::new(tri)));
+    c.add_wire("bus", Wire::new("bus"));
+    c.connect("tri", "bus").unwrap();
+    let bus = c.gate("bus").unwrap();
+
+    assert_eq!(bus.borrow().eval(), Signal::HiZ);
+
+    c.add_pull_up("r", "bus").unwrap();
+    assert_eq!(bus.borrow().eval(), Signal::High);
+
+    // a driven net ignores the pull
+    c.set_input_bool("en", true).unwrap();
+    assert_eq!(bus.borrow().eval(), Signal::Low);
+
+    c.set_input_bool("en", false).unwrap();
+    c.disconnect("r", "bus").unwrap();
+    c.add_pull_down("r2", "bus").unwrap();
+    assert_eq!(bus.borrow().eval(), Signal::Low);
+    assert!(c.disconnect("r", "bus").is_err());
+}
+
+#[test]
+fn test_open_drain_wired_and() {
+    let mut c = Circuit::new();
+    c.add_wire("line", Wire::new("line"));
+    c.add_pull_up("r", "line").unwrap();
+    for (i, id) in ["a", "b", "c"].iter().enumerate() {
+        c.add_gate(*id, Rc::new(RefCell::new(InputGate::new(true))));
+        let od = format!("od{i}");
+        c.add_open_drain(&od, id).unwrap();
+        c.add_driver(&od, "line").unwrap();
+    }
+    c.add_output("line");
+
+    assert!(c.eval()["line"]);
+    c.set_input_bool("b", false).unwrap();
+    assert!(!c.eval()["line"]);
+    c.set_input_bool("c", false).unwrap();
+    assert!(!c.eval()["line"]);
+}
+
+#[test]
+fn test_driver_contention() {
+    let high = Rc::new(RefCell::new(ConstGate::new(Signal::High)));
+    let low = Rc::new(RefCell::new(ConstGate::new(Signal::Low)));
+    let z = Rc::new(RefCell::new(ConstGate::new(Signal::HiZ)));
+    let mut wire = Wire::new("w");
+
+    wire.add_driver(high.clone());
+    wire.add_driver(z);
+    wire.add_driver(high.clone());
+    assert_eq!(wire.eval(), Signal::High);
+    assert_eq!(wire.inputs().len(), 2);
+
+    wire.add_driver(low.clone());
+    assert_eq!(wire.eval(), Signal::X);
+    assert_eq!(and(wire.eval(), Signal::High), Signal::X);
+
+    // pulls fighting each other are just as unknown
+    let mut pulls = Wire::new("p");
+    pulls.add_driver(Rc::new(RefCell::new(PullResistor::up())));
+    pulls.add_driver(Rc::new(RefCell::new(PullResistor::down())));
+    assert_eq!(pulls.eval(), Signal::X);
+
+    wire.connect(low);
+    assert_eq!(wire.eval(), Signal::Low);
+}